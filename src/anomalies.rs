@@ -0,0 +1,407 @@
+//! Files of a size atypical for their extension, like a 4 GiB `.json` or a
+//! 2-byte `.mp4`, which tend to be corrupt exports or truncated downloads.
+//!
+//! Each extension is a group with its own median and quartiles. A file is
+//! atypical when it lies more than a multiple of the interquartile range
+//! beyond the quartiles, or is more than a multiple of the median larger
+//! or smaller than it. Groups too small for a typical range are skipped,
+//! and so are files without an extension, which aren't of one kind.
+//!
+//! The sizes of each group are counted in buckets, see
+//! [`CompactSizes`], so memory doesn't grow with the files of an
+//! extension and the statistics are within 0.39% of the exact ones. Only
+//! the paths of the largest and smallest files of each group are kept, as
+//! only those can be atypical.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+
+use crate::compact::CompactSizes;
+use crate::distribution::{Distribution, Ranked};
+use crate::format;
+use crate::table::{Align, Table};
+use crate::terminal::Capabilities;
+
+/// Paths kept at each end of a group
+pub const TAIL: usize = 10;
+
+/// When a file counts as atypical for its extension.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Thresholds {
+    /// How many interquartile ranges beyond the quartiles
+    pub iqr_multiple: f64,
+    /// How many times larger or smaller than the median
+    pub median_multiple: f64,
+    /// Files an extension needs before its files are judged
+    pub min_group: usize,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Thresholds {
+            iqr_multiple: 10.0,
+            median_multiple: 100.0,
+            min_group: 20,
+        }
+    }
+}
+
+impl Thresholds {
+    /// Whether `size` is atypical of a group with `dist`. Without a spread
+    /// between the quartiles only the median counts, or every file off the
+    /// common size would be.
+    fn atypical(&self, size: u64, dist: &Distribution) -> bool {
+        let size = size as f64;
+        let spread = dist.interquartile_range() * self.iqr_multiple;
+        let fenced = spread > 0.0
+            && (size > dist.upper_quartile + spread || size < dist.lower_quartile - spread);
+        let off_median = dist.median > 0.0
+            && (size > dist.median * self.median_multiple
+                || size * self.median_multiple < dist.median);
+        fenced || off_median
+    }
+}
+
+/// A file of a size atypical for its extension.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Anomaly {
+    pub path: PathBuf,
+    pub size: u64,
+    pub extension: String,
+    /// The median size of the extension's files
+    pub median: f64,
+}
+
+impl Anomaly {
+    /// How many times the median the file is.
+    pub fn times_median(&self) -> f64 {
+        self.size as f64 / self.median
+    }
+
+    /// How far off the median, larger or smaller, for ordering.
+    fn extremity(&self) -> f64 {
+        let times = self.times_median();
+        times.max(1.0 / times)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Group {
+    sizes: CompactSizes,
+    /// The largest files, smallest on top so it can be evicted
+    largest: BinaryHeap<Reverse<(u64, PathBuf)>>,
+    /// The smallest files, largest on top
+    smallest: BinaryHeap<(u64, PathBuf)>,
+}
+
+/// What [`AnomalyTally::finish`] found.
+#[derive(Debug, Clone, Default)]
+pub struct Anomalies {
+    /// The atypical files among those kept, the most atypical first
+    pub files: Vec<Anomaly>,
+    /// Atypical files whose paths weren't kept, past [`TAIL`] at an end of
+    /// their group
+    pub unlisted: u64,
+    /// Extensions with enough files to be judged
+    pub groups: usize,
+    pub thresholds: Thresholds,
+}
+
+/// Groups the files by extension to find those atypical for it.
+#[derive(Debug)]
+pub struct AnomalyTally {
+    thresholds: Thresholds,
+    groups: HashMap<String, Group>,
+}
+
+impl AnomalyTally {
+    pub fn new(thresholds: Thresholds) -> AnomalyTally {
+        AnomalyTally {
+            thresholds,
+            groups: HashMap::new(),
+        }
+    }
+
+    /// Counts a file with the extension as `--group-by ext` has it, "(none)"
+    /// for none.
+    pub fn add(&mut self, extension: &str, size: u64, path: &Path) {
+        if extension == "(none)" {
+            return;
+        }
+        let group = self.groups.entry(extension.to_string()).or_default();
+        group.sizes.add(size);
+        if group.largest.len() < TAIL || group.largest.peek().is_some_and(|top| top.0 .0 < size) {
+            group.largest.push(Reverse((size, path.to_path_buf())));
+            if group.largest.len() > TAIL {
+                group.largest.pop();
+            }
+        }
+        if group.smallest.len() < TAIL || group.smallest.peek().is_some_and(|top| top.0 > size) {
+            group.smallest.push((size, path.to_path_buf()));
+            if group.smallest.len() > TAIL {
+                group.smallest.pop();
+            }
+        }
+    }
+
+    /// Judges the files of the groups large enough.
+    ///
+    /// ```
+    /// use std::path::Path;
+    /// use dudist::anomalies::{AnomalyTally, Thresholds};
+    ///
+    /// let mut tally = AnomalyTally::new(Thresholds::default());
+    /// tally.add(".json", 4 << 30, Path::new("export.json"));
+    /// for anomaly in tally.finish().files {
+    ///     println!("{} is {:.0} times the median", anomaly.path.display(), anomaly.times_median());
+    /// }
+    /// ```
+    pub fn finish(self) -> Anomalies {
+        let thresholds = self.thresholds;
+        let mut found = Anomalies {
+            thresholds,
+            ..Anomalies::default()
+        };
+        for (extension, group) in self.groups {
+            let sizes = group.sizes;
+            if sizes.count() < thresholds.min_group {
+                continue;
+            }
+            found.groups += 1;
+            let dist = Distribution::from_ranked(&sizes);
+            let count = sizes
+                .runs()
+                .filter(|&(size, _)| thresholds.atypical(size, &dist))
+                .map(|(_, files)| files)
+                .sum::<u64>();
+            // The two ends overlap in groups of fewer than twice the tail
+            let mut kept = group
+                .largest
+                .into_iter()
+                .map(|Reverse(file)| file)
+                .chain(group.smallest)
+                .collect::<Vec<_>>();
+            kept.sort_unstable();
+            kept.dedup();
+            let listed = kept
+                .into_iter()
+                .filter(|&(size, _)| thresholds.atypical(size, &dist))
+                .map(|(size, path)| Anomaly {
+                    path,
+                    size,
+                    extension: extension.clone(),
+                    median: dist.median,
+                })
+                .collect::<Vec<_>>();
+            // A bucket straddling a threshold can put the count off by the
+            // files in it
+            found.unlisted += count.saturating_sub(listed.len() as u64);
+            found.files.extend(listed);
+        }
+        found.files.sort_by(|a, b| {
+            b.extremity()
+                .total_cmp(&a.extremity())
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        found
+    }
+}
+
+impl Anomalies {
+    /// The section of the report listing the atypical files.
+    pub fn render(&self, caps: &Capabilities) -> String {
+        let judged = format!(
+            "{} {} of at least {} files",
+            format::format_count(self.groups as u64),
+            if self.groups == 1 {
+                "extension"
+            } else {
+                "extensions"
+            },
+            format::format_count(self.thresholds.min_group as u64)
+        );
+        if self.files.is_empty() {
+            return format!(
+                "No files of atypical size for their extension ({})\n",
+                judged
+            );
+        }
+        let mut out = format!("Files of atypical size for their extension ({}):\n", judged);
+        let mut table = Table::new(&[Align::Right, Align::Right, Align::Right, Align::Left]);
+        table.row(["Size", "Median", "Ratio", "File"].map(str::to_string));
+        let times = caps.text("×", "x");
+        for anomaly in &self.files {
+            let ratio = anomaly.times_median();
+            table.row([
+                format::human(anomaly.size),
+                format::human_f64(anomaly.median),
+                match ratio >= 1.0 {
+                    true => format!("{}{}", format::count_f64(ratio.round()), times),
+                    false => format!("1/{}", format::count_f64((1.0 / ratio).round())),
+                },
+                format::path(&anomaly.path),
+            ]);
+        }
+        out += &table.render();
+        if self.unlisted > 0 {
+            out += &format!(
+                "and {} more at the ends of their extension, past the {} listed at each\n",
+                format::format_count(self.unlisted),
+                TAIL
+            );
+        }
+        out
+    }
+
+    /// The atypical files and the thresholds, for `--json`.
+    pub fn to_json(&self) -> Value {
+        let files = self
+            .files
+            .iter()
+            .map(|anomaly| {
+                json!({
+                    "path": format::path_to_json(&anomaly.path),
+                    "size": anomaly.size,
+                    "extension": anomaly.extension,
+                    "group_median": anomaly.median,
+                    "times_median": anomaly.times_median(),
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "thresholds": {
+                "iqr_multiple": self.thresholds.iqr_multiple,
+                "median_multiple": self.thresholds.median_multiple,
+                "min_group": self.thresholds.min_group,
+            },
+            "groups": self.groups,
+            "unlisted": self.unlisted,
+            "anomalies": files,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tally of 30 `.json` files around 1 KB and 30 `.mp4` around 1 MiB.
+    fn tally(thresholds: Thresholds) -> AnomalyTally {
+        let mut tally = AnomalyTally::new(thresholds);
+        for i in 0..30 {
+            tally.add(".json", 1000 + i * 10, Path::new(&format!("{}.json", i)));
+            tally.add(
+                ".mp4",
+                (1 << 20) + i * 4096,
+                Path::new(&format!("{}.mp4", i)),
+            );
+        }
+        tally
+    }
+
+    fn paths(found: &Anomalies) -> Vec<&str> {
+        found
+            .files
+            .iter()
+            .map(|anomaly| anomaly.path.to_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn files_far_off_their_extension_are_found_the_most_atypical_first() {
+        let mut tally = tally(Thresholds::default());
+        tally.add(".json", 4 << 30, Path::new("export.json"));
+        tally.add(".mp4", 2, Path::new("truncated.mp4"));
+        let found = tally.finish();
+        assert_eq!(found.groups, 2);
+        assert_eq!(found.unlisted, 0);
+        assert_eq!(paths(&found), ["export.json", "truncated.mp4"]);
+        // About 3.7 million times the median of the .json files, the median
+        // being taken from buckets
+        assert_eq!((found.files[0].times_median() / 1e5).round(), 37.0);
+        assert_eq!(found.files[1].extension, ".mp4");
+    }
+
+    #[test]
+    fn typical_files_are_not_found() {
+        let found = tally(Thresholds::default()).finish();
+        assert_eq!(found.groups, 2);
+        assert!(found.files.is_empty());
+        assert_eq!(found.unlisted, 0);
+    }
+
+    #[test]
+    fn small_groups_and_files_without_an_extension_are_skipped() {
+        let mut tally = tally(Thresholds::default());
+        tally.add(".txt", 1, Path::new("a.txt"));
+        tally.add(".txt", 1 << 30, Path::new("b.txt"));
+        tally.add("(none)", 1 << 40, Path::new("core"));
+        let found = tally.finish();
+        assert_eq!(found.groups, 2);
+        assert!(found.files.is_empty());
+
+        // Unless the groups needn't be as large
+        let mut tally = AnomalyTally::new(Thresholds {
+            min_group: 2,
+            ..Thresholds::default()
+        });
+        for i in 0..4 {
+            tally.add(".txt", 1000 + i, Path::new(&format!("{}.txt", i)));
+        }
+        tally.add(".txt", 1 << 30, Path::new("b.txt"));
+        assert_eq!(paths(&tally.finish()), ["b.txt"]);
+    }
+
+    #[test]
+    fn the_interquartile_fence_and_the_median_judge_apart() {
+        // 10 interquartile ranges of about 150 bytes beyond the upper
+        // quartile, though far from 100 times the median
+        let mut tally = tally(Thresholds::default());
+        tally.add(".json", 4000, Path::new("fenced.json"));
+        assert_eq!(paths(&tally.finish()), ["fenced.json"]);
+
+        // Without a spread, only the median counts
+        let mut tally = AnomalyTally::new(Thresholds::default());
+        for i in 0..30 {
+            tally.add(".log", 4096, Path::new(&format!("{}.log", i)));
+        }
+        tally.add(".log", 300_000, Path::new("busy.log"));
+        tally.add(".log", 500_000, Path::new("huge.log"));
+        assert_eq!(paths(&tally.finish()), ["huge.log"]);
+    }
+
+    #[test]
+    fn atypical_files_past_the_tail_are_counted() {
+        let mut tally = tally(Thresholds::default());
+        for i in 0..TAIL as u64 + 5 {
+            tally.add(".json", (4 << 30) + i, Path::new(&format!("big{}.json", i)));
+        }
+        let found = tally.finish();
+        assert_eq!(found.files.len(), TAIL);
+        assert_eq!(found.unlisted, 5);
+        // The largest are kept
+        assert!(paths(&found).contains(&"big14.json"));
+        assert!(!paths(&found).contains(&"big0.json"));
+        let rendered = found.render(&Capabilities::ascii(80));
+        assert!(rendered
+            .ends_with("and 5 more at the ends of their extension, past the 10 listed at each\n"));
+    }
+
+    #[test]
+    fn the_section_says_what_was_judged() {
+        let found = tally(Thresholds::default()).finish();
+        assert_eq!(
+            found.render(&Capabilities::ascii(80)),
+            "No files of atypical size for their extension (2 extensions of at least 20 files)\n"
+        );
+
+        let mut tally = tally(Thresholds::default());
+        tally.add(".mp4", 2, Path::new("truncated.mp4"));
+        let rendered = tally.finish().render(&Capabilities::ascii(80));
+        assert!(rendered.contains("1/"), "{}", rendered);
+        assert!(rendered.ends_with("truncated.mp4\n"), "{}", rendered);
+    }
+}
@@ -0,0 +1,372 @@
+//! Lists the members of tar and zip archives without extracting them.
+//!
+//! The archives are read with the tar, flate2 and zip crates, which need
+//! the `archives` cargo feature.
+
+#[cfg(feature = "archives")]
+use std::fs::File;
+#[cfg(feature = "archives")]
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use crate::scan::{FileRecord, ScanOptions};
+use crate::warnings::{Category, Warnings};
+
+/// What `--archives` reports for an archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveMode {
+    /// The members in place of the archive
+    Members,
+    /// The members and the archive itself
+    Both,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Tar,
+    TarGz,
+    Zip,
+    /// A tarball compressed other than with gzip, counted whole
+    CompressedTar,
+}
+
+fn kind(path: &Path) -> Option<Kind> {
+    let name = path.file_name()?.to_string_lossy().to_lowercase();
+    if name.ends_with(".tar") {
+        Some(Kind::Tar)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(Kind::TarGz)
+    } else if name.ends_with(".zip") {
+        Some(Kind::Zip)
+    } else if [".tar.bz2", ".tar.xz", ".tar.zst"]
+        .iter()
+        .any(|suffix| name.ends_with(suffix))
+    {
+        Some(Kind::CompressedTar)
+    } else {
+        None
+    }
+}
+
+/// A file inside an archive.
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// The regular files of a tarball. The tar crate follows GNU long names
+/// and pax headers, so long paths and sizes beyond 8 GiB come through.
+#[cfg(feature = "archives")]
+fn tar_members<R: Read>(
+    entries: std::io::Result<tar::Entries<'_, R>>,
+) -> Result<Vec<Member>, String> {
+    let corrupt = |e: std::io::Error| format!("not a valid tar archive: {}", e);
+    let mut members = Vec::new();
+    for entry in entries.map_err(corrupt)? {
+        let entry = entry.map_err(corrupt)?;
+        // Directories, links, devices and the like hold no bytes
+        let file = entry.header().entry_type();
+        if !(file.is_file() || file.is_contiguous()) {
+            continue;
+        }
+        members.push(Member {
+            path: entry.path().map_err(corrupt)?.into_owned(),
+            size: entry.size(),
+        });
+    }
+    Ok(members)
+}
+
+/// Plain tarballs are listed by seeking from header to header, gzipped ones
+/// have to be decompressed all the way through.
+#[cfg(feature = "archives")]
+fn tarball_members(path: &Path, gzip: bool) -> Result<Vec<Member>, String> {
+    let file = BufReader::new(File::open(path).map_err(|e| e.to_string())?);
+    match gzip {
+        true => tar_members(tar::Archive::new(flate2::bufread::GzDecoder::new(file)).entries()),
+        false => tar_members(tar::Archive::new(file).entries_with_seek()),
+    }
+}
+
+/// Reads the sizes from the central directory at the end of the archive,
+/// so none of the members need to be decompressed.
+#[cfg(feature = "archives")]
+fn zip_members(path: &Path) -> Result<Vec<Member>, String> {
+    let corrupt = |e: zip::result::ZipError| format!("not a valid zip archive: {}", e);
+    let file = BufReader::new(File::open(path).map_err(|e| e.to_string())?);
+    let mut archive = zip::ZipArchive::new(file).map_err(corrupt)?;
+    let mut members = Vec::new();
+    for i in 0..archive.len() {
+        let member = archive.by_index_raw(i).map_err(corrupt)?;
+        if member.encrypted() {
+            return Err("password-protected".to_string());
+        }
+        if !member.is_dir() {
+            members.push(Member {
+                path: PathBuf::from(String::from_utf8_lossy(member.name_raw()).into_owned()),
+                size: member.size(),
+            });
+        }
+    }
+    Ok(members)
+}
+
+/// The members of an archive of `kind`, other than a compressed tarball.
+#[cfg(feature = "archives")]
+fn members(path: &Path, kind: Kind) -> Result<Vec<Member>, String> {
+    match kind {
+        Kind::Tar => tarball_members(path, false),
+        Kind::TarGz => tarball_members(path, true),
+        Kind::Zip => zip_members(path),
+        Kind::CompressedTar => unreachable!("compressed tarballs are counted whole"),
+    }
+}
+
+/// `--archives` is refused by builds without the feature.
+#[cfg(not(feature = "archives"))]
+fn members(_path: &Path, _kind: Kind) -> Result<Vec<Member>, String> {
+    Err("this build of dudist can't list archives".to_string())
+}
+
+/// Counts what `--archives` did.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ArchiveCounts {
+    pub expanded: u64,
+    pub members: u64,
+    /// Archives that couldn't be listed and were kept as single files
+    pub failed: u64,
+    /// Tarballs compressed other than with gzip, kept as single files
+    pub compressed: u64,
+}
+
+/// Replaces archives among the scanned files by their members.
+#[derive(Debug)]
+pub struct Expander {
+    mode: ArchiveMode,
+    options: ScanOptions,
+    pub counts: ArchiveCounts,
+}
+
+impl Expander {
+    /// Members are filtered by size like other files, by `options`.
+    pub fn new(mode: ArchiveMode, options: &ScanOptions) -> Expander {
+        Expander {
+            mode,
+            options: options.clone(),
+            counts: ArchiveCounts::default(),
+        }
+    }
+
+    /// The records to report for `record`: the members of an archive, as
+    /// if they were files below it, and otherwise the record itself.
+    /// Archives inside archives are counted as members, not opened, and
+    /// archives that can't be listed end up in `warnings`.
+    pub fn expand(&mut self, record: FileRecord, warnings: &Warnings) -> Vec<FileRecord> {
+        let members = match kind(&record.path) {
+            None => return vec![record],
+            Some(Kind::CompressedTar) => {
+                self.counts.compressed += 1;
+                return vec![record];
+            }
+            Some(kind) => members(&record.path, kind),
+        };
+        let members = match members {
+            Ok(members) => members,
+            Err(e) => {
+                warnings.add(Category::Archive, Some(&record.path), e);
+                self.counts.failed += 1;
+                return vec![record];
+            }
+        };
+        self.counts.expanded += 1;
+        let mut records = members
+            .into_iter()
+            .filter(|member| self.options.matches(member.size))
+            .map(|member| FileRecord {
+                path: record.path.join(member.path),
+                size: member.size,
+                allocated: None,
+                inode: None,
+                links: 1,
+                ..record.clone()
+            })
+            .collect::<Vec<_>>();
+        self.counts.members += records.len() as u64;
+        if self.mode == ArchiveMode::Both {
+            records.push(record);
+        }
+        records
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Longer than the 100 bytes a tar header has room for
+    #[cfg(feature = "archives")]
+    const LONG: &str = "deep/deep/deep/deep/deep/deep/deep/deep/deep/deep/deep/deep/deep/deep/deep/deep/deep/deep/deep/deep/deep/file.bin";
+
+    /// Members of any size, or the small ones made here would be left out.
+    fn any_size() -> ScanOptions {
+        ScanOptions::builder().min_size(0).build().unwrap()
+    }
+
+    fn record(path: &Path) -> FileRecord {
+        FileRecord::from_metadata(path.to_path_buf(), &std::fs::metadata(path).unwrap())
+    }
+
+    /// The members of `path` as `--archives` reports them, relative to it.
+    fn expand(expander: &mut Expander, path: &Path, warnings: &Warnings) -> Vec<(String, u64)> {
+        let mut members = expander
+            .expand(record(path), warnings)
+            .into_iter()
+            .map(|record| {
+                let member = record.path.strip_prefix(path).unwrap_or(&record.path);
+                (member.to_string_lossy().into_owned(), record.size)
+            })
+            .collect::<Vec<_>>();
+        members.sort();
+        members
+    }
+
+    #[cfg(feature = "archives")]
+    fn write_tar(writer: impl std::io::Write) {
+        let mut builder = tar::Builder::new(writer);
+        let mut directory = tar::Header::new_gnu();
+        directory.set_entry_type(tar::EntryType::Directory);
+        directory.set_size(0);
+        builder
+            .append_data(&mut directory, "deep/", std::io::empty())
+            .unwrap();
+        for (name, size) in [("a.txt", 10), (LONG, 3000), ("inner.zip", 22)] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(size);
+            builder
+                .append_data(&mut header, name, &vec![b'x'; size as usize][..])
+                .unwrap();
+        }
+        builder.into_inner().unwrap();
+    }
+
+    #[cfg(feature = "archives")]
+    fn write_zip(path: &Path) {
+        use zip::write::SimpleFileOptions;
+        let options =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+        zip.add_directory("deep/", options).unwrap();
+        for (name, size) in [("a.txt", 10), (LONG, 3000), ("inner.zip", 22)] {
+            zip.start_file(name, options).unwrap();
+            std::io::Write::write_all(&mut zip, &vec![b'x'; size]).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    #[cfg(feature = "archives")]
+    #[test]
+    fn tar_tar_gz_and_zip_members_are_counted() {
+        let dir = tempfile::tempdir().unwrap();
+        let tar = dir.path().join("a.tar");
+        write_tar(File::create(&tar).unwrap());
+        let tgz = dir.path().join("a.tgz");
+        write_tar(flate2::write::GzEncoder::new(
+            File::create(&tgz).unwrap(),
+            flate2::Compression::default(),
+        ));
+        let zip = dir.path().join("a.zip");
+        write_zip(&zip);
+
+        let warnings = Warnings::new(None);
+        let mut expander = Expander::new(ArchiveMode::Members, &any_size());
+        // Directories are left out, and the archive inside isn't opened
+        let expected = [
+            ("a.txt".to_string(), 10),
+            (LONG.to_string(), 3000),
+            ("inner.zip".to_string(), 22),
+        ];
+        for archive in [&tar, &tgz, &zip] {
+            assert_eq!(expand(&mut expander, archive, &warnings), expected);
+        }
+        assert_eq!(expander.counts.expanded, 3);
+        assert_eq!(expander.counts.members, 9);
+        assert_eq!(expander.counts.failed, 0);
+        assert!(warnings.tallies().is_empty());
+    }
+
+    #[cfg(feature = "archives")]
+    #[test]
+    fn both_counts_the_archive_too() {
+        let dir = tempfile::tempdir().unwrap();
+        let zip = dir.path().join("a.zip");
+        write_zip(&zip);
+        let size = std::fs::metadata(&zip).unwrap().len();
+        let mut expander = Expander::new(ArchiveMode::Both, &any_size());
+        let members = expand(&mut expander, &zip, &Warnings::new(None));
+        assert_eq!(members.len(), 4);
+        assert!(members.contains(&(String::new(), size)));
+        assert_eq!(expander.counts.members, 3);
+    }
+
+    #[cfg(feature = "archives")]
+    #[test]
+    fn unreadable_archives_are_kept_whole_with_a_warning() {
+        let dir = tempfile::tempdir().unwrap();
+        let corrupt = dir.path().join("corrupt.tar.gz");
+        std::fs::write(&corrupt, b"not gzip at all").unwrap();
+        let truncated = dir.path().join("truncated.zip");
+        std::fs::write(&truncated, b"PK\x03\x04 and no more").unwrap();
+        // Sets the encryption flag of the member in the central directory
+        let locked = dir.path().join("locked.zip");
+        write_zip(&locked);
+        let mut bytes = std::fs::read(&locked).unwrap();
+        let central = bytes
+            .windows(4)
+            .position(|window| window == b"PK\x01\x02")
+            .unwrap();
+        bytes[central + 8] |= 1;
+        std::fs::write(&locked, bytes).unwrap();
+
+        let warnings = Warnings::new(None);
+        let mut expander = Expander::new(ArchiveMode::Members, &any_size());
+        for archive in [&corrupt, &truncated, &locked] {
+            let size = std::fs::metadata(archive).unwrap().len();
+            assert_eq!(
+                expand(&mut expander, archive, &warnings),
+                [(String::new(), size)]
+            );
+        }
+        assert_eq!(expander.counts.expanded, 0);
+        assert_eq!(expander.counts.failed, 3);
+        let tallies = warnings.tallies();
+        assert_eq!(tallies.len(), 1);
+        assert_eq!(tallies[0].category, Category::Archive);
+        let messages = tallies[0]
+            .examples
+            .iter()
+            .map(|example| example.message.as_str())
+            .collect::<Vec<_>>();
+        assert!(messages[0].starts_with("not a valid tar archive"));
+        assert_eq!(messages[1], "password-protected");
+        assert!(messages[2].starts_with("not a valid zip archive"));
+    }
+
+    #[test]
+    fn other_compressed_tarballs_are_counted_whole() {
+        let dir = tempfile::tempdir().unwrap();
+        let warnings = Warnings::new(None);
+        let mut expander = Expander::new(ArchiveMode::Members, &any_size());
+        for name in ["a.tar.xz", "a.tar.bz2", "a.tar.zst", "notes.txt"] {
+            let path = dir.path().join(name);
+            std::fs::write(&path, b"12345").unwrap();
+            assert_eq!(
+                expand(&mut expander, &path, &warnings),
+                [(String::new(), 5)]
+            );
+        }
+        assert_eq!(expander.counts.compressed, 3);
+        assert_eq!(expander.counts.failed, 0);
+        assert!(warnings.tallies().is_empty());
+    }
+}
@@ -0,0 +1,255 @@
+//! Size budgets per group, read from a TOML file like
+//!
+//! ```toml
+//! "assets/" = "2GiB"
+//! "docs/" = "200MiB"
+//! ".png" = 524288000
+//! ```
+//!
+//! Keys are group names as `--group-by` shows them, where directories may
+//! leave out the trailing `/` and extensions the leading `.`.
+
+use std::fs;
+use std::path::Path;
+
+use toml_edit::{DocumentMut, Item, Value};
+
+use crate::format;
+use crate::groups::{Group, GroupBy};
+use crate::table::{Align, Table};
+
+/// The budgets of a file, in the order it lists them.
+#[derive(Debug, Clone)]
+pub struct Budgets {
+    limits: Vec<(String, u64)>,
+}
+
+impl Budgets {
+    /// Reads the budgets for groups made by `group_by` from `path`.
+    pub fn load(path: &Path, group_by: GroupBy) -> Result<Budgets, String> {
+        let source = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        Budgets::parse(&source, group_by)
+            .map_err(|e| format!("invalid budgets in {}: {}", path.display(), e))
+    }
+
+    pub fn parse(source: &str, group_by: GroupBy) -> Result<Budgets, String> {
+        let document = source
+            .parse::<DocumentMut>()
+            .map_err(|e| e.to_string().trim_end().to_string())?;
+        let mut limits = Vec::new();
+        for (key, item) in document.iter() {
+            let invalid = |reason: &str| format!("budget for '{}' {}", key, reason);
+            let limit = match item {
+                Item::Value(Value::String(size)) => format::parse_size(size.value())
+                    .map_err(|e| invalid(&format!("is an {}", e)))?,
+                Item::Value(Value::Integer(bytes)) => {
+                    u64::try_from(*bytes.value()).map_err(|_| invalid("can't be negative"))?
+                }
+                _ => return Err(invalid("must be a size like \"2GiB\" or a number of bytes")),
+            };
+            let name = group_name(key, group_by);
+            if limits.iter().any(|(other, _)| *other == name) {
+                return Err(format!("'{}' has two budgets", name));
+            }
+            limits.push((name, limit));
+        }
+        Ok(Budgets { limits })
+    }
+
+    fn limit(&self, name: &str) -> Option<u64> {
+        self.limits
+            .iter()
+            .find(|(group, _)| group == name)
+            .map(|&(_, limit)| limit)
+    }
+
+    /// Renders a table of each of `groups` against its budget, followed by
+    /// the budgets of groups that weren't found, and gives the groups over
+    /// budget.
+    pub fn check(&self, groups: &[Group]) -> (String, Vec<Overrun>) {
+        let mut table = Table::new(&[
+            Align::Left,
+            Align::Right,
+            Align::Right,
+            Align::Right,
+            Align::Left,
+        ]);
+        table.row(["Group", "Used", "Budget", "Share", "Status"]);
+        let mut overruns = Vec::new();
+        let used = groups
+            .iter()
+            .map(|group| (group.name.as_str(), group.total))
+            .chain(
+                self.limits
+                    .iter()
+                    .filter(|(name, _)| !groups.iter().any(|group| group.name == *name))
+                    .map(|(name, _)| (name.as_str(), 0)),
+            );
+        for (name, used) in used {
+            let Some(limit) = self.limit(name) else {
+                // Reported, but with nothing to fail
+                table.row([name.to_string(), format::human(used), "-".to_string()]);
+                continue;
+            };
+            let status = if used > limit {
+                overruns.push(Overrun {
+                    group: name.to_string(),
+                    used,
+                    limit,
+                });
+                "OVER"
+            } else {
+                "ok"
+            };
+            table.row([
+                name.to_string(),
+                format::human(used),
+                format::human(limit),
+                share(used, limit),
+                status.to_string(),
+            ]);
+        }
+        (table.render(), overruns)
+    }
+}
+
+/// A group over its budget.
+#[derive(Debug, Clone)]
+pub struct Overrun {
+    pub group: String,
+    pub used: u64,
+    pub limit: u64,
+}
+
+impl std::fmt::Display for Overrun {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} uses {} of its {} budget ({})",
+            self.group,
+            format::human(self.used),
+            format::human(self.limit),
+            share(self.used, self.limit)
+        )
+    }
+}
+
+fn share(used: u64, limit: u64) -> String {
+    match limit {
+        0 => "-".to_string(),
+        _ => format!("{:.1}%", used as f64 / limit as f64 * 100.0),
+    }
+}
+
+/// The group a budget key means, completing the `/` of directories and
+/// the `.` of extensions.
+fn group_name(key: &str, group_by: GroupBy) -> String {
+    match group_by {
+        GroupBy::Directory => {
+            let dir = key.trim_start_matches("./").trim_end_matches('/');
+            match dir {
+                "" | "." => "./".to_string(),
+                _ => format!("{}/", dir),
+            }
+        }
+        GroupBy::Extension if key == "(none)" => key.to_string(),
+        GroupBy::Extension => format!(".{}", key.trim_start_matches('.').to_lowercase()),
+        GroupBy::Magic | GroupBy::Owner => key.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::groups::{Grouper, SortKey, SortOrder};
+
+    fn groups(files: &[(&str, u64)]) -> Vec<Group> {
+        let mut grouper = Grouper::new(false);
+        for &(key, size) in files {
+            grouper.add(key.to_string(), size);
+        }
+        grouper.finish(SortOrder {
+            key: SortKey::Total,
+            reverse: false,
+        })
+    }
+
+    #[test]
+    fn sizes_and_byte_counts_are_read_and_keys_completed() {
+        let budgets = Budgets::parse(
+            "\"assets\" = \"2GiB\"\n\"./docs/\" = 1000\n\".\" = \"1 MB\"\n",
+            GroupBy::Directory,
+        )
+        .unwrap();
+        assert_eq!(budgets.limit("assets/"), Some(2 << 30));
+        assert_eq!(budgets.limit("docs/"), Some(1000));
+        assert_eq!(budgets.limit("./"), Some(1_000_000));
+        assert_eq!(budgets.limit("assets"), None);
+
+        let budgets =
+            Budgets::parse("PNG = \"5MiB\"\n\"(none)\" = 0\n", GroupBy::Extension).unwrap();
+        assert_eq!(budgets.limit(".png"), Some(5 << 20));
+        assert_eq!(budgets.limit("(none)"), Some(0));
+    }
+
+    #[test]
+    fn errors_name_the_key() {
+        for (source, error) in [
+            ("docs = \"lots\"\n", "budget for 'docs' is an "),
+            ("docs = -1\n", "budget for 'docs' can't be negative"),
+            ("docs = 1.5\n", "budget for 'docs' must be a size like"),
+            ("[docs]\nmax = 1\n", "budget for 'docs' must be a size like"),
+            ("docs = 1\n\"docs/\" = 2\n", "'docs/' has two budgets"),
+        ] {
+            let e = Budgets::parse(source, GroupBy::Directory).unwrap_err();
+            assert!(e.starts_with(error), "{}: {}", source, e);
+        }
+        assert!(Budgets::parse("docs = ", GroupBy::Directory).is_err());
+    }
+
+    #[test]
+    fn groups_over_budget_fail_and_groups_without_one_never_do() {
+        let budgets = Budgets::parse(
+            "assets = \"2KiB\"\ndocs = \"10KiB\"\nvendor = 1\n",
+            GroupBy::Directory,
+        )
+        .unwrap();
+        let (table, overruns) = budgets.check(&groups(&[
+            ("assets/", 2048),
+            ("assets/", 1024),
+            ("docs/", 2560),
+            ("src/", 1 << 30),
+        ]));
+        assert_eq!(
+            table,
+            "Group       Used  Budget   Share  Status\n\
+             src/       1 GiB       -\n\
+             assets/    3 KiB   2 KiB  150.0%  OVER\n\
+             docs/    2.5 KiB  10 KiB   25.0%  ok\n\
+             vendor/      0 B     1 B    0.0%  ok\n"
+        );
+        assert_eq!(overruns.len(), 1);
+        assert_eq!(
+            overruns[0].to_string(),
+            "assets/ uses 3 KiB of its 2 KiB budget (150.0%)"
+        );
+    }
+
+    #[test]
+    fn a_zero_budget_fails_anything_in_the_group() {
+        let budgets = Budgets::parse("tmp = 0\n", GroupBy::Directory).unwrap();
+        let (table, overruns) = budgets.check(&groups(&[("tmp/", 0)]));
+        assert!(
+            table.ends_with("tmp/    0 B     0 B      -  ok\n"),
+            "{}",
+            table
+        );
+        assert!(overruns.is_empty());
+        let (_, overruns) = budgets.check(&groups(&[("tmp/", 1)]));
+        assert_eq!(
+            overruns[0].to_string(),
+            "tmp/ uses 1 B of its 0 B budget (-)"
+        );
+    }
+}
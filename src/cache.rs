@@ -0,0 +1,396 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+
+use crate::format::{path_from_json, path_to_json};
+use crate::ignore;
+use crate::interrupt;
+use crate::resources;
+use crate::scan::{self, DirRecord, FileRecord, ScanCounts, ScanOptions};
+
+/// Bumped whenever the layout of cache files changes
+const VERSION: u64 = 7;
+
+/// How many directories besides the root are checked before a cached scan
+/// is reused
+const VALIDATION_SAMPLE: usize = 64;
+
+/// The directory holding dudist's caches, following the XDG base directory
+/// spec where it applies.
+pub fn cache_dir() -> Option<PathBuf> {
+    let from_env = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty());
+    let base = if cfg!(windows) {
+        from_env("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        from_env("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| from_env("HOME").map(|home| PathBuf::from(home).join(".cache")))
+    };
+    base.map(|base| base.join("dudist"))
+}
+
+/// 64-bit FNV-1a, a hash whose value doesn't change between builds.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// A modification time as `[seconds, nanoseconds]` since the epoch.
+fn time_to_json(time: Option<SystemTime>) -> Value {
+    match time.and_then(|time| time.duration_since(UNIX_EPOCH).ok()) {
+        Some(since) => json!([since.as_secs(), since.subsec_nanos()]),
+        None => Value::Null,
+    }
+}
+
+fn time_from_json(value: &Value) -> Option<SystemTime> {
+    let secs = value.get(0)?.as_u64()?;
+    let nanos = value.get(1)?.as_u64()? as u32;
+    Some(UNIX_EPOCH + Duration::new(secs, nanos))
+}
+
+/// Compares modification times at the precision they are stored with.
+fn same_time(a: Option<SystemTime>, b: Option<SystemTime>) -> bool {
+    time_to_json(a) == time_to_json(b)
+}
+
+fn counts_to_json(counts: &ScanCounts) -> Value {
+    json!([
+        counts.entries,
+        counts.files,
+        counts.matched,
+        counts.cache_dirs,
+        counts.ignored,
+        counts.ignore_files
+    ])
+}
+
+fn counts_from_json(value: &Value) -> Option<ScanCounts> {
+    Some(ScanCounts {
+        entries: value.get(0)?.as_u64()?,
+        files: value.get(1)?.as_u64()?,
+        matched: value.get(2)?.as_u64()?,
+        cache_dirs: value.get(3)?.as_u64()?,
+        ignored: value.get(4)?.as_u64()?,
+        ignore_files: value.get(5)?.as_u64()?,
+        // Empty directories are only looked for by walks
+        ..ScanCounts::default()
+    })
+}
+
+/// A scan read back from the cache.
+#[derive(Debug)]
+pub struct Cached {
+    pub records: Vec<FileRecord>,
+    pub directories: Vec<DirRecord>,
+    pub counts: ScanCounts,
+    /// When the scan was made
+    pub created: SystemTime,
+}
+
+impl Cached {
+    pub fn age(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(self.created)
+            .unwrap_or_default()
+    }
+}
+
+/// The cache file for scanning one root with one set of options.
+#[derive(Debug)]
+pub struct Cache {
+    file: PathBuf,
+    /// The root as given, which paths read back are joined onto
+    root: PathBuf,
+    key: String,
+}
+
+impl Cache {
+    pub fn new(root: &Path, options: &ScanOptions) -> Result<Cache, String> {
+        let dir = cache_dir().ok_or("can't tell where to keep the cache, set XDG_CACHE_HOME")?;
+        let canonical = root
+            .canonicalize()
+            .map_err(|e| format!("failed to resolve {}: {}", root.display(), e))?;
+        let mut key = format!("{}\n{}", canonical.display(), options.fingerprint());
+        // Edits to the root's ignore file needn't touch the root's mtime
+        if options.ignore_files() {
+            if let Ok(rules) = fs::read(root.join(ignore::FILE_NAME)) {
+                key += &format!("\nignore={:016x}", fnv1a(&rules));
+            }
+        }
+        Ok(Cache {
+            file: dir.join(format!("{:016x}.json", fnv1a(key.as_bytes()))),
+            root: root.to_path_buf(),
+            key,
+        })
+    }
+
+    /// The file the scan is cached in.
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    /// Reads the cached scan, if there is one for this key. Unless `trust`
+    /// is set, it is only returned when the root and a sample of the other
+    /// directories haven't been modified since.
+    pub fn load(&self, trust: bool) -> Result<Option<Cached>, String> {
+        let contents = match fs::read_to_string(&self.file) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("failed to read {}: {}", self.file.display(), e)),
+        };
+        let invalid = || format!("{} is not a valid cache file", self.file.display());
+        let value = serde_json::from_str::<Value>(&contents).map_err(|_| invalid())?;
+        if value["version"].as_u64() != Some(VERSION) || value["key"].as_str() != Some(&self.key) {
+            return Ok(None);
+        }
+        let cached = self.parse(&value).ok_or_else(invalid)?;
+        if !trust && !self.is_fresh(&cached.directories) {
+            return Ok(None);
+        }
+        Ok(Some(cached))
+    }
+
+    fn parse(&self, value: &Value) -> Option<Cached> {
+        let path = |value: &Value| path_from_json(value).map(|path| self.root.join(path));
+        let directories = value["directories"]
+            .as_array()?
+            .iter()
+            .map(|dir| {
+                Some(DirRecord {
+                    path: path(&dir[0])?,
+                    modified: time_from_json(&dir[1]),
+                    children: counts_from_json(&dir[2])?,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let records = value["files"]
+            .as_array()?
+            .iter()
+            .map(|file| {
+                Some(FileRecord {
+                    path: path(&file[0])?,
+                    size: file[1].as_u64()?,
+                    modified: time_from_json(&file[2]),
+                    device: file[3].as_u64()?,
+                    accessed: time_from_json(&file[4]),
+                    owner: file[5].as_u64().and_then(|uid| u32::try_from(uid).ok()),
+                    allocated: file[6].as_u64(),
+                    inode: file[7].as_u64(),
+                    links: file[8].as_u64()?,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(Cached {
+            records,
+            directories,
+            counts: counts_from_json(&value["counts"])?,
+            created: time_from_json(&value["created"])?,
+        })
+    }
+
+    /// Checks the root, which comes first, and an even spread of the other
+    /// directories against their recorded modification times.
+    fn is_fresh(&self, directories: &[DirRecord]) -> bool {
+        let modified = |path: &Path| {
+            resources::stat();
+            fs::metadata(path).ok().and_then(|m| m.modified().ok())
+        };
+        let step = (directories.len() / VALIDATION_SAMPLE).max(1);
+        directories
+            .iter()
+            .take(1)
+            .chain(directories.iter().skip(1).step_by(step))
+            .all(|dir| same_time(modified(&dir.path), dir.modified))
+    }
+
+    /// Stores a complete scan, paths being kept relative to the root.
+    pub fn save(
+        &self,
+        records: &[FileRecord],
+        directories: &[DirRecord],
+        counts: &ScanCounts,
+    ) -> Result<(), String> {
+        let relative = |path: &Path| path_to_json(path.strip_prefix(&self.root).unwrap_or(path));
+        let value = json!({
+            "version": VERSION,
+            "key": self.key,
+            "created": time_to_json(Some(SystemTime::now())),
+            "counts": counts_to_json(counts),
+            "directories": directories
+                .iter()
+                .map(|dir| {
+                    json!([
+                        relative(&dir.path),
+                        time_to_json(dir.modified),
+                        counts_to_json(&dir.children)
+                    ])
+                })
+                .collect::<Vec<_>>(),
+            "files": records
+                .iter()
+                .map(|file| {
+                    json!([
+                        relative(&file.path),
+                        file.size,
+                        time_to_json(file.modified),
+                        file.device,
+                        time_to_json(file.accessed),
+                        file.owner,
+                        file.allocated,
+                        file.inode,
+                        file.links
+                    ])
+                })
+                .collect::<Vec<_>>(),
+        });
+        let write_error =
+            |e: std::io::Error| format!("failed to write {}: {}", self.file.display(), e);
+        if let Some(dir) = self.file.parent() {
+            fs::create_dir_all(dir).map_err(write_error)?;
+        }
+        // Written next to the cache and moved over it, so a concurrent run
+        // never reads half a file
+        let partial = self.file.with_extension("partial");
+        fs::write(&partial, value.to_string()).map_err(write_error)?;
+        fs::rename(&partial, &self.file).map_err(write_error)
+    }
+}
+
+/// The result of [`incremental_scan`].
+#[derive(Debug)]
+pub struct Incremental {
+    pub records: Vec<FileRecord>,
+    pub directories: Vec<DirRecord>,
+    pub counts: ScanCounts,
+    /// Directories listed again because they were modified
+    pub rewalked: u64,
+    /// Directories whose listing came from the cache
+    pub reused: u64,
+}
+
+/// Scans `root` again, only listing the directories whose mtime changed
+/// since `cached` was made. The other directories contribute their cached
+/// files and subdirectories.
+///
+/// A directory's mtime doesn't change when a file in it is rewritten in
+/// place, so cached files are statted again for their current size, unless
+/// `fast` is set. Files that were too small to be cached are never looked
+/// at again, and stay missing if they grew past the minimum size.
+pub fn incremental_scan(
+    root: &Path,
+    options: &ScanOptions,
+    cached: Cached,
+    fast: bool,
+) -> Incremental {
+    let mut cached_dirs = HashMap::new();
+    let mut subdirs = HashMap::<PathBuf, Vec<PathBuf>>::new();
+    for dir in cached.directories {
+        if let Some(parent) = dir.path.parent() {
+            subdirs
+                .entry(parent.to_path_buf())
+                .or_default()
+                .push(dir.path.clone());
+        }
+        cached_dirs.insert(dir.path.clone(), dir);
+    }
+    let mut cached_files = HashMap::<PathBuf, Vec<FileRecord>>::new();
+    for file in cached.records {
+        if let Some(parent) = file.path.parent() {
+            cached_files
+                .entry(parent.to_path_buf())
+                .or_default()
+                .push(file);
+        }
+    }
+
+    let recovered = Cell::new(0);
+    let stat = |path: &Path| {
+        let attempts = options.stat(path);
+        if attempts.recovered() {
+            recovered.set(recovered.get() + 1);
+        }
+        attempts.result
+    };
+    let root_device = stat(root).map_or(0, |metadata| scan::device(&metadata));
+    // Whether the subdirectories of a directory at `depth` are walked
+    let descends = |depth: usize| {
+        options.max_depth().is_none_or(|max| depth + 1 < max) && depth + 1 < options.depth_ceiling()
+    };
+
+    let mut scan = Incremental {
+        records: Vec::new(),
+        directories: Vec::new(),
+        counts: ScanCounts {
+            // The root itself
+            entries: 1,
+            ..ScanCounts::default()
+        },
+        rewalked: 0,
+        reused: 0,
+    };
+    // With a depth of 0 only the root itself is part of the scan
+    let mut pending = match options.max_depth() {
+        Some(0) => Vec::new(),
+        _ => vec![(root.to_path_buf(), 0)],
+    };
+    while let Some((dir, depth)) = pending.pop() {
+        if interrupt::interrupted() {
+            break;
+        }
+        if depth > 0 && options.one_file_system() {
+            let device = stat(&dir).ok().map(|metadata| scan::device(&metadata));
+            if !device.is_some_and(|device| options.enters_device(device, root_device)) {
+                continue;
+            }
+        }
+        let modified = stat(&dir)
+            .ok()
+            .and_then(|metadata| metadata.modified().ok());
+        let subdirs_of_dir = match cached_dirs.remove(&dir) {
+            Some(mut record) if modified.is_some() && same_time(record.modified, modified) => {
+                scan.reused += 1;
+                let files = cached_files.remove(&dir).unwrap_or_default();
+                if fast {
+                    scan.records.extend(files);
+                } else {
+                    let before = scan.records.len();
+                    scan.records.extend(files.into_iter().filter_map(|file| {
+                        let metadata = stat(&file.path).ok()?;
+                        (metadata.is_file() && options.matches(metadata.len()))
+                            .then(|| FileRecord::from_metadata(file.path, &metadata))
+                    }));
+                    record.children.matched = (scan.records.len() - before) as u64;
+                }
+                scan.counts.add(&record.children);
+                scan.directories.push(record);
+                subdirs.remove(&dir).unwrap_or_default()
+            }
+            _ => {
+                scan.rewalked += 1;
+                let mut level = scan::scan_level(&dir, options)
+                    .record_directories()
+                    .below_root(root);
+                scan.records.extend(level.by_ref().flatten());
+                scan.counts.recovered += level.counts.recovered;
+                let mut found = level.directories().iter();
+                // The directory itself comes first, then its subdirectories
+                if let Some(record) = found.next() {
+                    scan.counts.add(&record.children);
+                    scan.directories.push(record.clone());
+                }
+                found.map(|subdir| subdir.path.clone()).collect()
+            }
+        };
+        if descends(depth) {
+            pending.extend(subdirs_of_dir.into_iter().map(|subdir| (subdir, depth + 1)));
+        }
+    }
+    scan.counts.recovered += recovered.get();
+    scan
+}
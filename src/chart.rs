@@ -0,0 +1,523 @@
+use std::fmt::Write as _;
+use std::path::Path;
+use std::str::FromStr;
+
+#[cfg(feature = "png")]
+use plotters::prelude::*;
+#[cfg(feature = "png")]
+use plotters::style::text_anchor::{HPos, Pos, VPos};
+
+use crate::distribution::{self, Distribution, Ranked};
+use crate::format;
+
+/// Width and height of a chart in pixels.
+#[derive(Debug, Clone, Copy)]
+pub struct ChartSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for ChartSize {
+    fn default() -> Self {
+        ChartSize {
+            width: 1200,
+            height: 400,
+        }
+    }
+}
+
+impl FromStr for ChartSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid chart size '{}' (expected e.g. 1200x400)", s);
+        let (width, height) = s.split_once('x').ok_or_else(invalid)?;
+        let (width, height) = (
+            width.parse().map_err(|_| invalid())?,
+            height.parse().map_err(|_| invalid())?,
+        );
+        // Anything smaller leaves no room next to the margins
+        if width < 200 || height < 150 {
+            return Err(format!(
+                "chart size '{}' is too small (at least 200x150)",
+                s
+            ));
+        }
+        Ok(ChartSize { width, height })
+    }
+}
+
+/// The fill of the boxes and bars, SVG's steelblue
+#[cfg(feature = "png")]
+const STEEL_BLUE: RGBColor = RGBColor(70, 130, 180);
+
+/// Space around the plots, for the axis labels
+const MARGIN_LEFT: f64 = 60.0;
+const MARGIN_RIGHT: f64 = 30.0;
+const MARGIN_TOP: f64 = 30.0;
+const MARGIN_BOTTOM: f64 = 40.0;
+
+/// Renders a box plot and a histogram of `sizes` to `path`, in the format
+/// its extension asks for.
+pub fn write(
+    path: &Path,
+    size: ChartSize,
+    dist: &Distribution,
+    sizes: &(impl Ranked + ?Sized),
+) -> Result<(), String> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+    let contents = match extension.as_deref() {
+        Some("svg") => {
+            let mut svg = Svg::new(size);
+            draw(&mut svg, size, dist, sizes);
+            svg.finish()
+        }
+        #[cfg(feature = "png")]
+        Some("png") => {
+            // Created up front, as plotters only debug-prints its I/O errors
+            std::fs::File::create(path)
+                .map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+            let mut png = Png::new(path, size);
+            draw(&mut png, size, dist, sizes);
+            return png
+                .finish()
+                .map_err(|e| format!("failed to write {}: {}", path.display(), e));
+        }
+        #[cfg(not(feature = "png"))]
+        Some("png") => {
+            return Err("PNG charts aren't supported by this build, use .svg".to_string())
+        }
+        _ => {
+            return Err(format!(
+                "can't tell the chart format of {} (expected {})",
+                path.display(),
+                FORMATS
+            ))
+        }
+    };
+    std::fs::write(path, contents).map_err(|e| format!("failed to write {}: {}", path.display(), e))
+}
+
+/// The extensions charts can be written with
+#[cfg(feature = "png")]
+const FORMATS: &str = ".svg or .png";
+#[cfg(not(feature = "png"))]
+const FORMATS: &str = ".svg";
+
+/// Where a text is anchored horizontally, its baseline being at the point.
+#[derive(Debug, Clone, Copy)]
+enum Anchor {
+    Middle,
+    End,
+}
+
+/// How a rectangle is filled and outlined.
+#[derive(Debug, Clone, Copy)]
+enum Fill {
+    /// The whole image
+    Background,
+    /// The box of the box plot, see-through over the whiskers
+    Box,
+    /// A histogram bar
+    Bar,
+}
+
+/// What a chart is drawn on, so every format draws the same shapes at the
+/// same coordinates, in pixels from the top left.
+trait Canvas {
+    /// A black line, with a tooltip where the format has them.
+    fn line(&mut self, from: (f64, f64), to: (f64, f64), width: f64, title: Option<&str>);
+
+    fn rect(&mut self, corner: (f64, f64), size: (f64, f64), fill: Fill, title: Option<&str>);
+
+    /// A black ring.
+    fn circle(&mut self, center: (f64, f64), radius: f64);
+
+    fn text(&mut self, at: (f64, f64), anchor: Anchor, text: &str);
+}
+
+/// Draws the box plot and the histogram of `sizes` on `canvas`.
+fn draw(
+    canvas: &mut impl Canvas,
+    size: ChartSize,
+    dist: &Distribution,
+    sizes: &(impl Ranked + ?Sized),
+) {
+    let (width, height) = (size.width as f64, size.height as f64);
+    let bins = distribution::log2_histogram(sizes)
+        .into_iter()
+        .filter(|bin| bin.lower > 0)
+        .collect::<Vec<_>>();
+
+    // Both plots share a log2 size axis spanning the histogram bins
+    let lo = bins.first().map_or(0, |bin| bin.lower.ilog2());
+    let hi = bins
+        .last()
+        .map_or(1, |bin| bin.upper.max(2).ilog2())
+        .max(lo + 1);
+    let plot_width = width - MARGIN_LEFT - MARGIN_RIGHT;
+    let x = |size: f64| {
+        let log = size.max(1.0).log2().clamp(lo as f64, hi as f64);
+        MARGIN_LEFT + (log - lo as f64) / (hi - lo) as f64 * plot_width
+    };
+
+    let plot_height = height - MARGIN_TOP - MARGIN_BOTTOM;
+    let box_top = MARGIN_TOP;
+    let box_height = plot_height * 0.3;
+    let hist_top = box_top + box_height + 10.0;
+    let hist_height = plot_height - box_height - 10.0;
+    let bottom = hist_top + hist_height;
+
+    canvas.rect((0.0, 0.0), (width, height), Fill::Background, None);
+
+    // Box plot, whiskers reaching the furthest files within 1.5 IQR of the
+    // box and the files beyond drawn as outliers
+    let iqr = dist.upper_quartile - dist.lower_quartile;
+    let (low_fence, high_fence) = (
+        dist.lower_quartile - 1.5 * iqr,
+        dist.upper_quartile + 1.5 * iqr,
+    );
+    let whisker_low = sizes
+        .runs()
+        .map(|(size, _)| size)
+        .find(|&size| size as f64 >= low_fence)
+        .unwrap_or(dist.min);
+    let whisker_high = sizes
+        .runs()
+        .map(|(size, _)| size)
+        .filter(|&size| size as f64 <= high_fence)
+        .last()
+        .unwrap_or(dist.max);
+    let middle = box_top + box_height / 2.0;
+    let (box_y, box_h) = (box_top + box_height * 0.2, box_height * 0.6);
+    canvas.line(
+        (x(whisker_low as f64), middle),
+        (x(dist.lower_quartile), middle),
+        1.0,
+        None,
+    );
+    canvas.line(
+        (x(dist.upper_quartile), middle),
+        (x(whisker_high as f64), middle),
+        1.0,
+        None,
+    );
+    for whisker in [whisker_low, whisker_high] {
+        let position = x(whisker as f64);
+        canvas.line(
+            (position, box_y + box_h * 0.25),
+            (position, box_y + box_h * 0.75),
+            1.0,
+            None,
+        );
+    }
+    canvas.rect(
+        (x(dist.lower_quartile), box_y),
+        (x(dist.upper_quartile) - x(dist.lower_quartile), box_h),
+        Fill::Box,
+        None,
+    );
+    canvas.line(
+        (x(dist.median), box_y),
+        (x(dist.median), box_y + box_h),
+        2.0,
+        Some(&format!("Median: {}", format::human_f64(dist.median))),
+    );
+    // Many outliers land on the same pixel, one circle each is enough
+    let mut last_outlier = f64::NEG_INFINITY;
+    for size in sizes
+        .runs()
+        .map(|(size, _)| size)
+        .filter(|&size| (size as f64) < low_fence || size as f64 > high_fence)
+    {
+        let position = x(size as f64);
+        if position - last_outlier >= 1.0 {
+            canvas.circle((position, middle), 2.5);
+            last_outlier = position;
+        }
+    }
+
+    // Histogram, bars scaled to the fullest bin
+    let peak = bins.iter().map(|bin| bin.count).max().unwrap_or(1).max(1);
+    for bin in &bins {
+        let bar_height = bin.count as f64 / peak as f64 * hist_height;
+        canvas.rect(
+            (x(bin.lower as f64), bottom - bar_height),
+            (x(bin.upper as f64) - x(bin.lower as f64), bar_height),
+            Fill::Bar,
+            Some(&format!(
+                "{} to {}: {} files",
+                format::human(bin.lower),
+                format::human(bin.upper),
+                format::format_count(bin.count),
+            )),
+        );
+    }
+    canvas.text(
+        (MARGIN_LEFT - 6.0, hist_top + 12.0),
+        Anchor::End,
+        &format::format_count(peak),
+    );
+    canvas.text((MARGIN_LEFT - 6.0, bottom), Anchor::End, "0");
+
+    // Size axis, labeled at about every eighth of the width
+    canvas.line(
+        (MARGIN_LEFT, bottom),
+        (MARGIN_LEFT + plot_width, bottom),
+        1.0,
+        None,
+    );
+    let step = (hi - lo).div_ceil(8).max(1);
+    for power in (lo..=hi).step_by(step as usize) {
+        let position = x(2f64.powi(power as i32));
+        canvas.line((position, bottom), (position, bottom + 5.0), 1.0, None);
+        let label = 1u64
+            .checked_shl(power)
+            .map_or("16 EiB".to_string(), format::human);
+        canvas.text((position, bottom + 18.0), Anchor::Middle, &label);
+    }
+    canvas.text(
+        (MARGIN_LEFT + plot_width / 2.0, height - 6.0),
+        Anchor::Middle,
+        &format!(
+            "{} files, median {} (log2 scale)",
+            format::format_count(sizes.count() as u64),
+            format::human_f64(dist.median)
+        ),
+    );
+}
+
+/// Escapes text for use in SVG.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// An SVG document, written as the shapes are drawn.
+struct Svg(String);
+
+impl Svg {
+    fn new(size: ChartSize) -> Svg {
+        Svg(format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" font-family=\"sans-serif\" font-size=\"12\">\n",
+            w = size.width,
+            h = size.height
+        ))
+    }
+
+    fn finish(self) -> Vec<u8> {
+        (self.0 + "</svg>\n").into_bytes()
+    }
+}
+
+impl Canvas for Svg {
+    fn line(
+        &mut self,
+        (x1, y1): (f64, f64),
+        (x2, y2): (f64, f64),
+        width: f64,
+        title: Option<&str>,
+    ) {
+        let width = match width {
+            1.0 => String::new(),
+            width => format!(r#" stroke-width="{}""#, width),
+        };
+        let _ = match title {
+            Some(title) => writeln!(
+                self.0,
+                r#"<line x1="{x1:.1}" y1="{y1:.1}" x2="{x2:.1}" y2="{y2:.1}" stroke="black"{width}><title>{}</title></line>"#,
+                escape(title)
+            ),
+            None => writeln!(
+                self.0,
+                r#"<line x1="{x1:.1}" y1="{y1:.1}" x2="{x2:.1}" y2="{y2:.1}" stroke="black"{width}/>"#
+            ),
+        };
+    }
+
+    fn rect(&mut self, (x, y): (f64, f64), (w, h): (f64, f64), fill: Fill, title: Option<&str>) {
+        let style = match fill {
+            Fill::Background => {
+                let _ = writeln!(
+                    self.0,
+                    r#"<rect width="{}" height="{}" fill="white"/>"#,
+                    w, h
+                );
+                return;
+            }
+            Fill::Box => r#"fill="steelblue" fill-opacity="0.5" stroke="black""#,
+            Fill::Bar => r#"fill="steelblue" stroke="white""#,
+        };
+        let _ = match title {
+            Some(title) => writeln!(
+                self.0,
+                r#"<rect x="{x:.1}" y="{y:.1}" width="{w:.1}" height="{h:.1}" {style}><title>{}</title></rect>"#,
+                escape(title)
+            ),
+            None => writeln!(
+                self.0,
+                r#"<rect x="{x:.1}" y="{y:.1}" width="{w:.1}" height="{h:.1}" {style}/>"#
+            ),
+        };
+    }
+
+    fn circle(&mut self, (x, y): (f64, f64), radius: f64) {
+        let _ = writeln!(
+            self.0,
+            r#"<circle cx="{x:.1}" cy="{y:.1}" r="{radius}" fill="none" stroke="black"/>"#
+        );
+    }
+
+    fn text(&mut self, (x, y): (f64, f64), anchor: Anchor, text: &str) {
+        let anchor = match anchor {
+            Anchor::Middle => "middle",
+            Anchor::End => "end",
+        };
+        let _ = writeln!(
+            self.0,
+            r#"<text x="{x:.1}" y="{y:.1}" text-anchor="{anchor}">{}</text>"#,
+            escape(text)
+        );
+    }
+}
+
+/// A PNG drawn with the bitmap backend of plotters and written to its file
+/// when finished. The first error drawing it is kept for then.
+#[cfg(feature = "png")]
+struct Png<'a> {
+    backend: BitMapBackend<'a>,
+    error: Option<String>,
+}
+
+#[cfg(feature = "png")]
+impl<'a> Png<'a> {
+    fn new(path: &'a Path, size: ChartSize) -> Png<'a> {
+        Png {
+            backend: BitMapBackend::new(path, (size.width, size.height)),
+            error: None,
+        }
+    }
+
+    /// Keeps the first error of `drawn`.
+    fn check(&mut self, drawn: Result<(), impl std::fmt::Display>) {
+        if let Err(e) = drawn {
+            self.error.get_or_insert(e.to_string());
+        }
+    }
+
+    fn finish(mut self) -> Result<(), String> {
+        let presented = self.backend.present();
+        self.check(presented);
+        self.error.map_or(Ok(()), Err)
+    }
+}
+
+#[cfg(feature = "png")]
+fn pixel((x, y): (f64, f64)) -> (i32, i32) {
+    (x.round() as i32, y.round() as i32)
+}
+
+#[cfg(feature = "png")]
+impl Canvas for Png<'_> {
+    fn line(&mut self, from: (f64, f64), to: (f64, f64), width: f64, _: Option<&str>) {
+        let style = BLACK.stroke_width(width as u32);
+        let drawn = self.backend.draw_line(pixel(from), pixel(to), &style);
+        self.check(drawn);
+    }
+
+    fn rect(&mut self, (x, y): (f64, f64), (w, h): (f64, f64), fill: Fill, _: Option<&str>) {
+        let corners = (pixel((x, y)), pixel((x + w, y + h)));
+        let (fill, outline) = match fill {
+            Fill::Background => (WHITE.to_rgba(), None),
+            Fill::Box => (STEEL_BLUE.mix(0.5), Some(BLACK)),
+            Fill::Bar => (STEEL_BLUE.to_rgba(), Some(WHITE)),
+        };
+        let drawn = self.backend.draw_rect(corners.0, corners.1, &fill, true);
+        self.check(drawn);
+        if let Some(outline) = outline {
+            let drawn = self
+                .backend
+                .draw_rect(corners.0, corners.1, &outline, false);
+            self.check(drawn);
+        }
+    }
+
+    fn circle(&mut self, center: (f64, f64), radius: f64) {
+        let drawn = self
+            .backend
+            .draw_circle(pixel(center), radius.round() as u32, &BLACK, false);
+        self.check(drawn);
+    }
+
+    fn text(&mut self, at: (f64, f64), anchor: Anchor, text: &str) {
+        let anchor = match anchor {
+            Anchor::Middle => HPos::Center,
+            Anchor::End => HPos::Right,
+        };
+        let style =
+            TextStyle::from(("sans-serif", 12).into_font()).pos(Pos::new(anchor, VPos::Bottom));
+        let drawn = self.backend.draw_text(text, &style, pixel(at));
+        self.check(drawn);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sizes() -> Vec<u64> {
+        (1..=200).map(|i| i * i * 100).collect()
+    }
+
+    #[test]
+    fn svg_charts_show_the_report_numbers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("chart.svg");
+        let sizes = sizes();
+        let dist = Distribution::from_sorted(&sizes);
+        write(&path, ChartSize::default(), &dist, &sizes[..]).unwrap();
+        let svg = std::fs::read_to_string(&path).unwrap();
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.contains(&format!(
+            "<title>Median: {}</title>",
+            format::human_f64(dist.median)
+        )));
+        assert!(svg.contains("200 files"));
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn png_charts_are_the_size_asked_for() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("chart.PNG");
+        let sizes = sizes();
+        let size = ChartSize {
+            width: 640,
+            height: 240,
+        };
+        write(&path, size, &Distribution::from_sorted(&sizes), &sizes[..]).unwrap();
+        let png = std::fs::read(&path).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        // The width and height open the IHDR chunk
+        assert_eq!(
+            &png[16..24],
+            [640u32.to_be_bytes(), 240u32.to_be_bytes()].concat()
+        );
+    }
+
+    #[test]
+    fn unknown_formats_are_refused() {
+        let sizes = sizes();
+        let error = write(
+            Path::new("chart.gif"),
+            ChartSize::default(),
+            &Distribution::from_sorted(&sizes),
+            &sizes[..],
+        )
+        .unwrap_err();
+        assert!(error.starts_with("can't tell the chart format of chart.gif"));
+    }
+}
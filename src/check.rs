@@ -0,0 +1,381 @@
+//! Output of `--check`, following the Nagios plugin API so Icinga and the
+//! like can run dudist as a check.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::distribution::{Distribution, Ranked};
+use crate::format;
+
+/// The state a check reports, by its exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    Ok,
+    Warning,
+    Critical,
+    /// The check couldn't tell, e.g. when the scan failed
+    Unknown,
+}
+
+impl Status {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Status::Ok => 0,
+            Status::Warning => 1,
+            Status::Critical => 2,
+            Status::Unknown => 3,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Status::Ok => "OK",
+            Status::Warning => "WARNING",
+            Status::Critical => "CRITICAL",
+            Status::Unknown => "UNKNOWN",
+        }
+    }
+}
+
+/// The plugin output line for a check that couldn't run.
+pub fn unknown(message: &str) -> String {
+    format!("DUDIST {} - {}", Status::Unknown.label(), message)
+}
+
+/// Metrics thresholds can be set on, `count` being the only one that isn't
+/// in bytes.
+pub const METRICS: [&str; 10] = [
+    "count", "total", "min", "q1", "median", "q3", "p90", "p95", "p99", "max",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
+impl Op {
+    fn holds(self, value: f64, limit: f64) -> bool {
+        match self {
+            Op::Greater => value > limit,
+            Op::GreaterOrEqual => value >= limit,
+            Op::Less => value < limit,
+            Op::LessOrEqual => value <= limit,
+        }
+    }
+}
+
+/// A condition like `p99>2GiB`, which raises the check's status when it
+/// holds.
+#[derive(Debug, Clone)]
+pub struct Threshold {
+    source: String,
+    metric: &'static str,
+    op: Op,
+    limit: f64,
+}
+
+impl FromStr for Threshold {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: String| format!("invalid threshold '{}': {}", s, reason);
+        let split = s
+            .find(['<', '>'])
+            .ok_or_else(|| invalid("expected e.g. 'total>500GiB' or 'count<10'".to_string()))?;
+        let (name, rest) = s.split_at(split);
+        let (op, limit) = match rest.split_at(1) {
+            (">", limit) => match limit.strip_prefix('=') {
+                Some(limit) => (Op::GreaterOrEqual, limit),
+                None => (Op::Greater, limit),
+            },
+            (_, limit) => match limit.strip_prefix('=') {
+                Some(limit) => (Op::LessOrEqual, limit),
+                None => (Op::Less, limit),
+            },
+        };
+        let name = name.trim();
+        let metric = METRICS
+            .iter()
+            .find(|metric| **metric == name)
+            .ok_or_else(|| {
+                invalid(format!(
+                    "unknown metric '{}' (expected {})",
+                    name,
+                    METRICS.join(", ")
+                ))
+            })?;
+        let limit = limit.trim();
+        // Plain numbers, like 1e7, go for every metric
+        let limit = match limit.parse::<f64>() {
+            Ok(number) if number.is_finite() => number,
+            _ if *metric == "count" => {
+                return Err(invalid(format!("'{}' is not a number", limit)));
+            }
+            _ => format::parse_size(limit).map_err(invalid)? as f64,
+        };
+        Ok(Threshold {
+            source: s.to_string(),
+            metric,
+            op,
+            limit,
+        })
+    }
+}
+
+impl fmt::Display for Threshold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Threshold {
+    /// The limit as a Nagios range, where the plugin API can express it.
+    fn range(&self) -> Option<String> {
+        match self.op {
+            Op::Greater => Some(format!("{}", self.limit)),
+            Op::Less => Some(format!("{}:", self.limit)),
+            Op::GreaterOrEqual | Op::LessOrEqual => None,
+        }
+    }
+}
+
+/// The values of the [`METRICS`] for the files of `sizes`. Only `count`
+/// and `total` are known without any files.
+fn metrics(sizes: &(impl Ranked + ?Sized)) -> Vec<(&'static str, Option<f64>)> {
+    let matched = sizes.count() > 0;
+    let dist = matched.then(|| Distribution::from_ranked(sizes));
+    let percentile = |p| matched.then(|| sizes.percentile(p));
+    vec![
+        ("count", Some(sizes.count() as f64)),
+        ("total", Some(sizes.total() as f64)),
+        ("min", dist.as_ref().map(|dist| dist.min as f64)),
+        ("q1", dist.as_ref().map(|dist| dist.lower_quartile)),
+        ("median", dist.as_ref().map(|dist| dist.median)),
+        ("q3", dist.as_ref().map(|dist| dist.upper_quartile)),
+        ("p90", percentile(90.0)),
+        ("p95", percentile(95.0)),
+        ("p99", percentile(99.0)),
+        ("max", dist.as_ref().map(|dist| dist.max as f64)),
+    ]
+}
+
+/// The thresholds of `--check`.
+#[derive(Debug, Clone, Default)]
+pub struct Check {
+    pub warn: Vec<Threshold>,
+    pub crit: Vec<Threshold>,
+}
+
+impl Check {
+    /// Checks the files of `sizes`, giving the status
+    /// and the plugin output line, with perfdata after the `|`.
+    ///
+    /// ```
+    /// use dudist::check::{Check, Status};
+    ///
+    /// let check = Check {
+    ///     warn: vec!["total>1KiB".parse().unwrap()],
+    ///     crit: vec!["count>1e7".parse().unwrap()],
+    /// };
+    /// let (status, line) = check.run(&[512, 1024, 4096][..]);
+    /// assert_eq!(status, Status::Warning);
+    /// assert!(line.starts_with("DUDIST WARNING - total=5.5KiB count=3"));
+    /// ```
+    pub fn run(&self, sizes: &(impl Ranked + ?Sized)) -> (Status, String) {
+        let metrics = metrics(sizes);
+        let value = |metric: &str| {
+            metrics
+                .iter()
+                .find(|(name, _)| *name == metric)
+                .and_then(|(_, value)| *value)
+        };
+        let mut status = Status::Ok;
+        let mut reasons = Vec::new();
+        let levels = [
+            (Status::Critical, &self.crit),
+            (Status::Warning, &self.warn),
+        ];
+        for (level, thresholds) in levels {
+            for threshold in thresholds {
+                match value(threshold.metric) {
+                    None => {
+                        status = Status::Unknown;
+                        reasons.push(format!("no files to tell {} from", threshold.metric));
+                    }
+                    Some(value) if threshold.op.holds(value, threshold.limit) => {
+                        status = status.max(level);
+                        reasons.push(threshold.to_string());
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+
+        // The count and total, and whatever the thresholds look at
+        let shown = metrics.iter().filter(|(name, value)| {
+            value.is_some()
+                && (*name == "count"
+                    || *name == "total"
+                    || self
+                        .warn
+                        .iter()
+                        .chain(&self.crit)
+                        .any(|threshold| threshold.metric == *name))
+        });
+        let mut shown = shown
+            .map(|&(name, value)| {
+                let value = value.unwrap_or_default();
+                match name {
+                    "count" => format!("{}={}", name, value),
+                    _ => format!("{}={}", name, format::human_f64(value).replace(' ', "")),
+                }
+            })
+            .collect::<Vec<_>>();
+        // The total leads, as it's what a disk usage check is mostly about
+        shown.swap(0, 1);
+
+        let perfdata = metrics
+            .iter()
+            .filter_map(|&(name, value)| {
+                let range = |thresholds: &[Threshold]| {
+                    thresholds
+                        .iter()
+                        .filter(|threshold| threshold.metric == name)
+                        .find_map(Threshold::range)
+                        .unwrap_or_default()
+                };
+                let unit = if name == "count" { "" } else { "B" };
+                Some(format!(
+                    "{}={}{};{};{};0",
+                    name,
+                    value?.round(),
+                    unit,
+                    range(&self.warn),
+                    range(&self.crit)
+                ))
+            })
+            .collect::<Vec<_>>();
+
+        let mut line = format!("DUDIST {} - {}", status.label(), shown.join(" "));
+        if !reasons.is_empty() {
+            line += &format!(" ({})", reasons.join(", "));
+        }
+        line += &format!(" | {}", perfdata.join(" "));
+        (status, line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Files of 1, 2, 3, 4 and 100 KiB.
+    const SIZES: &[u64] = &[1 << 10, 2 << 10, 3 << 10, 4 << 10, 100 << 10];
+
+    fn check(warn: &[&str], crit: &[&str]) -> Check {
+        let parse = |list: &[&str]| list.iter().map(|s| s.parse().unwrap()).collect();
+        Check {
+            warn: parse(warn),
+            crit: parse(crit),
+        }
+    }
+
+    #[test]
+    fn no_threshold_holding_is_ok() {
+        let (status, line) = check(&["total>1MiB"], &["count>10"]).run(SIZES);
+        assert_eq!(status, Status::Ok);
+        assert_eq!(
+            line,
+            "DUDIST OK - total=110KiB count=5 | count=5;;10;0 total=112640B;1048576;;0 \
+             min=1024B;;;0 q1=2048B;;;0 median=3072B;;;0 q3=4096B;;;0 p90=102400B;;;0 \
+             p95=102400B;;;0 p99=102400B;;;0 max=102400B;;;0"
+        );
+    }
+
+    #[test]
+    fn a_warning_threshold_holding_warns() {
+        let (status, line) = check(&["p99>64KiB"], &["count>10"]).run(SIZES);
+        assert_eq!(status, Status::Warning);
+        assert!(
+            line.starts_with("DUDIST WARNING - total=110KiB count=5 p99=100KiB (p99>64KiB) | "),
+            "{}",
+            line
+        );
+        assert!(line.contains(" p99=102400B;65536;;0 "), "{}", line);
+    }
+
+    #[test]
+    fn a_critical_threshold_outranks_a_warning() {
+        let (status, line) = check(&["total>1KiB"], &["max>=100KiB"]).run(SIZES);
+        assert_eq!(status, Status::Critical);
+        // Critical reasons first. An inclusive limit has no Nagios range
+        assert!(
+            line.starts_with(
+                "DUDIST CRITICAL - total=110KiB count=5 max=100KiB (max>=100KiB, total>1KiB) | "
+            ),
+            "{}",
+            line
+        );
+        assert!(line.ends_with(" max=102400B;;;0"), "{}", line);
+    }
+
+    #[test]
+    fn lower_limits_become_ranges_with_a_colon() {
+        let (status, line) = check(&["count<10"], &["min<2KiB"]).run(SIZES);
+        assert_eq!(status, Status::Critical);
+        assert!(line.contains(" | count=5;10:;;0 "), "{}", line);
+        assert!(line.contains(" min=1024B;;2048:;0 "), "{}", line);
+    }
+
+    #[test]
+    fn limits_are_exclusive_unless_they_say_otherwise() {
+        assert_eq!(check(&["count>5"], &[]).run(SIZES).0, Status::Ok);
+        assert_eq!(check(&["count>=5"], &[]).run(SIZES).0, Status::Warning);
+        assert_eq!(check(&["count<5"], &[]).run(SIZES).0, Status::Ok);
+        assert_eq!(check(&["count<=5"], &[]).run(SIZES).0, Status::Warning);
+    }
+
+    #[test]
+    fn statistics_of_no_files_are_unknown() {
+        let none: &[u64] = &[];
+        let (status, line) = check(&["median>1KiB"], &["count>=0"]).run(none);
+        // Even over a critical threshold that holds
+        assert_eq!(status, Status::Unknown);
+        assert_eq!(
+            line,
+            "DUDIST UNKNOWN - total=0B count=0 (count>=0, no files to tell median from) \
+             | count=0;;;0 total=0B;;;0"
+        );
+        assert_eq!(status.exit_code(), 3);
+        assert_eq!(unknown("scan failed"), "DUDIST UNKNOWN - scan failed");
+    }
+
+    #[test]
+    fn errors_point_at_the_bad_expression() {
+        let error = |s: &str| s.parse::<Threshold>().unwrap_err();
+        assert_eq!(
+            error("total=5GiB"),
+            "invalid threshold 'total=5GiB': expected e.g. 'total>500GiB' or 'count<10'"
+        );
+        assert!(
+            error("size>5GiB").starts_with("invalid threshold 'size>5GiB': unknown metric 'size'")
+        );
+        assert_eq!(
+            error("count>5k"),
+            "invalid threshold 'count>5k': '5k' is not a number"
+        );
+        assert!(error("total>lots").starts_with("invalid threshold 'total>lots': "));
+    }
+
+    #[test]
+    fn limits_take_size_literals_and_plain_numbers() {
+        let limit = |s: &str| s.parse::<Threshold>().unwrap().limit;
+        assert_eq!(limit("total > 2GiB"), (2u64 << 30) as f64);
+        assert_eq!(limit("total>1.5KiB"), 1536.0);
+        assert_eq!(limit("count>1e7"), 1e7);
+        assert_eq!(limit("max>=4096"), 4096.0);
+    }
+}
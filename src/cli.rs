@@ -0,0 +1,1192 @@
+use std::ffi::OsString;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use dudist::anomalies::Thresholds;
+use dudist::archive::ArchiveMode;
+use dudist::chart::ChartSize;
+use dudist::check::Check;
+use dudist::cold::TimeField;
+use dudist::dirstats::Stat;
+use dudist::events::Target;
+use dudist::export::Export;
+use dudist::format;
+use dudist::groups::{FlagThresholds, GroupBy, GroupLimits, SortKey, SortOrder};
+use dudist::magic;
+use dudist::plot::{ClipSpec, Origin, PlotStyle, Scatter};
+use dudist::retry::Retry;
+use dudist::timeline::Period;
+
+const USAGE: &str = "\
+A disk usage distribution tool for the CLI
+
+Usage: dudist [OPTIONS] <PATH>...
+       dudist merge [OPTIONS] <SNAPSHOT>...
+       dudist --list-fs
+
+Several PATHs are scanned at the same time and reported on together.
+dudist merge reports on the files of several --snapshot files together.
+
+Options:
+      --min-size <SIZE>     Skip files of at most this size [default: 4KiB]
+      --max-size <SIZE>     Skip files larger than this
+      --max-depth <N>       Don't descend more than N directories below PATH
+  -L, --follow-symlinks     Follow symbolic links
+  -x, --one-file-system     Don't cross into other filesystems
+      --allow-mount <PATH>  With -x, still cross into the filesystem mounted at
+                            PATH (Unix only, repeatable)
+      --exclude-hidden      Skip files and directories starting with a dot
+      --plot-style <STYLE>  Box plot style: shade or braille [default: shade]
+      --no-unicode          Only print ASCII, as with a locale that isn't UTF-8
+      --origin <ORIGIN>     Left edge of the box plot: zero (draws an axis) or
+                            min (dots up to the smallest file) [default: min]
+      --plot-marks <LIST>   Also mark these percentiles on the box plot, e.g.
+                            90,99
+      --clip <LIMIT>        End the box plot axis at a percentile (e.g. p99) or a
+                            size (e.g. 1GiB) instead of the largest file
+      --violin              Draw a density (violin) plot instead of the box plot
+      --violin-height <ROWS>
+                            Height of the violin plot [default: 7]
+      --modes <N>           Number of most common sizes to list [default: 1]
+      --rank <SIZE>         Also print the share of files and bytes below this
+                            size (repeatable)
+      --locate <FILE>       Mark where this file's size falls on the box plot
+                            (repeatable, up to 26 times)
+      --examples            Also name a file of about the size of the min, Q1,
+                            median, Q3 and max
+      --scatter <AXES>      Also draw a scatter plot: size-age (size against days
+                            since the last modification)
+      --timeline <PERIOD>   Also chart the bytes per modification day, week,
+                            month or year
+      --timeline-limit <N>  Number of periods in the timeline [default: 12]
+      --cold-after <AGE>    Also report the files not accessed for AGE, e.g. 90d
+                            (units s, m, h, d, w, mo and y)
+      --cold-by <TIME>      Timestamp telling cold files: atime or mtime
+                            [default: atime]
+      --list-cold <N>       Also list the N largest cold files
+      --retention <AGE>     Also preview deleting the files not modified for
+                            AGE, e.g. 180d (repeatable)
+      --anomalies           Also list the files of a size atypical for their
+                            extension
+      --anomaly-iqr <K>     Atypical beyond K interquartile ranges past the
+                            quartiles [default: 10]
+      --anomaly-ratio <R>   Atypical at R times the median or 1/R of it
+                            [default: 100]
+      --anomaly-min-group <N>
+                            Files an extension needs to be judged [default: 20]
+      --export <FORMAT> <PATH>
+                            Also write the summary and a histogram for gnuplot
+                            (data files and a script in the directory PATH),
+                            vega (a Vega-Lite spec in the file PATH) or
+                            prometheus (gauges in the file PATH)
+      --export-raw          Include every file size in the export
+      --chart-out <FILE>    Also draw a box plot and histogram to an SVG or PNG
+                            file, told by its extension
+      --chart-size <WxH>    Size of the chart in pixels [default: 1200x400]
+      --snapshot <FILE>     Save the sizes found to FILE for dudist merge
+      --compress            Compress the --snapshot with zstd, as a FILE
+                            ending in .zst is anyway
+      --history <FILE>      Append the file count, total, median and p95 of
+                            this scan to FILE (JSON Lines)
+      --show-trend          Also print how the statistics changed since earlier
+                            runs in the --history file
+      --trend-runs <N>      Number of earlier runs the trend spans [default: 30]
+      --template <FILE>     Only print the report as laid out in FILE, with
+                            placeholders like {{median.human}}
+      --template-str <TEXT> Like --template, with the template given inline
+      --template-preset <NAME>
+                            Like --template, with a built-in template: slack
+                            (one line) or email (a plain text report)
+      --check               Only print a Nagios plugin status line, exiting with
+                            0 (OK), 1 (WARNING), 2 (CRITICAL) or 3 (UNKNOWN)
+      --warn <EXPR>         WARNING when EXPR holds, e.g. 'total>500GiB' or
+                            'count>1e7', over count, total, min, q1, median,
+                            q3, p90, p95, p99 or max (repeatable)
+      --crit <EXPR>         CRITICAL when EXPR holds, like --warn (repeatable)
+      --summary             Only print one line of key=value statistics
+      --json                Print the report as one JSON document instead
+      --bytes               Print raw byte counts in --summary
+      --count               Only print the number of matching files
+      --total               Also print the total size (raw bytes with --count)
+      --slack               Also report the space lost to rounding files up to
+                            whole blocks (Unix only)
+      --hardlink-report     Also report the files with several names and the
+                            space linking saves (Unix only)
+      --stat <STAT>         Also report on the directories: dir-entries (the
+                            number of entries directly inside each)
+      --stat-files-only     Only count the regular files for --stat dir-entries
+      --top-dirs <N>        Number of directories --stat lists [default: 10]
+      --empty-dirs          Also count the directories without files anywhere
+                            below them
+      --list-empty-dirs     Also list the topmost empty directories, those
+                            whose parent isn't empty
+      --fs-usage            Also relate the total to the size and usage of the
+                            filesystem holding PATH
+      --group-by <KEY>      Also report per group: ext (file extension), dir
+                            (top-level directory), magic (content type, read
+                            from the start of each file) or owner (Unix only)
+      --magic-sample-limit <SIZE>
+                            Bytes read per file for --group-by magic
+                            [default: 512]
+      --sort <KEY>          Row order of tables: total, count, median or name
+                            [default: total]
+      --reverse             Reverse the row order of tables
+      --min-group-count <N> Merge groups of fewer than N files into (other)
+      --min-group-bytes <SIZE>
+                            Merge groups smaller than SIZE in total into (other)
+      --max-groups <N>      Merge all groups after the first N into (other)
+      --skew-factor <F>     Flag groups whose mean is over F times their median
+                            [default: 3]
+      --outlier-factor <K>  Flag groups whose largest file is over K times
+                            their p99 [default: 10]
+      --concentration <PERCENT>
+                            Flag groups whose largest file holds over PERCENT
+                            of their bytes [default: 50]
+      --budgets <FILE>      Also check the totals of the groups against the
+                            budgets in the TOML file FILE, e.g. \"assets/\" =
+                            \"2GiB\", exiting with status 4 if any is exceeded
+      --small-multiples     Plot the groups as box plots on a shared scale
+                            instead of a table
+      --exclude-fs-type <TYPE>
+                            Skip directories on filesystems of this type, e.g.
+                            tmpfs (Linux only, repeatable)
+      --exclude-caches      Skip directories tagged with a CACHEDIR.TAG file
+      --no-dudistignore     Don't skip what .dudistignore files list
+      --by-mount            Also report per mount point (Linux only)
+      --cache               Reuse the files found by an earlier scan of PATH with
+                            the same filters while the tree looks unchanged
+      --cache-trust         Reuse the cached scan without checking the tree
+      --refresh             Scan again and update the cache
+      --incremental         Update the cache by only listing the directories
+                            modified since, statting the cached files again
+      --incremental-fast    Like --incremental, but trust the cached file sizes
+      --list-fs             Print the detected mounts and their types and exit
+      --dry-run             Check the options and print what a scan would do,
+                            then exit without scanning
+      --fast-estimate       Count every file, but only stat an even spread of
+                            them and estimate the rest, for filesystems where
+                            reading metadata is slow
+      --stat-budget <N>     Number of files --fast-estimate stats [default:
+                            10000]
+      --compact             Count the sizes in buckets, in a few KiB of memory
+                            however many files, with the statistics within 0.4%
+      --retries <N>         Retry metadata calls failing with EIO, ESTALE or
+                            EAGAIN up to N times, as on busy network
+                            filesystems [default: 0]
+      --retry-delay <DELAY> Longest wait between retries, e.g. 2s [default: 1s]
+      --max-path-bytes <N>  Skip entries whose path is longer than N bytes, and
+                            everything below them, with a warning
+      --jobs <N>            Number of PATHs scanned at the same time [default:
+                            number of CPUs]
+      --allow-overlap       Scan a PATH inside another PATH only once, with a
+                            warning, rather than failing
+      --root-timeout <AGE>  Give up on a PATH still being scanned after AGE,
+                            e.g. 10m, reporting the files found until then and
+                            exiting with status 5
+      --archives[=both]     Count the files inside .tar, .tar.gz and .zip
+                            archives instead of the archives, or with both as
+                            well as them. Reads every archive, so it is much
+                            slower
+      --strict[=errors-only]
+                            End with exit status 3 at the first entry the walk
+                            can't read, or with errors-only finish the report
+                            and list them all before exiting with status 3
+      --strict-utf8         Fail on the first path that isn't valid UTF-8
+                            instead of showing it with replacement characters
+      --no-timing           Don't print how long the scan took
+      --resource-stats      End with the directory listings and stat calls
+                            issued, the entries kept in memory, the peak memory
+                            and the wall and CPU time of the run
+      --timestamp           Start the report with a '# generated <time>' line
+      --no-header           Don't start the report with the version, PATHs and
+                            settings of the scan
+      --no-warnings         Don't print the warnings section at the end
+      --hints               Suggest options for the next run after the report,
+                            the default when stdout is a terminal
+      --no-hints            Don't suggest options for the next run
+      --progress-json <FD|FILE>
+                            Also write progress events as JSON lines to the file
+                            descriptor FD or the file FILE
+  -v, --verbose             List every entry in the warnings section, not just
+                            the first few of each kind
+  -h, --help                Print help
+";
+
+#[derive(Debug)]
+pub struct Args {
+    pub path: PathBuf,
+    /// Every PATH given, the first being `path`
+    pub roots: Vec<PathBuf>,
+    pub jobs: usize,
+    pub root_timeout: Option<Duration>,
+    pub allow_overlap: bool,
+    pub min_size: u64,
+    pub max_size: Option<u64>,
+    pub max_depth: Option<usize>,
+    pub follow_symlinks: bool,
+    pub one_file_system: bool,
+    pub allow_mounts: Vec<PathBuf>,
+    pub exclude_hidden: bool,
+    pub plot_style: PlotStyle,
+    pub unicode: bool,
+    pub origin: Origin,
+    pub plot_marks: Vec<f64>,
+    pub clip: Option<ClipSpec>,
+    pub violin: bool,
+    pub violin_height: usize,
+    pub modes: usize,
+    pub ranks: Vec<u64>,
+    pub locate: Vec<PathBuf>,
+    pub examples: bool,
+    pub scatter: Option<Scatter>,
+    pub timeline: Option<Period>,
+    pub timeline_limit: usize,
+    pub cold_after: Option<Duration>,
+    pub cold_by: TimeField,
+    pub list_cold: usize,
+    pub retention: Vec<Duration>,
+    pub anomalies: Option<Thresholds>,
+    pub export: Option<Export>,
+    pub chart_out: Option<PathBuf>,
+    pub chart_size: ChartSize,
+    pub snapshot: Option<PathBuf>,
+    /// Whether the `--snapshot` is written zstd-compressed, asked for with
+    /// `--compress` or a name ending in `.zst`
+    pub compress: bool,
+    /// The snapshots to merge instead of scanning, for `dudist merge`
+    pub merge: Vec<PathBuf>,
+    pub history: Option<PathBuf>,
+    pub show_trend: bool,
+    pub trend_runs: usize,
+    pub template: Option<TemplateSource>,
+    /// The thresholds of `--check`, when given
+    pub check: Option<Check>,
+    pub summary: bool,
+    /// Whether the report is printed as JSON rather than text
+    pub json: bool,
+    pub bytes: bool,
+    pub count: bool,
+    pub total: bool,
+    pub timing: bool,
+    pub resource_stats: bool,
+    pub timestamp: bool,
+    pub header: bool,
+    pub warnings: bool,
+    pub hints: bool,
+    pub verbose: bool,
+    pub progress_json: Option<Target>,
+    pub archives: Option<ArchiveMode>,
+    /// The number of files to stat, for `--fast-estimate`
+    pub fast_estimate: Option<u64>,
+    pub compact: bool,
+    pub retry: Retry,
+    pub max_path_bytes: Option<usize>,
+    pub strict: Option<Strict>,
+    pub strict_utf8: bool,
+    pub slack: bool,
+    pub hardlink_report: bool,
+    pub stat: Option<Stat>,
+    pub stat_files_only: bool,
+    pub top_dirs: usize,
+    pub empty_dirs: bool,
+    pub list_empty_dirs: bool,
+    pub fs_usage: bool,
+    pub exclude_fs_types: Vec<String>,
+    pub budgets: Option<PathBuf>,
+    pub exclude_caches: bool,
+    pub dudistignore: bool,
+    pub cache: bool,
+    pub cache_trust: bool,
+    pub refresh: bool,
+    pub incremental: bool,
+    pub incremental_fast: bool,
+    pub list_fs: bool,
+    pub dry_run: bool,
+    pub by_mount: bool,
+    pub group_by: Option<GroupBy>,
+    pub magic_sample_limit: u64,
+    pub sort: SortOrder,
+    pub group_limits: GroupLimits,
+    pub flag_thresholds: FlagThresholds,
+    pub small_multiples: bool,
+}
+
+/// Where the `--template` comes from.
+#[derive(Debug, Clone)]
+pub enum TemplateSource {
+    File(PathBuf),
+    Inline(String),
+    /// One of [`template::PRESETS`](dudist::template::PRESETS)
+    Preset(String),
+}
+
+/// How `--strict` treats entries the walk can't read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strict {
+    /// End the run at the first one
+    Abort,
+    /// Finish the report, then list them and fail
+    ErrorsOnly,
+}
+
+impl Args {
+    /// Parses the command line, printing the usage and exiting on `--help`.
+    pub fn parse() -> Result<Args, String> {
+        Args::parse_from(std::env::args_os().skip(1))
+    }
+
+    fn parse_from(args: impl IntoIterator<Item = OsString>) -> Result<Args, String> {
+        let mut path = None;
+        let mut merge = Vec::new();
+        let mut more_roots = Vec::new();
+        let mut jobs = None;
+        let mut root_timeout = None;
+        let mut allow_overlap = false;
+        let mut min_size = 4096;
+        let mut max_size = None;
+        let mut max_depth = None;
+        let mut follow_symlinks = false;
+        let mut one_file_system = false;
+        let mut allow_mounts = Vec::new();
+        let mut exclude_hidden = false;
+        let mut plot_style = PlotStyle::Shade;
+        let mut unicode = true;
+        let mut origin = Origin::Min;
+        let mut plot_marks = Vec::new();
+        let mut clip = None;
+        let mut violin = false;
+        let mut violin_height = 7;
+        let mut modes = 1;
+        let mut ranks = Vec::new();
+        let mut locate = Vec::new();
+        let mut examples = false;
+        let mut scatter = None;
+        let mut timeline = None;
+        let mut timeline_limit = 12;
+        let mut cold_after = None;
+        let mut cold_by = TimeField::Accessed;
+        let mut list_cold = 0;
+        let mut retention = Vec::new();
+        let mut anomalies = false;
+        let mut thresholds_set = false;
+        let mut anomaly_thresholds = Thresholds::default();
+        let mut export = None;
+        let mut export_raw = false;
+        let mut chart_out = None;
+        let mut chart_size = ChartSize::default();
+        let mut snapshot = None;
+        let mut compress = false;
+        let mut history = None;
+        let mut show_trend = false;
+        let mut trend_runs = 30;
+        let mut template = None;
+        let mut summary = false;
+        let mut json = false;
+        let mut bytes = false;
+        let mut count = false;
+        let mut total = false;
+        let mut timing = true;
+        let mut resource_stats = false;
+        let mut archives = None;
+        let mut fast_estimate = false;
+        let mut stat_budget = None;
+        let mut compact = false;
+        let mut retry = Retry::default();
+        let mut retry_delay = false;
+        let mut max_path_bytes = None;
+        let mut check = false;
+        let mut thresholds = Check::default();
+        let mut slack = false;
+        let mut hardlink_report = false;
+        let mut stat = None;
+        let mut stat_files_only = false;
+        let mut top_dirs = 10;
+        let mut empty_dirs = false;
+        let mut list_empty_dirs = false;
+        let mut budgets = None;
+        let mut timestamp = false;
+        let mut header = true;
+        let mut warnings = true;
+        let mut verbose = false;
+        let mut progress_json = None;
+        let mut hints = None;
+        let mut dudistignore = true;
+        let mut strict = None;
+        let mut strict_utf8 = false;
+        let mut fs_usage = false;
+        let mut exclude_fs_types = Vec::new();
+        let mut exclude_caches = false;
+        let mut cache = false;
+        let mut cache_trust = false;
+        let mut refresh = false;
+        let mut incremental = false;
+        let mut incremental_fast = false;
+        let mut list_fs = false;
+        let mut dry_run = false;
+        let mut by_mount = false;
+        let mut group_by = None;
+        let mut magic_sample_limit = magic::DEFAULT_SAMPLE_LIMIT;
+        let mut sort = SortOrder {
+            key: SortKey::Total,
+            reverse: false,
+        };
+        let mut group_limits = GroupLimits::default();
+        let mut flag_thresholds = FlagThresholds::default();
+        let mut small_multiples = false;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            // Flags are ASCII, only paths may not be valid UTF-8
+            let text = arg.to_string_lossy().into_owned();
+            let (flag, inline_value) = match text.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") && arg.to_str().is_some() => {
+                    (flag.to_string(), Some(value.to_string()))
+                }
+                _ => (text.clone(), None),
+            };
+            match flag.as_str() {
+                "-h" | "--help" => {
+                    print!("{}", USAGE);
+                    std::process::exit(0);
+                }
+                "--min-size" => {
+                    min_size = format::parse_size(&required_value(&flag, inline_value, &mut args)?)?
+                }
+                "--max-size" => {
+                    max_size = Some(format::parse_size(&required_value(
+                        &flag,
+                        inline_value,
+                        &mut args,
+                    )?)?)
+                }
+                "--max-depth" => max_depth = Some(parse_value(&flag, inline_value, &mut args)?),
+                "-L" | "--follow-symlinks" => follow_symlinks = true,
+                "-x" | "--one-file-system" => one_file_system = true,
+                "--allow-mount" => allow_mounts.push(path_value(&flag, inline_value, &mut args)?),
+                "--exclude-hidden" => exclude_hidden = true,
+                "--plot-style" => plot_style = parse_value(&flag, inline_value, &mut args)?,
+                "--no-unicode" => unicode = false,
+                "--origin" => origin = parse_value(&flag, inline_value, &mut args)?,
+                "--plot-marks" => {
+                    plot_marks =
+                        parse_percentiles(&required_value(&flag, inline_value, &mut args)?)?
+                }
+                "--clip" => clip = Some(parse_value(&flag, inline_value, &mut args)?),
+                "--violin" => violin = true,
+                "--violin-height" => {
+                    violin_height = parse_value(&flag, inline_value, &mut args)?;
+                    if violin_height == 0 {
+                        return Err("'--violin-height' must be at least 1".to_string());
+                    }
+                }
+                "--modes" => {
+                    modes = parse_value(&flag, inline_value, &mut args)?;
+                    if modes == 0 {
+                        return Err("'--modes' must be at least 1".to_string());
+                    }
+                }
+                "--rank" => ranks.push(format::parse_size(&required_value(
+                    &flag,
+                    inline_value,
+                    &mut args,
+                )?)?),
+                "--locate" => {
+                    // Markers are labeled a to z
+                    if locate.len() == 26 {
+                        return Err("'--locate' can be given at most 26 times".to_string());
+                    }
+                    locate.push(path_value(&flag, inline_value, &mut args)?)
+                }
+                "--examples" => examples = true,
+                "--scatter" => scatter = Some(parse_value(&flag, inline_value, &mut args)?),
+                "--timeline" => timeline = Some(parse_value(&flag, inline_value, &mut args)?),
+                "--timeline-limit" => {
+                    timeline_limit = parse_value(&flag, inline_value, &mut args)?;
+                    if timeline_limit == 0 {
+                        return Err("'--timeline-limit' must be at least 1".to_string());
+                    }
+                }
+                "--cold-after" => {
+                    cold_after = Some(format::parse_duration(&required_value(
+                        &flag,
+                        inline_value,
+                        &mut args,
+                    )?)?)
+                }
+                "--cold-by" => cold_by = parse_value(&flag, inline_value, &mut args)?,
+                "--list-cold" => list_cold = parse_value(&flag, inline_value, &mut args)?,
+                "--retention" => retention.push(format::parse_duration(&required_value(
+                    &flag,
+                    inline_value,
+                    &mut args,
+                )?)?),
+                "--anomalies" => anomalies = true,
+                "--anomaly-iqr" | "--anomaly-ratio" => {
+                    let multiple: f64 = parse_value(&flag, inline_value, &mut args)?;
+                    if !(multiple > 0.0 && multiple.is_finite()) {
+                        return Err(format!("'{}' must be a positive number", flag));
+                    }
+                    match flag.as_str() {
+                        "--anomaly-iqr" => anomaly_thresholds.iqr_multiple = multiple,
+                        _ => anomaly_thresholds.median_multiple = multiple,
+                    }
+                    thresholds_set = true;
+                }
+                "--anomaly-min-group" => {
+                    anomaly_thresholds.min_group = parse_value(&flag, inline_value, &mut args)?;
+                    if anomaly_thresholds.min_group < 4 {
+                        return Err("'--anomaly-min-group' must be at least 4".to_string());
+                    }
+                    thresholds_set = true;
+                }
+                "--export" => {
+                    export = Some(Export {
+                        format: parse_value(&flag, inline_value, &mut args)?,
+                        path: path_value(&flag, None, &mut args)?,
+                        raw: false,
+                    })
+                }
+                "--export-raw" => export_raw = true,
+                "--chart-out" => chart_out = Some(path_value(&flag, inline_value, &mut args)?),
+                "--chart-size" => chart_size = parse_value(&flag, inline_value, &mut args)?,
+                "--snapshot" => snapshot = Some(path_value(&flag, inline_value, &mut args)?),
+                "--compress" => compress = true,
+                "--history" => history = Some(path_value(&flag, inline_value, &mut args)?),
+                "--show-trend" => show_trend = true,
+                "--trend-runs" => {
+                    trend_runs = parse_value(&flag, inline_value, &mut args)?;
+                    if trend_runs == 0 {
+                        return Err("'--trend-runs' must be at least 1".to_string());
+                    }
+                }
+                "--template" => {
+                    template = Some(TemplateSource::File(path_value(
+                        &flag,
+                        inline_value,
+                        &mut args,
+                    )?))
+                }
+                "--template-str" => {
+                    template = Some(TemplateSource::Inline(required_value(
+                        &flag,
+                        inline_value,
+                        &mut args,
+                    )?))
+                }
+                "--template-preset" => {
+                    template = Some(TemplateSource::Preset(required_value(
+                        &flag,
+                        inline_value,
+                        &mut args,
+                    )?))
+                }
+                "--check" => check = true,
+                "--warn" => thresholds
+                    .warn
+                    .push(parse_value(&flag, inline_value, &mut args)?),
+                "--crit" => thresholds
+                    .crit
+                    .push(parse_value(&flag, inline_value, &mut args)?),
+                "--summary" => summary = true,
+                "--json" => json = true,
+                "--bytes" => bytes = true,
+                "--count" => count = true,
+                "--total" => total = true,
+                "--no-timing" => timing = false,
+                "--resource-stats" => resource_stats = true,
+                "--timestamp" => timestamp = true,
+                "--no-header" => header = false,
+                "--no-warnings" => warnings = false,
+                "--hints" => hints = Some(true),
+                "--no-hints" => hints = Some(false),
+                "-v" | "--verbose" => verbose = true,
+                "--progress-json" => {
+                    progress_json = Some(Target::parse(path_value(&flag, inline_value, &mut args)?))
+                }
+                "--fast-estimate" => fast_estimate = true,
+                "--compact" => compact = true,
+                "--retries" => retry.retries = parse_value(&flag, inline_value, &mut args)?,
+                "--retry-delay" => {
+                    retry.max_delay =
+                        format::parse_duration(&required_value(&flag, inline_value, &mut args)?)?;
+                    retry_delay = true;
+                }
+                "--max-path-bytes" => {
+                    max_path_bytes = Some(parse_value(&flag, inline_value, &mut args)?)
+                }
+                "--jobs" => {
+                    jobs = Some(parse_value(&flag, inline_value, &mut args)?);
+                    if jobs == Some(0) {
+                        return Err("'--jobs' must be at least 1".to_string());
+                    }
+                }
+                "--allow-overlap" => allow_overlap = true,
+                "--root-timeout" => {
+                    root_timeout = Some(format::parse_duration(&required_value(
+                        &flag,
+                        inline_value,
+                        &mut args,
+                    )?)?)
+                }
+                "--stat-budget" => {
+                    stat_budget = Some(parse_value::<u64>(&flag, inline_value, &mut args)?)
+                }
+                "--archives" => {
+                    archives = Some(match inline_value.as_deref() {
+                        None => ArchiveMode::Members,
+                        Some("both") => ArchiveMode::Both,
+                        Some(value) => {
+                            return Err(format!(
+                                "invalid value for '--archives': '{}' (expected both)",
+                                value
+                            ))
+                        }
+                    })
+                }
+                "--strict" => {
+                    strict = Some(match inline_value.as_deref() {
+                        None => Strict::Abort,
+                        Some("errors-only") => Strict::ErrorsOnly,
+                        Some(value) => {
+                            return Err(format!(
+                                "invalid value for '--strict': '{}' (expected errors-only)",
+                                value
+                            ))
+                        }
+                    })
+                }
+                "--strict-utf8" => strict_utf8 = true,
+                "--slack" => slack = true,
+                "--hardlink-report" => hardlink_report = true,
+                "--stat" => stat = Some(parse_value(&flag, inline_value, &mut args)?),
+                "--stat-files-only" => stat_files_only = true,
+                "--top-dirs" => top_dirs = parse_value(&flag, inline_value, &mut args)?,
+                "--empty-dirs" => empty_dirs = true,
+                "--list-empty-dirs" => list_empty_dirs = true,
+                "--fs-usage" => fs_usage = true,
+                "--exclude-fs-type" => {
+                    exclude_fs_types.push(parse_value(&flag, inline_value, &mut args)?)
+                }
+                "--exclude-caches" => exclude_caches = true,
+                "--no-dudistignore" => dudistignore = false,
+                "--cache" => cache = true,
+                "--cache-trust" => cache_trust = true,
+                "--refresh" => refresh = true,
+                "--incremental" => incremental = true,
+                "--incremental-fast" => incremental_fast = true,
+                "--list-fs" => list_fs = true,
+                "--dry-run" => dry_run = true,
+                "--by-mount" => by_mount = true,
+                "--group-by" => group_by = Some(parse_value(&flag, inline_value, &mut args)?),
+                "--magic-sample-limit" => {
+                    magic_sample_limit =
+                        format::parse_size(&required_value(&flag, inline_value, &mut args)?)?
+                }
+                "--sort" => sort.key = parse_value(&flag, inline_value, &mut args)?,
+                "--reverse" => sort.reverse = true,
+                "--min-group-count" => {
+                    group_limits.min_count = parse_value(&flag, inline_value, &mut args)?
+                }
+                "--min-group-bytes" => {
+                    group_limits.min_bytes =
+                        format::parse_size(&required_value(&flag, inline_value, &mut args)?)?
+                }
+                "--max-groups" => {
+                    group_limits.max_groups = Some(parse_value(&flag, inline_value, &mut args)?)
+                }
+                "--skew-factor" => {
+                    flag_thresholds.skew =
+                        parse_factor(&required_value(&flag, inline_value, &mut args)?)?
+                }
+                "--outlier-factor" => {
+                    flag_thresholds.outlier =
+                        parse_factor(&required_value(&flag, inline_value, &mut args)?)?
+                }
+                "--concentration" => {
+                    flag_thresholds.concentration =
+                        parse_percent(&required_value(&flag, inline_value, &mut args)?)? / 100.0
+                }
+                "--budgets" => budgets = Some(path_value(&flag, inline_value, &mut args)?),
+                "--small-multiples" => small_multiples = true,
+                _ if flag.starts_with('-') && flag != "-" => {
+                    return Err(format!("unexpected argument '{}'", flag));
+                }
+                _ if path.is_none() => path = Some(PathBuf::from(arg)),
+                // Only a directory named merge can be scanned on its own
+                _ if path.as_deref() == Some(Path::new("merge")) => merge.push(PathBuf::from(arg)),
+                _ => more_roots.push(PathBuf::from(arg)),
+            }
+        }
+
+        if !merge.is_empty() {
+            path = Some(PathBuf::new());
+        }
+        let path = match path {
+            Some(path) => path,
+            // The mount listing doesn't scan anything
+            None if list_fs => PathBuf::new(),
+            None => return Err("Please provide a path".to_string()),
+        };
+        let roots = std::iter::once(path.clone())
+            .chain(more_roots)
+            .collect::<Vec<_>>();
+        if roots.len() > 1 || root_timeout.is_some() {
+            // These take the one PATH as their root
+            let conflicts = [
+                (
+                    cache || cache_trust || refresh || incremental || incremental_fast,
+                    "--cache",
+                ),
+                (fast_estimate, "--fast-estimate"),
+                (group_by == Some(GroupBy::Directory), "--group-by dir"),
+                (snapshot.is_some(), "--snapshot"),
+                (history.is_some(), "--history"),
+                (export.is_some(), "--export"),
+                (template.is_some(), "--template"),
+                (fs_usage, "--fs-usage"),
+                (stat.is_some(), "--stat"),
+            ];
+            if let Some((_, flag)) = conflicts.iter().find(|(conflict, _)| *conflict) {
+                return Err(format!(
+                    "'{}' can't be combined with several PATHs or '--root-timeout'",
+                    flag
+                ));
+            }
+        }
+        if let Some(export) = &mut export {
+            export.raw = export_raw;
+        }
+        if !retention.is_empty() {
+            if !merge.is_empty() {
+                return Err(
+                    "'--retention' needs a scan, snapshots don't keep modification times"
+                        .to_string(),
+                );
+            }
+            if summary || count || check || template.is_some() {
+                return Err(
+                    "'--retention' needs the full report, not --summary, --count, --check or a template"
+                        .to_string(),
+                );
+            }
+        }
+        if thresholds_set && !anomalies {
+            return Err(
+                "'--anomaly-iqr', '--anomaly-ratio' and '--anomaly-min-group' need '--anomalies'"
+                    .to_string(),
+            );
+        }
+        if anomalies {
+            if !merge.is_empty() {
+                return Err("'--anomalies' needs a scan, snapshots don't keep paths".to_string());
+            }
+            if summary || count || check || template.is_some() {
+                return Err(
+                    "'--anomalies' needs the full report, not --summary, --count, --check or a template"
+                        .to_string(),
+                );
+            }
+        }
+        if resource_stats {
+            if !merge.is_empty() {
+                return Err(
+                    "'--resource-stats' needs a scan, dudist merge scans nothing".to_string(),
+                );
+            }
+            if summary || count || check || template.is_some() {
+                return Err(
+                    "'--resource-stats' needs the full report, not --summary, --count, --check or a template"
+                        .to_string(),
+                );
+            }
+        }
+        if list_cold > 0 && cold_after.is_none() {
+            return Err("'--list-cold' needs '--cold-after'".to_string());
+        }
+        if !allow_mounts.is_empty() {
+            if !one_file_system {
+                return Err("'--allow-mount' needs '--one-file-system'".to_string());
+            }
+            if !cfg!(unix) {
+                return Err("'--allow-mount' is only supported on Unix".to_string());
+            }
+        }
+        if dry_run && !merge.is_empty() {
+            return Err("'--dry-run' needs a scan, dudist merge scans nothing".to_string());
+        }
+        if progress_json.is_some() && !merge.is_empty() {
+            return Err("'--progress-json' needs a scan, dudist merge scans nothing".to_string());
+        }
+        if slack && !cfg!(unix) {
+            return Err("'--slack' is only supported on Unix".to_string());
+        }
+        if hardlink_report {
+            if !cfg!(unix) {
+                return Err("'--hardlink-report' is only supported on Unix".to_string());
+            }
+            if !merge.is_empty() {
+                return Err(
+                    "'--hardlink-report' needs a scan, snapshots don't keep inodes".to_string(),
+                );
+            }
+        }
+        if stat_files_only && stat.is_none() {
+            return Err("'--stat-files-only' needs '--stat dir-entries'".to_string());
+        }
+        if stat.is_some() {
+            if !merge.is_empty() {
+                return Err("'--stat' needs a scan, snapshots don't keep directories".to_string());
+            }
+            if summary || count || check || template.is_some() {
+                return Err(
+                    "'--stat' needs the full report, not --summary, --count, --check or a template"
+                        .to_string(),
+                );
+            }
+        }
+        // Listing them counts them too
+        let empty_dirs = empty_dirs || list_empty_dirs;
+        if empty_dirs {
+            if !merge.is_empty() {
+                return Err(
+                    "'--empty-dirs' needs a scan, snapshots don't keep directories".to_string(),
+                );
+            }
+            if cache || cache_trust || refresh || incremental || incremental_fast {
+                return Err("'--empty-dirs' needs a walk of the tree, not '--cache'".to_string());
+            }
+            if summary || count || check || template.is_some() {
+                return Err(
+                    "'--empty-dirs' needs the full report, not --summary, --count, --check or a template"
+                        .to_string(),
+                );
+            }
+        }
+        if compress && snapshot.is_none() {
+            return Err("'--compress' needs a '--snapshot' file".to_string());
+        }
+        if let Some(snapshot) = &snapshot {
+            let zst = snapshot
+                .extension()
+                .is_some_and(|extension| extension == "zst");
+            if zst && !cfg!(feature = "zstd") {
+                return Err(format!(
+                    "this build of dudist can't compress snapshots, save {} without the .zst",
+                    snapshot.display()
+                ));
+            }
+            if compress && !cfg!(feature = "zstd") {
+                return Err("'--compress' needs dudist built with the zstd feature".to_string());
+            }
+            compress |= zst;
+        }
+        if archives.is_some() && !cfg!(feature = "archives") {
+            return Err("'--archives' needs dudist built with the archives feature".to_string());
+        }
+        let has_thresholds = !thresholds.warn.is_empty() || !thresholds.crit.is_empty();
+        if has_thresholds && !check {
+            return Err("'--warn' and '--crit' need '--check'".to_string());
+        }
+        if budgets.is_some() {
+            if group_by.is_none() {
+                return Err("'--budgets' needs '--group-by'".to_string());
+            }
+            if summary || count || check || template.is_some() {
+                return Err(
+                    "'--budgets' needs the full report, not --summary, --count, --check or a template"
+                        .to_string(),
+                );
+            }
+        }
+        if examples {
+            if !merge.is_empty() {
+                return Err("'--examples' needs a scan, snapshots don't keep paths".to_string());
+            }
+            if summary || count || check || template.is_some() {
+                return Err(
+                    "'--examples' needs the full report, not --summary, --count, --check or a template"
+                        .to_string(),
+                );
+            }
+        }
+        if retry_delay && retry.retries == 0 {
+            return Err("'--retry-delay' needs '--retries'".to_string());
+        }
+        if stat_budget.is_some() && !fast_estimate {
+            return Err("'--stat-budget' needs '--fast-estimate'".to_string());
+        }
+        if stat_budget == Some(0) {
+            return Err("'--stat-budget' must be at least 1".to_string());
+        }
+        if fast_estimate {
+            // Estimates would pass for exact numbers in these
+            let conflicts = [
+                (summary, "--summary"),
+                (count, "--count"),
+                (check, "--check"),
+                (template.is_some(), "--template"),
+                (
+                    cache || cache_trust || refresh || incremental || incremental_fast,
+                    "--cache",
+                ),
+                (strict.is_some(), "--strict"),
+                (snapshot.is_some(), "--snapshot"),
+                (history.is_some(), "--history"),
+                (export.is_some(), "--export"),
+                (chart_out.is_some(), "--chart-out"),
+                (group_by.is_some(), "--group-by"),
+                (by_mount, "--by-mount"),
+                (archives.is_some(), "--archives"),
+                (cold_after.is_some(), "--cold-after"),
+                (anomalies, "--anomalies"),
+                (!retention.is_empty(), "--retention"),
+                (slack, "--slack"),
+                (hardlink_report, "--hardlink-report"),
+                (empty_dirs, "--empty-dirs"),
+                (stat.is_some(), "--stat"),
+                (scatter.is_some(), "--scatter"),
+                (timeline.is_some(), "--timeline"),
+                (fs_usage, "--fs-usage"),
+                (progress_json.is_some(), "--progress-json"),
+            ];
+            if let Some((_, flag)) = conflicts.iter().find(|(conflict, _)| *conflict) {
+                return Err(format!(
+                    "'--fast-estimate' can't be combined with '{}'",
+                    flag
+                ));
+            }
+        }
+        if compact {
+            // These keep or write every size or every file, or sample them
+            let conflicts = [
+                (count, "--count"),
+                (!merge.is_empty(), "merge"),
+                (
+                    cache || cache_trust || refresh || incremental || incremental_fast,
+                    "--cache",
+                ),
+                (fast_estimate, "--fast-estimate"),
+                (snapshot.is_some(), "--snapshot"),
+                (
+                    export.as_ref().is_some_and(|export| export.raw),
+                    "--export-raw",
+                ),
+                (scatter.is_some(), "--scatter"),
+                (timeline.is_some(), "--timeline"),
+                (hardlink_report, "--hardlink-report"),
+                (!retention.is_empty(), "--retention"),
+            ];
+            if let Some((_, flag)) = conflicts.iter().find(|(conflict, _)| *conflict) {
+                return Err(format!("'--compact' can't be combined with '{}'", flag));
+            }
+        }
+        if json {
+            // These print something else
+            let conflicts = [
+                (summary, "--summary"),
+                (count, "--count"),
+                (check, "--check"),
+                (template.is_some(), "--template"),
+                (dry_run, "--dry-run"),
+                (!merge.is_empty(), "merge"),
+            ];
+            if let Some((_, flag)) = conflicts.iter().find(|(conflict, _)| *conflict) {
+                return Err(format!("'--json' can't be combined with '{}'", flag));
+            }
+        }
+        if count {
+            // These print something else, or write files from the sizes
+            // --count doesn't keep
+            let conflicts = [
+                (summary, "--summary"),
+                (check, "--check"),
+                (template.is_some(), "--template"),
+                (snapshot.is_some(), "--snapshot"),
+                (history.is_some(), "--history"),
+                (export.is_some(), "--export"),
+                (chart_out.is_some(), "--chart-out"),
+            ];
+            if let Some((_, flag)) = conflicts.iter().find(|(conflict, _)| *conflict) {
+                return Err(format!("'--count' can't be combined with '{}'", flag));
+            }
+        }
+        if show_trend && history.is_none() {
+            return Err("'--show-trend' needs a '--history' file".to_string());
+        }
+
+        Ok(Args {
+            path,
+            roots,
+            jobs: jobs.unwrap_or_else(|| {
+                std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get)
+            }),
+            root_timeout,
+            allow_overlap,
+            min_size,
+            max_size,
+            max_depth,
+            follow_symlinks,
+            one_file_system,
+            allow_mounts,
+            exclude_hidden,
+            plot_style,
+            unicode,
+            origin,
+            plot_marks,
+            clip,
+            violin,
+            violin_height,
+            modes,
+            ranks,
+            locate,
+            examples,
+            scatter,
+            timeline,
+            timeline_limit,
+            cold_after,
+            cold_by,
+            list_cold,
+            retention,
+            anomalies: anomalies.then_some(anomaly_thresholds),
+            export,
+            chart_out,
+            chart_size,
+            snapshot,
+            compress,
+            merge,
+            history,
+            show_trend,
+            trend_runs,
+            template,
+            check: check.then_some(thresholds),
+            summary,
+            json,
+            bytes,
+            count,
+            total,
+            timing,
+            resource_stats,
+            timestamp,
+            header,
+            warnings,
+            // Only people read the hints, not the tools reports are piped to
+            hints: hints.unwrap_or_else(|| std::io::stdout().is_terminal()),
+            verbose,
+            progress_json,
+            archives,
+            fast_estimate: fast_estimate.then_some(stat_budget.unwrap_or(10_000)),
+            compact,
+            retry,
+            max_path_bytes,
+            strict,
+            strict_utf8,
+            slack,
+            hardlink_report,
+            stat,
+            stat_files_only,
+            top_dirs,
+            empty_dirs,
+            list_empty_dirs,
+            fs_usage,
+            exclude_fs_types,
+            budgets,
+            exclude_caches,
+            dudistignore,
+            // Trusting, refreshing or updating the cache only makes sense with
+            // one
+            cache: cache || cache_trust || refresh || incremental || incremental_fast,
+            cache_trust,
+            refresh,
+            incremental: incremental || incremental_fast,
+            incremental_fast,
+            list_fs,
+            dry_run,
+            by_mount,
+            group_by,
+            magic_sample_limit,
+            sort,
+            group_limits,
+            flag_thresholds,
+            small_multiples,
+        })
+    }
+}
+
+/// Parses a comma-separated list of percentiles such as `90,99.9`.
+fn parse_percentiles(list: &str) -> Result<Vec<f64>, String> {
+    list.split(',')
+        .map(|p| match p.trim().parse::<f64>() {
+            Ok(value) if (0.0..=100.0).contains(&value) => Ok(value),
+            _ => Err(format!("invalid percentile '{}'", p)),
+        })
+        .collect()
+}
+
+/// Parses a factor of at least 1, such as `2.5`.
+fn parse_factor(s: &str) -> Result<f64, String> {
+    match s.trim().parse::<f64>() {
+        Ok(value) if value >= 1.0 && value.is_finite() => Ok(value),
+        _ => Err(format!(
+            "invalid factor '{}' (expected a number of at least 1)",
+            s
+        )),
+    }
+}
+
+/// Parses a percentage such as `50` or `12.5`.
+fn parse_percent(s: &str) -> Result<f64, String> {
+    match s.trim().trim_end_matches('%').parse::<f64>() {
+        Ok(value) if (0.0..=100.0).contains(&value) => Ok(value),
+        _ => Err(format!("invalid percentage '{}'", s)),
+    }
+}
+
+/// Takes the value of `flag`, either given inline (`--flag=value`) or as the
+/// next argument, and parses it.
+fn parse_value<T>(
+    flag: &str,
+    inline_value: Option<String>,
+    args: &mut impl Iterator<Item = OsString>,
+) -> Result<T, String>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    required_value(flag, inline_value, args)?
+        .parse()
+        .map_err(|e| format!("invalid value for '{}': {}", flag, e))
+}
+
+/// Takes the value of `flag` as is.
+fn required_value(
+    flag: &str,
+    inline_value: Option<String>,
+    args: &mut impl Iterator<Item = OsString>,
+) -> Result<String, String> {
+    match inline_value {
+        Some(value) => Ok(value),
+        None => args
+            .next()
+            .ok_or_else(|| format!("a value is required for '{}'", flag))?
+            .into_string()
+            .map_err(|_| format!("the value for '{}' is not valid UTF-8", flag)),
+    }
+}
+
+/// Takes the value of `flag` as a path, which unlike other values needn't
+/// be valid UTF-8.
+fn path_value(
+    flag: &str,
+    inline_value: Option<String>,
+    args: &mut impl Iterator<Item = OsString>,
+) -> Result<PathBuf, String> {
+    match inline_value {
+        Some(value) => Ok(PathBuf::from(value)),
+        None => args
+            .next()
+            .map(PathBuf::from)
+            .ok_or_else(|| format!("a value is required for '{}'", flag)),
+    }
+}
@@ -0,0 +1,146 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use crate::format;
+use crate::scan::FileRecord;
+use crate::table::{Align, Table};
+
+/// The timestamp deciding whether a file is cold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeField {
+    Accessed,
+    Modified,
+}
+
+impl FromStr for TimeField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "atime" => Ok(TimeField::Accessed),
+            "mtime" => Ok(TimeField::Modified),
+            _ => Err(format!(
+                "invalid timestamp '{}' (expected atime or mtime)",
+                s
+            )),
+        }
+    }
+}
+
+impl TimeField {
+    fn of(self, record: &FileRecord) -> Option<SystemTime> {
+        match self {
+            TimeField::Accessed => record.accessed,
+            TimeField::Modified => record.modified,
+        }
+    }
+
+    fn verb(self) -> &'static str {
+        match self {
+            TimeField::Accessed => "accessed",
+            TimeField::Modified => "modified",
+        }
+    }
+}
+
+/// Counts the files that haven't been touched for some time.
+#[derive(Debug)]
+pub struct ColdTally {
+    age: Duration,
+    field: TimeField,
+    cutoff: SystemTime,
+    pub files: u64,
+    pub bytes: u64,
+    /// Files without a usable timestamp, neither cold nor warm
+    pub unknown_files: u64,
+    pub unknown_bytes: u64,
+    list_limit: usize,
+    /// The largest cold files, smallest on top so it can be evicted
+    largest: BinaryHeap<Reverse<(u64, PathBuf)>>,
+}
+
+impl ColdTally {
+    /// Files are cold when their `field` is more than `age` in the past.
+    /// The `list_limit` largest are kept for [`print`](Self::print).
+    pub fn new(age: Duration, field: TimeField, list_limit: usize) -> ColdTally {
+        ColdTally {
+            age,
+            field,
+            cutoff: SystemTime::now()
+                .checked_sub(age)
+                .unwrap_or(SystemTime::UNIX_EPOCH),
+            files: 0,
+            bytes: 0,
+            unknown_files: 0,
+            unknown_bytes: 0,
+            list_limit,
+            largest: BinaryHeap::new(),
+        }
+    }
+
+    pub fn add(&mut self, record: &FileRecord) {
+        match self.field.of(record) {
+            None => {
+                self.unknown_files += 1;
+                self.unknown_bytes += record.size;
+            }
+            Some(time) if time < self.cutoff => {
+                self.files += 1;
+                self.bytes += record.size;
+                if self.list_limit > 0 {
+                    self.largest
+                        .push(Reverse((record.size, record.path.clone())));
+                    if self.largest.len() > self.list_limit {
+                        self.largest.pop();
+                    }
+                }
+            }
+            Some(_) => {}
+        }
+    }
+
+    /// Prints the cold files' share of the `total` bytes scanned and the
+    /// largest of them.
+    pub fn print(self, total: u64) {
+        let share = if total > 0 {
+            self.bytes as f64 / total as f64 * 100.0
+        } else {
+            0.0
+        };
+        println!(
+            "Not {} in {}: {} files, {} ({:.1}% of the scanned bytes)",
+            self.field.verb(),
+            format::human_duration(self.age),
+            format::format_count(self.files),
+            format::human(self.bytes),
+            share
+        );
+        if self.unknown_files > 0 {
+            println!(
+                "Unknown {} time: {} files, {}",
+                match self.field {
+                    TimeField::Accessed => "access",
+                    TimeField::Modified => "modification",
+                },
+                format::format_count(self.unknown_files),
+                format::human(self.unknown_bytes)
+            );
+        }
+        if self.field == TimeField::Accessed {
+            println!(
+                "(access times lag with relatime and stay put with noatime, try --cold-by mtime)"
+            );
+        }
+        if !self.largest.is_empty() {
+            println!("Largest cold files:");
+            let mut table = Table::new(&[Align::Right, Align::Left]);
+            for Reverse((size, path)) in self.largest.into_sorted_vec() {
+                table.row([format::human(size), format::path(&path)]);
+            }
+            print!("{}", table.render());
+        }
+    }
+}
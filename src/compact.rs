@@ -0,0 +1,382 @@
+//! Sizes counted in buckets rather than kept one by one, for `--compact`
+//! runs whose memory mustn't grow with the tree.
+//!
+//! Sizes below 256 bytes get a bucket each. Above that, every doubling is
+//! split into 128 buckets of equal width, so a bucket spans less than 1/128
+//! of the sizes in it and its middle is within 1/256 (0.39%) of each of
+//! them. The statistics taken from the middles, like the median, are within
+//! that of the exact ones. The count, the total and the extremes are kept
+//! exactly on the side. All of it takes at most 58 KiB, however many files
+//! there are.
+
+use crate::distribution::Ranked;
+
+/// Sizes below this are counted exactly
+const EXACT_BELOW: u64 = 256;
+
+/// Every doubling above [`EXACT_BELOW`] is split into 2^`SUB_BITS` buckets
+const SUB_BITS: u32 = 7;
+
+/// The largest relative error of a size read back from its bucket
+pub const RELATIVE_ERROR: f64 = 1.0 / 256.0;
+
+/// The sizes of a scan, counted in buckets.
+#[derive(Debug, Clone, Default)]
+pub struct CompactSizes {
+    /// Files per bucket, up to the highest bucket used
+    buckets: Vec<u64>,
+    count: u64,
+    total: u64,
+    min: u64,
+    max: u64,
+}
+
+/// The bucket holding `size`.
+fn bucket(size: u64) -> usize {
+    if size < EXACT_BELOW {
+        return size as usize;
+    }
+    let exponent = 63 - size.leading_zeros();
+    let shift = exponent - SUB_BITS;
+    let sub = (size >> shift) as usize & ((1 << SUB_BITS) - 1);
+    EXACT_BELOW as usize + ((exponent - EXACT_BELOW.trailing_zeros()) << SUB_BITS) as usize + sub
+}
+
+/// The middle of `bucket`, the size it stands for.
+fn middle(bucket: usize) -> u64 {
+    if bucket < EXACT_BELOW as usize {
+        return bucket as u64;
+    }
+    let above = bucket - EXACT_BELOW as usize;
+    let exponent = (above >> SUB_BITS) as u32 + EXACT_BELOW.trailing_zeros();
+    let sub = (above & ((1 << SUB_BITS) - 1)) as u64;
+    let shift = exponent - SUB_BITS;
+    let low = ((1 << SUB_BITS) + sub) << shift;
+    low + ((1 << shift) - 1) / 2
+}
+
+impl CompactSizes {
+    pub fn add(&mut self, size: u64) {
+        let i = bucket(size);
+        if i >= self.buckets.len() {
+            self.buckets.resize(i + 1, 0);
+        }
+        self.buckets[i] += 1;
+        self.min = if self.count == 0 {
+            size
+        } else {
+            self.min.min(size)
+        };
+        self.max = self.max.max(size);
+        self.count += 1;
+        // Only sizes close to 2^64 bytes, which no disk holds, saturate
+        self.total = self.total.saturating_add(size);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Adds the sizes counted in `other`.
+    pub fn merge(&mut self, other: &CompactSizes) {
+        if other.is_empty() {
+            return;
+        }
+        if other.buckets.len() > self.buckets.len() {
+            self.buckets.resize(other.buckets.len(), 0);
+        }
+        for (bucket, &count) in self.buckets.iter_mut().zip(&other.buckets) {
+            *bucket += count;
+        }
+        self.min = if self.count == 0 {
+            other.min
+        } else {
+            self.min.min(other.min)
+        };
+        self.max = self.max.max(other.max);
+        self.count += other.count;
+        self.total = self.total.saturating_add(other.total);
+    }
+}
+
+impl Ranked for CompactSizes {
+    fn count(&self) -> usize {
+        self.count as usize
+    }
+
+    /// The middle of the bucket holding the size at `rank`, within
+    /// [`RELATIVE_ERROR`] of it, but the extremes exactly.
+    fn nth(&self, rank: usize) -> u64 {
+        if rank == 0 {
+            return self.min;
+        }
+        if rank + 1 >= self.count() {
+            return self.max;
+        }
+        let mut below = 0;
+        for (i, &count) in self.buckets.iter().enumerate() {
+            below += count as usize;
+            if rank < below {
+                return middle(i).clamp(self.min, self.max);
+            }
+        }
+        self.max
+    }
+
+    /// The exact sum of the sizes.
+    fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// The sizes the buckets stand for, with the number of files in each.
+    fn runs(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(i, &count)| (middle(i).clamp(self.min, self.max), count))
+    }
+}
+
+/// The sizes of a scan or a group, kept one by one or, with `--compact`,
+/// counted in buckets. The reports read them through [`Ranked`] either way.
+#[derive(Debug, Clone)]
+pub enum Sizes {
+    /// Every size, sorted ascending by [`Sizes::sort`] before ranking
+    Exact(Vec<u64>),
+    Compact(CompactSizes),
+}
+
+impl Default for Sizes {
+    fn default() -> Self {
+        Sizes::Exact(Vec::new())
+    }
+}
+
+impl Sizes {
+    /// No sizes, to be counted in buckets when `compact`.
+    pub fn new(compact: bool) -> Sizes {
+        match compact {
+            true => Sizes::Compact(CompactSizes::default()),
+            false => Sizes::default(),
+        }
+    }
+
+    pub fn add(&mut self, size: u64) {
+        match self {
+            Sizes::Exact(sizes) => sizes.push(size),
+            Sizes::Compact(compact) => compact.add(size),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count() == 0
+    }
+
+    /// Sorts the sizes kept one by one, which the buckets needn't be.
+    pub fn sort(&mut self) {
+        if let Sizes::Exact(sizes) = self {
+            sizes.sort_unstable();
+        }
+    }
+
+    /// Adds the sizes of `other`, kept the same way. Exact sizes need
+    /// sorting again.
+    pub fn merge(&mut self, other: Sizes) {
+        match (self, other) {
+            (Sizes::Exact(sizes), Sizes::Exact(other)) => sizes.extend(other),
+            (Sizes::Compact(compact), Sizes::Compact(other)) => compact.merge(&other),
+            _ => panic!("sizes kept one by one and in buckets can't be merged"),
+        }
+    }
+
+    /// Every size, unless they were counted in buckets.
+    pub fn exact(&self) -> Option<&[u64]> {
+        match self {
+            Sizes::Exact(sizes) => Some(sizes),
+            Sizes::Compact(_) => None,
+        }
+    }
+}
+
+/// Ranks exact sizes once sorted.
+///
+/// ```
+/// use dudist::compact::Sizes;
+/// use dudist::distribution::{Distribution, Ranked};
+///
+/// let (mut exact, mut compact) = (Sizes::new(false), Sizes::new(true));
+/// for size in [300, 10, 20, 100_000, 40] {
+///     exact.add(size);
+///     compact.add(size);
+/// }
+/// exact.sort();
+/// assert_eq!(exact.total(), compact.total());
+/// assert_eq!(exact.percentile(50.0), 40.0);
+/// assert_eq!(compact.percentile(50.0), 40.0);
+/// assert_eq!(Distribution::from_ranked(&compact).max, 100_000);
+/// ```
+impl Ranked for Sizes {
+    fn count(&self) -> usize {
+        match self {
+            Sizes::Exact(sizes) => sizes.len(),
+            Sizes::Compact(compact) => compact.count(),
+        }
+    }
+
+    fn nth(&self, rank: usize) -> u64 {
+        match self {
+            Sizes::Exact(sizes) => sizes[rank],
+            Sizes::Compact(compact) => compact.nth(rank),
+        }
+    }
+
+    fn total(&self) -> u64 {
+        match self {
+            Sizes::Exact(sizes) => sizes.total(),
+            Sizes::Compact(compact) => compact.total(),
+        }
+    }
+
+    fn runs(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        let (exact, compact) = match self {
+            Sizes::Exact(sizes) => (Some(sizes.runs()), None),
+            Sizes::Compact(compact) => (None, Some(compact.runs())),
+        };
+        exact
+            .into_iter()
+            .flatten()
+            .chain(compact.into_iter().flatten())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distribution::Distribution;
+
+    /// xorshift64 from `seed`, random enough and the same every run.
+    fn random(seed: u64) -> impl FnMut() -> u64 {
+        let mut state = seed;
+        move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        }
+    }
+
+    /// Checks the ranks, percentiles and distribution of `sizes` counted in
+    /// buckets against the exact ones.
+    fn assert_within_the_error(mut sizes: Vec<u64>) {
+        let mut compact = CompactSizes::default();
+        sizes.iter().for_each(|&size| compact.add(size));
+        sizes.sort_unstable();
+        // Give or take the rounding of f64 near 2^64
+        let within = |approx: f64, exact: f64| {
+            (approx - exact).abs() <= exact * RELATIVE_ERROR * (1.0 + 1e-12)
+        };
+        for (rank, &size) in sizes.iter().enumerate() {
+            let approx = compact.nth(rank);
+            assert!(
+                within(approx as f64, size as f64),
+                "{} for {}",
+                approx,
+                size
+            );
+        }
+        for p in [0.0, 1.0, 10.0, 25.0, 50.0, 75.0, 90.0, 99.0, 99.9, 100.0] {
+            assert!(within(compact.percentile(p), sizes.percentile(p)), "p{}", p);
+        }
+        let (approx, exact) = (
+            Distribution::from_ranked(&compact),
+            Distribution::from_sorted(&sizes),
+        );
+        assert_eq!((approx.min, approx.max), (exact.min, exact.max));
+        assert!(within(approx.median, exact.median));
+        assert!(within(approx.lower_quartile, exact.lower_quartile));
+        assert!(within(approx.upper_quartile, exact.upper_quartile));
+        assert_eq!(compact.count(), sizes.len());
+        let total = sizes.iter().fold(0_u64, |a, &b| a.saturating_add(b));
+        assert_eq!(compact.total(), total);
+    }
+
+    #[test]
+    fn random_sizes_of_every_magnitude_are_within_the_error() {
+        let mut next = random(0x2545_f491_4f6c_dd1d);
+        assert_within_the_error((0..5000).map(|_| next() >> (next() % 64)).collect());
+    }
+
+    #[test]
+    fn sizes_crowded_into_a_few_buckets_are_within_the_error() {
+        let mut next = random(0x2545_f491_4f6c_dd1d);
+        assert_within_the_error((0..5000).map(|_| 4096 + next() % 8192).collect());
+        assert_within_the_error(vec![1 << 40; 1000]);
+    }
+
+    #[test]
+    fn sizes_at_the_edges_of_the_buckets_are_within_the_error() {
+        assert_within_the_error(
+            (8..64)
+                .flat_map(|bit| [(1 << bit) - 1, 1 << bit, (1 << bit) + 1])
+                .collect(),
+        );
+        assert_within_the_error(vec![u64::MAX, u64::MAX - 1, 1 << 63, 0]);
+    }
+
+    #[test]
+    fn small_sizes_are_exact() {
+        let sizes = (0..300).collect::<Vec<u64>>();
+        let mut compact = CompactSizes::default();
+        sizes.iter().for_each(|&size| compact.add(size));
+        // One bucket per size below 256
+        for rank in 0..256 {
+            assert_eq!(compact.nth(rank), rank as u64);
+        }
+        assert_within_the_error(sizes);
+    }
+
+    #[test]
+    fn merged_buckets_equal_counting_the_concatenation() {
+        let mut next = random(0x9e37_79b9_7f4a_7c15);
+        for round in 0..100 {
+            let parts = (0..1 + next() % 4)
+                .map(|_| {
+                    (0..next() % 300)
+                        .map(|_| next() >> (next() % 64))
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            let mut merged = CompactSizes::default();
+            for part in &parts {
+                let mut compact = CompactSizes::default();
+                part.iter().for_each(|&size| compact.add(size));
+                merged.merge(&compact);
+            }
+            let mut all = CompactSizes::default();
+            parts.concat().into_iter().for_each(|size| all.add(size));
+
+            assert_eq!(merged.buckets, all.buckets, "round {}", round);
+            assert_eq!(
+                (merged.count, merged.total, merged.min, merged.max),
+                (all.count, all.total, all.min, all.max),
+                "round {}",
+                round
+            );
+            let mut exact = Sizes::new(false);
+            parts.concat().into_iter().for_each(|size| exact.add(size));
+            exact.sort();
+            let merged = Sizes::Compact(merged);
+            for rank in (0..exact.count()).step_by(5) {
+                let (bucketed, exact) = (merged.nth(rank) as f64, exact.nth(rank) as f64);
+                assert!((bucketed - exact).abs() <= exact * RELATIVE_ERROR);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "can't be merged")]
+    fn exact_and_bucketed_sizes_do_not_mix() {
+        Sizes::new(false).merge(Sizes::new(true));
+    }
+}
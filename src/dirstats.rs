@@ -0,0 +1,195 @@
+//! Statistics over the directories of a scan rather than its files, as for
+//! finding the directory that grew to millions of entries before it slows
+//! its filesystem down.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::distribution::Distribution;
+use crate::format;
+use crate::scan::DirRecord;
+use crate::table::{Align, Table};
+
+/// What `--stat` reports on besides the file sizes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stat {
+    /// The number of entries directly inside each directory
+    DirEntries,
+}
+
+impl FromStr for Stat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dir-entries" => Ok(Stat::DirEntries),
+            _ => Err(format!("invalid statistic '{}' (expected dir-entries)", s)),
+        }
+    }
+}
+
+/// Counts the entries directly inside each directory walked.
+#[derive(Debug)]
+pub struct DirEntryTally {
+    /// Only count the regular files, not subdirectories and the like
+    files_only: bool,
+    counts: Vec<u64>,
+    list_limit: usize,
+    /// The directories with the most entries, fewest on top so it can be
+    /// evicted
+    largest: BinaryHeap<Reverse<(u64, PathBuf)>>,
+}
+
+impl DirEntryTally {
+    /// The `list_limit` directories with the most entries are kept for
+    /// [`render`](Self::render).
+    pub fn new(files_only: bool, list_limit: usize) -> DirEntryTally {
+        DirEntryTally {
+            files_only,
+            counts: Vec::new(),
+            list_limit,
+            largest: BinaryHeap::new(),
+        }
+    }
+
+    pub fn add(&mut self, dir: &DirRecord) {
+        let count = match self.files_only {
+            true => dir.children.files,
+            false => dir.children.entries,
+        };
+        self.counts.push(count);
+        if self.list_limit > 0 {
+            self.largest.push(Reverse((count, dir.path.clone())));
+            if self.largest.len() > self.list_limit {
+                self.largest.pop();
+            }
+        }
+    }
+
+    /// Renders the distribution of the counts and the directories with the
+    /// most entries, or nothing without directories.
+    pub fn render(self) -> String {
+        let mut counts = self.counts;
+        if counts.is_empty() {
+            return String::new();
+        }
+        counts.sort_unstable();
+        let dist = Distribution::from_sorted(&counts);
+        let what = match self.files_only {
+            true => "Files",
+            false => "Entries",
+        };
+        let mut out = format!(
+            "{} per directory ({} {}):\n",
+            what,
+            format::format_count(counts.len() as u64),
+            if counts.len() == 1 {
+                "directory"
+            } else {
+                "directories"
+            }
+        );
+        let mut table = Table::new(&[Align::Left, Align::Right]);
+        for (name, value) in [
+            ("Smallest:", dist.min as f64),
+            ("Lower Quartile:", dist.lower_quartile),
+            ("Median:", dist.median),
+            ("Upper Quartile:", dist.upper_quartile),
+            ("Largest:", dist.max as f64),
+        ] {
+            table.row([name.to_string(), format::count_f64(value)]);
+        }
+        out += &table.render();
+        if !self.largest.is_empty() {
+            out += &format!("Most {}:\n", what.to_lowercase());
+            let mut table = Table::new(&[Align::Right, Align::Left]);
+            for Reverse((count, path)) in self.largest.into_sorted_vec() {
+                table.row([format::format_count(count), format::path(&path)]);
+            }
+            out += &table.render();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan::ScanCounts;
+
+    /// A directory holding `files` files and `entries` entries in all.
+    fn dir(name: &str, files: u64, entries: u64) -> DirRecord {
+        DirRecord {
+            path: PathBuf::from(name),
+            modified: None,
+            children: ScanCounts {
+                entries,
+                files,
+                ..ScanCounts::default()
+            },
+        }
+    }
+
+    #[test]
+    fn the_counts_and_the_fullest_directories_are_rendered() {
+        let mut tally = DirEntryTally::new(false, 1);
+        for (name, entries) in [("a", 2), ("b", 3), ("c", 8), ("mail", 1_000_001)] {
+            tally.add(&dir(name, 0, entries));
+        }
+        assert_eq!(
+            tally.render(),
+            "Entries per directory (4 directories):\n\
+             Smallest:                2\n\
+             Lower Quartile:        2.5\n\
+             Median:                5.5\n\
+             Upper Quartile:  500,004.5\n\
+             Largest:         1,000,001\n\
+             Most entries:\n\
+             1,000,001  mail\n"
+        );
+    }
+
+    #[test]
+    fn only_files_are_counted_when_asked() {
+        let mut tally = DirEntryTally::new(true, 2);
+        for (name, files, entries) in [("a", 1, 9), ("b", 4, 4), ("c", 2, 50)] {
+            tally.add(&dir(name, files, entries));
+        }
+        let rendered = tally.render();
+        assert!(rendered.starts_with("Files per directory (3 directories):\n"));
+        assert!(rendered.contains("Largest:         4\n"), "{}", rendered);
+        // The fullest first, up to the limit
+        assert!(
+            rendered.ends_with("Most files:\n4  b\n2  c\n"),
+            "{}",
+            rendered
+        );
+    }
+
+    #[test]
+    fn one_directory_and_none() {
+        let mut tally = DirEntryTally::new(false, 0);
+        tally.add(&dir("only", 0, 7));
+        assert_eq!(
+            tally.render(),
+            "Entries per directory (1 directory):\n\
+             Smallest:        7\n\
+             Lower Quartile:  7\n\
+             Median:          7\n\
+             Upper Quartile:  7\n\
+             Largest:         7\n"
+        );
+        assert_eq!(DirEntryTally::new(false, 10).render(), "");
+    }
+
+    #[test]
+    fn statistics_are_parsed_by_name() {
+        assert_eq!("dir-entries".parse(), Ok(Stat::DirEntries));
+        assert_eq!(
+            "dir-sizes".parse::<Stat>(),
+            Err("invalid statistic 'dir-sizes' (expected dir-entries)".to_string())
+        );
+    }
+}
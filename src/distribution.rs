@@ -0,0 +1,511 @@
+use serde_json::{json, Value};
+
+use crate::format;
+
+#[derive(Debug)]
+pub struct Distribution {
+    pub min: u64,
+    pub max: u64,
+    pub median: f64,
+    pub lower_quartile: f64,
+    pub upper_quartile: f64,
+}
+
+impl Distribution {
+    /// Computes the distribution of `sizes` in any order, e.g. the sizes of
+    /// a [`scan_iter`](crate::scan::scan_iter). `None` when there are none.
+    ///
+    /// ```no_run
+    /// use dudist::distribution::Distribution;
+    /// use dudist::scan::{scan_iter, ScanOptions};
+    ///
+    /// let options = ScanOptions::default();
+    /// let sizes = scan_iter(".", &options).filter_map(Result::ok).map(|record| record.size);
+    /// if let Some(dist) = Distribution::from_sizes(sizes) {
+    ///     println!("median {} bytes", dist.median);
+    /// }
+    /// ```
+    pub fn from_sizes(sizes: impl IntoIterator<Item = u64>) -> Option<Distribution> {
+        let mut sizes = sizes.into_iter().collect::<Vec<_>>();
+        if sizes.is_empty() {
+            return None;
+        }
+        sizes.sort_unstable();
+        Some(Distribution::from_sorted(&sizes))
+    }
+
+    /// Computes the distribution of `sizes`, which must be sorted ascending.
+    pub fn from_sorted(sizes: &[u64]) -> Distribution {
+        Distribution::from_ranked(sizes)
+    }
+
+    /// Computes the distribution of non-empty `sizes` kept in any
+    /// [`Ranked`] way.
+    pub fn from_ranked(sizes: &(impl Ranked + ?Sized)) -> Distribution {
+        Distribution {
+            min: sizes.nth(0),
+            max: sizes.nth(sizes.count() - 1),
+            median: sizes.percentile(50.0),
+            lower_quartile: sizes.percentile(25.0),
+            upper_quartile: sizes.percentile(75.0),
+        }
+    }
+
+    /// The extremes and the quartiles, for `--json`.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "min": self.min,
+            "lower_quartile": self.lower_quartile,
+            "median": self.median,
+            "upper_quartile": self.upper_quartile,
+            "max": self.max,
+        })
+    }
+
+    /// The spread of the middle half of the sizes.
+    pub fn interquartile_range(&self) -> f64 {
+        self.upper_quartile - self.lower_quartile
+    }
+
+    /// Whether all sizes are shown the same, when the statistics and plots
+    /// have nothing to tell apart. Sizes that differ by less than the
+    /// rounding of [`format::human`] count as the same.
+    ///
+    /// ```
+    /// use dudist::distribution::Distribution;
+    ///
+    /// let exactly = Distribution::from_sorted(&[8 << 20, 8 << 20]);
+    /// assert!(exactly.is_degenerate());
+    /// // 8.00 MiB both
+    /// let about = Distribution::from_sorted(&[8 << 20, (8 << 20) + 100]);
+    /// assert!(about.is_degenerate());
+    /// // 100 B and 101 B
+    /// assert!(!Distribution::from_sorted(&[100, 101]).is_degenerate());
+    /// ```
+    pub fn is_degenerate(&self) -> bool {
+        format::human(self.min) == format::human(self.max)
+    }
+}
+
+/// The median absolute deviation of `sizes` (non-empty) from their
+/// `median`, a spread measure that a few huge files can't skew.
+///
+/// ```
+/// use dudist::distribution::median_absolute_deviation;
+///
+/// let sizes: &[u64] = &[1, 1, 2, 2, 4, 6, 9];
+/// // The deviations from 2 are 1, 1, 0, 0, 2, 4 and 7
+/// assert_eq!(median_absolute_deviation(sizes, 2.0), 1.0);
+/// ```
+pub fn median_absolute_deviation(sizes: &(impl Ranked + ?Sized), median: f64) -> f64 {
+    // The median is a whole or half number of bytes, so the doubled
+    // deviations are whole numbers that can be ranked like sizes
+    let doubled_median = (median * 2.0) as u64;
+    let mut deviations = sizes
+        .runs()
+        .map(|(size, files)| (size.saturating_mul(2).abs_diff(doubled_median), files))
+        .collect::<Vec<_>>();
+    deviations.sort_unstable();
+    Runs(&deviations).percentile(50.0) / 2.0
+}
+
+/// Sizes that can be looked up by their rank in ascending order, kept
+/// exactly in a sorted slice or approximately in
+/// [`CompactSizes`](crate::compact::CompactSizes).
+pub trait Ranked {
+    /// The number of sizes
+    fn count(&self) -> usize;
+
+    /// The size at `rank`, counting from the smallest at 0.
+    fn nth(&self, rank: usize) -> u64;
+
+    /// The `p`th percentile (0 to 100) of the sizes, which mustn't be
+    /// empty: the size `p`% of the way up, or halfway between two sizes
+    /// when that falls right between them. The quartiles are the 25th and
+    /// 75th percentiles, so every statistic and plot mark agrees.
+    ///
+    /// ```
+    /// use dudist::distribution::{Distribution, Ranked};
+    ///
+    /// let sizes: &[u64] = &[10, 20, 30, 40, 50, 60];
+    /// assert_eq!(sizes.percentile(25.0), 20.0);
+    /// assert_eq!(sizes.percentile(50.0), 35.0);
+    /// assert_eq!(Distribution::from_sorted(sizes).upper_quartile, sizes.percentile(75.0));
+    /// ```
+    fn percentile(&self, p: f64) -> f64 {
+        let n = self.count();
+        let rank = n as f64 * p.clamp(0.0, 100.0) / 100.0;
+        let below = rank.floor() as usize;
+        match below {
+            0 => self.nth(0) as f64,
+            _ if below >= n => self.nth(n - 1) as f64,
+            // Halfway between the sizes at two ranks, exact below 2^52 bytes
+            _ if rank == below as f64 => {
+                (self.nth(below - 1) as f64 + self.nth(below) as f64) / 2.0
+            }
+            _ => self.nth(below) as f64,
+        }
+    }
+
+    /// The sum of the sizes.
+    fn total(&self) -> u64;
+
+    /// The distinct sizes, ascending, with the number of files of each.
+    fn runs(&self) -> impl Iterator<Item = (u64, u64)> + '_;
+}
+
+/// Sizes sorted ascending.
+impl Ranked for [u64] {
+    fn count(&self) -> usize {
+        self.len()
+    }
+
+    fn nth(&self, rank: usize) -> u64 {
+        self[rank]
+    }
+
+    fn total(&self) -> u64 {
+        self.iter().sum()
+    }
+
+    fn runs(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.chunk_by(|a, b| a == b)
+            .map(|run| (run[0], run.len() as u64))
+    }
+}
+
+/// Runs of sizes with their number of files, sorted ascending.
+struct Runs<'a>(&'a [(u64, u64)]);
+
+impl Ranked for Runs<'_> {
+    fn count(&self) -> usize {
+        self.0.iter().map(|&(_, files)| files as usize).sum()
+    }
+
+    fn nth(&self, rank: usize) -> u64 {
+        let mut below = 0;
+        for &(size, files) in self.0 {
+            below += files as usize;
+            if rank < below {
+                return size;
+            }
+        }
+        self.0[self.0.len() - 1].0
+    }
+
+    fn total(&self) -> u64 {
+        self.0.iter().map(|&(size, files)| size * files).sum()
+    }
+
+    fn runs(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.0.iter().copied()
+    }
+}
+
+/// The `p`th percentile (0 to 100) of `sizes` (sorted ascending, non-empty),
+/// see [`Ranked::percentile`].
+pub fn percentile(sizes: &[u64], p: f64) -> f64 {
+    sizes.percentile(p)
+}
+
+/// The 95% confidence interval of the `p`th percentile (0 to 100) of the
+/// files that `sample` (non-empty) was drawn from at
+/// random, `fraction` being the share of them in the sample.
+///
+/// It makes no assumption about the sizes: the number of sampled files below
+/// the percentile is binomial, so the bounds are the sampled sizes at the
+/// ranks 1.96 standard deviations either side of it, using the normal
+/// approximation and the finite population correction.
+///
+/// ```
+/// use dudist::distribution::percentile_interval;
+///
+/// let sample = (1..=1000).collect::<Vec<u64>>();
+/// // Ranks 500 ∓ 1.96 × √(1000 × 0.5 × 0.5), rounded outwards
+/// assert_eq!(percentile_interval(&sample[..], 50.0, 0.0), (469.0, 531.0));
+/// // Sampling every file leaves no doubt
+/// assert_eq!(percentile_interval(&sample[..], 50.0, 1.0), (500.0, 500.0));
+/// ```
+pub fn percentile_interval(sample: &(impl Ranked + ?Sized), p: f64, fraction: f64) -> (f64, f64) {
+    let n = sample.count() as f64;
+    let q = p.clamp(0.0, 100.0) / 100.0;
+    let spread = 1.96 * (n * q * (1.0 - q) * (1.0 - fraction.clamp(0.0, 1.0))).sqrt();
+    // One-based ranks, as the order statistics are usually numbered
+    let size = |rank: f64| sample.nth((rank.clamp(1.0, n) - 1.0) as usize) as f64;
+    (
+        size((n * q - spread).floor()),
+        size((n * q + spread).ceil()),
+    )
+}
+
+/// Estimates the density of `sizes` over log2(size) at `points` evenly
+/// spaced positions from log2(min) to log2(max).
+///
+/// The sizes are binned into `points` bins which are then smoothed with a
+/// Gaussian kernel whose bandwidth follows Silverman's rule of thumb. The
+/// result sums to 1.
+pub fn log2_density(sizes: &(impl Ranked + ?Sized), points: usize) -> Vec<f64> {
+    let count = sizes.count();
+    if count == 0 || points == 0 {
+        return vec![0.0; points];
+    }
+    let log = |size: u64| (size.max(1) as f64).log2();
+    let (lo, hi) = (log(sizes.nth(0)), log(sizes.nth(count - 1)));
+    if points == 1 || hi <= lo {
+        return vec![1.0 / points as f64; points];
+    }
+    let step = (hi - lo) / (points - 1) as f64;
+
+    let mut bins = vec![0.0; points];
+    for (size, files) in sizes.runs() {
+        bins[((log(size) - lo) / step).round() as usize] += files as f64;
+    }
+
+    let n = count as f64;
+    let mean = sizes
+        .runs()
+        .map(|(size, files)| log(size) * files as f64)
+        .sum::<f64>()
+        / n;
+    let std_dev = (sizes
+        .runs()
+        .map(|(size, files)| (log(size) - mean).powi(2) * files as f64)
+        .sum::<f64>()
+        / n)
+        .sqrt();
+    let iqr = log(sizes.nth(count * 3 / 4)) - log(sizes.nth(count / 4));
+    let spread = if iqr > 0.0 {
+        std_dev.min(iqr / 1.34)
+    } else {
+        std_dev
+    };
+    // Never narrower than a bin, so sparse data still forms a shape
+    let bandwidth = (0.9 * spread * n.powf(-0.2)).max(step);
+
+    let mut density = (0..points)
+        .map(|i| {
+            bins.iter()
+                .enumerate()
+                .filter(|(_, count)| **count > 0.0)
+                .map(|(j, count)| {
+                    let z = (i as f64 - j as f64) * step / bandwidth;
+                    count * (-0.5 * z * z).exp()
+                })
+                .sum::<f64>()
+        })
+        .collect::<Vec<_>>();
+    let total = density.iter().sum::<f64>();
+    density.iter_mut().for_each(|d| *d /= total);
+    density
+}
+
+/// A range of sizes and how many files fall into it.
+#[derive(Debug, Clone, Copy)]
+pub struct Bin {
+    /// Smallest size in the bin
+    pub lower: u64,
+    /// Smallest size above the bin
+    pub upper: u64,
+    pub count: u64,
+}
+
+/// Counts `sizes` (non-empty) in power-of-two bins, from the bin of the
+/// smallest size to the bin of the largest. Empty files get a bin of their
+/// own.
+pub fn log2_histogram(sizes: &(impl Ranked + ?Sized)) -> Vec<Bin> {
+    // Bin 0 holds empty files, bin k + 1 the sizes from 2^k below 2^(k + 1)
+    let bin_of = |size: u64| (u64::BITS - size.leading_zeros()) as usize;
+    let bounds = |bin: usize| match bin {
+        0 => (0, 1),
+        _ => (
+            1 << (bin - 1),
+            1u64.checked_shl(bin as u32).unwrap_or(u64::MAX),
+        ),
+    };
+    let (first, last) = (bin_of(sizes.nth(0)), bin_of(sizes.nth(sizes.count() - 1)));
+    let mut bins = (first..=last)
+        .map(|bin| {
+            let (lower, upper) = bounds(bin);
+            Bin {
+                lower,
+                upper,
+                count: 0,
+            }
+        })
+        .collect::<Vec<_>>();
+    for (size, files) in sizes.runs() {
+        bins[bin_of(size) - first].count += files;
+    }
+    bins
+}
+
+/// The share of files and of bytes below a size.
+#[derive(Debug, Clone, Copy)]
+pub struct Rank {
+    /// Percentage of files smaller than the size
+    pub files: f64,
+    /// Percentage of all bytes in those files
+    pub bytes: f64,
+}
+
+/// Answers rank queries for a set of sizes.
+pub struct RankIndex {
+    /// The distinct sizes, ascending
+    sizes: Vec<u64>,
+    /// Files and total size of the first i distinct sizes at index i
+    cumulative: Vec<(u64, u64)>,
+}
+
+impl RankIndex {
+    /// Indexes `sizes`, which mustn't be empty.
+    pub fn new(sizes: &(impl Ranked + ?Sized)) -> RankIndex {
+        let mut index = RankIndex {
+            sizes: Vec::new(),
+            cumulative: vec![(0, 0)],
+        };
+        for (size, files) in sizes.runs() {
+            let (below, bytes) = index.cumulative[index.sizes.len()];
+            index.sizes.push(size);
+            index.cumulative.push((below + files, bytes + size * files));
+        }
+        index
+    }
+
+    /// Where `size` falls, sizes outside the observed range ranking at 0%
+    /// or 100%.
+    pub fn rank(&self, size: u64) -> Rank {
+        let below = self.sizes.partition_point(|&s| s < size);
+        let (count, total) = self.cumulative[self.sizes.len()];
+        let (files, bytes) = self.cumulative[below];
+        Rank {
+            files: files as f64 / count as f64 * 100.0,
+            bytes: if total > 0 {
+                bytes as f64 / total as f64 * 100.0
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+/// The `k` sizes occurring most often in `sizes` with their number of
+/// files, most frequent first and smaller sizes first on a tie. Sizes
+/// occurring only once are left out.
+pub fn modes(sizes: &(impl Ranked + ?Sized), k: usize) -> Vec<(u64, usize)> {
+    let mut runs = sizes
+        .runs()
+        .filter(|&(_, files)| files > 1)
+        .map(|(size, files)| (size, files as usize))
+        .collect::<Vec<_>>();
+    runs.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    runs.truncate(k);
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_interquartile_range_spans_the_middle_half() {
+        let sizes: &[u64] = &[10, 20, 30, 40, 50, 60];
+        // 50 - 20
+        assert_eq!(Distribution::from_sorted(sizes).interquartile_range(), 30.0);
+        assert_eq!(Distribution::from_sorted(&[7]).interquartile_range(), 0.0);
+    }
+
+    #[test]
+    fn the_median_absolute_deviation_ignores_the_tail() {
+        let sizes: &[u64] = &[1, 2, 3, 4, 100];
+        // Deviations from 3 of 2, 1, 0, 1 and 97
+        assert_eq!(median_absolute_deviation(sizes, 3.0), 1.0);
+        let sizes: &[u64] = &[1, 2, 3, 4, 100_000];
+        assert_eq!(median_absolute_deviation(sizes, 3.0), 1.0);
+    }
+
+    #[test]
+    fn the_median_absolute_deviation_from_a_half_byte_median() {
+        let sizes: &[u64] = &[1, 2, 5, 6];
+        // Deviations from 3.5 of 2.5, 1.5, 1.5 and 2.5
+        assert_eq!(median_absolute_deviation(sizes, 3.5), 2.0);
+        assert_eq!(median_absolute_deviation(&[9, 9, 9][..], 9.0), 0.0);
+    }
+
+    #[test]
+    fn modes_put_the_most_frequent_sizes_first() {
+        let sizes: &[u64] = &[1, 2, 2, 3, 3, 3, 4, 4, 5];
+        assert_eq!(modes(sizes, 1), [(3, 3)]);
+        // 2 and 4 tie, the smaller first
+        assert_eq!(modes(sizes, 5), [(3, 3), (2, 2), (4, 2)]);
+    }
+
+    #[test]
+    fn sizes_seen_once_are_no_modes() {
+        let sizes: &[u64] = &[1, 2, 3];
+        assert!(modes(sizes, 3).is_empty());
+        assert!(modes(&[] as &[u64], 3).is_empty());
+    }
+
+    #[test]
+    fn modes_of_compact_sizes_count_each_bucket() {
+        let mut compact = crate::compact::CompactSizes::default();
+        for size in [100, 100, 100, 7, 7] {
+            compact.add(size);
+        }
+        assert_eq!(modes(&compact, 2), [(100, 3), (7, 2)]);
+    }
+
+    /// Indices higher than both neighbours, the ends having just one
+    fn peaks(density: &[f64]) -> Vec<usize> {
+        (0..density.len())
+            .filter(|&i| {
+                (i == 0 || density[i] > density[i - 1])
+                    && (i + 1 == density.len() || density[i] > density[i + 1])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn log2_density_finds_both_humps_of_bimodal_sizes() {
+        // Around a kilobyte and around a megabyte, ten doublings apart
+        let mut sizes = (0..200)
+            .map(|i| 1000 + i)
+            .chain((0..300).map(|i| 1_000_000 + 100 * i))
+            .collect::<Vec<u64>>();
+        sizes.sort_unstable();
+        let density = log2_density(&sizes[..], 41);
+
+        assert_eq!(density.len(), 41);
+        assert!((density.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        let [low, high] = peaks(&density)[..] else {
+            panic!("expected two peaks in {:?}", density);
+        };
+        assert!(low < 5 && high > 35);
+        // The larger hump holds more files
+        assert!(density[high] > density[low]);
+        // Next to nothing in the gap between them
+        assert!(density[20] < density[low] / 100.0);
+    }
+
+    #[test]
+    fn log2_density_of_one_hump_has_one_peak() {
+        // log2(size) is the sum of two uniform spreads, a triangle from 12 to 20
+        let mut sizes = (0..40)
+            .flat_map(|i| (0..25).map(move |j| i as f64 / 40.0 + j as f64 / 25.0))
+            .map(|spread| 2f64.powf(12.0 + 4.0 * spread) as u64)
+            .collect::<Vec<u64>>();
+        sizes.sort_unstable();
+        let density = log2_density(&sizes[..], 33);
+        let [peak] = peaks(&density)[..] else {
+            panic!("expected one peak in {:?}", density);
+        };
+        assert!((14..=18).contains(&peak));
+    }
+
+    #[test]
+    fn log2_density_without_a_spread_is_flat() {
+        assert_eq!(log2_density(&[] as &[u64], 3), [0.0; 3]);
+        assert_eq!(log2_density(&[5, 5, 5][..], 4), [0.25; 4]);
+        assert!(log2_density(&[1, 2][..], 0).is_empty());
+    }
+}
@@ -0,0 +1,94 @@
+//! Estimates for trees where reading metadata is what takes time, as on
+//! network filesystems: every file is counted from the directory listings,
+//! but only an even spread of them is statted.
+
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+
+use crate::scan::{self, FileRecord, ScanCounts, ScanOptions};
+
+/// What [`estimate`] found.
+#[derive(Debug)]
+pub struct Estimate {
+    /// Every regular file the walk found, counted exactly. The path
+    /// filters apply, like `.dudistignore`, but not the size filters,
+    /// which would need a stat.
+    pub files: u64,
+    /// One file in `stride` was statted
+    pub stride: u64,
+    pub statted: u64,
+    /// Statted files that were gone or couldn't be read
+    pub failed: u64,
+    /// The statted files that passed the size filters
+    pub records: Vec<FileRecord>,
+    pub counts: ScanCounts,
+}
+
+impl Estimate {
+    /// How many files each statted one stands for.
+    pub fn scale(&self) -> f64 {
+        let statted = self.statted - self.failed;
+        if statted == 0 {
+            0.0
+        } else {
+            self.files as f64 / statted as f64
+        }
+    }
+
+    /// The estimated number of files passing the size filters.
+    pub fn matched(&self) -> f64 {
+        self.records.len() as f64 * self.scale()
+    }
+
+    /// The estimated total size of the files passing the size filters.
+    pub fn total(&self) -> f64 {
+        self.records.iter().map(|record| record.size).sum::<u64>() as f64 * self.scale()
+    }
+}
+
+/// Walks `root` like [`scan_iter`](scan::scan_iter), but stats at most
+/// about `stat_budget` files, evenly spread over the walk. The paths of all
+/// files are kept until the walk is over, so the stride can be chosen.
+/// Entries the walk can't read are skipped.
+pub fn estimate(root: &Path, options: &ScanOptions, stat_budget: u64) -> Estimate {
+    let mut scan = scan::scan_iter(root, options);
+    let paths = std::iter::from_fn(|| scan.next_path())
+        .flatten()
+        .collect::<Vec<PathBuf>>();
+    let stride = (paths.len() as u64).div_ceil(stat_budget.max(1)).max(1);
+    // Starting halfway into the first stride centers the samples
+    let sample = paths
+        .iter()
+        .skip(stride as usize / 2)
+        .step_by(stride as usize)
+        .collect::<Vec<_>>();
+    let attempts = sample
+        .par_iter()
+        .map(|path| (path, options.stat(path)))
+        .collect::<Vec<_>>();
+    let mut counts = scan.counts;
+    counts.recovered += attempts
+        .iter()
+        .filter(|(_, attempts)| attempts.recovered())
+        .count() as u64;
+    let statted = attempts
+        .into_iter()
+        .map(|(path, attempts)| attempts.result.ok().map(|metadata| (path, metadata)))
+        .collect::<Vec<_>>();
+    let failed = statted.iter().filter(|found| found.is_none()).count() as u64;
+    let records = statted
+        .into_iter()
+        .flatten()
+        .filter(|(_, metadata)| options.matches(metadata.len()))
+        .map(|(path, metadata)| FileRecord::from_metadata(path.to_path_buf(), &metadata))
+        .collect();
+    Estimate {
+        files: paths.len() as u64,
+        stride,
+        statted: sample.len() as u64,
+        failed,
+        records,
+        counts,
+    }
+}
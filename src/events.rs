@@ -0,0 +1,403 @@
+//! Newline-delimited JSON events about a scan, for programs wrapping dudist
+//! that would otherwise have to scrape the report.
+//!
+//! Every event is an object with the schema `version` and the `event` name:
+//! `scan_started` with the `roots`, `progress` every half second or so with
+//! the `entries` walked, the `files` and `bytes` matched so far and the
+//! `current_dir`, one `warning` per category with its `count` and
+//! `examples`, and `scan_finished` with the totals. Paths are strings, or
+//! `{"bytes": [...]}` where they aren't valid UTF-8. Warnings that only come
+//! up once the scan is over, like unreadable `--history` lines, are left to
+//! the report.
+//!
+//! They are written on a thread of their own, which takes the progress
+//! from counters the scan bumps as it goes. A slow reader holds up only
+//! that thread, and the progress events it had no time for are dropped
+//! rather than holding up the scan.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::format;
+use crate::scan::{FileRecord, Progress, ScanCounts};
+use crate::warnings::Warnings;
+
+/// Version of the event schema, raised when fields change meaning or go
+/// away
+pub const SCHEMA_VERSION: u64 = 1;
+
+/// How often a progress event is sent
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Events other than progress waiting for the writer
+const QUEUE_CAPACITY: usize = 64;
+
+/// Where `--progress-json` writes the events.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// An inherited file descriptor, given as a number
+    Fd(i32),
+    /// A file, created or truncated
+    File(PathBuf),
+}
+
+impl Target {
+    /// Takes a number for a file descriptor and anything else for a file,
+    /// so a file named like a number needs a `./` in front.
+    ///
+    /// ```
+    /// use std::path::PathBuf;
+    /// use dudist::events::Target;
+    ///
+    /// assert_eq!(Target::parse(PathBuf::from("3")), Target::Fd(3));
+    /// assert_eq!(
+    ///     Target::parse(PathBuf::from("./3")),
+    ///     Target::File(PathBuf::from("./3"))
+    /// );
+    /// ```
+    pub fn parse(value: PathBuf) -> Target {
+        match value.to_str().map(str::parse) {
+            Some(Ok(fd)) => Target::Fd(fd),
+            _ => Target::File(value),
+        }
+    }
+}
+
+/// What the scan counted so far, read by the writer for progress events.
+#[derive(Debug, Default)]
+struct Counters {
+    files: AtomicU64,
+    bytes: AtomicU64,
+    /// Progress events the writer had no time for
+    dropped: AtomicU64,
+}
+
+/// Sends the events of a scan to a writer thread.
+pub struct Events {
+    sender: SyncSender<Value>,
+    writer: JoinHandle<io::Result<()>>,
+    started: Instant,
+    counters: Arc<Counters>,
+    progress: Progress,
+}
+
+impl Events {
+    /// Opens `target` for the events.
+    pub fn open(target: &Target) -> Result<Events, String> {
+        let file = match target {
+            Target::Fd(fd) => open_fd(*fd)?,
+            Target::File(path) => {
+                File::create(path).map_err(|e| format!("can't create {}: {}", path.display(), e))?
+            }
+        };
+        Ok(Events::new(file))
+    }
+
+    /// Writes the events to `out`.
+    pub fn new(out: impl Write + Send + 'static) -> Events {
+        let (sender, receiver) = mpsc::sync_channel::<Value>(QUEUE_CAPACITY);
+        let counters = Arc::new(Counters::default());
+        let progress = Progress::default();
+        let writer = {
+            let (counters, progress) = (Arc::clone(&counters), progress.clone());
+            thread::spawn(move || {
+                let mut out = BufWriter::new(out);
+                let mut result = Ok(());
+                // Progress is only told between scan_started and the end
+                let mut started = None;
+                let mut last_progress = Instant::now();
+                // Drained to the end even after a failed write, so sending
+                // never blocks on a reader that went away
+                loop {
+                    let wait = PROGRESS_INTERVAL.saturating_sub(last_progress.elapsed());
+                    let event = match receiver.recv_timeout(wait) {
+                        Ok(event) => Some(event),
+                        Err(RecvTimeoutError::Timeout) => None,
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    };
+                    if let Some(event) = event {
+                        if event["event"] == "scan_started" {
+                            started = Some(Instant::now());
+                            last_progress = Instant::now();
+                        }
+                        if result.is_ok() {
+                            result = writeln!(out, "{}", event).and_then(|_| out.flush());
+                        }
+                    }
+                    let since = last_progress.elapsed();
+                    let Some(started) = started.filter(|_| since >= PROGRESS_INTERVAL) else {
+                        continue;
+                    };
+                    // Intervals that passed while a write held the thread
+                    let missed = since.as_millis() / PROGRESS_INTERVAL.as_millis() - 1;
+                    counters.dropped.fetch_add(missed as u64, Ordering::Relaxed);
+                    last_progress = Instant::now();
+                    let event = progress_event(&counters, &progress, started);
+                    if result.is_ok() {
+                        result = writeln!(out, "{}", event).and_then(|_| out.flush());
+                    }
+                }
+                result
+            })
+        };
+        Events {
+            sender,
+            writer,
+            started: Instant::now(),
+            counters,
+            progress,
+        }
+    }
+
+    fn send(&self, name: &str, fields: Value) {
+        // Only fails once the writer is gone, which it isn't before finish
+        let _ = self.sender.send(event(name, fields));
+    }
+
+    /// What the walks report their entries and directories to, see
+    /// [`Scan::report_progress`](crate::scan::Scan::report_progress).
+    pub fn progress(&self) -> &Progress {
+        &self.progress
+    }
+
+    pub fn scan_started(&mut self, roots: &[PathBuf]) {
+        self.started = Instant::now();
+        let roots = roots
+            .iter()
+            .map(|root| format::path_to_json(root))
+            .collect::<Vec<_>>();
+        self.send("scan_started", json!({ "roots": roots }));
+    }
+
+    /// Counts a file that matched, for the next progress event.
+    pub fn file(&self, record: &FileRecord) {
+        self.counters.files.fetch_add(1, Ordering::Relaxed);
+        self.counters
+            .bytes
+            .fetch_add(record.size, Ordering::Relaxed);
+    }
+
+    /// Sends a `warning` event for each category of `warnings`.
+    pub fn warnings(&self, warnings: &Warnings) {
+        for tally in warnings.tallies() {
+            self.send("warning", tally.to_json());
+        }
+    }
+
+    /// Sends `scan_finished`, with the number of empty directories when
+    /// they were looked for, and waits for the writer to write everything.
+    pub fn finish(
+        self,
+        counts: &ScanCounts,
+        empty_dirs: Option<u64>,
+        interrupted: bool,
+    ) -> Result<(), String> {
+        let mut finished = json!({
+            "files": self.counters.files.load(Ordering::Relaxed),
+            "bytes": self.counters.bytes.load(Ordering::Relaxed),
+            "entries": counts.entries,
+            "elapsed_ms": self.started.elapsed().as_millis() as u64,
+            "interrupted": interrupted,
+        });
+        if let Some(empty_dirs) = empty_dirs {
+            finished["empty_dirs"] = json!(empty_dirs);
+        }
+        // Counted by the writer up to the last progress event before this
+        finished["dropped_progress"] = json!(self.counters.dropped.load(Ordering::Relaxed));
+        self.send("scan_finished", finished);
+        drop(self.sender);
+        match self.writer.join() {
+            Ok(result) => result.map_err(|e| format!("can't write progress events: {}", e)),
+            Err(_) => Err("the progress event writer failed".to_string()),
+        }
+    }
+}
+
+/// A `progress` event with the counts so far, for a scan started at
+/// `started`.
+fn progress_event(counters: &Counters, progress: &Progress, started: Instant) -> Value {
+    event(
+        "progress",
+        json!({
+            "entries": progress.entries(),
+            "files": counters.files.load(Ordering::Relaxed),
+            "bytes": counters.bytes.load(Ordering::Relaxed),
+            "current_dir": progress.current_dir().as_deref().map(format::path_to_json),
+            "elapsed_ms": started.elapsed().as_millis() as u64,
+        }),
+    )
+}
+
+/// Adds the schema version and the event name to `fields`.
+fn event(name: &str, mut fields: Value) -> Value {
+    fields["version"] = json!(SCHEMA_VERSION);
+    fields["event"] = json!(name);
+    fields
+}
+
+#[cfg(unix)]
+fn open_fd(fd: i32) -> Result<File, String> {
+    use std::os::unix::io::FromRawFd;
+
+    // Written through a duplicate, so closing it at the end leaves the
+    // descriptor itself open, which matters for stdout and stderr
+    let duplicate = match fd {
+        0.. => unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 3) },
+        _ => -1,
+    };
+    if duplicate == -1 {
+        return Err(format!("file descriptor {} isn't open", fd));
+    }
+    Ok(unsafe { File::from_raw_fd(duplicate) })
+}
+
+#[cfg(not(unix))]
+fn open_fd(fd: i32) -> Result<File, String> {
+    Err(format!(
+        "can't write to file descriptor {} on this platform, give a file",
+        fd
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufRead;
+    use std::path::Path;
+
+    use super::*;
+    use crate::scan::{scan_iter, ScanOptions};
+    use crate::warnings::{Category, Warnings};
+
+    fn read_events(path: &Path) -> Vec<Value> {
+        std::fs::read(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+            .collect()
+    }
+
+    /// Scans `dir` with `options`, sending the events to `out` and waiting
+    /// `pause` before the end of the scan.
+    fn scan_with_events(dir: &Path, options: &ScanOptions, out: &Path, pause: Duration) {
+        let mut events = Events::new(File::create(out).unwrap());
+        events.scan_started(&[dir.to_path_buf()]);
+        let mut scan = scan_iter(dir, options).report_progress(events.progress());
+        for record in scan.by_ref().flatten() {
+            events.file(&record);
+        }
+        thread::sleep(pause);
+        let warnings = Warnings::new(None);
+        warnings.add(
+            Category::Unreadable,
+            Some(&dir.join("c")),
+            "Permission denied",
+        );
+        events.warnings(&warnings);
+        events.finish(&scan.counts, None, false).unwrap();
+    }
+
+    #[test]
+    fn events_come_in_order_with_the_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("a"), [0; 1000]).unwrap();
+        std::fs::write(dir.path().join("sub/b"), [0; 500]).unwrap();
+        // Outside the tree, or the scan would count it
+        let out = dir.path().with_extension("jsonl");
+        let options = ScanOptions::builder().min_size(0).build().unwrap();
+        scan_with_events(dir.path(), &options, &out, Duration::ZERO);
+
+        let events = read_events(&out);
+        std::fs::remove_file(&out).unwrap();
+        assert!(events
+            .iter()
+            .all(|event| event["version"] == SCHEMA_VERSION));
+        let names = events
+            .iter()
+            .map(|event| event["event"].as_str().unwrap())
+            .filter(|&name| name != "progress")
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["scan_started", "warning", "scan_finished"]);
+        assert_eq!(events[0]["roots"][0], dir.path().to_str().unwrap());
+        let warning = &events[events.len() - 2];
+        assert_eq!(warning["category"], "unreadable");
+        assert_eq!(warning["count"], 1);
+        assert_eq!(warning["examples"][0]["message"], "Permission denied");
+        let finished = &events[events.len() - 1];
+        assert_eq!(finished["bytes"], 1500);
+        assert_eq!(finished["interrupted"], false);
+        assert!(finished["elapsed_ms"].is_u64());
+        assert!(finished["dropped_progress"].is_u64());
+    }
+
+    #[test]
+    fn progress_counts_entries_that_dont_match() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("sub/small"), "x").unwrap();
+        let out = dir.path().with_extension("jsonl");
+        let options = ScanOptions::builder().min_size(1 << 20).build().unwrap();
+        scan_with_events(dir.path(), &options, &out, PROGRESS_INTERVAL * 2);
+
+        let events = read_events(&out);
+        std::fs::remove_file(&out).unwrap();
+        let progress = events
+            .iter()
+            .find(|event| event["event"] == "progress")
+            .expect("a progress event while the scan ran");
+        assert_eq!(progress["entries"], 3);
+        assert_eq!(progress["files"], 0);
+        assert_eq!(
+            progress["current_dir"],
+            dir.path().join("sub").to_str().unwrap()
+        );
+        // Every progress event comes between the start and the end
+        assert_eq!(events[0]["event"], "scan_started");
+        assert_eq!(events[events.len() - 1]["event"], "scan_finished");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn paths_that_arent_utf8_keep_their_bytes() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join(std::ffi::OsStr::from_bytes(b"caf\xe9"));
+        std::fs::create_dir(&root).unwrap();
+        let out = dir.path().join("events.jsonl");
+        let options = ScanOptions::builder().build().unwrap();
+        scan_with_events(&root, &options, &out, Duration::ZERO);
+
+        let events = read_events(&out);
+        assert_eq!(
+            format::path_from_json(&events[0]["roots"][0]).unwrap(),
+            root
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn writing_to_a_descriptor_leaves_it_open() {
+        use std::os::unix::io::AsRawFd;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        let mut file = File::create(&path).unwrap();
+        let events = Events::open(&Target::Fd(file.as_raw_fd())).unwrap();
+        events.finish(&ScanCounts::default(), None, false).unwrap();
+        // The descriptor the events went to still takes writes
+        writeln!(file, "after").unwrap();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(written.contains("scan_finished"));
+        assert!(written.ends_with("after\n"));
+    }
+}
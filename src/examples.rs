@@ -0,0 +1,128 @@
+//! Example files for the statistics, like a file of about the median size.
+//!
+//! Which sizes are interesting is only known once the walk is over, and
+//! keeping every path until then would take a lot of memory on large
+//! trees. Instead sizes are split into narrow bands, 32 for every doubling,
+//! and the band only keeps its smallest and largest file. The example for a
+//! statistic is the closest of those, so it may be off from the closest
+//! file by up to a band, about 2% of the size.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Bands for every doubling of the size
+const BANDS_PER_DOUBLING: f64 = 32.0;
+
+/// A file of some size.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Example {
+    pub size: u64,
+    pub path: PathBuf,
+}
+
+/// Collects example candidates, see the [module](self) for how.
+#[derive(Debug, Default)]
+pub struct Examples {
+    /// The smallest and largest file of each band, ties going to the
+    /// smaller path
+    bands: BTreeMap<u32, (Example, Example)>,
+}
+
+fn band(size: u64) -> u32 {
+    match size {
+        0 => 0,
+        _ => ((size as f64).log2() * BANDS_PER_DOUBLING) as u32 + 1,
+    }
+}
+
+impl Examples {
+    pub fn add(&mut self, size: u64, path: &Path) {
+        let example = || Example {
+            size,
+            path: path.to_path_buf(),
+        };
+        match self.bands.get_mut(&band(size)) {
+            None => {
+                self.bands.insert(band(size), (example(), example()));
+            }
+            Some((smallest, largest)) => {
+                if (size, path) < (smallest.size, smallest.path.as_path()) {
+                    *smallest = example();
+                }
+                // Equal sizes keep the smaller path, as for the smallest
+                if size > largest.size || (size == largest.size && path < largest.path.as_path()) {
+                    *largest = example();
+                }
+            }
+        }
+    }
+
+    /// The candidate closest to `target` bytes, smaller sizes and then
+    /// smaller paths winning ties.
+    pub fn closest(&self, target: f64) -> Option<&Example> {
+        let distance = |example: &Example| (example.size as f64 - target).abs();
+        self.bands
+            .values()
+            .flat_map(|(smallest, largest)| [smallest, largest])
+            .min_by(|a, b| distance(a).total_cmp(&distance(b)).then_with(|| a.cmp(b)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn examples(files: &[(u64, &str)]) -> Examples {
+        let mut examples = Examples::default();
+        for &(size, path) in files {
+            examples.add(size, Path::new(path));
+        }
+        examples
+    }
+
+    fn closest(examples: &Examples, target: f64) -> (u64, &str) {
+        let example = examples.closest(target).unwrap();
+        (example.size, example.path.to_str().unwrap())
+    }
+
+    #[test]
+    fn the_closest_file_is_the_example() {
+        let examples = examples(&[(0, "empty"), (1000, "a"), (5000, "b"), (1 << 30, "c")]);
+        assert_eq!(closest(&examples, 0.0), (0, "empty"));
+        assert_eq!(closest(&examples, 2000.0), (1000, "a"));
+        assert_eq!(closest(&examples, 4000.0), (5000, "b"));
+        assert_eq!(closest(&examples, 1e12), (1 << 30, "c"));
+    }
+
+    #[test]
+    fn ties_go_to_the_smaller_size_then_the_smaller_path() {
+        let around = examples(&[(100, "z"), (300, "y")]);
+        assert_eq!(closest(&around, 200.0), (100, "z"));
+        // The order files are found in doesn't matter
+        let one = examples(&[(100, "b"), (100, "a"), (100, "c")]);
+        let other = examples(&[(100, "c"), (100, "a"), (100, "b")]);
+        assert_eq!(closest(&one, 100.0), (100, "a"));
+        assert_eq!(closest(&other, 100.0), (100, "a"));
+    }
+
+    #[test]
+    fn a_band_keeps_only_its_smallest_and_largest_file() {
+        // 32 bands per doubling, so these share one
+        let examples = examples(&[
+            (1 << 20, "low"),
+            ((1 << 20) + 10, "mid"),
+            ((1 << 20) + 20, "high"),
+        ]);
+        assert_eq!(examples.bands.len(), 1);
+        assert_eq!(
+            closest(&examples, (1 << 20) as f64 + 10.0),
+            (1 << 20, "low")
+        );
+        assert_eq!(closest(&examples, 2e6), ((1 << 20) + 20, "high"));
+    }
+
+    #[test]
+    fn nothing_added_has_no_example() {
+        assert!(Examples::default().closest(100.0).is_none());
+    }
+}
@@ -0,0 +1,619 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde_json::json;
+
+use crate::distribution::{self, Distribution, Ranked};
+
+/// Tools the data can be exported for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Data files and a script in a directory
+    Gnuplot,
+    /// A single Vega-Lite spec with the data inline
+    Vega,
+    /// Gauges in the Prometheus text format, e.g. for node_exporter's
+    /// textfile collector
+    Prometheus,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gnuplot" => Ok(ExportFormat::Gnuplot),
+            "vega" => Ok(ExportFormat::Vega),
+            "prometheus" => Ok(ExportFormat::Prometheus),
+            _ => Err(format!(
+                "invalid export format '{}' (expected gnuplot, vega or prometheus)",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Export {
+    pub format: ExportFormat,
+    /// Directory for gnuplot, file for Vega-Lite and Prometheus
+    pub path: PathBuf,
+    /// Whether to include every size, not just the summary and histogram
+    pub raw: bool,
+}
+
+/// Writes the summary, histogram and optionally the sizes (ascending) of
+/// the scan of `root` for plotting elsewhere. All numbers are in bytes.
+pub fn write(
+    export: &Export,
+    root: &Path,
+    dist: &Distribution,
+    sizes: &(impl Ranked + ?Sized),
+) -> Result<(), String> {
+    match export.format {
+        ExportFormat::Gnuplot => write_gnuplot(&export.path, dist, sizes, export.raw),
+        ExportFormat::Vega => write_vega(&export.path, dist, sizes, export.raw),
+        ExportFormat::Prometheus => write_prometheus(&export.path, root, sizes),
+    }
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<(), String> {
+    fs::write(path, contents).map_err(|e| format!("failed to write {}: {}", path.display(), e))
+}
+
+const GNUPLOT_SCRIPT: &str = "\
+# Generated by dudist, run with `gnuplot dudist.gp` from this directory
+set terminal pngcairo size 1200,450
+set output 'dudist.png'
+set multiplot layout 1,2
+
+set title 'Five-number summary'
+set logscale y 2
+set ylabel 'bytes'
+set xrange [0:2]
+unset xtics
+set boxwidth 0.5
+# Columns of summary.dat: min, lower quartile, median, upper quartile, max
+plot 'summary.dat' using (1):2:1:5:4 with candlesticks whiskerbars notitle, \\
+     '' using (1):3:3:3:3 with candlesticks lt -1 notitle
+
+set title 'Files per size'
+unset xrange
+set xtics
+set logscale x 2
+unset logscale y
+set xlabel 'bytes'
+set ylabel 'files'
+set style fill solid 0.5
+# Columns of histogram.dat: lower bound, upper bound, count
+plot 'histogram.dat' using (sqrt($1 * $2)):($3 / 2):1:2:(0):3 with boxxyerror notitle
+
+unset multiplot
+";
+
+fn write_gnuplot(
+    dir: &Path,
+    dist: &Distribution,
+    sizes: &(impl Ranked + ?Sized),
+    raw: bool,
+) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+    write_file(
+        &dir.join("summary.dat"),
+        &format!(
+            "# min lower_quartile median upper_quartile max\n{} {} {} {} {}\n",
+            dist.min, dist.lower_quartile, dist.median, dist.upper_quartile, dist.max
+        ),
+    )?;
+    let mut histogram = "# lower upper count\n".to_string();
+    for bin in distribution::log2_histogram(sizes) {
+        // An empty file bin starting at 0 can't be drawn on a log axis
+        if bin.lower > 0 {
+            histogram.push_str(&format!("{} {} {}\n", bin.lower, bin.upper, bin.count));
+        }
+    }
+    write_file(&dir.join("histogram.dat"), &histogram)?;
+    if raw {
+        let mut contents = "# size\n".to_string();
+        for (size, files) in sizes.runs() {
+            contents.push_str(&format!("{}\n", size).repeat(files as usize));
+        }
+        write_file(&dir.join("sizes.dat"), &contents)?;
+    }
+    write_file(&dir.join("dudist.gp"), GNUPLOT_SCRIPT)
+}
+
+fn write_vega(
+    path: &Path,
+    dist: &Distribution,
+    sizes: &(impl Ranked + ?Sized),
+    raw: bool,
+) -> Result<(), String> {
+    let summary = json!({
+        "min": dist.min,
+        "lower_quartile": dist.lower_quartile,
+        "median": dist.median,
+        "upper_quartile": dist.upper_quartile,
+        "max": dist.max,
+    });
+    let histogram = distribution::log2_histogram(sizes)
+        .into_iter()
+        .filter(|bin| bin.lower > 0)
+        .map(|bin| json!({ "lower": bin.lower, "upper": bin.upper, "count": bin.count }))
+        .collect::<Vec<_>>();
+    let size_scale = json!({ "type": "log", "base": 2 });
+    let mut spec = json!({
+        "$schema": "https://vega.github.io/schema/vega-lite/v5.json",
+        "description": "File size distribution exported by dudist",
+        "vconcat": [
+            {
+                "title": "Five-number summary",
+                "data": { "values": [summary] },
+                "width": 600,
+                "layer": [
+                    {
+                        "mark": "rule",
+                        "encoding": {
+                            "x": {
+                                "field": "min",
+                                "type": "quantitative",
+                                "scale": size_scale,
+                                "title": "bytes",
+                            },
+                            "x2": { "field": "max" },
+                        },
+                    },
+                    {
+                        "mark": { "type": "bar", "size": 20 },
+                        "encoding": {
+                            "x": { "field": "lower_quartile", "type": "quantitative" },
+                            "x2": { "field": "upper_quartile" },
+                        },
+                    },
+                    {
+                        "mark": { "type": "tick", "color": "black", "size": 20 },
+                        "encoding": { "x": { "field": "median", "type": "quantitative" } },
+                    },
+                ],
+            },
+            {
+                "title": "Files per size",
+                "data": { "values": histogram },
+                "width": 600,
+                "mark": "bar",
+                "encoding": {
+                    "x": {
+                        "field": "lower",
+                        "type": "quantitative",
+                        "scale": size_scale,
+                        "title": "bytes",
+                    },
+                    "x2": { "field": "upper" },
+                    "y": { "field": "count", "type": "quantitative", "title": "files" },
+                },
+            },
+        ],
+    });
+    if raw {
+        // Not drawn, but kept with the spec for further transforms
+        let sizes = sizes
+            .runs()
+            .flat_map(|(size, files)| std::iter::repeat_n(size, files as usize))
+            .collect::<Vec<_>>();
+        spec["datasets"] = json!({ "sizes": sizes });
+    }
+    let contents = serde_json::to_string_pretty(&spec).expect("JSON values always serialize");
+    write_file(path, &(contents + "\n"))
+}
+
+/// Quantiles exported as `dudist_size_bytes`, with the label values they get
+const PROMETHEUS_QUANTILES: [(f64, &str); 7] = [
+    (0.0, "0"),
+    (25.0, "0.25"),
+    (50.0, "0.5"),
+    (75.0, "0.75"),
+    (95.0, "0.95"),
+    (99.0, "0.99"),
+    (100.0, "1"),
+];
+
+/// Escapes a label value as the exposition format requires.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// The metrics are a stable interface: names, labels and quantiles must
+/// not change.
+fn write_prometheus(
+    path: &Path,
+    root: &Path,
+    sizes: &(impl Ranked + ?Sized),
+) -> Result<(), String> {
+    let root = format!("root=\"{}\"", escape_label(&root.display().to_string()));
+    let mut contents = String::new();
+    let mut gauge = |name: &str, help: &str, samples: &[(String, f64)]| {
+        contents.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} gauge\n",
+            name, help, name
+        ));
+        for (labels, value) in samples {
+            contents.push_str(&format!("{}{{{}}} {}\n", name, labels, value));
+        }
+    };
+    gauge(
+        "dudist_files_total",
+        "Number of files matching the filters.",
+        &[(root.clone(), sizes.count() as f64)],
+    );
+    gauge(
+        "dudist_bytes_total",
+        "Total size of the matching files in bytes.",
+        &[(root.clone(), sizes.total() as f64)],
+    );
+    let quantiles = PROMETHEUS_QUANTILES
+        .iter()
+        .map(|&(p, label)| {
+            (
+                format!("{},quantile=\"{}\"", root, label),
+                sizes.percentile(p),
+            )
+        })
+        .collect::<Vec<_>>();
+    gauge(
+        "dudist_size_bytes",
+        "Quantiles of the sizes of the matching files in bytes.",
+        &quantiles,
+    );
+    // The textfile collector only reads *.prom files, so it never sees the
+    // file half written
+    let partial = path.with_extension("prom.partial");
+    write_file(&partial, &contents)?;
+    fs::rename(&partial, path).map_err(|e| format!("failed to write {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Files of 600 B, twice 1000 B, 3000 B and 5000 B, in three bins.
+    const SIZES: &[u64] = &[600, 1000, 1000, 3000, 5000];
+
+    fn read(path: &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn gnuplot_gets_raw_bytes_in_its_data_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("plots");
+        write_gnuplot(&out, &Distribution::from_sorted(SIZES), SIZES, true).unwrap();
+
+        assert_eq!(
+            read(&out.join("summary.dat")),
+            "# min lower_quartile median upper_quartile max\n600 1000 1000 3000 5000\n"
+        );
+        assert_eq!(
+            read(&out.join("histogram.dat")),
+            "# lower upper count\n512 1024 3\n1024 2048 0\n2048 4096 1\n4096 8192 1\n"
+        );
+        assert_eq!(
+            read(&out.join("sizes.dat")),
+            "# size\n600\n1000\n1000\n3000\n5000\n"
+        );
+        assert_eq!(read(&out.join("dudist.gp")), GNUPLOT_SCRIPT);
+    }
+
+    #[test]
+    fn the_gnuplot_script_reads_the_files_written_beside_it() {
+        let dir = tempfile::tempdir().unwrap();
+        write_gnuplot(dir.path(), &Distribution::from_sorted(SIZES), SIZES, false).unwrap();
+        for name in ["summary.dat", "histogram.dat"] {
+            assert!(GNUPLOT_SCRIPT.contains(&format!("'{}'", name)));
+            assert!(dir.path().join(name).exists());
+        }
+        assert!(!dir.path().join("sizes.dat").exists());
+    }
+
+    #[test]
+    fn empty_files_get_no_bin_on_the_log_axis() {
+        let sizes: &[u64] = &[0, 0, 3];
+        let dir = tempfile::tempdir().unwrap();
+        write_gnuplot(dir.path(), &Distribution::from_sorted(sizes), sizes, false).unwrap();
+        assert_eq!(
+            read(&dir.path().join("histogram.dat")),
+            "# lower upper count\n1 2 0\n2 4 1\n"
+        );
+    }
+
+    #[test]
+    fn the_vega_spec_draws_the_summary_and_histogram() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dudist.vl.json");
+        write_vega(&path, &Distribution::from_sorted(SIZES), SIZES, false).unwrap();
+        assert_eq!(
+            read(&path),
+            r#"{
+  "$schema": "https://vega.github.io/schema/vega-lite/v5.json",
+  "description": "File size distribution exported by dudist",
+  "vconcat": [
+    {
+      "data": {
+        "values": [
+          {
+            "lower_quartile": 1000.0,
+            "max": 5000,
+            "median": 1000.0,
+            "min": 600,
+            "upper_quartile": 3000.0
+          }
+        ]
+      },
+      "layer": [
+        {
+          "encoding": {
+            "x": {
+              "field": "min",
+              "scale": {
+                "base": 2,
+                "type": "log"
+              },
+              "title": "bytes",
+              "type": "quantitative"
+            },
+            "x2": {
+              "field": "max"
+            }
+          },
+          "mark": "rule"
+        },
+        {
+          "encoding": {
+            "x": {
+              "field": "lower_quartile",
+              "type": "quantitative"
+            },
+            "x2": {
+              "field": "upper_quartile"
+            }
+          },
+          "mark": {
+            "size": 20,
+            "type": "bar"
+          }
+        },
+        {
+          "encoding": {
+            "x": {
+              "field": "median",
+              "type": "quantitative"
+            }
+          },
+          "mark": {
+            "color": "black",
+            "size": 20,
+            "type": "tick"
+          }
+        }
+      ],
+      "title": "Five-number summary",
+      "width": 600
+    },
+    {
+      "data": {
+        "values": [
+          {
+            "count": 3,
+            "lower": 512,
+            "upper": 1024
+          },
+          {
+            "count": 0,
+            "lower": 1024,
+            "upper": 2048
+          },
+          {
+            "count": 1,
+            "lower": 2048,
+            "upper": 4096
+          },
+          {
+            "count": 1,
+            "lower": 4096,
+            "upper": 8192
+          }
+        ]
+      },
+      "encoding": {
+        "x": {
+          "field": "lower",
+          "scale": {
+            "base": 2,
+            "type": "log"
+          },
+          "title": "bytes",
+          "type": "quantitative"
+        },
+        "x2": {
+          "field": "upper"
+        },
+        "y": {
+          "field": "count",
+          "title": "files",
+          "type": "quantitative"
+        }
+      },
+      "mark": "bar",
+      "title": "Files per size",
+      "width": 600
+    }
+  ]
+}
+"#
+        );
+    }
+
+    #[test]
+    fn the_raw_vega_sizes_are_a_named_dataset() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dudist.vl.json");
+        write_vega(&path, &Distribution::from_sorted(SIZES), SIZES, true).unwrap();
+        let spec: serde_json::Value = serde_json::from_str(&read(&path)).unwrap();
+        assert_eq!(spec["datasets"]["sizes"], json!(SIZES));
+    }
+
+    type Labels = Vec<(String, String)>;
+
+    /// A sample of an exposition: metric name, labels and value.
+    type Sample = (String, Labels, f64);
+
+    fn is_name(name: &str, colons: bool) -> bool {
+        let mut chars = name.chars();
+        chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || (colons && c == ':'))
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || (colons && c == ':'))
+    }
+
+    /// Parses the labels between the braces of a sample, unescaping their
+    /// values, up to the closing brace. Returns them with the rest of the
+    /// line.
+    fn parse_labels(mut rest: &str) -> Result<(Labels, &str), String> {
+        let mut labels = Vec::new();
+        loop {
+            if let Some(after) = rest.strip_prefix('}') {
+                return Ok((labels, after));
+            }
+            let (name, after) = rest.split_once("=\"").ok_or("label without a value")?;
+            if !is_name(name, false) || name.starts_with("__") {
+                return Err(format!("invalid label name '{}'", name));
+            }
+            let mut value = String::new();
+            let mut chars = after.char_indices();
+            rest = loop {
+                match chars.next().ok_or("unterminated label value")? {
+                    (end, '"') => break &after[end + 1..],
+                    (_, '\\') => value.push(match chars.next() {
+                        Some((_, '\\')) => '\\',
+                        Some((_, '"')) => '"',
+                        Some((_, 'n')) => '\n',
+                        _ => return Err("invalid escape in a label value".to_string()),
+                    }),
+                    (_, '\n') => return Err("raw newline in a label value".to_string()),
+                    (_, c) => value.push(c),
+                }
+            };
+            if labels.iter().any(|(other, _)| other == name) {
+                return Err(format!("label '{}' given twice", name));
+            }
+            labels.push((name.to_string(), value));
+            rest = rest.strip_prefix(',').unwrap_or(rest);
+        }
+    }
+
+    /// Checks `text` against the rules of the Prometheus text exposition
+    /// format that matter here, returning its samples.
+    fn check_exposition(text: &str) -> Result<Vec<Sample>, String> {
+        if !text.ends_with('\n') {
+            return Err("no newline at the end".to_string());
+        }
+        let (mut helped, mut typed) = (Vec::new(), Vec::new());
+        let mut samples: Vec<Sample> = Vec::new();
+        for line in text.lines() {
+            if let Some(comment) = line.strip_prefix("# ") {
+                let (keyword, rest) = comment.split_once(' ').ok_or("empty comment")?;
+                let (name, rest) = rest.split_once(' ').ok_or("comment without text")?;
+                let seen = match keyword {
+                    "HELP" => &mut helped,
+                    "TYPE"
+                        if ["counter", "gauge", "summary", "histogram", "untyped"]
+                            .contains(&rest) =>
+                    {
+                        &mut typed
+                    }
+                    _ => return Err(format!("invalid comment '{}'", line)),
+                };
+                if seen.contains(&name) || samples.iter().any(|sample| sample.0 == name) {
+                    return Err(format!(
+                        "{} of {} repeated or after its samples",
+                        keyword, name
+                    ));
+                }
+                seen.push(name);
+                continue;
+            }
+            let end = line.find(['{', ' ']).ok_or("sample without a value")?;
+            let name = &line[..end];
+            if !is_name(name, true) || !typed.contains(&name) {
+                return Err(format!("invalid or untyped metric '{}'", name));
+            }
+            let (labels, rest) = match line[end..].strip_prefix('{') {
+                Some(rest) => parse_labels(rest)?,
+                None => (Vec::new(), &line[end..]),
+            };
+            let value = rest
+                .strip_prefix(' ')
+                .and_then(|value| value.parse::<f64>().ok())
+                .ok_or_else(|| format!("invalid value in '{}'", line))?;
+            if samples
+                .iter()
+                .any(|sample| sample.0 == name && sample.1 == labels)
+            {
+                return Err(format!("repeated series in '{}'", line));
+            }
+            samples.push((name.to_string(), labels, value));
+        }
+        Ok(samples)
+    }
+
+    #[test]
+    fn the_checker_rejects_what_prometheus_would() {
+        for bad in [
+            "dudist_x 1\n",
+            "# TYPE dudist_x gauge\ndudist_x{a=\"b\\q\"} 1\n",
+            "# TYPE dudist_x gauge\ndudist_x{a=\"b\"} one\n",
+            "# TYPE dudist_x gauge\ndudist_x 1\ndudist_x 2\n",
+            "# TYPE dudist_x gauge\ndudist_x 1\n# HELP dudist_x Late.\n",
+            "# TYPE 1x gauge\n1x 1\n",
+            "# TYPE dudist_x gauge\ndudist_x 1",
+        ] {
+            assert!(check_exposition(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn prometheus_metrics_parse_and_keep_their_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dudist.prom");
+        let root = Path::new("/srv/a \"quoted\" \\ dir\nwith a newline");
+        write_prometheus(&path, root, SIZES).unwrap();
+        let samples = check_exposition(&read(&path)).unwrap();
+        assert!(!dir.path().join("dudist.prom.partial").exists());
+
+        let labels = |quantile: Option<&str>| {
+            let mut labels = vec![("root".to_string(), root.display().to_string())];
+            if let Some(quantile) = quantile {
+                labels.push(("quantile".to_string(), quantile.to_string()));
+            }
+            labels
+        };
+        let sample = |name: &str, quantile, value| (name.to_string(), labels(quantile), value);
+        assert_eq!(
+            samples,
+            [
+                sample("dudist_files_total", None, 5.0),
+                sample("dudist_bytes_total", None, 10600.0),
+                sample("dudist_size_bytes", Some("0"), 600.0),
+                sample("dudist_size_bytes", Some("0.25"), 1000.0),
+                sample("dudist_size_bytes", Some("0.5"), 1000.0),
+                sample("dudist_size_bytes", Some("0.75"), 3000.0),
+                sample("dudist_size_bytes", Some("0.95"), 5000.0),
+                sample("dudist_size_bytes", Some("0.99"), 5000.0),
+                sample("dudist_size_bytes", Some("1"), 5000.0),
+            ]
+        );
+    }
+}
@@ -0,0 +1,319 @@
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use serde_json::{json, Value};
+
+use crate::terminal::Capabilities;
+
+/// Formats `n` with thousands separators, e.g. `1,204,551`.
+pub fn format_count(n: u64) -> String {
+    let digits = n.to_string();
+    let mut formatted = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+/// Like [`format_count`], for statistics of counts that can fall halfway
+/// between two of them.
+///
+/// ```
+/// use dudist::format::count_f64;
+///
+/// assert_eq!(count_f64(1204551.0), "1,204,551");
+/// assert_eq!(count_f64(2.5), "2.5");
+/// assert_eq!(count_f64(f64::NAN), "n/a");
+/// ```
+pub fn count_f64(count: f64) -> String {
+    if !count.is_finite() || count < 0.0 || count >= u64::MAX as f64 {
+        return "n/a".to_string();
+    }
+    if count.fract() == 0.0 {
+        return format_count(count as u64);
+    }
+    // Rounded as a whole, so a fraction rounding up carries
+    let rounded = format!("{:.1}", count);
+    let (whole, tenths) = rounded.split_once('.').expect("one decimal");
+    let whole = whole.parse().expect("below u64::MAX");
+    format!("{}.{}", format_count(whole), tenths)
+}
+
+/// Formats a byte count with a binary unit, e.g. `1.50 MiB`.
+pub fn human(bytes: u64) -> String {
+    format!("{:#.2}", binary_unit(bytes))
+}
+
+/// `bytes` in the largest binary unit it reaches, for [`human`] and for
+/// lines that want another precision. The conversion `byte_unit` does by
+/// default picks decimal units for sizes they show more exactly.
+pub fn binary_unit(bytes: u64) -> byte_unit::AdjustedByte {
+    byte_unit::Byte::from_u64(bytes).get_appropriate_unit(byte_unit::UnitType::Binary)
+}
+
+/// Statistics [`human_f64`] and [`exact_f64`] couldn't show
+static INVALID_VALUES: AtomicU64 = AtomicU64::new(0);
+
+/// The byte count closest to `bytes`, unless it isn't a size at all, like
+/// NaN, an infinity or a negative number.
+pub fn checked_bytes(bytes: f64) -> Result<u64, String> {
+    if bytes.is_finite() && bytes >= 0.0 && bytes.round() <= u64::MAX as f64 {
+        Ok(bytes.round() as u64)
+    } else {
+        INVALID_VALUES.fetch_add(1, Ordering::Relaxed);
+        Err(format!("{} isn't a size", bytes))
+    }
+}
+
+/// Like [`human`], for statistics that can fall between two byte counts.
+/// Values [`checked_bytes`] refuses show as `n/a` and are counted in
+/// [`invalid_values`].
+///
+/// ```
+/// use dudist::format::human_f64;
+///
+/// assert_eq!(human_f64(1536.0), "1.5 KiB");
+/// assert_eq!(human_f64(f64::NAN), "n/a");
+/// assert_eq!(human_f64(-1.0), "n/a");
+/// ```
+pub fn human_f64(bytes: f64) -> String {
+    checked_bytes(bytes).map_or_else(|_| "n/a".to_string(), human)
+}
+
+/// Shows `bytes` exactly, e.g. `2,009,500 B`, or `n/a` like [`human_f64`].
+pub fn exact_f64(bytes: f64) -> String {
+    checked_bytes(bytes).map_or_else(
+        |_| "n/a".to_string(),
+        |bytes| format!("{} B", format_count(bytes)),
+    )
+}
+
+/// How many statistics [`checked_bytes`] has refused.
+pub fn invalid_values() -> u64 {
+    INVALID_VALUES.load(Ordering::Relaxed)
+}
+
+/// Parses a human-readable size such as `4096`, `100MiB` or `1.5 GB`.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    byte_unit::Byte::parse_str(s, true)
+        .map(|byte| byte.as_u64())
+        .map_err(|e| format!("invalid size '{}': {}", s, e))
+}
+
+/// Parses a duration such as `90d`, `12h` or `2y`, in seconds (`s`),
+/// minutes (`m`), hours (`h`), days (`d`), weeks (`w`), months (`mo`, 30.44
+/// days) or years (`y`, 365.25 days).
+pub fn parse_duration(s: &str) -> Result<std::time::Duration, String> {
+    let invalid = || format!("invalid duration '{}' (expected e.g. 90d, 12h or 2y)", s);
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .ok_or_else(invalid)?;
+    let (number, unit) = s.split_at(split);
+    let seconds = match unit.trim() {
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        "d" => 86400.0,
+        "w" => 7.0 * 86400.0,
+        "mo" => 30.44 * 86400.0,
+        "y" => 365.25 * 86400.0,
+        _ => return Err(invalid()),
+    };
+    let number = number.parse::<f64>().map_err(|_| invalid())?;
+    std::time::Duration::try_from_secs_f64(number * seconds).map_err(|_| invalid())
+}
+
+/// Paths [`path`] had to show with replacement characters
+static NON_UTF8_PATHS: AtomicU64 = AtomicU64::new(0);
+
+/// Whether [`path`] shows only ASCII, see [`show_paths_in`]
+static ASCII_PATHS: AtomicBool = AtomicBool::new(false);
+
+/// Makes [`path`] follow `caps` from now on. Paths are shown in so many
+/// places, error messages among them, that they follow it for the whole
+/// process rather than taking it at every call.
+pub fn show_paths_in(caps: &Capabilities) {
+    ASCII_PATHS.store(!caps.unicode, Ordering::Relaxed);
+}
+
+/// Shows `path` for the terminal. Bytes that aren't valid UTF-8 become
+/// `\u{FFFD}` and are counted in [`non_utf8_paths`]. When only ASCII can
+/// be shown, any other character becomes `?`, as `ls` shows them.
+pub fn path(path: &Path) -> String {
+    let shown = match path.to_string_lossy() {
+        Cow::Borrowed(valid) => valid.to_string(),
+        Cow::Owned(replaced) => {
+            NON_UTF8_PATHS.fetch_add(1, Ordering::Relaxed);
+            replaced
+        }
+    };
+    match ASCII_PATHS.load(Ordering::Relaxed) {
+        true => shown
+            .chars()
+            .map(|c| if c.is_ascii() { c } else { '?' })
+            .collect(),
+        false => shown,
+    }
+}
+
+/// A path for the JSON dudist writes: a string, or where it isn't valid
+/// UTF-8 `{"bytes": [...]}` holding the raw bytes, so no name is changed
+/// on the way through.
+///
+/// ```
+/// use std::path::Path;
+/// use dudist::format::{path_from_json, path_to_json};
+///
+/// let json = path_to_json(Path::new("/srv/a b"));
+/// assert_eq!(json, "/srv/a b");
+/// assert_eq!(path_from_json(&json).unwrap(), Path::new("/srv/a b"));
+/// ```
+pub fn path_to_json(path: &Path) -> Value {
+    match path.to_str() {
+        Some(valid) => json!(valid),
+        None => json!({ "bytes": path_bytes(path) }),
+    }
+}
+
+/// Reads a path written by [`path_to_json`].
+pub fn path_from_json(value: &Value) -> Option<PathBuf> {
+    match value {
+        Value::String(valid) => Some(PathBuf::from(valid)),
+        _ => {
+            let bytes = value["bytes"]
+                .as_array()?
+                .iter()
+                .map(|byte| byte.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+                .collect::<Option<Vec<_>>>()?;
+            path_from_bytes(bytes)
+        }
+    }
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> Option<PathBuf> {
+    use std::os::unix::ffi::OsStringExt;
+    Some(PathBuf::from(std::ffi::OsString::from_vec(bytes)))
+}
+
+/// Elsewhere paths aren't bytes, so a name written this way is never read
+/// back, and a cache holding one is scanned again.
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(_bytes: Vec<u8>) -> Option<PathBuf> {
+    None
+}
+
+/// Shortens a path of more than twice `ends` components to the first and
+/// last `ends` of them, with `...` for those between. Takes time in the
+/// length of the path however deep it is.
+///
+/// ```
+/// use std::path::Path;
+/// use dudist::format::elide;
+///
+/// let deep = Path::new("/srv").join("a/".repeat(1000));
+/// assert_eq!(elide(&deep, 2), Path::new("/srv/.../a/a"));
+/// assert_eq!(elide(Path::new("src/main.rs"), 2), Path::new("src/main.rs"));
+/// ```
+pub fn elide(path: &Path, ends: usize) -> Cow<'_, Path> {
+    let count = path.components().count();
+    if count <= 2 * ends {
+        return Cow::Borrowed(path);
+    }
+    let mut elided = path.components().take(ends).collect::<PathBuf>();
+    elided.push("...");
+    elided.extend(path.components().skip(count - ends));
+    Cow::Owned(elided)
+}
+
+/// How many paths [`path`] has shown with replacement characters.
+pub fn non_utf8_paths() -> u64 {
+    NON_UTF8_PATHS.load(Ordering::Relaxed)
+}
+
+/// Shortens `s` to at most `width` characters, marking the cut with an
+/// ellipsis, or `...` in ASCII.
+///
+/// ```
+/// use dudist::format::truncate;
+/// use dudist::terminal::Capabilities;
+///
+/// assert_eq!(truncate("photos-2024", 8, &Capabilities::ascii(80)), "photo...");
+/// ```
+pub fn truncate(s: &str, width: usize, caps: &Capabilities) -> String {
+    if s.chars().count() <= width {
+        return s.to_string();
+    }
+    let ellipsis = caps.text("\u{2026}", "...");
+    let mut truncated = s
+        .chars()
+        .take(width.saturating_sub(ellipsis.chars().count()))
+        .collect::<String>();
+    truncated.push_str(ellipsis);
+    truncated
+}
+
+/// Formats an age in days with the largest fitting unit, e.g. `3d`, `5w`,
+/// `4mo` or `2.5y`.
+pub fn human_days(days: f64) -> String {
+    if days < 14.0 {
+        format!("{:.0}d", days)
+    } else if days < 60.0 {
+        format!("{:.0}w", days / 7.0)
+    } else if days < 365.0 {
+        format!("{:.0}mo", days / 30.44)
+    } else {
+        format!("{:.1}y", days / 365.25)
+    }
+}
+
+/// Formats a duration roughly, in the largest fitting unit, e.g. `40s`,
+/// `12m`, `3h` or `2d`.
+pub fn human_duration(duration: std::time::Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m", secs / 60),
+        3600..86400 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_round_to_a_tenth_carrying_into_the_whole() {
+        for (count, shown) in [
+            (2.95, "3.0"),
+            (2.96, "3.0"),
+            (2.99, "3.0"),
+            (9.99, "10.0"),
+            (999.97, "1,000.0"),
+            (1_999_999.96, "2,000,000.0"),
+            (2.94, "2.9"),
+            (2.04, "2.0"),
+            (0.5, "0.5"),
+            (1_204_551.25, "1,204,551.2"),
+            (1_204_551.0, "1,204,551"),
+        ] {
+            assert_eq!(count_f64(count), shown, "{}", count);
+        }
+    }
+}
@@ -0,0 +1,492 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::{Component, Path};
+use std::str::FromStr;
+
+use serde_json::{json, Value};
+
+use crate::compact::Sizes;
+use crate::distribution::{Distribution, Ranked};
+use crate::format;
+use crate::magic;
+use crate::owners;
+use crate::scan::FileRecord;
+use crate::table::{Align, Table};
+use crate::terminal::Capabilities;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    Extension,
+    Directory,
+    /// Content type detected from the first bytes of each file
+    Magic,
+    /// User owning each file
+    Owner,
+}
+
+impl FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ext" => Ok(GroupBy::Extension),
+            "dir" => Ok(GroupBy::Directory),
+            "magic" => Ok(GroupBy::Magic),
+            "owner" if cfg!(unix) => Ok(GroupBy::Owner),
+            "owner" => Err("grouping by owner is only supported on Unix".to_string()),
+            _ => Err(format!(
+                "invalid grouping '{}' (expected ext, dir, magic or owner)",
+                s
+            )),
+        }
+    }
+}
+
+impl GroupBy {
+    /// Whether finding the group means reading the file, which is slow
+    /// enough to be worth doing in parallel.
+    pub fn reads_content(&self) -> bool {
+        *self == GroupBy::Magic
+    }
+
+    /// The group a file below the scan `root` belongs to. At most
+    /// `sample_limit` bytes are read when grouping by content.
+    pub fn key(&self, root: &Path, record: &FileRecord, sample_limit: u64) -> String {
+        let path = record.path.as_path();
+        match self {
+            GroupBy::Extension => path
+                .extension()
+                .map(|ext| format!(".{}", ext.to_string_lossy().to_lowercase()))
+                .unwrap_or_else(|| "(none)".to_string()),
+            GroupBy::Directory => {
+                let relative = path.strip_prefix(root).unwrap_or(path);
+                match relative.components().next() {
+                    Some(Component::Normal(dir)) if relative.components().count() > 1 => {
+                        format!("{}/", dir.to_string_lossy())
+                    }
+                    _ => "./".to_string(),
+                }
+            }
+            GroupBy::Magic => magic::classify(path, sample_limit).to_string(),
+            GroupBy::Owner => record
+                .owner
+                .map_or_else(|| owners::UNKNOWN.to_string(), owners::name),
+        }
+    }
+
+    pub fn caption(&self) -> &'static str {
+        match self {
+            GroupBy::Extension => "extension",
+            GroupBy::Directory => "top-level directory",
+            GroupBy::Magic => "content type",
+            GroupBy::Owner => "owner",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Total,
+    Count,
+    Median,
+    Name,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "total" => Ok(SortKey::Total),
+            "count" => Ok(SortKey::Count),
+            "median" => Ok(SortKey::Median),
+            "name" => Ok(SortKey::Name),
+            _ => Err(format!(
+                "invalid sort key '{}' (expected total, count, median or name)",
+                s
+            )),
+        }
+    }
+}
+
+/// Row order of tabular reports. Sizes and counts sort descending and names
+/// ascending unless reversed, with the name breaking ties.
+#[derive(Debug, Clone, Copy)]
+pub struct SortOrder {
+    pub key: SortKey,
+    pub reverse: bool,
+}
+
+impl SortOrder {
+    fn compare(&self, a: &Group, b: &Group) -> Ordering {
+        let ordering = match self.key {
+            SortKey::Total => b.total.cmp(&a.total),
+            SortKey::Count => b.sizes.count().cmp(&a.sizes.count()),
+            SortKey::Median => b.dist.median.total_cmp(&a.dist.median),
+            SortKey::Name => Ordering::Equal,
+        }
+        .then_with(|| a.name.cmp(&b.name));
+        if self.reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+
+    /// The order for `--json`, with the key as `--sort` takes it.
+    fn to_json(self) -> Value {
+        let key = match self.key {
+            SortKey::Total => "total",
+            SortKey::Count => "count",
+            SortKey::Median => "median",
+            SortKey::Name => "name",
+        };
+        json!({ "key": key, "reverse": self.reverse })
+    }
+
+    fn caption(&self) -> String {
+        let (key, descending) = match self.key {
+            SortKey::Total => ("total size", true),
+            SortKey::Count => ("file count", true),
+            SortKey::Median => ("median size", true),
+            SortKey::Name => ("name", false),
+        };
+        let direction = if descending != self.reverse {
+            "descending"
+        } else {
+            "ascending"
+        };
+        format!("sorted by {}, {}", key, direction)
+    }
+}
+
+#[derive(Debug)]
+pub struct Group {
+    pub name: String,
+    /// Sorted ascending when kept one by one
+    pub sizes: Sizes,
+    pub total: u64,
+    pub dist: Distribution,
+    /// The groups merged into this one, for "(other)"
+    pub members: Vec<String>,
+}
+
+impl Group {
+    /// The name to show, saying how many groups "(other)" holds.
+    pub fn label(&self) -> String {
+        match self.members.len() {
+            0 => self.name.clone(),
+            1 => "(1 other)".to_string(),
+            n => format!("({} others)", n),
+        }
+    }
+}
+
+/// Groups of fewer files aren't flagged, a handful of files is always uneven
+const MIN_FLAGGED_FILES: usize = 10;
+
+/// When a group is flagged in the groups table as worth a closer look.
+#[derive(Debug, Clone, Copy)]
+pub struct FlagThresholds {
+    /// The mean is more than this many times the median
+    pub skew: f64,
+    /// The largest file is more than this many times the 99th percentile
+    pub outlier: f64,
+    /// The largest file holds more than this share (0 to 1) of the bytes
+    pub concentration: f64,
+}
+
+impl Default for FlagThresholds {
+    fn default() -> Self {
+        FlagThresholds {
+            skew: 3.0,
+            outlier: 10.0,
+            concentration: 0.5,
+        }
+    }
+}
+
+/// Why a group is flagged, by the order the flags are shown in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flag {
+    /// A few large files pull the mean far above the median
+    Skewed,
+    /// The largest file is far beyond nearly all the others
+    Outlier,
+    /// The largest file holds most of the bytes
+    Concentrated,
+}
+
+impl Flag {
+    const ALL: [Flag; 3] = [Flag::Skewed, Flag::Outlier, Flag::Concentrated];
+
+    /// The flag's name in `--json`.
+    fn key(self) -> &'static str {
+        match self {
+            Flag::Skewed => "skewed",
+            Flag::Outlier => "outlier",
+            Flag::Concentrated => "concentrated",
+        }
+    }
+
+    fn symbol(self) -> char {
+        match self {
+            Flag::Skewed => 'S',
+            Flag::Outlier => 'O',
+            Flag::Concentrated => 'C',
+        }
+    }
+
+    fn describe(self, thresholds: &FlagThresholds, caps: &Capabilities) -> String {
+        let times = caps.glyph('\u{d7}', 'x');
+        match self {
+            Flag::Skewed => format!("mean over {}{} the median", thresholds.skew, times),
+            Flag::Outlier => format!("largest file over {}{} p99", thresholds.outlier, times),
+            Flag::Concentrated => format!(
+                "largest file over {}% of the bytes",
+                thresholds.concentration * 100.0
+            ),
+        }
+    }
+}
+
+impl Group {
+    /// The flags the group earns against `thresholds`.
+    ///
+    /// ```
+    /// use dudist::groups::{Flag, FlagThresholds, Grouper, SortKey, SortOrder};
+    ///
+    /// let mut grouper = Grouper::default();
+    /// for _ in 0..200 {
+    ///     grouper.add(".log".to_string(), 1000);
+    /// }
+    /// grouper.add(".log".to_string(), 1_000_000);
+    /// let order = SortOrder { key: SortKey::Total, reverse: false };
+    /// let group = &grouper.finish(order)[0];
+    /// assert_eq!(
+    ///     group.flags(&FlagThresholds::default()),
+    ///     [Flag::Skewed, Flag::Outlier, Flag::Concentrated]
+    /// );
+    /// ```
+    pub fn flags(&self, thresholds: &FlagThresholds) -> Vec<Flag> {
+        let count = self.sizes.count();
+        if count < MIN_FLAGGED_FILES {
+            return Vec::new();
+        }
+        let (max, total) = (self.dist.max as f64, self.total as f64);
+        let mean = total / count as f64;
+        let p99 = self.sizes.percentile(99.0);
+        Flag::ALL
+            .into_iter()
+            .filter(|flag| match flag {
+                Flag::Skewed => mean > thresholds.skew * self.dist.median,
+                Flag::Outlier => max > thresholds.outlier * p99,
+                Flag::Concentrated => total > 0.0 && max > thresholds.concentration * total,
+            })
+            .collect()
+    }
+}
+
+/// Collects file sizes per group key.
+#[derive(Default)]
+pub struct Grouper {
+    groups: HashMap<String, Sizes>,
+    /// Whether the sizes are counted in buckets, for `--compact`
+    compact: bool,
+}
+
+impl Grouper {
+    /// A grouper counting the sizes of each group in buckets when
+    /// `compact`, or keeping them one by one like the default.
+    pub fn new(compact: bool) -> Grouper {
+        Grouper {
+            groups: HashMap::new(),
+            compact,
+        }
+    }
+
+    pub fn add(&mut self, key: String, size: u64) {
+        let compact = self.compact;
+        self.groups
+            .entry(key)
+            .or_insert_with(|| Sizes::new(compact))
+            .add(size);
+    }
+
+    pub fn finish(self, order: SortOrder) -> Vec<Group> {
+        let mut groups = self
+            .groups
+            .into_iter()
+            .map(|(name, mut sizes)| {
+                sizes.sort();
+                Group {
+                    name,
+                    total: sizes.total(),
+                    dist: Distribution::from_ranked(&sizes),
+                    sizes,
+                    members: Vec::new(),
+                }
+            })
+            .collect::<Vec<_>>();
+        groups.sort_by(|a, b| order.compare(a, b));
+        groups
+    }
+}
+
+/// The name of the row aggregating the groups left out of a report
+pub const OTHER: &str = "(other)";
+
+/// When groups are left out of a report and merged into "(other)".
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GroupLimits {
+    /// Groups with fewer files are merged
+    pub min_count: usize,
+    /// Groups with a smaller total are merged
+    pub min_bytes: u64,
+    /// Of the groups left, only this many are kept
+    pub max_groups: Option<usize>,
+}
+
+/// Merges the groups that fall short of `limits` into an "(other)" group
+/// at the end, keeping the order of the rest.
+pub fn merge_small_groups(groups: Vec<Group>, limits: &GroupLimits) -> Vec<Group> {
+    let (mut kept, mut merged) = groups.into_iter().partition::<Vec<_>, _>(|group| {
+        group.sizes.count() >= limits.min_count && group.total >= limits.min_bytes
+    });
+    if let Some(max_groups) = limits.max_groups {
+        if kept.len() > max_groups {
+            merged.extend(kept.drain(max_groups..));
+        }
+    }
+    if merged.is_empty() {
+        return kept;
+    }
+    let members = merged
+        .iter()
+        .map(|group| group.name.clone())
+        .collect::<Vec<_>>();
+    let mut groups = merged.into_iter();
+    let mut sizes = groups.next().expect("checked above").sizes;
+    for group in groups {
+        sizes.merge(group.sizes);
+    }
+    sizes.sort();
+    kept.push(Group {
+        name: OTHER.to_string(),
+        total: sizes.total(),
+        dist: Distribution::from_ranked(&sizes),
+        sizes,
+        members,
+    });
+    kept
+}
+
+/// A column shown after the group name, with its header and cell contents
+pub type ExtraColumn<'a> = (&'a str, &'a dyn Fn(&Group) -> String);
+
+/// Prints the groups as a table captioned "Files by `what`". With
+/// `thresholds`, the groups earning [`Flag`]s get them in a last column,
+/// explained below the table.
+pub fn print_groups(
+    groups: &[Group],
+    what: &str,
+    order: SortOrder,
+    header: &str,
+    extra_column: Option<ExtraColumn>,
+    thresholds: Option<&FlagThresholds>,
+    caps: &Capabilities,
+) {
+    let flags = groups
+        .iter()
+        .map(|group| thresholds.map_or_else(Vec::new, |thresholds| group.flags(thresholds)))
+        .collect::<Vec<_>>();
+    let flagged = flags.iter().any(|flags| !flags.is_empty());
+    let mut aligns = vec![Align::Left, Align::Right, Align::Right, Align::Right];
+    if extra_column.is_some() {
+        aligns.insert(1, Align::Left);
+    }
+    if flagged {
+        aligns.push(Align::Left);
+    }
+    let mut table = Table::new(&aligns);
+    let mut headers = vec![header, "Files", "Total", "Median"];
+    if let Some((extra_header, _)) = extra_column {
+        headers.insert(1, extra_header);
+    }
+    if flagged {
+        headers.push("Flags");
+    }
+    table.row(headers);
+    for (group, flags) in groups.iter().zip(&flags) {
+        let mut cells = vec![
+            group.label(),
+            format::format_count(group.sizes.count() as u64),
+            format::human(group.total),
+            format::human_f64(group.dist.median),
+        ];
+        if let Some((_, cell)) = extra_column {
+            cells.insert(1, cell(group));
+        }
+        if flagged {
+            cells.push(flags.iter().map(|flag| flag.symbol()).collect());
+        }
+        table.row(cells);
+    }
+    print_caption(what, order);
+    print!("{}", table.render());
+    if let (true, Some(thresholds)) = (flagged, thresholds) {
+        let legend = Flag::ALL
+            .into_iter()
+            .filter(|flag| flags.iter().any(|flags| flags.contains(flag)))
+            .map(|flag| format!("{} {}", flag.symbol(), flag.describe(thresholds, caps)))
+            .collect::<Vec<_>>();
+        println!("Flags: {}", legend.join(", "));
+    }
+}
+
+/// The groups for `--json`, in the order of the table, each with the
+/// columns of the table and the `extra_column` named by its key. The
+/// "(other)" row lists the groups merged into it. With `thresholds` each row
+/// says which flags it earns.
+pub fn to_json(
+    groups: &[Group],
+    what: &str,
+    order: SortOrder,
+    extra_column: Option<ExtraColumn>,
+    thresholds: Option<&FlagThresholds>,
+) -> Value {
+    let rows = groups
+        .iter()
+        .map(|group| {
+            let mut row = json!({
+                "name": group.name,
+                "files": group.sizes.count(),
+                "total": group.total,
+                "median": group.dist.median,
+            });
+            if let Some((key, cell)) = extra_column {
+                row[key] = json!(cell(group));
+            }
+            if let Some(thresholds) = thresholds {
+                let earned = group.flags(thresholds);
+                for flag in Flag::ALL {
+                    row["flags"][flag.key()] = json!(earned.contains(&flag));
+                }
+            }
+            // The groups merged into "(other)", for scripts to look up
+            if !group.members.is_empty() {
+                row["other_members"] = json!(group.members);
+            }
+            row
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "by": what,
+        "sort": order.to_json(),
+        "rows": rows,
+    })
+}
+
+pub fn print_caption(what: &str, order: SortOrder) {
+    println!("Files by {}, {}:", what, order.caption());
+}
@@ -0,0 +1,158 @@
+//! Suggestions for the next run, drawn from what a scan found, like
+//! clipping the plot when one file dwarfs the others.
+//!
+//! Each hint comes from a rule of [`RULES`], which looks at the [`Facts`]
+//! of the report and either suggests something or stays quiet. A new flag
+//! worth suggesting takes a fact or two and a rule.
+
+use crate::format;
+use crate::groups::GroupBy;
+
+/// Hints printed at most, in the order of [`RULES`]
+pub const LIMIT: usize = 3;
+
+/// Share of the total a single file needs before clipping is suggested
+const DOMINANT_FILE_SHARE: f64 = 0.4;
+
+/// Share of the files left out by `--min-size` before lowering it is
+/// suggested
+const EXCLUDED_SHARE: f64 = 0.5;
+
+/// Share of the total an extension needs before grouping is suggested
+const DOMINANT_EXTENSION_SHARE: f64 = 0.5;
+
+/// What the rules know of a scan and the options it ran with.
+#[derive(Debug, Clone, Default)]
+pub struct Facts {
+    /// Regular files the walk found, matching or not
+    pub files: u64,
+    /// The files in the report
+    pub matched: u64,
+    pub total: u64,
+    pub largest: u64,
+    pub min_size: u64,
+    /// Whether `--max-size` left out files too
+    pub max_size: bool,
+    /// Whether the plot is clipped already
+    pub clipped: bool,
+    pub group_by: Option<GroupBy>,
+    /// The extension holding the most bytes, with them
+    pub top_extension: Option<(String, u64)>,
+}
+
+/// A rule suggesting something when the facts call for it.
+pub type Rule = fn(&Facts) -> Option<String>;
+
+/// The rules, the more useful hints first.
+pub const RULES: &[Rule] = &[dominant_file, excluded_files, dominant_extension];
+
+/// One file holds a large share of the bytes, squeezing the plot of the
+/// others into its first cells.
+fn dominant_file(facts: &Facts) -> Option<String> {
+    let share = facts.largest as f64 / facts.total as f64;
+    (facts.matched > 1 && !facts.clipped && share >= DOMINANT_FILE_SHARE).then(|| {
+        format!(
+            "1 file accounts for {:.0}% of the total; rerun with --clip p99 for a readable plot",
+            share * 100.0
+        )
+    })
+}
+
+/// Most files were too small for `--min-size`.
+fn excluded_files(facts: &Facts) -> Option<String> {
+    let excluded = facts.files.saturating_sub(facts.matched);
+    let share = excluded as f64 / facts.files as f64;
+    (facts.min_size > 0 && !facts.max_size && share >= EXCLUDED_SHARE).then(|| {
+        format!(
+            "{:.0}% of files are below the {} threshold and were excluded; consider --min-size 0",
+            share * 100.0,
+            format::human(facts.min_size)
+        )
+    })
+}
+
+/// One extension holds most of the bytes, which grouping would show.
+fn dominant_extension(facts: &Facts) -> Option<String> {
+    let (extension, bytes) = facts.top_extension.as_ref()?;
+    let share = *bytes as f64 / facts.total as f64;
+    let grouped = facts.group_by == Some(GroupBy::Extension);
+    (!grouped && extension != "(none)" && share >= DOMINANT_EXTENSION_SHARE).then(|| {
+        format!(
+            "extension {} holds {}; try --group-by ext",
+            extension,
+            format::human(*bytes)
+        )
+    })
+}
+
+/// The hints of the rules that apply, at most [`LIMIT`] of them.
+///
+/// ```
+/// use dudist::hints::{suggest, Facts};
+///
+/// let facts = Facts {
+///     files: 600,
+///     matched: 100,
+///     total: 1000 << 20,
+///     largest: 480 << 20,
+///     min_size: 4096,
+///     top_extension: Some((".bak".to_string(), 600 << 20)),
+///     ..Facts::default()
+/// };
+/// assert_eq!(
+///     suggest(&facts),
+///     [
+///         "1 file accounts for 48% of the total; rerun with --clip p99 for a readable plot",
+///         "83% of files are below the 4 KiB threshold and were excluded; consider --min-size 0",
+///         "extension .bak holds 600 MiB; try --group-by ext",
+///     ]
+/// );
+/// ```
+pub fn suggest(facts: &Facts) -> Vec<String> {
+    RULES
+        .iter()
+        .filter_map(|rule| rule(facts))
+        .take(LIMIT)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scan that calls for every hint.
+    fn facts() -> Facts {
+        Facts {
+            files: 600,
+            matched: 100,
+            total: 1000 << 20,
+            largest: 480 << 20,
+            min_size: 4096,
+            top_extension: Some((".bak".to_string(), 600 << 20)),
+            ..Facts::default()
+        }
+    }
+
+    #[test]
+    fn each_hint_goes_away_once_its_flag_is_given() {
+        let flagged = Facts {
+            clipped: true,
+            min_size: 0,
+            group_by: Some(GroupBy::Extension),
+            ..facts()
+        };
+        assert!(suggest(&flagged).is_empty());
+    }
+
+    #[test]
+    fn no_hints_when_there_is_nothing_to_them() {
+        let even = Facts {
+            matched: 590,
+            largest: 2 << 20,
+            top_extension: Some((".bak".to_string(), 300 << 20)),
+            ..facts()
+        };
+        assert!(suggest(&even).is_empty());
+        assert!(suggest(&Facts::default()).is_empty());
+    }
+}
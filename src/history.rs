@@ -0,0 +1,206 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+
+use crate::cache;
+use crate::distribution::Ranked;
+use crate::format;
+use crate::scan::ScanOptions;
+use crate::terminal::Capabilities;
+
+/// One scan as remembered in a history file, a JSON object per line.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// When the scan finished
+    pub time: SystemTime,
+    pub root: String,
+    /// Hash of the options deciding which files were counted. Only runs
+    /// with the same hash are compared.
+    pub options: String,
+    pub files: u64,
+    pub total: u64,
+    /// `None` when no file matched
+    pub median: Option<f64>,
+    pub p95: Option<f64>,
+}
+
+impl Entry {
+    /// Describes a scan of `root` that found `sizes`.
+    pub fn new(root: &Path, options: &ScanOptions, sizes: &(impl Ranked + ?Sized)) -> Entry {
+        // Whole bytes, so reruns over the same files compare as unchanged
+        let quantile = |p: f64| (sizes.count() > 0).then(|| sizes.percentile(p).round());
+        Entry {
+            time: SystemTime::now(),
+            root: root.display().to_string(),
+            options: options_hash(options),
+            files: sizes.count() as u64,
+            total: sizes.total(),
+            median: quantile(50.0),
+            p95: quantile(95.0),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        let secs = self
+            .time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        json!({
+            "time": secs,
+            "root": self.root,
+            "options": self.options,
+            "files": self.files,
+            "total": self.total,
+            "median": self.median,
+            "p95": self.p95,
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Entry> {
+        Some(Entry {
+            time: UNIX_EPOCH + Duration::from_secs(value.get("time")?.as_u64()?),
+            root: value.get("root")?.as_str()?.to_string(),
+            options: value.get("options")?.as_str()?.to_string(),
+            files: value.get("files")?.as_u64()?,
+            total: value.get("total")?.as_u64()?,
+            median: value.get("median").and_then(Value::as_f64),
+            p95: value.get("p95").and_then(Value::as_f64),
+        })
+    }
+
+    /// Whether the two runs scanned the same root with the same options.
+    fn comparable(&self, other: &Entry) -> bool {
+        self.root == other.root && self.options == other.options
+    }
+}
+
+/// A short hash of the options deciding which files a scan counts.
+pub fn options_hash(options: &ScanOptions) -> String {
+    format!("{:016x}", cache::fnv1a(options.fingerprint().as_bytes()))
+}
+
+/// Appends `entry` to the history at `path`, creating the file if needed.
+pub fn append(path: &Path, entry: &Entry) -> Result<(), String> {
+    let error = |e: std::io::Error| format!("failed to write {}: {}", path.display(), e);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(error)?;
+    // One write per line, so concurrent runs don't interleave partial lines
+    file.write_all(format!("{}\n", entry.to_json()).as_bytes())
+        .map_err(error)
+}
+
+/// Reads the history at `path`, oldest first, along with the number of
+/// lines that couldn't be read. A missing file is an empty history.
+pub fn read(path: &Path) -> Result<(Vec<Entry>, usize), String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((Vec::new(), 0)),
+        Err(e) => return Err(format!("failed to read {}: {}", path.display(), e)),
+    };
+    let mut skipped = 0;
+    let entries = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            let entry = serde_json::from_str(line)
+                .ok()
+                .and_then(|value| Entry::from_json(&value));
+            if entry.is_none() {
+                skipped += 1;
+            }
+            entry
+        })
+        .collect();
+    Ok((entries, skipped))
+}
+
+/// Prints how `current` compares to the last run in `history` and to the
+/// earliest of the last `runs` runs, only counting runs over the same root
+/// with the same options.
+pub fn print_trend(history: &[Entry], current: &Entry, runs: usize, caps: &Capabilities) {
+    let earlier = history
+        .iter()
+        .filter(|entry| entry.comparable(current))
+        .collect::<Vec<_>>();
+    let others = history.len() - earlier.len();
+    println!("Trend:");
+    if others > 0 {
+        println!(
+            "  ({} runs over another root or with other options left out)",
+            format::format_count(others as u64)
+        );
+    }
+    let window = &earlier[earlier.len().saturating_sub(runs)..];
+    let (Some(last), Some(first)) = (window.last(), window.first()) else {
+        println!("  no earlier runs to compare with");
+        return;
+    };
+    let ago = |entry: &Entry| {
+        format::human_duration(current.time.duration_since(entry.time).unwrap_or_default())
+    };
+
+    type Metric = fn(&Entry) -> Option<f64>;
+    let metrics: [(&str, Metric); 4] = [
+        ("files", |entry| Some(entry.files as f64)),
+        ("total", |entry| Some(entry.total as f64)),
+        ("median", |entry| entry.median),
+        ("p95", |entry| entry.p95),
+    ];
+    for (name, metric) in metrics {
+        let mut line = format!(
+            "  {:<7} {}",
+            format!("{}:", name),
+            change(metric(last), metric(current))
+        );
+        line += &format!(" since {} ago", ago(last));
+        if window.len() > 1 {
+            line += &format!(
+                ", {} over {} runs ({})",
+                change(metric(first), metric(current)),
+                window.len(),
+                ago(first)
+            );
+        }
+        println!("{}", line);
+    }
+
+    let totals = window
+        .iter()
+        .map(|entry| entry.total)
+        .chain([current.total])
+        .collect::<Vec<_>>();
+    println!("  totals:  {}", sparkline(&totals, caps));
+}
+
+/// The relative change from `old` to `new`, e.g. `+4.2%`.
+fn change(old: Option<f64>, new: Option<f64>) -> String {
+    match (old, new) {
+        (Some(old), Some(new)) if old > 0.0 => format!("{:+.1}%", (new - old) / old * 100.0),
+        (Some(old), Some(new)) if old == new => "+0.0%".to_string(),
+        _ => "n/a".to_string(),
+    }
+}
+
+/// Draws `values` as a row of bars of eight heights, from the smallest
+/// value to the largest.
+fn sparkline(values: &[u64], caps: &Capabilities) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    const ASCII_BARS: [char; 8] = ['_', '.', ',', '-', '=', '+', '*', '#'];
+    let bars = if caps.unicode { BARS } else { ASCII_BARS };
+    let min = values.iter().copied().min().unwrap_or(0);
+    let max = values.iter().copied().max().unwrap_or(0);
+    values
+        .iter()
+        .map(|&value| match max - min {
+            0 => bars[0],
+            range => bars[((value - min) as f64 / range as f64 * 7.0).round() as usize],
+        })
+        .collect()
+}
@@ -0,0 +1,323 @@
+//! `.dudistignore` files, which use the syntax of `.gitignore`.
+//!
+//! A file applies to the directory holding it and everything below, and
+//! the rules of deeper files come after those of the files above them, so
+//! they win. Within the rules, the last one matching decides, `!` rules
+//! taking entries back in. Like with git, nothing below an ignored
+//! directory can be taken back in, as the walk doesn't go there.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the ignore files
+pub const FILE_NAME: &str = ".dudistignore";
+
+#[derive(Debug, Clone)]
+struct Rule {
+    /// Globs over the path components, `**` matching any number of them
+    segments: Vec<String>,
+    negated: bool,
+    /// Written with a trailing `/`
+    dirs_only: bool,
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Rule> {
+        // Trailing spaces are dropped unless escaped
+        let mut line = line.trim_end_matches(['\r', '\n']);
+        while line.ends_with(' ') && !line.ends_with("\\ ") {
+            line = &line[..line.len() - 1];
+        }
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            // `\#` and `\!` start rules with those characters
+            None if line.starts_with("\\#") || line.starts_with("\\!") => (false, &line[1..]),
+            None => (false, line),
+        };
+        let (dirs_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        if line.is_empty() {
+            return None;
+        }
+        // A slash anywhere but at the end ties the rule to the directory
+        // of the file, otherwise it matches names at any depth
+        let anchored = line.contains('/');
+        let mut segments = Vec::new();
+        if !anchored {
+            segments.push("**".to_string());
+        }
+        segments.extend(
+            line.trim_start_matches('/')
+                .split('/')
+                .filter(|segment| !segment.is_empty())
+                .map(str::to_string),
+        );
+        Some(Rule {
+            segments,
+            negated,
+            dirs_only,
+        })
+    }
+
+    fn matches(&self, components: &[&str], is_dir: bool) -> bool {
+        (is_dir || !self.dirs_only) && match_segments(&self.segments, components)
+    }
+}
+
+fn match_segments(segments: &[String], components: &[&str]) -> bool {
+    match segments.split_first() {
+        None => components.is_empty(),
+        // A trailing `/**` matches what is inside, not the directory itself
+        Some((segment, [])) if segment == "**" => !components.is_empty(),
+        Some((segment, rest)) if segment == "**" => {
+            (0..=components.len()).any(|skip| match_segments(rest, &components[skip..]))
+        }
+        Some((segment, rest)) => components.split_first().is_some_and(|(first, others)| {
+            glob(
+                &segment.chars().collect::<Vec<_>>(),
+                &first.chars().collect::<Vec<_>>(),
+            ) && match_segments(rest, others)
+        }),
+    }
+}
+
+/// Matches a single path component against `*`, `?`, `[...]` and `\`
+/// escapes.
+fn glob(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| glob(rest, &name[skip..])),
+        Some(('?', rest)) => !name.is_empty() && glob(rest, &name[1..]),
+        Some(('[', rest)) => match (class(rest), name.split_first()) {
+            (Some((matches, after)), Some((&c, others))) => matches(c) && glob(after, others),
+            // An unclosed bracket stands for itself
+            (None, Some(('[', others))) => glob(rest, others),
+            _ => false,
+        },
+        Some(('\\', [escaped, rest @ ..])) => {
+            name.first() == Some(escaped) && glob(rest, &name[1..])
+        }
+        Some((c, rest)) => name.first() == Some(c) && glob(rest, &name[1..]),
+    }
+}
+
+type Class<'a> = (Box<dyn Fn(char) -> bool + 'a>, &'a [char]);
+
+/// Parses the inside of a `[...]` class, giving the test and the pattern
+/// after the closing bracket.
+fn class(pattern: &[char]) -> Option<Class<'_>> {
+    let (negated, body) = match pattern.first() {
+        Some('!' | '^') => (true, &pattern[1..]),
+        _ => (false, pattern),
+    };
+    // A `]` right at the start is part of the class
+    let end = body.iter().skip(1).position(|&c| c == ']').map(|i| i + 1)?;
+    let members = &body[..end];
+    let matches = move |c: char| {
+        let mut i = 0;
+        let mut found = false;
+        while i < members.len() {
+            if i + 2 < members.len() && members[i + 1] == '-' {
+                found |= (members[i]..=members[i + 2]).contains(&c);
+                i += 3;
+            } else {
+                found |= members[i] == c;
+                i += 1;
+            }
+        }
+        found != negated
+    };
+    Some((Box::new(matches), &body[end + 1..]))
+}
+
+/// The rules of one ignore file.
+#[derive(Debug, Clone)]
+struct IgnoreFile {
+    dir: PathBuf,
+    rules: Vec<Rule>,
+}
+
+impl IgnoreFile {
+    /// Reads the ignore file of `dir`, if it has one with any rules.
+    fn read(dir: &Path) -> Option<IgnoreFile> {
+        let contents = fs::read_to_string(dir.join(FILE_NAME)).ok()?;
+        let rules = contents.lines().filter_map(Rule::parse).collect::<Vec<_>>();
+        (!rules.is_empty()).then(|| IgnoreFile {
+            dir: dir.to_path_buf(),
+            rules,
+        })
+    }
+
+    /// Whether the last rule matching `path` ignores it, `None` when none
+    /// does.
+    fn decide(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let relative = path.strip_prefix(&self.dir).ok()?;
+        let components = relative
+            .iter()
+            .map(|component| component.to_string_lossy())
+            .collect::<Vec<_>>();
+        let components = components.iter().map(AsRef::as_ref).collect::<Vec<_>>();
+        if components.is_empty() {
+            return None;
+        }
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.matches(&components, is_dir))
+            .map(|rule| !rule.negated)
+    }
+}
+
+/// The number of rules in the ignore file of `dir`, if it has one with any.
+pub fn rule_count(dir: &Path) -> Option<usize> {
+    IgnoreFile::read(dir).map(|file| file.rules.len())
+}
+
+/// The ignore files applying at the current point of a depth-first walk.
+#[derive(Debug, Default)]
+pub(crate) struct IgnoreStack {
+    files: Vec<IgnoreFile>,
+}
+
+impl IgnoreStack {
+    /// Starts a walk of `dir`, somewhere below the scan root `root`, with
+    /// the ignore files of the directories in between.
+    pub fn below(root: &Path, dir: &Path) -> IgnoreStack {
+        let mut stack = IgnoreStack::default();
+        if let Ok(relative) = dir.strip_prefix(root) {
+            let mut ancestor = root.to_path_buf();
+            for component in relative.iter() {
+                stack.enter(&ancestor);
+                ancestor.push(component);
+            }
+        }
+        stack
+    }
+
+    /// Whether the entry at `path` is ignored. Entries must come in walk
+    /// order, directories before what is inside them.
+    pub fn ignores(&mut self, path: &Path, is_dir: bool) -> bool {
+        while self
+            .files
+            .last()
+            .is_some_and(|file| !path.starts_with(&file.dir))
+        {
+            self.files.pop();
+        }
+        // Deeper files come last and have the final say
+        self.files
+            .iter()
+            .rev()
+            .find_map(|file| file.decide(path, is_dir))
+            .unwrap_or(false)
+    }
+
+    /// Reads the ignore file of `dir`, which the walk enters next,
+    /// telling whether it had one.
+    pub fn enter(&mut self, dir: &Path) -> bool {
+        match IgnoreFile::read(dir) {
+            Some(file) => {
+                self.files.push(file);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether the rules `lines`, in a file at `/r`, ignore `/r/path`.
+    fn ignores(lines: &str, path: &str, is_dir: bool) -> bool {
+        let file = IgnoreFile {
+            dir: PathBuf::from("/r"),
+            rules: lines.lines().filter_map(Rule::parse).collect(),
+        };
+        file.decide(&Path::new("/r").join(path), is_dir)
+            .unwrap_or(false)
+    }
+
+    #[test]
+    fn names_match_at_any_depth_and_paths_from_the_file() {
+        assert!(ignores("target", "target", true));
+        assert!(ignores("target", "crates/a/target", true));
+        assert!(ignores("/target", "target", true));
+        assert!(!ignores("/target", "crates/a/target", true));
+        assert!(ignores("docs/*.pdf", "docs/a.pdf", false));
+        assert!(!ignores("docs/*.pdf", "x/docs/a.pdf", false));
+    }
+
+    #[test]
+    fn a_trailing_slash_only_matches_directories() {
+        assert!(ignores("build/", "build", true));
+        assert!(!ignores("build/", "build", false));
+    }
+
+    #[test]
+    fn the_last_matching_rule_decides() {
+        let rules = "*.log\n!keep.log\n";
+        assert!(ignores(rules, "a/debug.log", false));
+        assert!(!ignores(rules, "a/keep.log", false));
+        assert!(ignores("!keep.log\n*.log\n", "keep.log", false));
+    }
+
+    #[test]
+    fn globs_classes_and_double_stars() {
+        assert!(ignores("cache-?", "cache-1", true));
+        assert!(!ignores("cache-?", "cache-10", true));
+        assert!(ignores("v[0-9].bin", "v7.bin", false));
+        assert!(!ignores("v[!0-9].bin", "v7.bin", false));
+        assert!(ignores("**/tmp/**", "a/b/tmp/c/d", false));
+        assert!(!ignores("**/tmp/**", "a/b/tmp", true));
+        assert!(ignores("a/**/z", "a/z", false));
+        assert!(ignores("a/**/z", "a/b/c/z", false));
+        // An unclosed bracket stands for itself
+        assert!(ignores("[oops", "[oops", false));
+    }
+
+    #[test]
+    fn comments_blanks_and_escapes() {
+        let rules = "# a comment\n\n   \n\\#hash\n\\!bang\ntrailing   \n";
+        assert!(!ignores(rules, "# a comment", false));
+        assert!(ignores(rules, "#hash", false));
+        assert!(ignores(rules, "!bang", false));
+        assert!(ignores(rules, "trailing", false));
+        assert!(ignores("space\\ ", "space ", false));
+    }
+
+    #[test]
+    fn deeper_files_override_those_above() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::create_dir_all(root.join("other")).unwrap();
+        fs::write(root.join(FILE_NAME), "*.iso\n").unwrap();
+        fs::write(root.join("sub").join(FILE_NAME), "!keep.iso\n").unwrap();
+
+        let mut stack = IgnoreStack::default();
+        assert!(stack.enter(root));
+        assert!(stack.ignores(&root.join("a.iso"), false));
+        assert!(!stack.ignores(&root.join("sub"), true));
+        assert!(stack.enter(&root.join("sub")));
+        assert!(!stack.ignores(&root.join("sub/keep.iso"), false));
+        assert!(stack.ignores(&root.join("sub/other.iso"), false));
+        // Leaving sub drops its rules
+        assert!(!stack.enter(&root.join("other")));
+        assert!(stack.ignores(&root.join("other/keep.iso"), false));
+
+        // Starting below the root picks up the files in between
+        let mut below = IgnoreStack::below(root, &root.join("sub"));
+        assert!(below.enter(&root.join("sub")));
+        assert!(!below.ignores(&root.join("sub/keep.iso"), false));
+        assert!(below.ignores(&root.join("sub/b.iso"), false));
+        assert_eq!(rule_count(root), Some(1));
+        assert_eq!(rule_count(&root.join("other")), None);
+    }
+}
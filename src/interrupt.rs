@@ -0,0 +1,62 @@
+//! Ctrl-C handling. The first interrupt asks the scan to stop so the files
+//! seen so far can still be reported, a second one exits right away.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Exit code after an interrupt, following the shell's 128 + SIGINT
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether Ctrl-C was pressed since [`install`] was called.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+/// Only async-signal-safe work may happen here, which the atomic swap and
+/// `_exit` are.
+#[cfg(unix)]
+extern "C" fn on_interrupt(_signal: libc::c_int) {
+    if INTERRUPTED.swap(true, Ordering::Relaxed) {
+        unsafe { libc::_exit(EXIT_INTERRUPTED) };
+    }
+}
+
+#[cfg(unix)]
+pub fn install() {
+    let handler = on_interrupt as extern "C" fn(libc::c_int);
+    unsafe {
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+    }
+}
+
+#[cfg(windows)]
+pub fn install() {
+    const CTRL_C_EVENT: u32 = 0;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetConsoleCtrlHandler(
+            handler: Option<unsafe extern "system" fn(u32) -> i32>,
+            add: i32,
+        ) -> i32;
+    }
+
+    // Runs on a thread of its own, so exiting from here is fine
+    unsafe extern "system" fn on_interrupt(ctrl_type: u32) -> i32 {
+        if ctrl_type != CTRL_C_EVENT {
+            return 0;
+        }
+        if INTERRUPTED.swap(true, Ordering::Relaxed) {
+            std::process::exit(EXIT_INTERRUPTED);
+        }
+        1
+    }
+
+    unsafe {
+        SetConsoleCtrlHandler(Some(on_interrupt), 1);
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn install() {}
@@ -0,0 +1,53 @@
+//! The library behind the `dudist` tool: walking a tree for file sizes and
+//! summarizing, plotting and exporting their distribution.
+//!
+//! [`scan::scan_iter`] streams the files of a tree as they are found, so
+//! callers can show progress or apply their own filters without the whole
+//! tree being buffered:
+//!
+//! ```no_run
+//! use dudist::scan::{scan_iter, ScanOptions};
+//!
+//! let options = ScanOptions::builder().min_size(0).build().unwrap();
+//! for record in scan_iter(".", &options).flatten() {
+//!     println!("{} {}", record.size, record.path.display());
+//! }
+//! ```
+
+pub mod anomalies;
+pub mod archive;
+pub mod budget;
+pub mod cache;
+pub mod chart;
+pub mod check;
+pub mod cold;
+pub mod compact;
+pub mod dirstats;
+pub mod distribution;
+pub mod estimate;
+pub mod events;
+pub mod examples;
+pub mod export;
+pub mod format;
+pub mod groups;
+pub mod hints;
+pub mod history;
+pub mod ignore;
+pub mod interrupt;
+pub mod links;
+pub mod magic;
+pub mod mounts;
+pub mod owners;
+pub mod plot;
+pub mod resources;
+pub mod retention;
+pub mod retry;
+pub mod roots;
+pub mod scan;
+pub mod slack;
+pub mod snapshot;
+pub mod table;
+pub mod template;
+pub mod terminal;
+pub mod timeline;
+pub mod warnings;
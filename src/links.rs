@@ -0,0 +1,223 @@
+//! Files with several names, hard links, and the space they save over
+//! separate copies, as in rsnapshot-style backup trees.
+//!
+//! Only files with more than one name are kept track of, and of those only
+//! the first path found, so the memory taken grows with the linked files
+//! rather than with the tree.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use serde_json::{json, Value};
+
+use crate::format;
+use crate::scan::FileRecord;
+use crate::table::{Align, Table};
+
+/// Linked files listed, the largest first
+const LIST_LIMIT: usize = 10;
+
+/// A file with several names, as found by the walk.
+#[derive(Debug)]
+struct Linked {
+    size: u64,
+    /// Names the walk found, which leaves out those outside the PATHs
+    found: u64,
+    /// Names the filesystem has for the file
+    links: u64,
+    /// The first of them by path
+    path: PathBuf,
+}
+
+/// Groups the files with several names by their device and inode.
+#[derive(Debug, Default)]
+pub struct LinkTally {
+    linked: HashMap<(u64, u64), Linked>,
+}
+
+impl LinkTally {
+    pub fn add(&mut self, record: &FileRecord) {
+        let Some(inode) = record.inode.filter(|_| record.links > 1) else {
+            return;
+        };
+        self.linked
+            .entry((record.device, inode))
+            .and_modify(|linked| {
+                linked.found += 1;
+                if record.path < linked.path {
+                    linked.path = record.path.clone();
+                }
+            })
+            .or_insert_with(|| Linked {
+                size: record.size,
+                found: 1,
+                links: record.links,
+                path: record.path.clone(),
+            });
+    }
+
+    /// The bytes of the linked files counted once for each name found, and
+    /// once for each file.
+    pub fn totals(&self) -> (u64, u64) {
+        self.linked
+            .values()
+            .fold((0, 0), |(logical, physical), linked| {
+                (logical + linked.size * linked.found, physical + linked.size)
+            })
+    }
+
+    /// The linked files taking the most bytes over all their names found,
+    /// as many as are listed.
+    fn largest(&self) -> Vec<&Linked> {
+        let mut largest = self.linked.values().collect::<Vec<_>>();
+        largest.sort_by(|a, b| {
+            (b.size * b.found)
+                .cmp(&(a.size * a.found))
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        largest.truncate(LIST_LIMIT);
+        largest
+    }
+
+    /// What [`print`](Self::print) shows, for `--json`.
+    pub fn to_json(&self) -> Value {
+        let (logical, physical) = self.totals();
+        let largest = self
+            .largest()
+            .into_iter()
+            .map(|linked| {
+                json!({
+                    "path": format::path_to_json(&linked.path),
+                    "size": linked.size,
+                    "names": linked.found,
+                    "links": linked.links,
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "names": self.linked.values().map(|linked| linked.found).sum::<u64>(),
+            "files": self.linked.len(),
+            "logical": logical,
+            "physical": physical,
+            "saved": logical - physical,
+            "largest": largest,
+        })
+    }
+
+    /// Prints how many names the linked files have, the space linking saves
+    /// and the largest of them.
+    pub fn print(&self) {
+        let names = self.linked.values().map(|linked| linked.found).sum::<u64>();
+        println!(
+            "Hard links:      {} names for {} files with several names",
+            format::format_count(names),
+            format::format_count(self.linked.len() as u64)
+        );
+        if self.linked.is_empty() {
+            return;
+        }
+        // A file whose other names are outside the PATHs saves nothing here
+        if self.linked.values().any(|linked| linked.found > 1) {
+            let (logical, physical) = self.totals();
+            println!(
+                "                 {} as separate copies, {} on disk, {} saved by linking",
+                format::human(logical),
+                format::human(physical),
+                format::human(logical - physical)
+            );
+        }
+        println!("Largest linked files:");
+        let mut table = Table::new(&[Align::Right, Align::Right, Align::Left]);
+        table.row(["Size", "Names", "File"].map(str::to_string));
+        for linked in self.largest() {
+            let names = match linked.links > linked.found {
+                true => format!(
+                    "{} of {}",
+                    format::format_count(linked.found),
+                    format::format_count(linked.links)
+                ),
+                false => format::format_count(linked.found),
+            };
+            table.row([
+                format::human(linked.size),
+                names,
+                format::path(&linked.path),
+            ]);
+        }
+        print!("{}", table.render());
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    /// Tallies the files `names` in `dir`.
+    fn tally(dir: &Path, names: &[&str]) -> LinkTally {
+        let mut links = LinkTally::default();
+        for name in names {
+            let path = dir.join(name);
+            let metadata = std::fs::metadata(&path).unwrap();
+            links.add(&FileRecord::from_metadata(path, &metadata));
+        }
+        links
+    }
+
+    #[test]
+    fn totals_count_the_bytes_once_per_name_and_once_per_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a"), [0; 1000]).unwrap();
+        std::fs::hard_link(dir.path().join("a"), dir.path().join("b")).unwrap();
+        std::fs::write(dir.path().join("c"), [0; 500]).unwrap();
+
+        let links = tally(dir.path(), &["a", "b", "c"]);
+        // c has a single name and is left out
+        assert_eq!(links.totals(), (2000, 1000));
+        assert_eq!(links.to_json()["names"], 2);
+        assert_eq!(links.to_json()["files"], 1);
+    }
+
+    #[test]
+    fn names_outside_the_walk_save_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a"), [0; 1000]).unwrap();
+        std::fs::hard_link(dir.path().join("a"), dir.path().join("elsewhere")).unwrap();
+
+        let links = tally(dir.path(), &["a"]);
+        assert_eq!(links.totals(), (1000, 1000));
+        let largest = &links.to_json()["largest"][0];
+        assert_eq!(largest["names"], 1);
+        assert_eq!(largest["links"], 2);
+    }
+
+    #[test]
+    fn the_largest_take_the_most_bytes_over_their_names() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("big"), [0; 3000]).unwrap();
+        std::fs::hard_link(dir.path().join("big"), dir.path().join("big2")).unwrap();
+        std::fs::write(dir.path().join("small"), [0; 1000]).unwrap();
+        for name in ["small2", "small3", "small4", "small5"] {
+            std::fs::hard_link(dir.path().join("small"), dir.path().join(name)).unwrap();
+        }
+
+        let names = [
+            "big2", "big", "small3", "small", "small2", "small4", "small5",
+        ];
+        let links = tally(dir.path(), &names);
+        let largest = links
+            .largest()
+            .iter()
+            .map(|linked| {
+                (
+                    linked.path.file_name().unwrap().to_str().unwrap(),
+                    linked.found,
+                )
+            })
+            .collect::<Vec<_>>();
+        // 6,000 bytes over two names before 5,000 over five, each under
+        // its first path
+        assert_eq!(largest, [("big", 2), ("small", 5)]);
+    }
+}
@@ -0,0 +1,100 @@
+//! Classifies files by the magic bytes at their start.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// How many bytes are read from each file by default, enough to find the
+/// tar signature at offset 257 and to judge whether a file is text
+pub const DEFAULT_SAMPLE_LIMIT: u64 = 512;
+
+pub const UNREADABLE: &str = "unreadable";
+
+/// Signatures as (offset, bytes, category), checked in order.
+const SIGNATURES: &[(usize, &[u8], &str)] = &[
+    // Images
+    (0, b"\x89PNG\r\n\x1a\n", "image"),
+    (0, b"\xff\xd8\xff", "image"),
+    (0, b"GIF87a", "image"),
+    (0, b"GIF89a", "image"),
+    (0, b"II*\0", "image"),
+    (0, b"MM\0*", "image"),
+    (8, b"WEBP", "image"),
+    (4, b"ftypheic", "image"),
+    (4, b"ftypheix", "image"),
+    (4, b"ftypmif1", "image"),
+    // Audio, before video since both use ftyp and RIFF containers
+    (4, b"ftypM4A", "audio"),
+    (8, b"WAVE", "audio"),
+    (0, b"ID3", "audio"),
+    (0, b"fLaC", "audio"),
+    (0, b"OggS", "audio"),
+    (0, b"\xff\xfb", "audio"),
+    // Video
+    (4, b"ftyp", "video"),
+    (0, b"\x1a\x45\xdf\xa3", "video"),
+    (8, b"AVI ", "video"),
+    (0, b"\x00\x00\x01\xba", "video"),
+    (0, b"\x00\x00\x01\xb3", "video"),
+    (0, b"FLV", "video"),
+    // Archives and compressed data
+    (0, b"PK\x03\x04", "archive"),
+    (0, b"\x1f\x8b", "archive"),
+    (0, b"BZh", "archive"),
+    (0, b"\xfd7zXZ\0", "archive"),
+    (0, b"7z\xbc\xaf\x27\x1c", "archive"),
+    (0, b"Rar!\x1a\x07", "archive"),
+    (0, b"\x28\xb5\x2f\xfd", "archive"),
+    (257, b"ustar", "archive"),
+    // Documents
+    (0, b"%PDF", "document"),
+    (0, b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1", "document"),
+    // Executables
+    (0, b"\x7fELF", "executable"),
+    (0, b"MZ", "executable"),
+    (0, b"\xcf\xfa\xed\xfe", "executable"),
+    (0, b"\xfe\xed\xfa\xce", "executable"),
+    (0, b"\xca\xfe\xba\xbe", "executable"),
+    (0, b"\0asm", "executable"),
+    (0, b"#!", "executable"),
+];
+
+/// Reads up to `limit` bytes of the file at `path` and names its category.
+pub fn classify(path: &Path, limit: u64) -> &'static str {
+    let mut sample = Vec::new();
+    match File::open(path).and_then(|file| file.take(limit).read_to_end(&mut sample)) {
+        Ok(_) => classify_bytes(&sample),
+        Err(_) => UNREADABLE,
+    }
+}
+
+fn classify_bytes(sample: &[u8]) -> &'static str {
+    let signature = SIGNATURES.iter().find(|(offset, magic, _)| {
+        sample
+            .get(*offset..offset + magic.len())
+            .is_some_and(|bytes| bytes == *magic)
+    });
+    match signature {
+        Some((_, _, category)) => category,
+        None if is_text(sample) => "text",
+        None => "unknown",
+    }
+}
+
+/// UTF-8 without NUL bytes or control characters other than whitespace. A
+/// character cut off at the end of the sample is tolerated.
+fn is_text(sample: &[u8]) -> bool {
+    if sample.is_empty() {
+        return false;
+    }
+    let valid = match std::str::from_utf8(sample) {
+        Ok(text) => text,
+        Err(e) if e.error_len().is_none() => {
+            std::str::from_utf8(&sample[..e.valid_up_to()]).unwrap_or_default()
+        }
+        Err(_) => return false,
+    };
+    valid
+        .chars()
+        .all(|c| !c.is_control() || matches!(c, '\n' | '\r' | '\t' | '\x0c'))
+}
@@ -1,159 +1,2799 @@
-use std::path::Path;
+mod cli;
 
-use walkdir::WalkDir;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
-use libc::{ioctl, isatty, STDOUT_FILENO, TIOCGWINSZ};
+use rayon::prelude::*;
+use serde_json::{json, Value};
+use std::time::{Duration, Instant, SystemTime};
 
-pub fn get_terminal_width() -> Option<u16> {
-    // Check if stdout is a terminal first to avoid unnecessary unsafe operations
-    if unsafe { isatty(STDOUT_FILENO) } != 1 {
-        return None;
+use cli::{Args, Strict, TemplateSource};
+use dudist::anomalies::AnomalyTally;
+use dudist::archive::{self, ArchiveCounts};
+use dudist::budget::{Budgets, Overrun};
+use dudist::cache::{self, Cache};
+use dudist::cold::ColdTally;
+use dudist::compact::{self, Sizes};
+use dudist::dirstats::DirEntryTally;
+use dudist::distribution::{self, Distribution, RankIndex, Ranked};
+use dudist::events::{Events, Target};
+use dudist::examples::Examples;
+use dudist::groups::{self, GroupBy, Grouper};
+use dudist::hints::{self, Facts};
+use dudist::links::LinkTally;
+use dudist::mounts::{self, FsTypeFilter, MountLookup};
+use dudist::plot::{self, PlotStyle};
+use dudist::resources::{self, Usage};
+use dudist::retention::Retention;
+use dudist::roots::{self, MultiScan, RootState, RootStatus};
+use dudist::scan::{
+    self, DirRecord, FileRecord, Progress, ScanCounts, ScanError, ScanOptions, ScanSettings,
+    Visited,
+};
+use dudist::slack::SlackTally;
+use dudist::snapshot::Snapshot;
+use dudist::table::{Align, Table};
+use dudist::template::{self, Scope, Template};
+use dudist::terminal::Capabilities;
+use dudist::warnings::{Category, CategoryTally, Warnings};
+use dudist::{chart, check, estimate, export, format, history, ignore, interrupt, timeline};
+
+/// Exit code when there were files, but the filters excluded all of them
+const EXIT_NO_MATCHES: i32 = 1;
+
+/// Exit code when `--strict` met an entry the walk couldn't read
+const EXIT_UNREADABLE: i32 = 3;
+
+/// Exit code when a group exceeded its `--budgets` entry
+const EXIT_OVER_BUDGET: i32 = 4;
+
+/// Exit code when `--root-timeout` gave up on a root
+const EXIT_ROOT_TIMEOUT: i32 = 5;
+
+/// Everything the walk gathers for the report.
+struct Collected {
+    /// Kept one by one or, with `--compact`, counted in buckets
+    sizes: Sizes,
+    /// Size and modification time of each file, for the scatter plot, the
+    /// timeline and the retention preview
+    dated: Vec<(u64, SystemTime)>,
+    /// Sizes of the files without a usable modification time, for the
+    /// retention preview
+    undated: Vec<u64>,
+    groups: Grouper,
+    mounts: Grouper,
+    cold: Option<ColdTally>,
+    slack: Option<SlackTally>,
+    links: Option<LinkTally>,
+    examples: Option<Examples>,
+    archives: Option<ArchiveCounts>,
+    /// Bytes per extension, for the hints
+    extensions: Option<HashMap<String, u64>>,
+    anomalies: Option<AnomalyTally>,
+}
+
+/// Gathers the report from the files found by a scan.
+fn process_records(
+    args: &Args,
+    options: &ScanOptions,
+    records: impl Iterator<Item = FileRecord>,
+    mount_lookup: Option<&MountLookup>,
+    warnings: &Warnings,
+) -> Collected {
+    let start = SystemTime::now();
+    let mut sizes = Sizes::new(args.compact);
+    let mut dated = Vec::new();
+    let mut undated = Vec::new();
+    let mut groups = Grouper::new(args.compact);
+    let mut mounts = Grouper::new(args.compact);
+    let mut cold = args
+        .cold_after
+        .map(|age| ColdTally::new(age, args.cold_by, args.list_cold));
+    let mut slack = args.slack.then(SlackTally::default);
+    let mut links = args.hardlink_report.then(LinkTally::default);
+    let mut examples = args.examples.then(Examples::default);
+    let mut extensions = args.hints.then(HashMap::new);
+    let mut anomalies = args.anomalies.map(AnomalyTally::new);
+    // Files whose group can only be told by reading them, done in parallel
+    // once the walk is over
+    let mut pending = Vec::new();
+    let mut expander = args
+        .archives
+        .map(|mode| archive::Expander::new(mode, options));
+    let records = records.flat_map(|record| match &mut expander {
+        Some(expander) => expander.expand(record, warnings),
+        None => vec![record],
+    });
+    for record in records {
+        if args.strict_utf8 && record.path.to_str().is_none() {
+            eprintln!("error: {} is not valid UTF-8", format::path(&record.path));
+            std::process::exit(2);
+        }
+        let size = record.size;
+        if let Some(modified) = record.modified.filter(|&modified| modified > start) {
+            warnings.add(
+                Category::FutureModified,
+                Some(&record.path),
+                format!("modified {}", timeline::utc_timestamp(modified)),
+            );
+        }
+        if let Some(lookup) = mount_lookup {
+            mounts.add(lookup.mount_point(record.device), size);
+        }
+        if let Some(cold) = &mut cold {
+            cold.add(&record);
+        }
+        if let Some(slack) = &mut slack {
+            slack.add(&record);
+        }
+        if let Some(links) = &mut links {
+            links.add(&record);
+        }
+        if let Some(examples) = &mut examples {
+            examples.add(size, &record.path);
+        }
+        if let Some(extensions) = &mut extensions {
+            let extension = GroupBy::Extension.key(&args.path, &record, 0);
+            *extensions.entry(extension).or_default() += size;
+        }
+        if let Some(anomalies) = &mut anomalies {
+            let extension = GroupBy::Extension.key(&args.path, &record, 0);
+            anomalies.add(&extension, size, &record.path);
+        }
+        if args.scatter.is_some() || args.timeline.is_some() || !args.retention.is_empty() {
+            // Files without a usable modification time are left out, but
+            // for the retention preview, which counts them apart
+            match record.modified {
+                Some(modified) => dated.push((size, modified)),
+                None if !args.retention.is_empty() => undated.push(size),
+                None => {}
+            }
+        }
+        match args.group_by {
+            Some(group_by) if group_by.reads_content() => {
+                pending.push(record);
+            }
+            Some(group_by) => groups.add(
+                group_by.key(&args.path, &record, args.magic_sample_limit),
+                size,
+            ),
+            None => {}
+        }
+        sizes.add(size);
     }
+    if let Some(group_by) = args.group_by {
+        let keys = pending
+            .par_iter()
+            .map(|record| {
+                let key = group_by.key(&args.path, record, args.magic_sample_limit);
+                (key, record.size)
+            })
+            .collect::<Vec<_>>();
+        for (key, size) in keys {
+            groups.add(key, size);
+        }
+    }
+    Collected {
+        sizes,
+        dated,
+        undated,
+        groups,
+        mounts,
+        cold,
+        slack,
+        links,
+        examples,
+        archives: expander.map(|expander| expander.counts),
+        extensions,
+        anomalies,
+    }
+}
 
-    // Isolate the unsafe code in a small, well-defined block
-    let ws = unsafe {
-        let mut ws: libc::winsize = std::mem::zeroed();
-        if ioctl(STDOUT_FILENO, TIOCGWINSZ, &mut ws) != 0 {
+/// Counts the matching files without keeping their sizes around.
+fn count_files(
+    args: &Args,
+    options: &ScanOptions,
+    skipped: &mut Vec<ScanError>,
+    warnings: &Warnings,
+    events: &mut Option<Events>,
+    caps: &Capabilities,
+) -> (u64, ScanCounts, Vec<RootStatus>) {
+    let visited = Visited::default();
+    let progress = events.as_ref().map(Events::progress);
+    let mut walk = walk(args, options, &visited, progress, caps);
+    let records = readable(walk.by_ref(), args.strict, skipped, warnings);
+    let total = track(records, events).map(|record| record.size).sum();
+    note_revisits(&visited, warnings);
+    (total, walk.counts(), walk.statuses(warnings))
+}
+
+/// The walk of the PATHs, the one on its own or several at once.
+enum Walk<'a> {
+    Single(scan::Scan<'a>),
+    Roots(MultiScan),
+}
+
+/// Walks the PATHs, skipping the directories reached again that `visited`
+/// records and reporting every entry to `progress` if given.
+fn walk<'a>(
+    args: &Args,
+    options: &'a ScanOptions,
+    visited: &Visited,
+    progress: Option<&Progress>,
+    caps: &Capabilities,
+) -> Walk<'a> {
+    if args.roots.len() > 1 || args.root_timeout.is_some() {
+        let mut scan = roots::scan_roots(&args.roots, options, args.jobs, args.root_timeout)
+            .skip_visited(visited)
+            .show_progress(caps);
+        if let Some(progress) = progress {
+            scan = scan.report_progress(progress);
+        }
+        if args.empty_dirs {
+            scan = scan.find_empty_dirs();
+        }
+        Walk::Roots(scan)
+    } else {
+        let mut scan = scan::scan_iter(&args.path, options).skip_visited(visited);
+        if let Some(progress) = progress {
+            scan = scan.report_progress(progress);
+        }
+        if args.empty_dirs {
+            scan = scan.find_empty_dirs();
+        }
+        if args.stat.is_some() {
+            scan = scan.record_directories();
+        }
+        Walk::Single(scan)
+    }
+}
+
+/// Warns about the directories skipped for having been walked already.
+fn note_revisits(visited: &Visited, warnings: &Warnings) {
+    for (path, first) in visited.skipped() {
+        warnings.add(
+            Category::Revisited,
+            Some(&path),
+            format!("the same directory as {}", format::path(&first)),
+        );
+    }
+}
+
+impl Walk<'_> {
+    fn counts(&self) -> ScanCounts {
+        match self {
+            Walk::Single(scan) => scan.counts,
+            Walk::Roots(scan) => scan.counts(),
+        }
+    }
+
+    /// The entries per directory, for `--stat dir-entries`, which only
+    /// walks of a single PATH record.
+    fn dir_entries(&self, args: &Args) -> Option<DirEntryTally> {
+        match self {
+            Walk::Single(scan) => tally_dir_entries(args, scan.directories()),
+            Walk::Roots(_) => None,
+        }
+    }
+
+    /// The topmost empty directories, in path order.
+    fn empty_trees(&self) -> Vec<PathBuf> {
+        let mut trees = match self {
+            Walk::Single(scan) => scan.empty_trees().to_vec(),
+            Walk::Roots(scan) => scan.empty_trees().to_vec(),
+        };
+        trees.sort();
+        trees
+    }
+
+    /// How the walk of each root went, when there were several, with a
+    /// warning for each that timed out.
+    fn statuses(&self, warnings: &Warnings) -> Vec<RootStatus> {
+        let Walk::Roots(scan) = self else {
+            return Vec::new();
+        };
+        for status in scan.statuses() {
+            if status.state == RootState::TimedOut {
+                warnings.add(
+                    Category::RootTimeout,
+                    Some(&status.root),
+                    format!(
+                        "abandoned after {} with {} matching files",
+                        format::human_duration(status.elapsed),
+                        format::format_count(status.counts.matched)
+                    ),
+                );
+            }
+        }
+        scan.statuses().to_vec()
+    }
+}
+
+impl Iterator for Walk<'_> {
+    type Item = Result<FileRecord, ScanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Walk::Single(scan) => scan.next(),
+            Walk::Roots(scan) => scan.next(),
+        }
+    }
+}
+
+fn timed_out(statuses: &[RootStatus]) -> bool {
+    statuses
+        .iter()
+        .any(|status| status.state == RootState::TimedOut)
+}
+
+/// Renders how far the walk of each root got.
+fn render_roots(statuses: &[RootStatus]) -> String {
+    let mut table = Table::new(&[Align::Left, Align::Left, Align::Right, Align::Left]);
+    for (i, status) in statuses.iter().enumerate() {
+        let elapsed = format::human_duration(status.elapsed);
+        let state = match status.state {
+            RootState::Done => format!("complete in {}", elapsed),
+            RootState::TimedOut => format!("timed out after {}, incomplete", elapsed),
+            // Only left when the run was interrupted
+            RootState::Waiting | RootState::Scanning => "interrupted, incomplete".to_string(),
+        };
+        table.row([
+            if i == 0 { "Roots:" } else { "" }.to_string(),
+            format::path(&status.root),
+            format!("{} files", format::format_count(status.counts.matched)),
+            state,
+        ]);
+    }
+    table.render()
+}
+
+/// Passes on the files of a scan and drops the entries it couldn't read
+/// with a warning, unless `--strict` asks to end the run on the first of
+/// them or to collect them in `skipped`.
+fn readable<'a>(
+    results: impl Iterator<Item = Result<FileRecord, ScanError>> + 'a,
+    strict: Option<Strict>,
+    skipped: &'a mut Vec<ScanError>,
+    warnings: &'a Warnings,
+) -> impl Iterator<Item = FileRecord> + 'a {
+    results.filter_map(move |result| {
+        let error = match result {
+            Ok(record) => return Some(record),
+            Err(error) => error,
+        };
+        // Skipped on purpose rather than unreadable, so --strict lets it be
+        if error.too_long {
+            warnings.add(Category::TooLong, error.path.as_deref(), error.message);
             return None;
         }
-        ws
+        match strict {
+            Some(Strict::Abort) => {
+                eprintln!("error: {}", error);
+                std::process::exit(EXIT_UNREADABLE);
+            }
+            Some(Strict::ErrorsOnly) => skipped.push(error),
+            None => {
+                let path = error.path.as_deref();
+                match &error.loop_ancestor {
+                    Some(ancestor) => warnings.add(
+                        Category::SymlinkLoop,
+                        path,
+                        format!("points to {}", ancestor.display()),
+                    ),
+                    None => warnings.add(Category::Unreadable, path, error.message),
+                }
+            }
+        }
+        None
+    })
+}
+
+/// Passes on the files of a scan, counting them for `--progress-json`.
+fn track<'a>(
+    records: impl Iterator<Item = FileRecord> + 'a,
+    events: &'a mut Option<Events>,
+) -> impl Iterator<Item = FileRecord> + 'a {
+    records.inspect(move |record| {
+        if let Some(events) = events {
+            events.file(record);
+        }
+    })
+}
+
+/// Sends the warnings so far and the end of the scan to `--progress-json`,
+/// with the empty directories when they were looked for.
+fn finish_events(
+    events: Option<Events>,
+    counts: &ScanCounts,
+    empty_dirs: bool,
+    warnings: &Warnings,
+) {
+    let Some(events) = events else {
+        return;
     };
+    events.warnings(warnings);
+    let empty_dirs = empty_dirs.then_some(counts.empty_dirs);
+    if let Err(e) = events.finish(counts, empty_dirs, interrupt::interrupted()) {
+        warnings.add(Category::ProgressJson, None, e);
+    }
+}
+
+/// Lists the entries `--strict=errors-only` collected, telling whether
+/// there were any.
+fn report_skipped(skipped: &[ScanError]) -> bool {
+    if skipped.is_empty() {
+        return false;
+    }
+    // In path order, which doesn't depend on the walk
+    let mut skipped = skipped.iter().collect::<Vec<_>>();
+    skipped.sort_by(|a, b| a.path.cmp(&b.path));
+    eprintln!(
+        "error: skipped {} entries that couldn't be read:",
+        format::format_count(skipped.len() as u64)
+    );
+    for error in skipped {
+        eprintln!("  {}", error);
+    }
+    true
+}
 
-    Some(ws.ws_col)
+/// The files a run reports on, and where they came from.
+struct Found {
+    records: Vec<FileRecord>,
+    counts: ScanCounts,
+    /// Age of the cached scan, when it was used as is
+    cache_age: Option<Duration>,
+    /// Directories walked again and taken from the cache, for incremental
+    /// scans
+    incremental: Option<(u64, u64)>,
+    /// The entries per directory, for `--stat dir-entries`
+    dir_entries: Option<DirEntryTally>,
 }
 
-#[derive(Debug)]
-struct Distribution {
-    min: u64,
-    max: u64,
-    median: f64,
-    lower_quartile: f64,
-    upper_quartile: f64,
+/// Takes the files from the cache when it is fresh, and scans and stores
+/// them otherwise. Incremental scans start from any cached scan and only
+/// walk the modified directories.
+fn cached_scan(
+    args: &Args,
+    options: &ScanOptions,
+    cache: &Cache,
+    skipped: &mut Vec<ScanError>,
+    warnings: &Warnings,
+) -> Found {
+    // A cached scan doesn't remember what it couldn't read, so --strict
+    // always walks the whole tree
+    let cached = if args.refresh || args.strict.is_some() {
+        None
+    } else {
+        // Incremental scans check every directory on their own
+        cache
+            .load(args.cache_trust || args.incremental)
+            .unwrap_or_else(|e| {
+                warnings.add(Category::Cache, None, format!("{}, rescanning", e));
+                None
+            })
+    };
+    match cached {
+        Some(cached) if args.incremental => {
+            let scan = cache::incremental_scan(&args.path, options, cached, args.incremental_fast);
+            save_scan(
+                cache,
+                &scan.records,
+                &scan.directories,
+                &scan.counts,
+                warnings,
+            );
+            Found {
+                dir_entries: tally_dir_entries(args, &scan.directories),
+                records: scan.records,
+                counts: scan.counts,
+                cache_age: None,
+                incremental: Some((scan.rewalked, scan.reused)),
+            }
+        }
+        Some(cached) => Found {
+            dir_entries: tally_dir_entries(args, &cached.directories),
+            cache_age: Some(cached.age()),
+            records: cached.records,
+            counts: cached.counts,
+            incremental: None,
+        },
+        None => {
+            let visited = Visited::default();
+            let mut scan = scan::scan_iter(&args.path, options)
+                .record_directories()
+                .skip_visited(&visited);
+            let records =
+                readable(scan.by_ref(), args.strict, skipped, warnings).collect::<Vec<_>>();
+            note_revisits(&visited, warnings);
+            save_scan(cache, &records, scan.directories(), &scan.counts, warnings);
+            Found {
+                records,
+                counts: scan.counts,
+                cache_age: None,
+                incremental: None,
+                dir_entries: tally_dir_entries(args, scan.directories()),
+            }
+        }
+    }
 }
 
-impl Distribution {
-    fn from_vec(sizes: Vec<u64>) -> Distribution {
-        let mut sizes = sizes;
-        sizes.sort();
-        Distribution {
-            min: sizes[0],
-            max: sizes[sizes.len() - 1],
-            median: if sizes.len() % 2 == 0 {
-                let mid = sizes.len() / 2;
-                (sizes[mid - 1] + sizes[mid]) as f64 / 2.0
-            } else {
-                sizes[sizes.len() / 2] as f64
-            },
-            lower_quartile: if sizes.len() % 4 == 0 {
-                let mid = sizes.len() / 4;
-                (sizes[mid - 1] + sizes[mid]) as f64 / 2.0
-            } else {
-                sizes[sizes.len() / 4] as f64
-            },
-            upper_quartile: if sizes.len() % 4 == 0 {
-                let mid = sizes.len() * 3 / 4;
-                (sizes[mid - 1] + sizes[mid]) as f64 / 2.0
-            } else {
-                sizes[sizes.len() * 3 / 4] as f64
-            },
+/// Counts the entries of the `directories` a scan recorded, for `--stat
+/// dir-entries`. Those at the `--max-depth` limit weren't listed and are
+/// left out.
+fn tally_dir_entries(args: &Args, directories: &[DirRecord]) -> Option<DirEntryTally> {
+    args.stat?;
+    let mut tally = DirEntryTally::new(args.stat_files_only, args.top_dirs);
+    for dir in directories {
+        let depth = dir
+            .path
+            .strip_prefix(&args.path)
+            .map_or(0, |path| path.components().count());
+        if args.max_depth.is_none_or(|max| depth < max) {
+            tally.add(dir);
         }
     }
+    Some(tally)
 }
 
-fn process_dir(path: &Path) -> Vec<u64> {
-    WalkDir::new(path)
-        .into_iter()
-        .filter_map(|entry| entry.ok())
-        .filter(|entry| entry.file_type().is_file())
-        .map(|entry| {
-            let metadata = entry.metadata().unwrap();
-            metadata.len()
-        })
-        .filter(|size| *size > 4096)
-        .collect::<Vec<_>>()
+fn save_scan(
+    cache: &Cache,
+    records: &[FileRecord],
+    directories: &[DirRecord],
+    counts: &ScanCounts,
+    warnings: &Warnings,
+) {
+    // A partial scan would be taken for the whole tree next time
+    if interrupt::interrupted() {
+        return;
+    }
+    if let Err(e) = cache.save(records, directories, counts) {
+        warnings.add(Category::Cache, None, e);
+    }
+}
+
+/// Renders the hints for the next run, nothing without any.
+fn render_hints(hints: &[String]) -> String {
+    if hints.is_empty() {
+        return String::new();
+    }
+    let mut out = String::from("Hints:\n");
+    for hint in hints {
+        out += &format!("  {}\n", hint);
+    }
+    out
+}
+
+/// Prints the warnings section at the end of the run, unless
+/// `--no-warnings` asks not to.
+fn print_warnings(args: &Args, warnings: &Warnings, caps: &Capabilities) {
+    // The JSON report has them already
+    if args.json {
+        return;
+    }
+    count_warnings(warnings);
+    if args.warnings {
+        eprint!("{}", warnings.render(caps));
+    }
+}
+
+/// Adds the warnings only counted along the way to `warnings`.
+fn count_warnings(warnings: &Warnings) {
+    warnings.add_count(Category::NonUtf8, format::non_utf8_paths());
+    warnings.add_count(Category::InvalidValue, format::invalid_values());
+}
+
+/// The warnings section for `--json`, a `warnings` array unless
+/// `--no-warnings`.
+fn add_warnings_json(report: &mut Value, args: &Args, warnings: &Warnings) {
+    if args.warnings {
+        count_warnings(warnings);
+        let tallies = warnings
+            .tallies()
+            .iter()
+            .map(CategoryTally::to_json)
+            .collect::<Vec<_>>();
+        report["warnings"] = json!(tallies);
+    }
+}
+
+fn interrupted_message(counts: &ScanCounts) -> String {
+    format!(
+        "Interrupted after {} files, the results are partial",
+        format::format_count(counts.files)
+    )
 }
 
-fn print_distribution(dist: &Distribution) {
+/// Explains why nothing is reported and returns the matching exit code.
+fn report_no_matches(counts: &ScanCounts, min_size: u64) -> i32 {
+    if counts.files == 0 {
+        println!("No files found in the directory");
+        return 0;
+    }
+    let reason = if min_size > 0 {
+        format!("all at most {}; try --min-size 0", format::human(min_size))
+    } else {
+        "all are empty".to_string()
+    };
     println!(
-        "Smallest:       {:#.2}",
-        byte_unit::AdjustedByte::from(byte_unit::Byte::from_u64(dist.min))
+        "Found {} files but none matched the filters ({})",
+        format::format_count(counts.files),
+        reason
     );
-    println!(
-        "Lower Quartile: {:#.2}",
-        byte_unit::AdjustedByte::from(
-            byte_unit::Byte::from_f64(dist.lower_quartile.round()).expect("Invalid lower quartile")
+    EXIT_NO_MATCHES
+}
+
+fn print_timing(count: usize, total: u64, elapsed: Duration, caps: &Capabilities) {
+    let seconds = elapsed.as_secs_f64();
+    let rate = if seconds > 0.0 {
+        format!(
+            " {} {} files/s",
+            caps.text("\u{2014}", "-"),
+            format::format_count((count as f64 / seconds) as u64)
         )
+    } else {
+        String::new()
+    };
+    // Short scans get an extra digit so they don't read as 0.0s
+    let precision = if seconds < 10.0 { 2 } else { 1 };
+    println!(
+        "Scanned {} files ({:#.1}) in {:.*}s{}",
+        format::format_count(count as u64),
+        format::binary_unit(total),
+        precision,
+        seconds,
+        rate
     );
+}
+
+/// `--fast-estimate`: the report over the statted files, scaled up to all.
+fn print_estimate(
+    args: &Args,
+    options: &ScanOptions,
+    stat_budget: u64,
+    run_start: Instant,
+    located: &[Located],
+    warnings: &Warnings,
+    caps: &Capabilities,
+) {
+    let start = Instant::now();
+    let estimate = estimate::estimate(&args.path, options, stat_budget);
+    let elapsed = start.elapsed();
+    warnings.add_count(Category::Recovered, estimate.counts.recovered);
+    if interrupt::interrupted() {
+        match args.json {
+            true => eprintln!("{}", interrupted_message(&estimate.counts)),
+            false => println!("{}", interrupted_message(&estimate.counts)),
+        }
+        std::process::exit(interrupt::EXIT_INTERRUPTED);
+    }
+    let mut sizes = estimate
+        .records
+        .iter()
+        .map(|record| record.size)
+        .collect::<Vec<_>>();
+    sizes.sort();
+    if args.json {
+        // The files and bytes of all the files listed, not just the sample
+        let mut report = json_report(args, options, sizes.as_slice());
+        report["files"] = json!(estimate.matched().round() as u64);
+        report["total"] = json!(estimate.total().round() as u64);
+        report["estimate"] = json!({
+            "listed": estimate.files,
+            "statted": estimate.statted,
+            "stride": estimate.stride,
+            "failed": estimate.failed,
+        });
+        if args.timing {
+            report["timing"] = timing_json(sizes.len(), elapsed);
+        }
+        if estimate.stride > 1 && !sizes.is_empty() {
+            report["confidence_intervals"] =
+                intervals_json(&sizes, &args.plot_marks, 1.0 / estimate.stride as f64);
+        }
+        if args.examples && !sizes.is_empty() {
+            let mut examples = Examples::default();
+            for record in &estimate.records {
+                examples.add(record.size, &record.path);
+            }
+            let dist = Distribution::from_sorted(&sizes);
+            report["examples"] = examples_json(&examples, &dist);
+            // Like the note above the table
+            report["examples"]["sampled"] = json!(estimate.stride > 1);
+        }
+        if args.resource_stats {
+            report["resources"] = Usage::measure(run_start, estimate_retained(&estimate)).to_json();
+        }
+        add_warnings_json(&mut report, args, warnings);
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&report).expect("valid JSON")
+        );
+        if sizes.is_empty() && estimate.files > 0 {
+            std::process::exit(EXIT_NO_MATCHES);
+        }
+        return;
+    }
+    if estimate.files == 0 {
+        std::process::exit(report_no_matches(&estimate.counts, args.min_size));
+    }
+    if args.header {
+        print!(
+            "{}",
+            render_header(&root_names(args), Some(&options.describe()))
+        );
+    }
     println!(
-        "Median:         {:#.2}",
-        byte_unit::AdjustedByte::from(
-            byte_unit::Byte::from_f64(dist.median.round()).expect("Invalid median")
-        )
+        "Files listed:    {} (of any size, the size filters need a stat)",
+        format::format_count(estimate.files)
     );
+    if estimate.stride > 1 {
+        println!(
+            "Statted {} of them (1 in {}), the figures below are estimates",
+            format::format_count(estimate.statted),
+            format::format_count(estimate.stride)
+        );
+    } else {
+        println!("Statted all of them, within the --stat-budget");
+    }
+    if estimate.failed > 0 {
+        println!(
+            "{} of the statted files couldn't be read and were left out",
+            format::format_count(estimate.failed)
+        );
+    }
+    let about = if estimate.stride > 1 { "~" } else { "" };
     println!(
-        "Upper Quartile: {:#.2}",
-        byte_unit::AdjustedByte::from(
-            byte_unit::Byte::from_f64(dist.upper_quartile.round()).expect("Invalid upper quartile")
-        )
+        "Matching files:  {}{}",
+        about,
+        format::format_count(estimate.matched().round() as u64)
     );
     println!(
-        "Largest:        {:#.2}",
-        byte_unit::AdjustedByte::from(byte_unit::Byte::from_u64(dist.max))
+        "Total size:      {}{}",
+        about,
+        format::human_f64(estimate.total())
     );
+    if sizes.is_empty() {
+        println!("None of the statted files matched the filters");
+        std::process::exit(EXIT_NO_MATCHES);
+    }
+    let sampled = (estimate.stride > 1).then(|| 1.0 / estimate.stride as f64);
+    let drawn = print_distribution(args, sizes.as_slice(), sampled, located, caps);
+    if args.examples {
+        let mut examples = Examples::default();
+        for record in &estimate.records {
+            examples.add(record.size, &record.path);
+        }
+        if estimate.stride > 1 {
+            println!("Examples are among the statted files:");
+        }
+        print!("{}", render_examples(&examples, &drawn.dist));
+    }
+    if args.timing {
+        print_timing(sizes.len(), sizes.iter().sum(), elapsed, caps);
+    }
+    // The paths of all the files, and the records of those statted
+    if args.resource_stats {
+        print!(
+            "{}",
+            Usage::measure(run_start, estimate_retained(&estimate)).render()
+        );
+    }
+}
+
+/// The entries `--fast-estimate` keeps: the paths of all the files, and the
+/// records of those statted.
+fn estimate_retained(estimate: &estimate::Estimate) -> u64 {
+    estimate.files + estimate.records.len() as u64
+}
+
+/// The values a full report keeps for each file, for the statistics, plots
+/// and previews.
+fn retained(sizes: &Sizes, dated: &[(u64, SystemTime)], undated: &[u64]) -> u64 {
+    (sizes.exact().map_or(0, <[u64]>::len) + dated.len() + undated.len()) as u64
+}
+
+/// Renders the summary statistics as a table of human-readable value, exact
+/// bytes and percentage of the largest file, with the `marks` (percentile,
+/// value) in order among them.
+///
+/// When `sizes` are a sample, `sampled` being the share of the files in it,
+/// the percentiles also get half the width of their 95% confidence interval.
+/// The extremes don't, the sample can't tell how far beyond them the files go.
+fn render_distribution(
+    dist: &Distribution,
+    marks: &[(f64, f64)],
+    mad: f64,
+    sizes: &(impl Ranked + ?Sized),
+    sampled: Option<f64>,
+    caps: &Capabilities,
+) -> String {
+    let mut table = Table::new(&[
+        Align::Left,
+        Align::Right,
+        Align::Right,
+        Align::Right,
+        Align::Left,
+    ]);
+    let mut stats = vec![
+        (0.0, "Smallest:".to_string(), dist.min as f64),
+        (25.0, "Lower Quartile:".to_string(), dist.lower_quartile),
+        (50.0, "Median:".to_string(), dist.median),
+        (75.0, "Upper Quartile:".to_string(), dist.upper_quartile),
+        (100.0, "Largest:".to_string(), dist.max as f64),
+    ];
+    for &(percentile, value) in marks {
+        stats.push((percentile, format!("p{}:", percentile), value));
+    }
+    // Stable, so the named statistics come first on a tie
+    stats.sort_by(|a, b| a.0.total_cmp(&b.0));
+    for (percentile, name, value) in stats {
+        let interval = match sampled {
+            Some(fraction) if percentile > 0.0 && percentile < 100.0 => {
+                let (low, high) = distribution::percentile_interval(sizes, percentile, fraction);
+                format!(
+                    "({}{})",
+                    caps.text("±", "+/-"),
+                    format::human_f64((high - low) / 2.0)
+                )
+            }
+            _ => String::new(),
+        };
+        let share = value / dist.max as f64 * 100.0;
+        table.row([
+            name,
+            format::human_f64(value),
+            format::exact_f64(value),
+            match share.is_finite() {
+                true => format!("{:.1}%", share),
+                false => "n/a".to_string(),
+            },
+            interval,
+        ]);
+    }
+    // Spreads aren't positions on the axis, so they get no percentage
+    let spreads = [("IQR:", dist.interquartile_range()), ("MAD:", mad)];
+    for (name, value) in spreads {
+        table.row([
+            name.to_string(),
+            format::human_f64(value),
+            format::exact_f64(value),
+            String::new(),
+            String::new(),
+        ]);
+    }
+    table.render()
+}
+
+/// Renders a file of about the size of each of the quartiles and the
+/// extremes, with its exact size.
+fn render_examples(examples: &Examples, dist: &Distribution) -> String {
+    let mut table = Table::new(&[Align::Left, Align::Right, Align::Left]);
+    table.row(["Example for", "Size", "File"].map(str::to_string));
+    for (name, _, value) in example_stats(dist) {
+        if let Some(example) = examples.closest(value) {
+            table.row([
+                name.to_string(),
+                format!("{} B", format::format_count(example.size)),
+                format::path(&example.path),
+            ]);
+        }
+    }
+    table.render()
+}
+
+/// The statistics that get an example: their name in the table and in
+/// `--json`, and their value.
+fn example_stats(dist: &Distribution) -> [(&'static str, &'static str, f64); 5] {
+    [
+        ("Smallest", "min", dist.min as f64),
+        ("Lower Quartile", "lower_quartile", dist.lower_quartile),
+        ("Median", "median", dist.median),
+        ("Upper Quartile", "upper_quartile", dist.upper_quartile),
+        ("Largest", "max", dist.max as f64),
+    ]
+}
+
+/// [`render_examples`] for `--json`, keyed like the distribution.
+fn examples_json(examples: &Examples, dist: &Distribution) -> Value {
+    let mut json = json!({});
+    for (_, key, value) in example_stats(dist) {
+        if let Some(example) = examples.closest(value) {
+            json[key] = json!({
+                "path": format::path_to_json(&example.path),
+                "size": example.size,
+            });
+        }
+    }
+    json
+}
+
+/// Follows a symbolic link given as the root, with a note, and tells
+/// whether the root is a single file. A root that doesn't exist fails
+/// straight away, rather than passing for an empty tree.
+fn resolve_root(path: &Path) -> Result<(PathBuf, bool), String> {
+    let shown = format::path(path);
+    let metadata = std::fs::symlink_metadata(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => format!("{} doesn't exist", shown),
+        _ => format!("can't read {}: {}", shown, e),
+    })?;
+    let mut root = path.to_path_buf();
+    if metadata.file_type().is_symlink() {
+        root = std::fs::canonicalize(path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => format!(
+                "{} is a symbolic link to {}, which doesn't exist",
+                shown,
+                std::fs::read_link(path)
+                    .map_or_else(|_| "?".to_string(), |target| format::path(&target))
+            ),
+            _ => format!("can't follow the symbolic link {}: {}", shown, e),
+        })?;
+        eprintln!(
+            "note: {} is a symbolic link, scanning {}",
+            shown,
+            format::path(&root)
+        );
+    }
+    let is_file = std::fs::metadata(&root).is_ok_and(|metadata| metadata.is_file());
+    Ok((root, is_file))
 }
 
-fn plot_box_diagram(dist: &Distribution, max_value: u64, width: u16) {
-    let light_shade = "\u{2591}"; // use between min and lower quartile, and upper quartile and max
-    let medium_shade = "\u{2592}"; // use between lower quartile and median, and median and upper quartile
-    let dark_shade = "\u{2593}"; // use for median
-    let cli_width = width as usize - 40;
+/// What [`print_distribution`] settled on, for the plots after it.
+struct Drawn {
+    dist: Distribution,
+    plot_style: PlotStyle,
+    clip: Option<plot::Clip>,
+}
 
-    let min = (dist.min as f64 / max_value as f64 * cli_width as f64).round() as usize;
-    let lower_quartile =
-        (dist.lower_quartile as f64 / max_value as f64 * cli_width as f64).round() as usize;
-    let median = (dist.median as f64 / max_value as f64 * cli_width as f64).round() as usize;
-    let upper_quartile =
-        (dist.upper_quartile as f64 / max_value as f64 * cli_width as f64).round() as usize;
-    let max = (dist.max as f64 / max_value as f64 * cli_width as f64).round() as usize;
+/// Prints the statistics of `sizes` (non-empty) and plots them, marking the
+/// `located` files. `sampled` is the share of the files `sizes` are a sample
+/// of, if they are one.
+fn print_distribution(
+    args: &Args,
+    sizes: &(impl Ranked + ?Sized),
+    sampled: Option<f64>,
+    located: &[Located],
+    caps: &Capabilities,
+) -> Drawn {
+    let dist = Distribution::from_ranked(sizes);
+    // Braille can't be drawn in ASCII, fall back to shades
+    let plot_style = match args.plot_style {
+        PlotStyle::Braille if !caps.unicode => PlotStyle::Shade,
+        style => style,
+    };
+    let clip = args.clip.map(|spec| plot::Clip::new(spec, sizes));
+    // Every statistic of a single file is its size, with nothing to plot
+    if sizes.count() == 1 {
+        println!(
+            "Size:            {} ({} B), the only file",
+            format::human(dist.min),
+            format::format_count(dist.min)
+        );
+        return Drawn {
+            dist,
+            plot_style,
+            clip,
+        };
+    }
+    // The statistics would all repeat the same size
+    if dist.is_degenerate() {
+        let count = format::format_count(sizes.count() as u64);
+        if dist.min == dist.max {
+            println!(
+                "All {} files are exactly {}",
+                count,
+                format::human(dist.min)
+            );
+        } else {
+            println!(
+                "All {} files are about {}, from {} B to {} B",
+                count,
+                format::human(dist.min),
+                format::format_count(dist.min),
+                format::format_count(dist.max)
+            );
+        }
+        if !args.ranks.is_empty() {
+            print!("{}", render_ranks(sizes, &args.ranks));
+        }
+        plot::plot_degenerate(&dist, caps);
+        if !located.is_empty() {
+            print!("{}", render_located(located, sizes));
+        }
+        return Drawn {
+            dist,
+            plot_style,
+            clip,
+        };
+    }
+    let marks = args
+        .plot_marks
+        .iter()
+        .map(|&p| (p, sizes.percentile(p)))
+        .collect::<Vec<_>>();
+    let mad = distribution::median_absolute_deviation(sizes, dist.median);
     print!(
-        "Smallest: {:#.2} ",
-        byte_unit::AdjustedByte::from(byte_unit::Byte::from_u64(dist.min))
+        "{}",
+        render_distribution(&dist, &marks, mad, sizes, sampled, caps)
     );
-    for _ in 0..min {
-        print!(" ");
+    // Above 256 bytes a bucket holds sizes that differ, so only exact sizes
+    // tell which are common
+    if args.compact {
+        println!(
+            "(counted in buckets, the statistics between the extremes are within {:.1}%)",
+            compact::RELATIVE_ERROR * 100.0
+        );
+    } else {
+        print!("{}", render_modes(sizes, args.modes));
+    }
+    if !args.ranks.is_empty() {
+        print!("{}", render_ranks(sizes, &args.ranks));
+    }
+
+    if args.violin {
+        plot::plot_violin(&dist, sizes, caps, args.violin_height);
+    } else {
+        let markers = located
+            .iter()
+            .map(|file| (file.label, file.size))
+            .collect::<Vec<_>>();
+        plot::plot_box_diagram(
+            &dist,
+            clip.as_ref(),
+            caps,
+            plot_style,
+            args.origin,
+            &markers,
+            &marks,
+        );
+    }
+    if !located.is_empty() {
+        print!("{}", render_located(located, sizes));
+    }
+    Drawn {
+        dist,
+        plot_style,
+        clip,
+    }
+}
+
+/// The block at the top of reports telling what was scanned and how.
+fn render_header(roots: &[String], settings: Option<&ScanSettings>) -> String {
+    let mut header = format!("dudist {}\n", env!("CARGO_PKG_VERSION"));
+    header += &format!("Roots:     {}\n", roots.join(", "));
+    if let Some(settings) = settings {
+        header += &format!("Filters:   {}\n", settings.filters.join(", "));
+        header += &format!("Traversal: {}\n", settings.traversal.join(", "));
+    }
+    header + "\n"
+}
+
+/// What `--dry-run` prints: the header, then what the walk would skip
+/// besides the filters, what was loaded and where the report would go.
+/// The mount table is read unless `mount_table` has it already.
+fn render_plan(
+    args: &Args,
+    options: &ScanOptions,
+    mount_table: &[mounts::Mount],
+    cache: Option<&Cache>,
+) -> String {
+    let mut plan = render_header(&root_names(args), Some(&options.describe()));
+    let mut line = |key: &str, value: String| plan += &format!("{:<11}{}\n", key, value);
+    if options.ignore_files() {
+        let files = args
+            .roots
+            .iter()
+            .filter_map(|root| {
+                let rules = ignore::rule_count(root)?;
+                Some(format!(
+                    "{} ({} {})",
+                    format::path(&root.join(ignore::FILE_NAME)),
+                    rules,
+                    if rules == 1 { "rule" } else { "rules" }
+                ))
+            })
+            .collect::<Vec<_>>();
+        line(
+            "Ignore:",
+            match files.is_empty() {
+                true => format!(
+                    "none at the {}, those below are read during the walk",
+                    if args.roots.len() == 1 {
+                        "root"
+                    } else {
+                        "roots"
+                    }
+                ),
+                false => format!(
+                    "{}, and those below, read during the walk",
+                    files.join(", ")
+                ),
+            },
+        );
+    }
+    if options.one_file_system() || options.fs_filter().is_some() {
+        let read;
+        let mount_table = match mount_table {
+            [] => {
+                read = mounts::read_mounts();
+                read.as_deref().map_err(Clone::clone)
+            }
+            table => Ok(table),
+        };
+        let pruned = mount_table.and_then(|table| {
+            let mut pruned = Vec::new();
+            for root in &args.roots {
+                let metadata = std::fs::metadata(root)
+                    .map_err(|e| format!("can't read {}: {}", format::path(root), e))?;
+                let device = scan::device(&metadata);
+                pruned.extend(mounts::pruned(table, root, device, options));
+            }
+            Ok(pruned)
+        });
+        match pruned {
+            Ok(pruned) if pruned.is_empty() => line("Pruned:", "no mounts".to_string()),
+            Ok(pruned) => {
+                for (i, (mount, reason)) in pruned.iter().enumerate() {
+                    line(
+                        if i == 0 { "Pruned:" } else { "" },
+                        format!("{} ({})", format::path(&mount.mount_point), reason),
+                    );
+                }
+            }
+            Err(e) => line("Pruned:", format!("can't tell, {}", e)),
+        }
+    }
+    if let Some(source) = &args.template {
+        line(
+            "Template:",
+            match source {
+                TemplateSource::File(path) => format::path(path),
+                TemplateSource::Inline(_) => "given inline".to_string(),
+                TemplateSource::Preset(name) => format!("preset {}", name),
+            },
+        );
+    }
+    if let Some(path) = &args.budgets {
+        line("Budgets:", format::path(path));
+    }
+    if let Some(cache) = cache {
+        let state = match cache.file().exists() {
+            true => "reused while the tree is unmodified",
+            false => "none yet",
+        };
+        line(
+            "Cache:",
+            format!("{} ({})", format::path(cache.file()), state),
+        );
+    }
+    let report = if args.check.is_some() {
+        "a --check status line"
+    } else if args.count {
+        "the number of files"
+    } else if args.summary {
+        "a --summary line"
+    } else if args.template.is_some() {
+        "the template"
+    } else {
+        "the full report"
+    };
+    line("Report:", format!("{}, to stdout", report));
+    let mut writes = Vec::new();
+    if let Some(export) = &args.export {
+        let what = match export.format {
+            export::ExportFormat::Gnuplot => "gnuplot data and script in",
+            export::ExportFormat::Vega => "a Vega-Lite spec to",
+            export::ExportFormat::Prometheus => "Prometheus gauges to",
+        };
+        writes.push(format!("{} {}", what, format::path(&export.path)));
+    }
+    if let Some(path) = &args.chart_out {
+        writes.push(format!("a chart to {}", format::path(path)));
+    }
+    if let Some(path) = &args.snapshot {
+        let compressed = if args.compress {
+            "zstd-compressed "
+        } else {
+            ""
+        };
+        writes.push(format!(
+            "a {}snapshot to {}",
+            compressed,
+            format::path(path)
+        ));
+    }
+    if let Some(path) = &args.history {
+        writes.push(format!("the run to the history in {}", format::path(path)));
+    }
+    if let Some(target) = &args.progress_json {
+        writes.push(match target {
+            Target::Fd(fd) => format!("progress events to file descriptor {}", fd),
+            Target::File(path) => format!("progress events to {}", format::path(path)),
+        });
+    }
+    for (i, write) in writes.into_iter().enumerate() {
+        line(if i == 0 { "Writes:" } else { "" }, write);
+    }
+    plan + "\nDry run, nothing was scanned\n"
+}
+
+/// Finds the PATHs inside others, or given twice, as pairs of their
+/// indices, the first of equal PATHs being kept.
+fn overlapping_roots(roots: &[PathBuf]) -> Vec<(usize, usize)> {
+    let canonical = roots
+        .iter()
+        .map(|root| std::fs::canonicalize(root).unwrap_or_else(|_| root.clone()))
+        .collect::<Vec<_>>();
+    canonical
+        .iter()
+        .enumerate()
+        .filter_map(|(i, inner)| {
+            let outer = canonical.iter().enumerate().position(|(j, outer)| {
+                j != i && inner.starts_with(outer) && (inner != outer || j < i)
+            })?;
+            Some((i, outer))
+        })
+        .collect()
+}
+
+fn root_names(args: &Args) -> Vec<String> {
+    args.roots.iter().map(|root| format::path(root)).collect()
+}
+
+/// The header of `dudist merge`, listing how the settings of the snapshots
+/// differ from those of the first one when they do.
+fn render_merge_header(args: &Args, snapshots: &[Snapshot]) -> String {
+    let first = &snapshots[0];
+    let differ = snapshots
+        .iter()
+        .any(|snapshot| snapshot.settings != first.settings);
+    let roots = snapshots
+        .iter()
+        .flat_map(|snapshot| snapshot.roots.iter().cloned())
+        .collect::<Vec<_>>();
+    let header = render_header(&roots, first.settings.as_ref().filter(|_| !differ));
+    if !differ {
+        return header;
+    }
+    let mut header = header.trim_end().to_string() + "\n";
+    for (path, snapshot) in args.merge.iter().zip(snapshots).skip(1) {
+        let diff = match (&first.settings, &snapshot.settings) {
+            (Some(first), Some(settings)) => settings.diff(first),
+            (None, Some(_)) | (Some(_), None) => {
+                vec!["settings unknown, saved by an older dudist".to_string()]
+            }
+            (None, None) => continue,
+        };
+        if diff.is_empty() {
+            continue;
+        }
+        header += &format!(
+            "{} was scanned with other settings than {}:\n",
+            format::path(path),
+            format::path(&args.merge[0])
+        );
+        for line in diff {
+            header += &format!("  {}\n", line);
+        }
+    }
+    header + "\n"
+}
+
+/// Writes the `--snapshot` of `sizes`, telling whether that failed. An
+/// interrupted scan isn't written, it would pass for the whole tree.
+fn write_snapshot(
+    args: &Args,
+    root: &str,
+    settings: Option<&ScanSettings>,
+    sizes: &(impl Ranked + ?Sized),
+) -> bool {
+    let Some(path) = &args.snapshot else {
+        return false;
+    };
+    if interrupt::interrupted() {
+        return false;
+    }
+    let sizes = sizes
+        .runs()
+        .flat_map(|(size, files)| std::iter::repeat_n(size, files as usize));
+    let mut snapshot = Snapshot::new(root, sizes);
+    snapshot.settings = settings.cloned();
+    snapshot
+        .save(path, args.compress)
+        .inspect_err(|e| eprintln!("error: {}", e))
+        .is_err()
+}
+
+/// `dudist merge`: the report over the sizes of saved snapshots.
+fn merge_snapshots(
+    args: &Args,
+    template: Option<&Template>,
+    located: &[Located],
+    warnings: &Warnings,
+    caps: &Capabilities,
+) {
+    let snapshots = args
+        .merge
+        .iter()
+        .map(|path| Snapshot::load(path))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            std::process::exit(2);
+        });
+    let merged = Snapshot::merge(&snapshots);
+    let root = merged.roots.join(" + ");
+    let sizes = merged.sizes.as_slice();
+    match template {
+        Some(template) => {
+            let scope = template_scope(Path::new(&root), sizes, &[]);
+            print!(
+                "{}",
+                template.render(&scope).expect("checked before merging")
+            );
+        }
+        None if args.summary => println!("{}", summary_line(sizes, args.bytes)),
+        None if sizes.is_empty() => {
+            println!("No files in the snapshots");
+            print_warnings(args, warnings, caps);
+            std::process::exit(EXIT_NO_MATCHES);
+        }
+        None => {
+            if args.header {
+                print!("{}", render_merge_header(args, &snapshots));
+            }
+            println!("Merged {} snapshots: {}", snapshots.len(), root);
+            println!("Number of files: {}", sizes.len());
+            if args.total {
+                println!(
+                    "Total size:      {}",
+                    format::human(sizes.iter().sum::<u64>())
+                );
+            }
+            print_distribution(args, sizes, None, located, caps);
+        }
     }
-    for _ in min..lower_quartile {
-        print!("{}", light_shade);
+    let failed = write_snapshot(args, &root, merged.settings.as_ref(), sizes);
+    print_warnings(args, warnings, caps);
+    if failed {
+        std::process::exit(2);
+    }
+}
+
+/// Says what `--archives` expanded, e.g. `Expanded 12 archives into 3,402
+/// files (1 couldn't be read)`.
+fn archives_line(counts: &ArchiveCounts) -> String {
+    let mut line = format!(
+        "Expanded {} archives into {} files",
+        format::format_count(counts.expanded),
+        format::format_count(counts.members)
+    );
+    let mut notes = Vec::new();
+    if counts.failed > 0 {
+        notes.push(format!(
+            "{} couldn't be read",
+            format::format_count(counts.failed)
+        ));
     }
-    for _ in lower_quartile..median {
-        print!("{}", medium_shade);
+    if counts.compressed > 0 {
+        notes.push(format!(
+            "{} compressed counted whole",
+            format::format_count(counts.compressed)
+        ));
     }
-    print!("{}", dark_shade);
-    for _ in median..upper_quartile {
-        print!("{}", medium_shade);
+    if !notes.is_empty() {
+        line += &format!(" ({})", notes.join(", "));
     }
-    for _ in upper_quartile..max {
-        print!("{}", light_shade);
+    line
+}
+
+/// Keys of each item of `{{#groups}}` in templates
+const GROUP_KEYS: [&str; 6] = [
+    "name",
+    "count",
+    "total.human",
+    "total.bytes",
+    "median.human",
+    "median.bytes",
+];
+
+/// Sets `key.human` and `key.bytes`, or `-` for both without a value.
+fn size_keys(scope: &mut Scope, key: &str, bytes: Option<f64>) {
+    let (human, raw) = match bytes {
+        Some(bytes) => (
+            format::human_f64(bytes),
+            format::checked_bytes(bytes).map_or_else(|_| "n/a".to_string(), |b| b.to_string()),
+        ),
+        None => ("-".to_string(), "-".to_string()),
+    };
+    scope.text(&format!("{}.human", key), human);
+    scope.text(&format!("{}.bytes", key), raw);
+}
+
+/// The values templates are rendered from, for the files of `sizes`
+/// (sorted ascending) found under `root` and their `groups`.
+fn template_scope(root: &Path, sizes: &(impl Ranked + ?Sized), groups: &[groups::Group]) -> Scope {
+    let mut scope = Scope::new();
+    scope.text("root", format::path(root));
+    scope.text("count", sizes.count().to_string());
+    size_keys(&mut scope, "total", Some(sizes.total() as f64));
+    let matched = sizes.count() > 0;
+    let dist = matched.then(|| Distribution::from_ranked(sizes));
+    let stats = [
+        ("min", dist.as_ref().map(|dist| dist.min as f64)),
+        ("q1", dist.as_ref().map(|dist| dist.lower_quartile)),
+        ("median", dist.as_ref().map(|dist| dist.median)),
+        ("q3", dist.as_ref().map(|dist| dist.upper_quartile)),
+        ("max", dist.as_ref().map(|dist| dist.max as f64)),
+        ("iqr", dist.as_ref().map(Distribution::interquartile_range)),
+        (
+            "mad",
+            dist.as_ref()
+                .map(|dist| distribution::median_absolute_deviation(sizes, dist.median)),
+        ),
+    ];
+    for (key, value) in stats {
+        size_keys(&mut scope, key, value);
     }
-    for _ in max..cli_width {
-        print!(" ");
+    for p in [90.0, 95.0, 99.0] {
+        let value = matched.then(|| sizes.percentile(p));
+        size_keys(&mut scope, &format!("p{}", p), value);
     }
+    let items = groups
+        .iter()
+        .map(|group| {
+            let mut item = Scope::new();
+            item.text("name", group.label());
+            item.text("count", group.sizes.count().to_string());
+            size_keys(&mut item, "total", Some(group.total as f64));
+            size_keys(&mut item, "median", Some(group.dist.median));
+            item
+        })
+        .collect();
+    scope.list("groups", items, &GROUP_KEYS);
+    scope
+}
+
+/// Reads and parses the `--template`, checking its keys up front so a typo
+/// doesn't surface only after a long scan.
+fn load_template(source: &TemplateSource, root: &Path) -> Result<Template, String> {
+    let template = match source {
+        TemplateSource::File(path) => Template::parse(
+            &std::fs::read_to_string(path)
+                .map_err(|e| format!("failed to read {}: {}", path.display(), e))?,
+        )?,
+        TemplateSource::Inline(text) => Template::parse(text)?,
+        TemplateSource::Preset(name) => Template::parse(template::preset(name)?)?,
+    };
+    template.check(&template_scope(root, &[][..], &[]))?;
+    Ok(template)
+}
+
+/// The `--summary` line for `sizes`, e.g.
+/// `n=120 total=1.5GiB min=4.1KiB q1=18.2KiB med=204KiB q3=3.1MiB max=8.7GiB`.
+/// The keys are always the same, statistics being `-` without files.
+fn summary_line(sizes: &(impl Ranked + ?Sized), raw: bool) -> String {
+    let value = |bytes: f64| {
+        if raw {
+            format::checked_bytes(bytes).map_or_else(|_| "n/a".to_string(), |b| b.to_string())
+        } else {
+            format::human_f64(bytes).replace(' ', "")
+        }
+    };
+    let total = sizes.total();
+    let stats = match sizes.count() {
+        0 => [
+            "-".to_string(),
+            "-".to_string(),
+            "-".to_string(),
+            "-".to_string(),
+            "-".to_string(),
+        ],
+        _ => {
+            let dist = Distribution::from_ranked(sizes);
+            [
+                value(dist.min as f64),
+                value(dist.lower_quartile),
+                value(dist.median),
+                value(dist.upper_quartile),
+                value(dist.max as f64),
+            ]
+        }
+    };
+    let [min, q1, med, q3, max] = stats;
+    format!(
+        "n={} total={} min={} q1={} med={} q3={} max={}",
+        sizes.count(),
+        value(total as f64),
+        min,
+        q1,
+        med,
+        q3,
+        max
+    )
+}
+
+/// Tells how many directories hold no files, and how many of them are
+/// topmost.
+fn empty_dirs_line(counts: &ScanCounts) -> String {
+    let mut line = format!(
+        "Empty directories: {}",
+        format::format_count(counts.empty_dirs)
+    );
+    if counts.empty_trees < counts.empty_dirs {
+        line += &format!(
+            ", {} of them topmost",
+            format::format_count(counts.empty_trees)
+        );
+    }
+    line
+}
+
+/// Relates the `scanned` bytes to the capacity of their filesystem.
+/// `part` as a percentage of `whole`, 0 of nothing.
+fn percent(part: u64, whole: u64) -> f64 {
+    if whole > 0 {
+        part as f64 / whole as f64 * 100.0
+    } else {
+        0.0
+    }
+}
+
+fn fs_usage_line(usage: &mounts::FsUsage, scanned: u64) -> String {
+    format!(
+        "Filesystem:      {} total, {} used ({:.0}%), scanned files account for {} ({:.0}% of used)",
+        format::human(usage.total),
+        format::human(usage.used),
+        percent(usage.used, usage.total),
+        format::human(scanned),
+        percent(scanned, usage.used)
+    )
+}
+
+/// [`fs_usage_line`] for `--json`.
+fn fs_usage_json(usage: &mounts::FsUsage, scanned: u64) -> Value {
+    json!({
+        "total": usage.total,
+        "used": usage.used,
+        "used_percent": percent(usage.used, usage.total),
+        "scanned": scanned,
+        "scanned_percent_of_used": percent(scanned, usage.used),
+    })
+}
+
+/// Prints the trend since earlier runs if asked to and appends this scan of
+/// `sizes` to the `--history` file. Errors are printed, unreadable lines go
+/// to `warnings`.
+fn update_history(
+    args: &Args,
+    options: &ScanOptions,
+    sizes: &(impl Ranked + ?Sized),
+    warnings: &Warnings,
+    caps: &Capabilities,
+) -> Result<(), ()> {
+    let Some(path) = &args.history else {
+        return Ok(());
+    };
+    let entry = history::Entry::new(&args.path, options, sizes);
+    if args.show_trend {
+        let (earlier, skipped) = history::read(path).map_err(|e| eprintln!("error: {}", e))?;
+        if skipped > 0 {
+            let lines = if skipped == 1 { "line" } else { "lines" };
+            warnings.add(
+                Category::History,
+                Some(path),
+                format!(
+                    "skipped {} unreadable {}",
+                    format::format_count(skipped as u64),
+                    lines
+                ),
+            );
+        }
+        println!();
+        history::print_trend(&earlier, &entry, args.trend_runs, caps);
+    }
+    history::append(path, &entry).map_err(|e| eprintln!("error: {}", e))
+}
+
+/// Renders the most common sizes, `count` of them, or says that no size
+/// repeats.
+fn render_modes(sizes: &(impl Ranked + ?Sized), count: usize) -> String {
+    let modes = distribution::modes(sizes, count);
+    let share = |files: usize| files as f64 / sizes.count() as f64 * 100.0;
+    match modes.as_slice() {
+        [] => "Most common size: none, no two files have the same size\n".to_string(),
+        [(size, files)] => format!(
+            "Most common size: {} ({} files, {:.0}% of all files)\n",
+            format::human(*size),
+            format::format_count(*files as u64),
+            share(*files)
+        ),
+        _ => {
+            let mut table = Table::new(&[Align::Left, Align::Right, Align::Right, Align::Right]);
+            for (i, &(size, files)) in modes.iter().enumerate() {
+                table.row([
+                    if i == 0 { "Most common sizes:" } else { "" }.to_string(),
+                    format::human(size),
+                    format!("{} files", format::format_count(files as u64)),
+                    format!("{:.0}%", share(files)),
+                ]);
+            }
+            table.render()
+        }
+    }
+}
+
+/// Renders the share of files and bytes below each of `queries`.
+fn render_ranks(sizes: &(impl Ranked + ?Sized), queries: &[u64]) -> String {
+    let index = RankIndex::new(sizes);
+    let mut table = Table::new(&[Align::Left, Align::Right, Align::Right]);
+    for &query in queries {
+        let rank = index.rank(query);
+        table.row([
+            format!("Below {}:", format::human(query)),
+            format!("{:.1}% of files", rank.files),
+            format!("{:.1}% of bytes", rank.bytes),
+        ]);
+    }
+    table.render()
+}
+
+/// A `--locate` file found on disk, with the label of its marker.
+struct Located<'a> {
+    label: char,
+    path: &'a Path,
+    size: u64,
+}
+
+/// Stats the `--locate` files, warning about the ones that can't be placed.
+/// Labels follow the order of the flags, so they stay the same when one is
+/// missing.
+fn locate_files<'a>(paths: &'a [PathBuf], warnings: &Warnings) -> Vec<Located<'a>> {
+    paths
+        .iter()
+        .zip('a'..='z')
+        .filter_map(|(path, label)| match std::fs::metadata(path) {
+            Ok(metadata) if metadata.is_file() => Some(Located {
+                label,
+                path,
+                size: metadata.len(),
+            }),
+            Ok(_) => {
+                warnings.add(Category::Locate, Some(path), "not a file");
+                None
+            }
+            Err(e) => {
+                warnings.add(Category::Locate, Some(path), e.to_string());
+                None
+            }
+        })
+        .collect()
+}
+
+/// Renders the legend of the `--locate` markers.
+fn render_located(located: &[Located], sizes: &(impl Ranked + ?Sized)) -> String {
+    let index = RankIndex::new(sizes);
+    let mut table = Table::new(&[Align::Left, Align::Left, Align::Right, Align::Left]);
+    for file in located {
+        table.row([
+            format!("{}:", file.label),
+            format::path(file.path),
+            format::human(file.size),
+            format!(
+                "larger than {:.1}% of the files",
+                index.rank(file.size).files
+            ),
+        ]);
+    }
+    table.render()
+}
+
+/// Resolves the PATHs in place, telling whether the only one is a file.
+fn resolve_roots(args: &mut Args) -> bool {
+    let only_root = args.roots.len() == 1;
+    let mut single_file = false;
+    for root in &mut args.roots {
+        match resolve_root(root) {
+            Ok((resolved, is_file)) => {
+                *root = resolved;
+                single_file = is_file && only_root;
+            }
+            Err(e) => {
+                if args.check.is_some() {
+                    println!("{}", check::unknown(&e));
+                    std::process::exit(check::Status::Unknown.exit_code());
+                }
+                eprintln!("error: {}", e);
+                std::process::exit(2);
+            }
+        }
+    }
+    args.path = args.roots[0].clone();
+    single_file
+}
+
+/// The scan options from the flags. A file named on the command line is
+/// reported whatever its size.
+fn scan_options(
+    args: &Args,
+    mount_table: &[mounts::Mount],
+    single_file: bool,
+    warnings: &Warnings,
+) -> ScanOptions {
+    let mut options = ScanOptions::builder()
+        .min_size(if single_file { 0 } else { args.min_size })
+        .follow_symlinks(args.follow_symlinks)
+        .one_file_system(args.one_file_system)
+        .skip_hidden(args.exclude_hidden)
+        .exclude_caches(args.exclude_caches)
+        .read_ignore_files(args.dudistignore)
+        .retry(args.retry);
+    if let Some(bytes) = args.max_path_bytes {
+        options = options.max_path_bytes(bytes);
+    }
+    if let Some(max_size) = args.max_size.filter(|_| !single_file) {
+        options = options.max_size(max_size);
+    }
+    if let Some(max_depth) = args.max_depth {
+        options = options.max_depth(max_depth);
+    }
+    if !args.exclude_fs_types.is_empty() {
+        options = options.fs_filter(FsTypeFilter::new(mount_table, &args.exclude_fs_types));
+    }
+    for path in &args.allow_mounts {
+        match scan::mount_device(path) {
+            Ok(device) => options = options.allow_mount(path, device),
+            Err(e) => warnings.add(Category::AllowMount, None, e),
+        }
+    }
+    options.build().unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(2);
+    })
+}
+
+/// Leaves out the PATHs given twice or inside another, whose files would be
+/// counted twice. Refused unless `--allow-overlap` says otherwise.
+fn drop_overlapping_roots(args: &mut Args, warnings: &Warnings) {
+    let overlaps = overlapping_roots(&args.roots);
+    if overlaps.is_empty() {
+        return;
+    }
+    for &(inner, outer) in &overlaps {
+        let (shown, outer) = (
+            format::path(&args.roots[inner]),
+            format::path(&args.roots[outer]),
+        );
+        let problem = if shown == outer {
+            format!("{} is given twice", shown)
+        } else {
+            format!("{} is inside {}", shown, outer)
+        };
+        if !args.allow_overlap {
+            eprintln!(
+                "error: {}, so its files would be counted twice (--allow-overlap scans them once)",
+                problem
+            );
+            std::process::exit(2);
+        }
+        warnings.add(
+            Category::Revisited,
+            None,
+            format!("{}, scanned once", problem),
+        );
+    }
+    args.roots = args
+        .roots
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !overlaps.iter().any(|&(inner, _)| inner == *i))
+        .map(|(_, root)| root.clone())
+        .collect();
+    args.path = args.roots[0].clone();
+}
+
+/// `--count`: the number of matching files, and their bytes with
+/// `--total`, without keeping their sizes around.
+fn print_count(
+    args: &Args,
+    options: &ScanOptions,
+    cache: Option<&Cache>,
+    mut events: Option<Events>,
+    warnings: &Warnings,
+    caps: &Capabilities,
+) {
+    // Only filled for --strict=errors-only
+    let mut skipped = Vec::new();
+    let (total, counts, statuses) = match cache {
+        Some(cache) => {
+            let found = cached_scan(args, options, cache, &mut skipped, warnings);
+            let total = track(found.records.into_iter(), &mut events)
+                .map(|record| record.size)
+                .sum();
+            (total, found.counts, Vec::new())
+        }
+        None => count_files(args, options, &mut skipped, warnings, &mut events, caps),
+    };
+    warnings.add_count(Category::Recovered, counts.recovered);
+    finish_events(events, &counts, false, warnings);
+    if args.total {
+        println!("{} {}", counts.matched, total);
+    } else {
+        println!("{}", counts.matched);
+    }
+    print_warnings(args, warnings, caps);
+    if interrupt::interrupted() {
+        eprintln!("{}", interrupted_message(&counts));
+        std::process::exit(interrupt::EXIT_INTERRUPTED);
+    }
+    if report_skipped(&skipped) {
+        std::process::exit(EXIT_UNREADABLE);
+    }
+    if timed_out(&statuses) {
+        std::process::exit(EXIT_ROOT_TIMEOUT);
+    }
+    if counts.matched == 0 && counts.files > 0 {
+        std::process::exit(EXIT_NO_MATCHES);
+    }
+}
+
+/// What a scan found, and how it went.
+struct Scanned {
+    collected: Collected,
+    counts: ScanCounts,
+    /// Age of the cached scan, when it was used as is
+    cache_age: Option<Duration>,
+    /// Directories walked again and taken from the cache, for incremental
+    /// scans
+    incremental: Option<(u64, u64)>,
+    /// How each PATH went, when there are several
+    statuses: Vec<RootStatus>,
+    /// The trees without files, for `--list-empty-dirs`
+    empty_trees: Vec<PathBuf>,
+    dir_entries: Option<DirEntryTally>,
+    /// The entries `--strict=errors-only` skipped
+    skipped: Vec<ScanError>,
+    /// How long the scan took
+    time: Duration,
+}
+
+/// Scans the PATHs, or takes the files from the cache, and gathers the
+/// report from them. Ends the `--progress-json` events.
+fn scan_roots(
+    args: &Args,
+    options: &ScanOptions,
+    cache: Option<&Cache>,
+    mount_lookup: Option<&MountLookup>,
+    mut events: Option<Events>,
+    warnings: &Warnings,
+    caps: &Capabilities,
+) -> Scanned {
+    let start = Instant::now();
+    let mut skipped = Vec::new();
+    let mut scanned = match cache {
+        Some(cache) => {
+            let found = cached_scan(args, options, cache, &mut skipped, warnings);
+            let collected = process_records(
+                args,
+                options,
+                track(found.records.into_iter(), &mut events),
+                mount_lookup,
+                warnings,
+            );
+            Scanned {
+                collected,
+                counts: found.counts,
+                cache_age: found.cache_age,
+                incremental: found.incremental,
+                statuses: Vec::new(),
+                empty_trees: Vec::new(),
+                dir_entries: found.dir_entries,
+                skipped: Vec::new(),
+                time: Duration::ZERO,
+            }
+        }
+        None => {
+            let visited = Visited::default();
+            let progress = events.as_ref().map(|events| events.progress().clone());
+            let mut walk = walk(args, options, &visited, progress.as_ref(), caps);
+            let records = readable(walk.by_ref(), args.strict, &mut skipped, warnings);
+            let records = track(records, &mut events);
+            let collected = process_records(args, options, records, mount_lookup, warnings);
+            note_revisits(&visited, warnings);
+            Scanned {
+                collected,
+                counts: walk.counts(),
+                cache_age: None,
+                incremental: None,
+                statuses: walk.statuses(warnings),
+                empty_trees: walk.empty_trees(),
+                dir_entries: walk.dir_entries(args),
+                skipped: Vec::new(),
+                time: Duration::ZERO,
+            }
+        }
+    };
+    scanned.skipped = skipped;
+    scanned.time = start.elapsed();
+    warnings.add_count(Category::Recovered, scanned.counts.recovered);
+    finish_events(events, &scanned.counts, args.empty_dirs, warnings);
+    scanned
+}
+
+/// `--check`: prints the status line for monitoring and returns the status,
+/// UNKNOWN when the scan didn't see the whole tree.
+fn run_check(check: &check::Check, sizes: &Sizes, scanned: &Scanned) -> check::Status {
+    let unknown = if interrupt::interrupted() {
+        interrupted_message(&scanned.counts)
+    } else if !scanned.skipped.is_empty() {
+        format!(
+            "skipped {} entries that couldn't be read",
+            format::format_count(scanned.skipped.len() as u64)
+        )
+    } else if timed_out(&scanned.statuses) {
+        "gave up on PATHs that took longer than --root-timeout".to_string()
+    } else {
+        let (status, line) = check.run(sizes);
+        println!("{}", line);
+        return status;
+    };
+    println!("{}", check::unknown(&unknown));
+    check::Status::Unknown
+}
+
+/// `--summary` and `--template`: the one line or the filled template in
+/// place of the report.
+fn print_summary(
+    args: &Args,
+    options: &ScanOptions,
+    template: Option<&Template>,
+    scanned: Scanned,
+    warnings: &Warnings,
+    caps: &Capabilities,
+) {
+    let interrupted = interrupt::interrupted();
+    if interrupted {
+        eprintln!("{}", interrupted_message(&scanned.counts));
+    }
+    let Collected {
+        mut sizes, groups, ..
+    } = scanned.collected;
+    sizes.sort();
+    match template {
+        Some(template) => {
+            let groups = match args.group_by {
+                Some(_) => groups::merge_small_groups(groups.finish(args.sort), &args.group_limits),
+                None => Vec::new(),
+            };
+            let scope = template_scope(&args.path, &sizes, &groups);
+            print!(
+                "{}",
+                template.render(&scope).expect("checked before the scan")
+            );
+        }
+        None => println!("{}", summary_line(&sizes, args.bytes)),
+    }
+    let history_failed =
+        !interrupted && update_history(args, options, &sizes, warnings, caps).is_err();
+    print_warnings(args, warnings, caps);
+    if interrupted {
+        std::process::exit(interrupt::EXIT_INTERRUPTED);
+    }
+    if history_failed {
+        std::process::exit(2);
+    }
+    if write_snapshot(
+        args,
+        &format::path(&args.path),
+        Some(&options.describe()),
+        &sizes,
+    ) {
+        std::process::exit(2);
+    }
+    if report_skipped(&scanned.skipped) {
+        std::process::exit(EXIT_UNREADABLE);
+    }
+    if timed_out(&scanned.statuses) {
+        std::process::exit(EXIT_ROOT_TIMEOUT);
+    }
+    if sizes.is_empty() && scanned.counts.files > 0 {
+        std::process::exit(EXIT_NO_MATCHES);
+    }
+}
+
+/// The lines on how the scan went and what it skipped, before the
+/// statistics.
+fn print_scan_notes(args: &Args, scanned: &Scanned, total: u64, fs_usage: Option<mounts::FsUsage>) {
+    let counts = &scanned.counts;
+    if !scanned.statuses.is_empty() {
+        print!("{}", render_roots(&scanned.statuses));
+    }
+    if args.total {
+        println!("Total size:      {:#.2}", format::binary_unit(total));
+    }
+    if let Some(archives) = &scanned.collected.archives {
+        println!("{}", archives_line(archives));
+    }
+    if let Some(slack) = &scanned.collected.slack {
+        slack.print();
+    }
+    if let Some(links) = &scanned.collected.links {
+        links.print();
+    }
+    if let Some(usage) = fs_usage {
+        println!("{}", fs_usage_line(&usage, total));
+    }
+    if counts.ignore_files > 0 {
+        println!(
+            "Applied {} .dudistignore files, skipping {} entries",
+            format::format_count(counts.ignore_files),
+            format::format_count(counts.ignored)
+        );
+    }
+    if counts.cache_dirs > 0 {
+        println!(
+            "Skipped {} cache directories (CACHEDIR.TAG)",
+            format::format_count(counts.cache_dirs)
+        );
+    }
+    if args.empty_dirs {
+        println!("{}", empty_dirs_line(counts));
+        if args.list_empty_dirs {
+            for tree in &scanned.empty_trees {
+                println!("  {}", format::path(tree));
+            }
+        }
+    }
+}
+
+/// `--scatter` and `--timeline`, over the sizes and modification times.
+fn print_dated(args: &Args, dated: &[(u64, SystemTime)], caps: &Capabilities) {
+    if args.scatter.is_some() && !dated.is_empty() {
+        // Files from the future count as new
+        let now = SystemTime::now();
+        let points = dated
+            .iter()
+            .map(|&(size, modified)| {
+                let age = now.duration_since(modified).unwrap_or_default();
+                (size, age.as_secs_f64() / 86400.0)
+            })
+            .collect::<Vec<_>>();
+        println!();
+        plot::plot_scatter(&points, caps);
+    }
+    if let Some(period) = args.timeline {
+        println!();
+        timeline::print_timeline(dated, period, args.timeline_limit, caps);
+    }
+}
+
+/// `--group-by`, as a table or small multiples, with the `--budgets` check.
+/// Returns the groups over budget.
+fn print_grouped(
+    args: &Args,
+    groups: Grouper,
+    budgets: Option<&Budgets>,
+    drawn: &Drawn,
+    caps: &Capabilities,
+) -> Vec<Overrun> {
+    let Some(group_by) = args.group_by else {
+        return Vec::new();
+    };
+    let mut groups = groups.finish(args.sort);
+    // Checked before groups are merged into (other)
+    let mut overruns = Vec::new();
+    let budget_table = budgets.map(|budgets| {
+        let (table, over) = budgets.check(&groups);
+        overruns = over;
+        table
+    });
+    groups = groups::merge_small_groups(groups, &args.group_limits);
+    println!();
+    if args.small_multiples {
+        let max_value = drawn
+            .clip
+            .as_ref()
+            .map_or(drawn.dist.max, |clip| clip.value.min(drawn.dist.max));
+        groups::print_caption(group_by.caption(), args.sort);
+        plot::plot_small_multiples(&groups, max_value, caps, drawn.plot_style, args.origin);
+    } else {
+        groups::print_groups(
+            &groups,
+            group_by.caption(),
+            args.sort,
+            "Group",
+            None,
+            Some(&args.flag_thresholds),
+            caps,
+        );
+    }
+    if let Some(table) = budget_table {
+        println!();
+        println!("Budgets:");
+        print!("{}", table);
+    }
+    overruns
+}
+
+/// The files the report goes on to: the history, the snapshot, the export
+/// and the chart. A failed one doesn't take the report with it, nor the
+/// others. Returns whether any failed.
+fn write_outputs(
+    args: &Args,
+    options: &ScanOptions,
+    sizes: &Sizes,
+    dist: &Distribution,
+    warnings: &Warnings,
+    caps: &Capabilities,
+) -> bool {
+    let history_failed =
+        !interrupt::interrupted() && update_history(args, options, sizes, warnings, caps).is_err();
+    let snapshot_failed = write_snapshot(
+        args,
+        &format::path(&args.path),
+        Some(&options.describe()),
+        sizes,
+    );
+    let export_failed = args.export.as_ref().is_some_and(|export| {
+        export::write(export, &args.path, dist, sizes)
+            .inspect_err(|e| eprintln!("error: {}", e))
+            .is_err()
+    });
+    let chart_failed = args.chart_out.as_ref().is_some_and(|path| {
+        chart::write(path, args.chart_size, dist, sizes)
+            .inspect_err(|e| eprintln!("error: {}", e))
+            .is_err()
+    });
+    history_failed || snapshot_failed || export_failed || chart_failed
+}
+
+/// `--hints`: what to try on the next run.
+fn print_hints(
+    args: &Args,
+    counts: &ScanCounts,
+    sizes: &Sizes,
+    dist: &Distribution,
+    extensions: Option<HashMap<String, u64>>,
+) {
+    let facts = Facts {
+        files: counts.files,
+        matched: sizes.count() as u64,
+        total: sizes.total(),
+        largest: dist.max,
+        min_size: args.min_size,
+        max_size: args.max_size.is_some(),
+        clipped: args.clip.is_some(),
+        group_by: args.group_by,
+        // Ties go to the first name, so the hint doesn't change between
+        // runs
+        top_extension: extensions.and_then(|extensions| {
+            extensions
+                .into_iter()
+                .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+        }),
+    };
+    print!("{}", render_hints(&hints::suggest(&facts)));
+}
+
+/// The full report over a scan that matched files, then the files it goes
+/// on to. Ends the process with the exit status of the run.
+#[allow(clippy::too_many_arguments)]
+fn print_report(
+    args: &Args,
+    options: &ScanOptions,
+    scanned: Scanned,
+    run_start: Instant,
+    fs_usage: Option<mounts::FsUsage>,
+    budgets: Option<&Budgets>,
+    mount_lookup: Option<&MountLookup>,
+    located: &[Located],
+    warnings: &Warnings,
+    caps: &Capabilities,
+) {
+    let matched = scanned.collected.sizes.count();
+    if let Some(age) = scanned.cache_age {
+        println!(
+            "Using the cached scan from {} ago (--refresh to rescan)",
+            format::human_duration(age)
+        );
+    }
+    if let Some((rewalked, reused)) = scanned.incremental {
+        println!(
+            "Walked {} modified directories again, took {} from the cache",
+            format::format_count(rewalked),
+            format::format_count(reused)
+        );
+    }
+    let total = scanned.collected.sizes.total();
+    println!("Number of files: {}", matched);
+    print_scan_notes(args, &scanned, total, fs_usage);
+    let Scanned {
+        collected,
+        counts,
+        dir_entries,
+        skipped,
+        statuses,
+        time,
+        ..
+    } = scanned;
+    let Collected {
+        mut sizes,
+        dated,
+        mut undated,
+        groups,
+        mounts,
+        cold,
+        examples,
+        extensions,
+        anomalies,
+        ..
+    } = collected;
+    let retained = retained(&sizes, &dated, &undated);
+    sizes.sort();
+    let drawn = print_distribution(args, &sizes, None, located, caps);
+    if let Some(examples) = &examples {
+        print!("{}", render_examples(examples, &drawn.dist));
+    }
+    print_dated(args, &dated, caps);
+
+    if let Some(cold) = cold {
+        println!();
+        cold.print(total);
+    }
+
+    let retention = (!args.retention.is_empty())
+        .then(|| Retention::preview(&args.retention, &dated, &mut undated, SystemTime::now()));
+    if let Some(retention) = &retention {
+        println!();
+        print!("{}", retention.render());
+    }
+
+    if let Some(dir_entries) = dir_entries {
+        println!();
+        print!("{}", dir_entries.render());
+    }
+
+    if let Some(anomalies) = anomalies.map(AnomalyTally::finish) {
+        println!();
+        print!("{}", anomalies.render(caps));
+    }
+
+    let overruns = print_grouped(args, groups, budgets, &drawn, caps);
+
+    if let Some(lookup) = mount_lookup {
+        let fs_type = |group: &groups::Group| lookup.fs_type(&group.name).to_string();
+        println!();
+        groups::print_groups(
+            &mounts.finish(args.sort),
+            "mount point",
+            args.sort,
+            "Mount point",
+            Some(("Type", &fs_type)),
+            None,
+            caps,
+        );
+    }
+
+    let outputs_failed = write_outputs(args, options, &sizes, &drawn.dist, warnings, caps);
+    if args.timing {
+        print_timing(matched, total, time, caps);
+    }
+    if args.resource_stats {
+        print!("{}", Usage::measure(run_start, retained).render());
+    }
+    if args.hints {
+        print_hints(args, &counts, &sizes, &drawn.dist, extensions);
+    }
+    print_warnings(args, warnings, caps);
+    for overrun in &overruns {
+        eprintln!("error: over budget: {}", overrun);
+    }
+    exit_report(outputs_failed, &skipped, &overruns, &statuses);
+}
+
+/// Ends a report with its exit status: 2 when a file it goes on to couldn't
+/// be written, then for the interruption, the entries skipped, the groups
+/// over budget and the PATHs given up on, in that order.
+fn exit_report(failed: bool, skipped: &[ScanError], overruns: &[Overrun], statuses: &[RootStatus]) {
+    if failed {
+        std::process::exit(2);
+    }
+    if interrupt::interrupted() {
+        std::process::exit(interrupt::EXIT_INTERRUPTED);
+    }
+    if report_skipped(skipped) {
+        std::process::exit(EXIT_UNREADABLE);
+    }
+    if !overruns.is_empty() {
+        std::process::exit(EXIT_OVER_BUDGET);
+    }
+    if timed_out(statuses) {
+        std::process::exit(EXIT_ROOT_TIMEOUT);
+    }
+}
+
+/// The start of the `--json` report: the version, the PATHs and settings of
+/// the scan, the files that matched and their statistics. `distribution` is
+/// null without any.
+fn json_report(args: &Args, options: &ScanOptions, sizes: &(impl Ranked + ?Sized)) -> Value {
+    let mut report = json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "roots": args
+            .roots
+            .iter()
+            .map(|root| format::path_to_json(root))
+            .collect::<Vec<_>>(),
+        "settings": options.describe().to_json(),
+        "interrupted": interrupt::interrupted(),
+        "files": sizes.count(),
+        "total": sizes.total(),
+        "distribution": null,
+    });
+    if sizes.count() > 0 {
+        let dist = Distribution::from_ranked(sizes);
+        report["distribution"] = dist.to_json();
+        report["distribution"]["interquartile_range"] = json!(dist.interquartile_range());
+        report["distribution"]["median_absolute_deviation"] =
+            json!(distribution::median_absolute_deviation(sizes, dist.median));
+        // Buckets don't keep the exact sizes that could repeat
+        if !args.compact {
+            let modes = distribution::modes(sizes, args.modes)
+                .into_iter()
+                .map(|(size, count)| json!({"size": size, "count": count}))
+                .collect::<Vec<_>>();
+            report["modes"] = json!(modes);
+        }
+        if !args.ranks.is_empty() {
+            let index = RankIndex::new(sizes);
+            let ranks = args
+                .ranks
+                .iter()
+                .map(|&size| {
+                    let rank = index.rank(size);
+                    json!({
+                        "size": size,
+                        "files_below_percent": rank.files,
+                        "bytes_below_percent": rank.bytes,
+                    })
+                })
+                .collect::<Vec<_>>();
+            report["ranks"] = json!(ranks);
+        }
+    }
+    if args.timestamp {
+        report["generated"] = json!(timeline::utc_timestamp(SystemTime::now()));
+    }
+    report
+}
+
+/// The 95% confidence intervals of the quartiles and the `marks`
+/// percentiles of the `sample` of `fraction` of the files, for `--json`.
+/// See [`render_distribution`].
+fn intervals_json(sample: &[u64], marks: &[f64], fraction: f64) -> Value {
+    let mut intervals = json!({});
+    let quartiles = [
+        ("lower_quartile".to_string(), 25.0),
+        ("median".to_string(), 50.0),
+        ("upper_quartile".to_string(), 75.0),
+    ];
+    let marks = marks.iter().map(|&p| (format!("p{}", p), p));
+    for (name, percentile) in quartiles.into_iter().chain(marks) {
+        if percentile > 0.0 && percentile < 100.0 {
+            let (low, high) = distribution::percentile_interval(sample, percentile, fraction);
+            intervals[name] = json!({"low": low, "high": high});
+        }
+    }
+    intervals
+}
+
+/// How long the scan of `count` files took, for `--json`.
+fn timing_json(count: usize, elapsed: Duration) -> Value {
+    let seconds = elapsed.as_secs_f64();
+    json!({
+        "scan_seconds": seconds,
+        "files_per_second": (seconds > 0.0).then(|| count as f64 / seconds),
+    })
+}
+
+/// `--json`: the report as one JSON document on stdout, in place of the
+/// text. The files the report goes on to are written as usual.
+#[allow(clippy::too_many_arguments)]
+fn print_json_report(
+    args: &Args,
+    options: &ScanOptions,
+    scanned: Scanned,
+    run_start: Instant,
+    fs_usage: Option<mounts::FsUsage>,
+    budgets: Option<&Budgets>,
+    mount_lookup: Option<&MountLookup>,
+    warnings: &Warnings,
+    caps: &Capabilities,
+) {
+    let Scanned {
+        mut collected,
+        counts,
+        skipped,
+        statuses,
+        empty_trees,
+        time,
+        ..
+    } = scanned;
+    let mut sizes = collected.sizes;
+    sizes.sort();
+    let mut report = json_report(args, options, &sizes);
+    if let Some(links) = &collected.links {
+        report["hardlinks"] = links.to_json();
+    }
+    if !args.retention.is_empty() {
+        let retention = Retention::preview(
+            &args.retention,
+            &collected.dated,
+            &mut collected.undated,
+            SystemTime::now(),
+        );
+        report["retention"] = retention.to_json();
+    }
+    if let Some(anomalies) = collected.anomalies {
+        report["anomalies"] = anomalies.finish().to_json();
+    }
+    if args.empty_dirs {
+        report["empty_dirs"] = json!({
+            "count": counts.empty_dirs,
+            "topmost": counts.empty_trees,
+        });
+        if args.list_empty_dirs {
+            let paths = empty_trees
+                .iter()
+                .map(|tree| format::path_to_json(tree))
+                .collect::<Vec<_>>();
+            report["empty_dirs"]["paths"] = json!(paths);
+        }
+    }
+    if let Some(examples) = &collected.examples {
+        if !sizes.is_empty() {
+            report["examples"] = examples_json(examples, &Distribution::from_ranked(&sizes));
+        }
+    }
+    if args.timing {
+        report["timing"] = timing_json(sizes.count(), time);
+    }
+    if let Some(usage) = fs_usage {
+        report["fs_usage"] = fs_usage_json(&usage, sizes.total());
+    }
+    // In the order of the tables, budgets checked before the merge
+    let mut overruns = Vec::new();
+    if let Some(group_by) = args.group_by {
+        let groups = collected.groups.finish(args.sort);
+        if let Some(budgets) = budgets {
+            overruns = budgets.check(&groups).1;
+        }
+        let groups = groups::merge_small_groups(groups, &args.group_limits);
+        report["groups"] = groups::to_json(
+            &groups,
+            group_by.caption(),
+            args.sort,
+            None,
+            Some(&args.flag_thresholds),
+        );
+    }
+    if let Some(lookup) = mount_lookup {
+        let fs_type = |group: &groups::Group| lookup.fs_type(&group.name).to_string();
+        report["mounts"] = groups::to_json(
+            &collected.mounts.finish(args.sort),
+            "mount point",
+            args.sort,
+            Some(("type", &fs_type)),
+            None,
+        );
+    }
+    let failed = !sizes.is_empty() && {
+        let dist = Distribution::from_ranked(&sizes);
+        write_outputs(args, options, &sizes, &dist, warnings, caps)
+    };
+    // Last, to take in the whole run
+    if args.resource_stats {
+        let retained = retained(&sizes, &collected.dated, &collected.undated);
+        report["resources"] = Usage::measure(run_start, retained).to_json();
+    }
+    add_warnings_json(&mut report, args, warnings);
     println!(
-        " Largest: {:#.2}",
-        byte_unit::AdjustedByte::from(byte_unit::Byte::from_u64(dist.max))
+        "{}",
+        serde_json::to_string_pretty(&report).expect("valid JSON")
     );
+    for overrun in &overruns {
+        eprintln!("error: over budget: {}", overrun);
+    }
+    exit_report(failed, &skipped, &overruns, &statuses);
+    if sizes.is_empty() && counts.files > 0 {
+        std::process::exit(EXIT_NO_MATCHES);
+    }
 }
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    let path = Path::new(args.get(1).expect("Please provide a path"));
-    let sizes = process_dir(&path);
-    if sizes.is_empty() {
-        println!("No files found in the directory");
+    let run_start = Instant::now();
+    let mut args = Args::parse().unwrap_or_else(|e| {
+        // Monitoring takes usage errors for UNKNOWN too
+        if std::env::args_os().any(|arg| arg == "--check") {
+            println!("{}", check::unknown(&e));
+            std::process::exit(check::Status::Unknown.exit_code());
+        }
+        eprintln!("error: {}", e);
+        std::process::exit(2);
+    });
+    let caps = Capabilities::detect(!args.unicode);
+    format::show_paths_in(&caps);
+    if args.resource_stats {
+        resources::enable();
+    }
+
+    let mount_table = if args.list_fs || args.by_mount || !args.exclude_fs_types.is_empty() {
+        let mounts = mounts::read_mounts().unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            std::process::exit(2);
+        });
+        if args.list_fs {
+            mounts::print_mounts(&mounts);
+            return;
+        }
+        mounts
+    } else {
+        Vec::new()
+    };
+    // Dudist merge scans nothing
+    let single_file = args.merge.is_empty() && resolve_roots(&mut args);
+    let warnings = Warnings::new((!args.verbose).then_some(3));
+    let options = scan_options(&args, &mount_table, single_file, &warnings);
+    let mount_lookup = args.by_mount.then(|| MountLookup::new(&mount_table));
+    drop_overlapping_roots(&mut args, &warnings);
+    // Without a cache the scan simply runs uncached
+    let cache = args
+        .cache
+        .then(|| Cache::new(&args.path, &options))
+        .and_then(|cache| {
+            cache
+                .inspect_err(|e| warnings.add(Category::Cache, None, format!("{}, not caching", e)))
+                .ok()
+        });
+
+    // Queried up front, so an unsupported platform fails before the scan
+    let fs_usage = args.fs_usage.then(|| {
+        mounts::fs_usage(&args.path).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            std::process::exit(2);
+        })
+    });
+
+    let template = args.template.as_ref().map(|source| {
+        load_template(source, &args.path).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            std::process::exit(2);
+        })
+    });
+
+    let budgets = args.budgets.as_ref().map(|path| {
+        let group_by = args.group_by.expect("checked when parsing");
+        Budgets::load(path, group_by).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            std::process::exit(2);
+        })
+    });
+
+    // Before the scan, so a missing one is among the warnings it sends
+    let located = locate_files(&args.locate, &warnings);
+
+    if args.dry_run {
+        print!(
+            "{}",
+            render_plan(&args, &options, &mount_table, cache.as_ref())
+        );
+        print_warnings(&args, &warnings, &caps);
+        return;
+    }
+
+    if !args.merge.is_empty() {
+        merge_snapshots(&args, template.as_ref(), &located, &warnings, &caps);
+        return;
+    }
+
+    interrupt::install();
+
+    if let Some(stat_budget) = args.fast_estimate {
+        print_estimate(
+            &args,
+            &options,
+            stat_budget,
+            run_start,
+            &located,
+            &warnings,
+            &caps,
+        );
+        print_warnings(&args, &warnings, &caps);
+        return;
+    }
+
+    let mut events = args.progress_json.as_ref().map(|target| {
+        Events::open(target).unwrap_or_else(|e| {
+            eprintln!("error: --progress-json: {}", e);
+            std::process::exit(2);
+        })
+    });
+    if let Some(events) = &mut events {
+        events.scan_started(&args.roots);
+    }
+
+    if args.count {
+        print_count(&args, &options, cache.as_ref(), events, &warnings, &caps);
         return;
     }
-    println!("Number of files: {}", sizes.len());
-    let dist = Distribution::from_vec(sizes);
-    print_distribution(&dist);
-    plot_box_diagram(&dist, dist.max, get_terminal_width().unwrap_or(80));
+
+    let mut scanned = scan_roots(
+        &args,
+        &options,
+        cache.as_ref(),
+        mount_lookup.as_ref(),
+        events,
+        &warnings,
+        &caps,
+    );
+    let interrupted = interrupt::interrupted();
+    // The one line that changes between runs over the same files, easy to
+    // filter out when diffing reports. The JSON report has it as a field
+    if args.timestamp && args.check.is_none() && template.is_none() && !args.json {
+        println!("# generated {}", timeline::utc_timestamp(SystemTime::now()));
+    }
+    if let Some(check) = &args.check {
+        scanned.collected.sizes.sort();
+        let status = run_check(check, &scanned.collected.sizes, &scanned);
+        std::process::exit(status.exit_code());
+    }
+    if args.json {
+        print_json_report(
+            &args,
+            &options,
+            scanned,
+            run_start,
+            fs_usage,
+            budgets.as_ref(),
+            mount_lookup.as_ref(),
+            &warnings,
+            &caps,
+        );
+        return;
+    }
+    if args.summary || template.is_some() {
+        print_summary(
+            &args,
+            &options,
+            template.as_ref(),
+            scanned,
+            &warnings,
+            &caps,
+        );
+        return;
+    }
+    if args.header {
+        print!(
+            "{}",
+            render_header(&root_names(&args), Some(&options.describe()))
+        );
+    }
+    if interrupted {
+        println!("{}", interrupted_message(&scanned.counts));
+    }
+    if scanned.collected.sizes.is_empty() {
+        print_warnings(&args, &warnings, &caps);
+        if interrupted {
+            std::process::exit(interrupt::EXIT_INTERRUPTED);
+        }
+        if report_skipped(&scanned.skipped) {
+            std::process::exit(EXIT_UNREADABLE);
+        }
+        if timed_out(&scanned.statuses) {
+            print!("{}", render_roots(&scanned.statuses));
+            std::process::exit(EXIT_ROOT_TIMEOUT);
+        }
+        std::process::exit(report_no_matches(&scanned.counts, args.min_size));
+    }
+    print_report(
+        &args,
+        &options,
+        scanned,
+        run_start,
+        fs_usage,
+        budgets.as_ref(),
+        mount_lookup.as_ref(),
+        &located,
+        &warnings,
+        &caps,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One file at each of 1 KiB, 10 KiB, 100 KiB, 1 MiB and 1 GiB.
+    const SIZES: &[u64] = &[1 << 10, 10 << 10, 100 << 10, 1 << 20, 1 << 30];
+
+    fn render(marks: &[(f64, f64)], sampled: Option<f64>, caps: &Capabilities) -> String {
+        let dist = Distribution::from_sorted(SIZES);
+        let mad = distribution::median_absolute_deviation(SIZES, dist.median);
+        render_distribution(&dist, marks, mad, SIZES, sampled, caps)
+    }
+
+    #[test]
+    fn the_summary_aligns_values_bytes_and_shares() {
+        assert_eq!(
+            render(&[], None, &Capabilities::ascii(80)),
+            "\
+Smallest:           1 KiB          1,024 B    0.0%
+Lower Quartile:    10 KiB         10,240 B    0.0%
+Median:           100 KiB        102,400 B    0.0%
+Upper Quartile:     1 MiB      1,048,576 B    0.1%
+Largest:            1 GiB  1,073,741,824 B  100.0%
+IQR:             1014 KiB      1,038,336 B
+MAD:               99 KiB        101,376 B
+"
+        );
+    }
+
+    #[test]
+    fn marked_percentiles_take_their_place_among_the_quartiles() {
+        assert_eq!(
+            render(
+                &[(90.0, 500e6), (99.9, 1e9)],
+                None,
+                &Capabilities::ascii(80)
+            ),
+            "\
+Smallest:             1 KiB          1,024 B    0.0%
+Lower Quartile:      10 KiB         10,240 B    0.0%
+Median:             100 KiB        102,400 B    0.0%
+Upper Quartile:       1 MiB      1,048,576 B    0.1%
+p90:             476.84 MiB    500,000,000 B   46.6%
+p99.9:           953.67 MiB  1,000,000,000 B   93.1%
+Largest:              1 GiB  1,073,741,824 B  100.0%
+IQR:               1014 KiB      1,038,336 B
+MAD:                 99 KiB        101,376 B
+"
+        );
+    }
+
+    #[test]
+    fn a_sample_gives_the_quartiles_an_interval() {
+        let caps = Capabilities {
+            unicode: true,
+            width: 80,
+        };
+        assert_eq!(
+            render(&[], Some(0.5), &caps),
+            "\
+Smallest:           1 KiB          1,024 B    0.0%
+Lower Quartile:    10 KiB         10,240 B    0.0%  (±49.5 KiB)
+Median:           100 KiB        102,400 B    0.0%  (±512 MiB)
+Upper Quartile:     1 MiB      1,048,576 B    0.1%  (±512 MiB)
+Largest:            1 GiB  1,073,741,824 B  100.0%
+IQR:             1014 KiB      1,038,336 B
+MAD:               99 KiB        101,376 B
+"
+        );
+    }
+
+    #[test]
+    fn empty_files_have_no_share_of_the_largest() {
+        let sizes: &[u64] = &[0, 0];
+        let dist = Distribution::from_sorted(sizes);
+        assert_eq!(
+            render_distribution(&dist, &[], 0.0, sizes, None, &Capabilities::ascii(80)),
+            "\
+Smallest:        0 B  0 B  n/a
+Lower Quartile:  0 B  0 B  n/a
+Median:          0 B  0 B  n/a
+Upper Quartile:  0 B  0 B  n/a
+Largest:         0 B  0 B  n/a
+IQR:             0 B  0 B
+MAD:             0 B  0 B
+"
+        );
+    }
 }
@@ -0,0 +1,427 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use walkdir::DirEntry;
+
+use crate::format;
+use crate::resources;
+use crate::scan::ScanOptions;
+
+const MOUNTINFO: &str = "/proc/self/mountinfo";
+
+#[derive(Debug)]
+pub struct Mount {
+    pub mount_point: PathBuf,
+    pub fs_type: String,
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl Mount {
+    /// The device id files on this mount report in `st_dev`, using the
+    /// encoding of glibc's `makedev`.
+    pub fn device(&self) -> u64 {
+        let (major, minor) = (self.major as u64, self.minor as u64);
+        ((major & 0xfffff000) << 32)
+            | ((major & 0x00000fff) << 8)
+            | ((minor & 0xffffff00) << 12)
+            | (minor & 0x000000ff)
+    }
+}
+
+/// Reads the mount table of the running process.
+pub fn read_mounts() -> Result<Vec<Mount>, String> {
+    if !cfg!(target_os = "linux") {
+        return Err("filesystem types can only be detected on Linux".to_string());
+    }
+    let contents = std::fs::read_to_string(MOUNTINFO)
+        .map_err(|e| format!("failed to read {}: {}", MOUNTINFO, e))?;
+    Ok(parse_mountinfo(&contents))
+}
+
+/// Parses the lines of `/proc/self/mountinfo`, which look like
+///
+/// ```text
+/// 36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue
+/// ```
+///
+/// where the optional fields before `-` vary in number. Malformed lines are
+/// skipped.
+fn parse_mountinfo(contents: &str) -> Vec<Mount> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let fields = line.split(' ').collect::<Vec<_>>();
+            let (major, minor) = fields.get(2)?.split_once(':')?;
+            let mount_point = fields.get(4)?;
+            let separator = fields.iter().position(|field| *field == "-")?;
+            Some(Mount {
+                mount_point: unescape(mount_point),
+                fs_type: fields.get(separator + 1)?.to_string(),
+                major: major.parse().ok()?,
+                minor: minor.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Undoes the octal escaping (`\040` for a space) the kernel applies to
+/// paths in the mount table.
+fn unescape(field: &str) -> PathBuf {
+    let bytes = field.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes
+            .get(i + 1..i + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match octal {
+            Some(byte) if bytes[i] == b'\\' => {
+                unescaped.push(byte);
+                i += 4;
+            }
+            _ => {
+                unescaped.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    bytes_to_path(unescaped)
+}
+
+#[cfg(unix)]
+fn bytes_to_path(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    PathBuf::from(std::ffi::OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn bytes_to_path(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+pub fn print_mounts(mounts: &[Mount]) {
+    let width = mounts
+        .iter()
+        .map(|mount| mount.fs_type.len())
+        .max()
+        .unwrap_or(0);
+    for mount in mounts {
+        println!(
+            "{:<width$}  {:>7}  {}",
+            mount.fs_type,
+            format!("{}:{}", mount.major, mount.minor),
+            format::path(&mount.mount_point),
+            width = width
+        );
+    }
+}
+
+/// The mounts below `root`, on `root_device`, that a walk with `options`
+/// would skip, with the reason, sorted. Those inside a skipped one are left
+/// out, the walk never gets to them.
+///
+/// ```
+/// use std::path::Path;
+/// use dudist::mounts::{pruned, read_mounts};
+/// use dudist::scan::ScanOptions;
+///
+/// let mounts = read_mounts().unwrap_or_default();
+/// let options = ScanOptions::builder().one_file_system(true).build().unwrap();
+/// for (mount, reason) in pruned(&mounts, Path::new("/"), 0, &options) {
+///     println!("{}: {}", mount.mount_point.display(), reason);
+/// }
+/// ```
+pub fn pruned<'a>(
+    mounts: &'a [Mount],
+    root: &Path,
+    root_device: u64,
+    options: &ScanOptions,
+) -> Vec<(&'a Mount, String)> {
+    let excluded = |mount: &Mount| {
+        options
+            .fs_filter()
+            .is_some_and(|filter| filter.fs_types().contains(&mount.fs_type))
+    };
+    let mut below = mounts
+        .iter()
+        .filter(|mount| mount.mount_point.starts_with(root) && mount.mount_point != root)
+        .collect::<Vec<_>>();
+    below.sort_by(|a, b| a.mount_point.cmp(&b.mount_point));
+    let mut pruned: Vec<(&Mount, String)> = Vec::new();
+    for mount in below {
+        let inside_pruned = pruned
+            .iter()
+            .any(|(outer, _)| mount.mount_point.starts_with(&outer.mount_point));
+        if inside_pruned {
+            continue;
+        }
+        if excluded(mount) {
+            pruned.push((mount, format!("{}, --exclude-fs-type", mount.fs_type)));
+        } else if !options.enters_device(mount.device(), root_device) {
+            pruned.push((mount, "another filesystem, --one-file-system".to_string()));
+        }
+    }
+    pruned
+}
+
+/// Prunes directories residing on filesystems of the excluded types.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FsTypeFilter {
+    /// Sorted, so equal filters serialize alike
+    devices: BTreeSet<u64>,
+    fs_types: Vec<String>,
+}
+
+impl FsTypeFilter {
+    pub fn new(mounts: &[Mount], fs_types: &[String]) -> FsTypeFilter {
+        let mut fs_types = fs_types.to_vec();
+        fs_types.sort();
+        fs_types.dedup();
+        FsTypeFilter {
+            devices: mounts
+                .iter()
+                .filter(|mount| fs_types.contains(&mount.fs_type))
+                .map(Mount::device)
+                .collect(),
+            fs_types,
+        }
+    }
+
+    /// The excluded types, sorted.
+    pub fn fs_types(&self) -> &[String] {
+        &self.fs_types
+    }
+
+    #[cfg(unix)]
+    pub fn excludes(&self, entry: &DirEntry) -> bool {
+        use std::os::unix::fs::MetadataExt;
+        entry.file_type().is_dir() && {
+            resources::stat();
+            entry
+                .metadata()
+                .is_ok_and(|metadata| self.devices.contains(&metadata.dev()))
+        }
+    }
+
+    /// Mount tables are only read on Linux, so there is never anything to
+    /// exclude elsewhere.
+    #[cfg(not(unix))]
+    pub fn excludes(&self, _entry: &DirEntry) -> bool {
+        false
+    }
+}
+
+/// The row for files whose device isn't in the mount table, e.g. because
+/// it was mounted after the table was read
+pub const UNKNOWN: &str = "(unknown)";
+
+/// Finds the mount a file lives on from its device id.
+pub struct MountLookup {
+    /// Mount point and filesystem type by device id
+    by_device: HashMap<u64, (String, String)>,
+}
+
+impl MountLookup {
+    pub fn new(mounts: &[Mount]) -> MountLookup {
+        let mut by_device = HashMap::new();
+        for mount in mounts {
+            // Bind mounts share a device, the first one listed is the
+            // original mount
+            by_device.entry(mount.device()).or_insert_with(|| {
+                (
+                    mount.mount_point.display().to_string(),
+                    mount.fs_type.clone(),
+                )
+            });
+        }
+        MountLookup { by_device }
+    }
+
+    /// The mount point of the filesystem with the id `device`.
+    pub fn mount_point(&self, device: u64) -> String {
+        self.by_device
+            .get(&device)
+            .map_or_else(|| UNKNOWN.to_string(), |(point, _)| point.clone())
+    }
+
+    /// The filesystem type mounted at `mount_point`.
+    pub fn fs_type(&self, mount_point: &str) -> &str {
+        self.by_device
+            .values()
+            .find(|(point, _)| point == mount_point)
+            .map_or("", |(_, fs_type)| fs_type.as_str())
+    }
+}
+
+/// Capacity of a filesystem, in bytes.
+#[derive(Debug, Clone, Copy)]
+pub struct FsUsage {
+    pub total: u64,
+    pub used: u64,
+}
+
+/// Asks the filesystem holding `path` how big it is and how much is used.
+#[cfg(unix)]
+pub fn fs_usage(path: &std::path::Path) -> Result<FsUsage, String> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let error = |e: std::io::Error| {
+        format!(
+            "failed to query the filesystem of {}: {}",
+            path.display(),
+            e
+        )
+    };
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| error(std::io::ErrorKind::InvalidInput.into()))?;
+    let stats = unsafe {
+        let mut stats: libc::statvfs = std::mem::zeroed();
+        if libc::statvfs(c_path.as_ptr(), &mut stats) != 0 {
+            return Err(error(std::io::Error::last_os_error()));
+        }
+        stats
+    };
+    let block_size = stats.f_frsize as u64;
+    Ok(FsUsage {
+        total: stats.f_blocks as u64 * block_size,
+        used: (stats.f_blocks as u64).saturating_sub(stats.f_bfree as u64) * block_size,
+    })
+}
+
+#[cfg(not(unix))]
+pub fn fs_usage(_path: &std::path::Path) -> Result<FsUsage, String> {
+    Err("filesystem usage needs statvfs, which this platform lacks".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mount(path: &str, fs_type: &str, minor: u32) -> Mount {
+        Mount {
+            mount_point: PathBuf::from(path),
+            fs_type: fs_type.to_string(),
+            major: 0,
+            minor,
+        }
+    }
+
+    fn mounts() -> Vec<Mount> {
+        vec![
+            mount("/", "ext4", 1),
+            mount("/data", "xfs", 2),
+            mount("/data/nfs", "nfs", 3),
+            mount("/data/nfs/inner", "xfs", 6),
+            mount("/data/archive", "xfs", 4),
+            mount("/data/scratch", "xfs", 5),
+        ]
+    }
+
+    /// The mount points pruned below `root` with their reasons.
+    fn pruned_below(mounts: &[Mount], root: &str, options: &ScanOptions) -> Vec<(String, String)> {
+        let root_device = mounts
+            .iter()
+            .find(|mount| mount.mount_point == Path::new(root))
+            .unwrap()
+            .device();
+        pruned(mounts, Path::new(root), root_device, options)
+            .into_iter()
+            .map(|(mount, reason)| (format::path(&mount.mount_point), reason))
+            .collect()
+    }
+
+    fn pair(path: &str, reason: &str) -> (String, String) {
+        (path.to_string(), reason.to_string())
+    }
+
+    #[test]
+    fn other_filesystems_are_pruned_with_one_file_system() {
+        let mounts = mounts();
+        let options = ScanOptions::builder()
+            .one_file_system(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            pruned_below(&mounts, "/data", &options),
+            [
+                pair("/data/archive", "another filesystem, --one-file-system"),
+                pair("/data/nfs", "another filesystem, --one-file-system"),
+                pair("/data/scratch", "another filesystem, --one-file-system"),
+            ]
+        );
+    }
+
+    #[test]
+    fn allowed_mounts_are_walked_into() {
+        let mounts = mounts();
+        let options = ScanOptions::builder()
+            .one_file_system(true)
+            .allow_mount("/data/archive", mounts[4].device())
+            .build()
+            .unwrap();
+        assert_eq!(
+            pruned_below(&mounts, "/data", &options),
+            [
+                pair("/data/nfs", "another filesystem, --one-file-system"),
+                pair("/data/scratch", "another filesystem, --one-file-system"),
+            ]
+        );
+    }
+
+    #[test]
+    fn excluded_types_are_pruned_with_what_is_below_them() {
+        let mounts = mounts();
+        let options = ScanOptions::builder()
+            .one_file_system(true)
+            .allow_mount("/data/archive", mounts[4].device())
+            .fs_filter(FsTypeFilter::new(&mounts, &["nfs".to_string()]))
+            .build()
+            .unwrap();
+        // /data/nfs/inner is inside the pruned /data/nfs
+        assert_eq!(
+            pruned_below(&mounts, "/data", &options),
+            [
+                pair("/data/nfs", "nfs, --exclude-fs-type"),
+                pair("/data/scratch", "another filesystem, --one-file-system"),
+            ]
+        );
+    }
+
+    #[test]
+    fn nothing_is_pruned_across_filesystems_without_a_filter() {
+        let mounts = mounts();
+        let options = ScanOptions::builder().build().unwrap();
+        assert!(pruned_below(&mounts, "/data", &options).is_empty());
+        let options = ScanOptions::builder()
+            .fs_filter(FsTypeFilter::new(&mounts, &["nfs".to_string()]))
+            .build()
+            .unwrap();
+        assert_eq!(
+            pruned_below(&mounts, "/", &options),
+            [pair("/data/nfs", "nfs, --exclude-fs-type")]
+        );
+    }
+
+    #[test]
+    fn only_mounts_below_the_root_count() {
+        let mounts = mounts();
+        let options = ScanOptions::builder()
+            .one_file_system(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            pruned_below(&mounts, "/data/scratch", &options),
+            Vec::<(String, String)>::new()
+        );
+        // Neither the root itself nor /data2, which only shares its prefix
+        let mut mounts = mounts;
+        mounts.push(mount("/data2", "xfs", 7));
+        let pruned = pruned_below(&mounts, "/data", &options);
+        assert_eq!(pruned.len(), 3);
+        assert!(pruned.iter().all(|(path, _)| path.starts_with("/data/")));
+    }
+}
@@ -0,0 +1,58 @@
+//! Resolves file owners to user names.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+/// The row for files whose owner the platform doesn't report
+pub const UNKNOWN: &str = "(unknown)";
+
+/// Names already looked up, so the user database is asked once per uid
+static NAMES: OnceLock<Mutex<HashMap<u32, String>>> = OnceLock::new();
+
+/// The name of the user with the id `uid`, or the id itself when there is
+/// no such user.
+pub fn name(uid: u32) -> String {
+    let mut names = NAMES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    names
+        .entry(uid)
+        .or_insert_with(|| lookup(uid).unwrap_or_else(|| uid.to_string()))
+        .clone()
+}
+
+#[cfg(unix)]
+fn lookup(uid: u32) -> Option<String> {
+    use std::ffi::CStr;
+
+    // Grown while the entry doesn't fit, up to a limit no sane entry needs
+    let mut buffer = vec![0 as libc::c_char; 1024];
+    loop {
+        let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut result = std::ptr::null_mut();
+        let status = unsafe {
+            libc::getpwuid_r(
+                uid,
+                &mut entry,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut result,
+            )
+        };
+        if status == libc::ERANGE && buffer.len() < 1 << 20 {
+            buffer.resize(buffer.len() * 2, 0);
+            continue;
+        }
+        if status != 0 || result.is_null() || entry.pw_name.is_null() {
+            return None;
+        }
+        let name = unsafe { CStr::from_ptr(entry.pw_name) };
+        return Some(name.to_string_lossy().into_owned());
+    }
+}
+
+#[cfg(not(unix))]
+fn lookup(_uid: u32) -> Option<String> {
+    None
+}
@@ -0,0 +1,787 @@
+use std::str::FromStr;
+
+use crate::distribution::{self, Distribution, Ranked};
+use crate::format;
+use crate::groups::Group;
+use crate::terminal::Capabilities;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlotStyle {
+    Shade,
+    Braille,
+}
+
+impl FromStr for PlotStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "shade" => Ok(PlotStyle::Shade),
+            "braille" => Ok(PlotStyle::Braille),
+            _ => Err(format!(
+                "invalid plot style '{}' (expected shade or braille)",
+                s
+            )),
+        }
+    }
+}
+
+/// What the left edge of the box plot stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    /// Zero, with an axis under the plot so positions read as absolute sizes
+    Zero,
+    /// The smallest file, with a dotted lead-in from the edge up to it
+    Min,
+}
+
+impl FromStr for Origin {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zero" => Ok(Origin::Zero),
+            "min" => Ok(Origin::Min),
+            _ => Err(format!("invalid origin '{}' (expected zero or min)", s)),
+        }
+    }
+}
+
+/// Where the right edge of the box plot goes, if not at the largest file.
+#[derive(Debug, Clone, Copy)]
+pub enum ClipSpec {
+    Percentile(f64),
+    Size(u64),
+}
+
+impl FromStr for ClipSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix('p') {
+            Some(p) => match p.parse::<f64>() {
+                Ok(p) if (0.0..=100.0).contains(&p) => Ok(ClipSpec::Percentile(p)),
+                _ => Err(format!("invalid percentile '{}'", s)),
+            },
+            None => Ok(ClipSpec::Size(format::parse_size(s)?)),
+        }
+    }
+}
+
+/// The quantities on the axes of a scatter plot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scatter {
+    /// File size across, age since the last modification up
+    SizeAge,
+}
+
+impl FromStr for Scatter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "size-age" => Ok(Scatter::SizeAge),
+            _ => Err(format!("invalid scatter plot '{}' (expected size-age)", s)),
+        }
+    }
+}
+
+/// A resolved clip of the plot axis.
+pub struct Clip {
+    pub value: u64,
+    /// Files larger than `value`, which are left out of the plot
+    pub beyond: usize,
+    caption: String,
+}
+
+impl Clip {
+    pub fn new(spec: ClipSpec, sizes: &(impl Ranked + ?Sized)) -> Clip {
+        let (value, caption) = match spec {
+            ClipSpec::Percentile(p) => {
+                let value = sizes.percentile(p).round() as u64;
+                (value, format!("p{} ({})", p, format::human(value)))
+            }
+            ClipSpec::Size(value) => (value, format::human(value)),
+        };
+        Clip {
+            value,
+            beyond: sizes
+                .runs()
+                .filter(|&(size, _)| size > value)
+                .map(|(_, files)| files as usize)
+                .sum(),
+            caption,
+        }
+    }
+}
+
+/// Positions of the five statistics on an axis of `cells` units, shared by
+/// every renderer so they agree on where things are drawn.
+struct Positions {
+    min: usize,
+    lower_quartile: usize,
+    median: usize,
+    upper_quartile: usize,
+    max: usize,
+}
+
+/// Where `value` goes on an axis of `cells` units from zero to `max_value`.
+/// Anything beyond the axis, when it is clipped, sits at the edge. The cast
+/// saturates, so NaN and negative values sit at zero and an empty axis
+/// keeps everything at its edges rather than panicking.
+fn scale_to(value: f64, max_value: u64, cells: usize) -> usize {
+    ((value / max_value as f64 * cells as f64).round() as usize).min(cells)
+}
+
+impl Positions {
+    fn new(dist: &Distribution, max_value: u64, cells: usize) -> Positions {
+        let scale = |value: f64| scale_to(value, max_value, cells);
+        Positions {
+            min: scale(dist.min as f64),
+            lower_quartile: scale(dist.lower_quartile),
+            median: scale(dist.median),
+            upper_quartile: scale(dist.upper_quartile),
+            max: scale(dist.max as f64),
+        }
+    }
+}
+
+/// Plots the box diagram of `dist`. Braille `style` needs a `caps` that
+/// can show Unicode, which the caller checks.
+///
+/// Statistics gone wrong, like NaN, are drawn at the origin rather than
+/// bringing the report down:
+///
+/// ```
+/// use dudist::distribution::Distribution;
+/// use dudist::plot::{self, Origin, PlotStyle};
+/// use dudist::terminal::Capabilities;
+///
+/// let dist = Distribution {
+///     min: 0,
+///     max: 0,
+///     median: f64::NAN,
+///     lower_quartile: -1.0,
+///     upper_quartile: f64::INFINITY,
+/// };
+/// let caps = Capabilities { unicode: true, width: 80 };
+/// for style in [PlotStyle::Shade, PlotStyle::Braille] {
+///     for origin in [Origin::Zero, Origin::Min] {
+///         plot::plot_box_diagram(&dist, None, &caps, style, origin, &[('a', 0)], &[(90.0, f64::NAN)]);
+///     }
+/// }
+/// plot::plot_violin(&dist, &[0, 0][..], &caps, 5);
+/// plot::plot_degenerate(&dist, &Capabilities::ascii(80));
+/// ```
+pub fn plot_box_diagram(
+    dist: &Distribution,
+    clip: Option<&Clip>,
+    caps: &Capabilities,
+    style: PlotStyle,
+    origin: Origin,
+    markers: &[(char, u64)],
+    marks: &[(f64, f64)],
+) {
+    let cli_width = caps.width as usize - 40;
+    let max_value = clip.map_or(dist.max, |clip| clip.value.min(dist.max));
+    let lead_in = origin == Origin::Min;
+    let bar = match style {
+        PlotStyle::Shade => shade_bar(dist, max_value, cli_width, lead_in, caps),
+        PlotStyle::Braille => braille_bar(dist, max_value, cli_width, lead_in),
+    };
+    let (bar, annotations) = mark_percentiles(bar, dist, marks, max_value, cli_width, caps);
+    let left = format!("Smallest: {:#.2} ", format::binary_unit(dist.min));
+    match clip {
+        Some(clip) if clip.beyond > 0 => {
+            println!(
+                "{}{} {} +{} files beyond",
+                left,
+                bar,
+                caps.text("\u{21a6}", "->"),
+                format::format_count(clip.beyond as u64)
+            );
+        }
+        _ => println!(
+            "{}{} Largest: {:#.2}",
+            left,
+            bar,
+            format::binary_unit(dist.max)
+        ),
+    }
+    let indent = left.chars().count();
+    if !annotations.is_empty() {
+        println!("{:indent$}{}", "", annotations, indent = indent);
+    }
+    if !markers.is_empty() {
+        for line in marker_lines(markers, max_value, cli_width, caps) {
+            println!("{:indent$}{}", "", line, indent = indent);
+        }
+    }
+    if origin == Origin::Zero {
+        for line in axis(cli_width + 1, &size_ticks(max_value, cli_width), caps) {
+            println!("{:indent$}{}", "", line, indent = indent);
+        }
+    }
+    if let Some(clip) = clip.filter(|clip| clip.value < dist.max) {
+        println!(
+            "{:indent$}(axis clipped at {}, statistics are exact)",
+            "",
+            clip.caption,
+            indent = indent
+        );
+    }
+}
+
+/// Plots sizes that are all shown the same, see
+/// [`Distribution::is_degenerate`], as a single marker in the middle rather
+/// than a box squeezed into the last cell.
+pub fn plot_degenerate(dist: &Distribution, caps: &Capabilities) {
+    let label = format::human_f64(dist.median);
+    let indent = (caps.width as usize).saturating_sub(label.chars().count() + 2) / 2;
+    println!(
+        "{:indent$}{} {}",
+        "",
+        caps.glyph('\u{2593}', '#'),
+        label,
+        indent = indent
+    );
+}
+
+/// Draws a `╵` on `bar` at each of `marks`, given as (percentile, value)
+/// pairs, and returns it with a row labeling them. A mark landing on the
+/// median or on an earlier mark is only labeled, and labels that would
+/// overlap are moved to the right.
+fn mark_percentiles(
+    bar: String,
+    dist: &Distribution,
+    marks: &[(f64, f64)],
+    max_value: u64,
+    cli_width: usize,
+    caps: &Capabilities,
+) -> (String, String) {
+    let mut cells = bar.chars().collect::<Vec<_>>();
+    let mut marks = marks
+        .iter()
+        .map(|&(percentile, value)| (scale_to(value, max_value, cli_width), percentile))
+        .collect::<Vec<_>>();
+    marks.sort_by_key(|&(position, _)| position);
+
+    let mut taken = vec![scale_to(dist.median, max_value, cli_width)];
+    let mut annotations = String::new();
+    for (position, percentile) in marks {
+        if !taken.contains(&position) {
+            cells[position] = caps.glyph('\u{2575}', '|');
+            taken.push(position);
+        }
+        let used = annotations.chars().count();
+        let start = match used {
+            0 => position,
+            _ => position.max(used + 1),
+        };
+        annotations.push_str(&" ".repeat(start - used));
+        annotations.push_str(&format!("p{}", percentile));
+    }
+    (cells.into_iter().collect(), annotations)
+}
+
+/// Renders a `▲` under the position of each marked size, with its label
+/// below. When two markers share a position, the first one is shown.
+fn marker_lines(
+    markers: &[(char, u64)],
+    max_value: u64,
+    cli_width: usize,
+    caps: &Capabilities,
+) -> [String; 2] {
+    let mut arrows = vec![' '; cli_width + 1];
+    let mut labels = vec![' '; cli_width + 1];
+    for &(label, size) in markers {
+        let position = scale_to(size as f64, max_value, cli_width);
+        if arrows[position] == ' ' {
+            arrows[position] = caps.glyph('\u{25b2}', '^');
+            labels[position] = label;
+        }
+    }
+    [arrows, labels].map(|line| line.into_iter().collect::<String>().trim_end().to_string())
+}
+
+/// Renders `fraction` (0 to 1) of `width` cells as a bar of full blocks, with
+/// eighth blocks for the remainder. In ASCII the bar is of `#` and rounded
+/// to whole cells.
+pub fn horizontal_bar(fraction: f64, width: usize, caps: &Capabilities) -> String {
+    if !caps.unicode {
+        return "#".repeat((fraction.clamp(0.0, 1.0) * width as f64).round() as usize);
+    }
+    let partial = [
+        "", "\u{258f}", "\u{258e}", "\u{258d}", "\u{258c}", "\u{258b}", "\u{258a}", "\u{2589}",
+    ];
+    let eighths = (fraction.clamp(0.0, 1.0) * width as f64 * 8.0).round() as usize;
+    let mut bar = "\u{2588}".repeat(eighths / 8);
+    bar.push_str(partial[eighths % 8]);
+    bar
+}
+
+/// Width of the group name column in small multiples
+const GROUP_NAME_WIDTH: usize = 12;
+/// Width of the median column in small multiples, enough for "999.99 KiB"
+const GROUP_MEDIAN_WIDTH: usize = 10;
+
+/// Plots one box plot per group, all on the same scale running up to
+/// `max_value`, with a shared axis at the bottom.
+pub fn plot_small_multiples(
+    groups: &[Group],
+    max_value: u64,
+    caps: &Capabilities,
+    style: PlotStyle,
+    origin: Origin,
+) {
+    let cli_width = caps.width as usize - (GROUP_NAME_WIDTH + GROUP_MEDIAN_WIDTH + 3);
+    let lead_in = origin == Origin::Min;
+    for group in groups {
+        let bar = match style {
+            PlotStyle::Shade => shade_bar(&group.dist, max_value, cli_width, lead_in, caps),
+            PlotStyle::Braille => braille_bar(&group.dist, max_value, cli_width, lead_in),
+        };
+        println!(
+            "{:<name_width$} {} {:>median_width$}",
+            format::truncate(&group.label(), GROUP_NAME_WIDTH, caps),
+            bar,
+            format::human_f64(group.dist.median),
+            name_width = GROUP_NAME_WIDTH,
+            median_width = GROUP_MEDIAN_WIDTH
+        );
+    }
+    for line in axis(cli_width + 1, &size_ticks(max_value, cli_width), caps) {
+        println!("{:indent$}{}", "", line, indent = GROUP_NAME_WIDTH + 1);
+    }
+    println!(
+        "{:indent$}(shared scale, median on the right)",
+        "",
+        indent = GROUP_NAME_WIDTH + 1
+    );
+}
+
+/// Ticks at every quarter of an axis from zero to `max_value`, `cli_width`
+/// being the position of `max_value`.
+fn size_ticks(max_value: u64, cli_width: usize) -> Vec<(usize, String)> {
+    (0..=4)
+        .map(|quarter| {
+            let label = match quarter {
+                0 => "0".to_string(),
+                4 => format::human(max_value),
+                _ => format::human(max_value / 4 * quarter as u64),
+            };
+            (quarter * cli_width / 4, label)
+        })
+        .collect()
+}
+
+/// Renders a horizontal axis `cells` wide with a tick at each position in
+/// `ticks`, and a line of labels below it. Labels are centered on their
+/// tick where possible. The first and last label are always shown, others
+/// are dropped when they would overlap.
+pub fn axis(cells: usize, ticks: &[(usize, String)], caps: &Capabilities) -> [String; 2] {
+    let mut line = vec![caps.glyph('\u{2500}', '-'); cells];
+    let mut labels = vec![' '; cells];
+    let span = |position: usize, label: &str| {
+        let len = label.chars().count();
+        let start = position
+            .saturating_sub(len / 2)
+            .min(cells.saturating_sub(len));
+        (start, start + len)
+    };
+    let last_start = ticks
+        .last()
+        .map_or(cells, |(position, label)| span(*position, label).0);
+
+    let mut free_from = 0;
+    for (i, (position, label)) in ticks.iter().enumerate() {
+        let position = (*position).min(cells - 1);
+        line[position] = match position {
+            0 => caps.glyph('\u{2514}', '+'),
+            _ if position == cells - 1 => caps.glyph('\u{2518}', '+'),
+            _ => caps.glyph('\u{2534}', '+'),
+        };
+
+        let (start, end) = span(position, label);
+        let is_last = i == ticks.len() - 1;
+        if start < free_from || end > cells || (!is_last && end >= last_start) {
+            continue;
+        }
+        for (j, c) in label.chars().enumerate() {
+            labels[start + j] = c;
+        }
+        free_from = end + 1;
+    }
+    [
+        line.into_iter().collect(),
+        labels
+            .into_iter()
+            .collect::<String>()
+            .trim_end()
+            .to_string(),
+    ]
+}
+
+fn shade_bar(
+    dist: &Distribution,
+    max_value: u64,
+    cli_width: usize,
+    lead_in: bool,
+    caps: &Capabilities,
+) -> String {
+    let light_shade = caps.text("\u{2591}", "-"); // use between min and lower quartile, and upper quartile and max
+    let medium_shade = caps.text("\u{2592}", "="); // use between lower quartile and median, and median and upper quartile
+    let dark_shade = caps.text("\u{2593}", "#"); // use for median
+    let dot = caps.glyph('\u{00b7}', '.'); // use between the edge and min, if drawn at all
+
+    let pos = Positions::new(dist, max_value, cli_width);
+    let mut bar = String::new();
+    for _ in 0..pos.min {
+        bar.push(if lead_in { dot } else { ' ' });
+    }
+    for _ in pos.min..pos.lower_quartile {
+        bar.push_str(light_shade);
+    }
+    for _ in pos.lower_quartile..pos.median {
+        bar.push_str(medium_shade);
+    }
+    bar.push_str(dark_shade);
+    for _ in pos.median..pos.upper_quartile {
+        bar.push_str(medium_shade);
+    }
+    for _ in pos.upper_quartile..pos.max {
+        bar.push_str(light_shade);
+    }
+    for _ in pos.max..cli_width {
+        bar.push(' ');
+    }
+    bar
+}
+
+// Dot bits of a Braille cell, indexed by row, for the left and right column.
+const BRAILLE_LEFT: [u32; 4] = [0x01, 0x02, 0x04, 0x40];
+const BRAILLE_RIGHT: [u32; 4] = [0x08, 0x10, 0x20, 0x80];
+
+/// Draws the box plot with Braille dots, giving two horizontal sub-columns
+/// per character cell. The bar is as wide as the shaded one.
+fn braille_bar(dist: &Distribution, max_value: u64, cli_width: usize, lead_in: bool) -> String {
+    let cells = cli_width + 1;
+    let pos = Positions::new(dist, max_value, 2 * cells - 1);
+
+    // Rows lit in each sub-column, bit n meaning row n from the top
+    let whisker = 0b0110;
+    let box_edges = 0b1001;
+    let full = 0b1111;
+    let dot = 0b0100;
+    let column = |x: usize| -> u32 {
+        if [
+            pos.min,
+            pos.lower_quartile,
+            pos.median,
+            pos.upper_quartile,
+            pos.max,
+        ]
+        .contains(&x)
+        {
+            full
+        } else if x > pos.lower_quartile && x < pos.upper_quartile {
+            box_edges
+        } else if x > pos.min && x < pos.max {
+            whisker
+        } else if lead_in && x < pos.min && x.is_multiple_of(4) {
+            dot
+        } else {
+            0
+        }
+    };
+
+    (0..cells)
+        .map(|cell| {
+            let (left, right) = (column(2 * cell), column(2 * cell + 1));
+            let dots = (0..4)
+                .filter(|row| left & (1 << row) != 0)
+                .map(|row| BRAILLE_LEFT[row])
+                .chain(
+                    (0..4)
+                        .filter(|row| right & (1 << row) != 0)
+                        .map(|row| BRAILLE_RIGHT[row]),
+                )
+                .sum::<u32>();
+            if dots == 0 {
+                ' '
+            } else {
+                char::from_u32(0x2800 + dots).expect("Invalid Braille pattern")
+            }
+        })
+        .collect()
+}
+
+/// Draws a violin plot of `sizes` on a log2 axis, `height` rows tall and
+/// thicker where files are more common.
+pub fn plot_violin(
+    dist: &Distribution,
+    sizes: &(impl Ranked + ?Sized),
+    caps: &Capabilities,
+    height: usize,
+) {
+    let medium_shade = caps.text("\u{2592}", "="); // use for the body of the violin
+    let dark_shade = caps.text("\u{2593}", "#"); // use for median
+
+    let cells = caps.width as usize - 40 + 1;
+    let density = distribution::log2_density(sizes, cells);
+    let peak = density.iter().cloned().fold(0.0, f64::max);
+
+    let log = |size: f64| size.max(1.0).log2();
+    let (lo, hi) = (log(dist.min as f64), log(dist.max as f64));
+    let median = if hi > lo {
+        ((log(dist.median) - lo) / (hi - lo) * (cells - 1) as f64).round() as usize
+    } else {
+        cells / 2
+    };
+
+    let left = format!("Smallest: {:#.2} ", format::binary_unit(dist.min));
+    let right = format!(" Largest: {:#.2}", format::binary_unit(dist.max));
+    let center = (height - 1) as f64 / 2.0;
+    for row in 0..height {
+        let bar = density
+            .iter()
+            .enumerate()
+            .map(|(x, d)| {
+                let thickness = d / peak * height as f64 / 2.0;
+                if (row as f64 - center).abs() >= thickness {
+                    " "
+                } else if x == median {
+                    dark_shade
+                } else {
+                    medium_shade
+                }
+            })
+            .collect::<String>();
+        if row == height / 2 {
+            println!("{}{}{}", left, bar, right);
+        } else {
+            println!("{:width$}{}", "", bar, width = left.chars().count());
+        }
+    }
+    println!(
+        "{:width$}(log2 scale, median marked)",
+        "",
+        width = left.chars().count()
+    );
+}
+
+/// Height of the scatter plot in rows
+const SCATTER_HEIGHT: usize = 15;
+/// Width of the age labels left of the scatter plot, enough for "10.5y"
+const AGE_LABEL_WIDTH: usize = 6;
+
+/// Plots the size of files against their age in days (`points` being
+/// `(size, age)` pairs) on log2 scales, shading each cell by how many files
+/// fall into it.
+pub fn plot_scatter(points: &[(u64, f64)], caps: &Capabilities) {
+    let shades = match caps.unicode {
+        true => ['\u{2591}', '\u{2592}', '\u{2593}', '\u{2588}'],
+        false => ['.', '+', '*', '#'],
+    };
+
+    let cells = caps.width as usize - AGE_LABEL_WIDTH - 2;
+    let log_size = |size: u64| (size.max(1) as f64).log2();
+    let log_age = |age: f64| (age.max(0.0) + 1.0).log2();
+    let range = |values: &mut dyn Iterator<Item = f64>| {
+        values.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| {
+            (lo.min(v), hi.max(v))
+        })
+    };
+    let (size_lo, size_hi) = range(&mut points.iter().map(|&(size, _)| log_size(size)));
+    let (age_lo, age_hi) = range(&mut points.iter().map(|&(_, age)| log_age(age)));
+    // Where `value` falls on an axis of `cells` positions from `lo` to `hi`
+    let position = |value: f64, lo: f64, hi: f64, cells: usize| {
+        if hi > lo {
+            ((value - lo) / (hi - lo) * (cells - 1) as f64).round() as usize
+        } else {
+            cells / 2
+        }
+    };
+
+    let mut grid = vec![vec![0usize; cells]; SCATTER_HEIGHT];
+    for &(size, age) in points {
+        let x = position(log_size(size), size_lo, size_hi, cells);
+        let y = position(log_age(age), age_lo, age_hi, SCATTER_HEIGHT);
+        // The oldest files go at the top
+        grid[SCATTER_HEIGHT - 1 - y][x] += 1;
+    }
+    let busiest = grid.iter().flatten().copied().max().unwrap_or(0);
+
+    let age_at_row = |row: usize| {
+        let fraction = (SCATTER_HEIGHT - 1 - row) as f64 / (SCATTER_HEIGHT - 1) as f64;
+        (age_lo + (age_hi - age_lo) * fraction).exp2() - 1.0
+    };
+    // Files all of one age sit on the middle row, the only one labelled
+    let labelled = |row: usize| match age_hi > age_lo {
+        true => row == 0 || row == SCATTER_HEIGHT / 2 || row == SCATTER_HEIGHT - 1,
+        false => row == SCATTER_HEIGHT - 1 - SCATTER_HEIGHT / 2,
+    };
+    for (row, counts) in grid.iter().enumerate() {
+        let label = if labelled(row) {
+            format::human_days(age_at_row(row))
+        } else {
+            String::new()
+        };
+        let line = counts
+            .iter()
+            .map(|&count| match count {
+                0 => ' ',
+                // Counts are shaded on a log scale, a few busy cells would
+                // otherwise wash out the rest
+                _ => {
+                    let level = ((count as f64).ln_1p() / (busiest as f64).ln_1p()
+                        * shades.len() as f64)
+                        .ceil() as usize;
+                    shades[level.clamp(1, shades.len()) - 1]
+                }
+            })
+            .collect::<String>();
+        println!(
+            "{:>label_width$} {}{}",
+            label,
+            caps.glyph('\u{2502}', '|'),
+            line.trim_end(),
+            label_width = AGE_LABEL_WIDTH
+        );
+    }
+
+    let ticks = match size_hi > size_lo {
+        true => (0..=4)
+            .map(|quarter| {
+                let fraction = quarter as f64 / 4.0;
+                let size = (size_lo + (size_hi - size_lo) * fraction).exp2().round() as u64;
+                (quarter * (cells - 1) / 4, format::human(size))
+            })
+            .collect::<Vec<_>>(),
+        // Likewise for files all of one size
+        false => vec![(cells / 2, format::human(size_lo.exp2().round() as u64))],
+    };
+    let [line, labels] = axis(cells, &ticks, caps);
+    // The corner belongs to the age axis, the first tick joins it
+    let line = line.replacen(caps.glyph('\u{2514}', '+'), caps.text("\u{2534}", "+"), 1);
+    println!(
+        "{:indent$}{}{}",
+        "",
+        caps.glyph('\u{2514}', '+'),
+        line,
+        indent = AGE_LABEL_WIDTH + 1
+    );
+    println!("{:indent$}{}", "", labels, indent = AGE_LABEL_WIDTH + 2);
+    println!(
+        "{:indent$}({} files, size across and age up on log2 scales, darker cells hold more)",
+        "",
+        format::format_count(points.len() as u64),
+        indent = AGE_LABEL_WIDTH + 2
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spread() -> Distribution {
+        Distribution {
+            min: 10,
+            lower_quartile: 20.0,
+            median: 40.0,
+            upper_quartile: 60.0,
+            max: 100,
+        }
+    }
+
+    fn unicode() -> Capabilities {
+        Capabilities {
+            unicode: true,
+            width: 80,
+        }
+    }
+
+    #[test]
+    fn braille_bar_draws_whiskers_box_and_median() {
+        assert_eq!(
+            braille_bar(&spread(), 100, 20, false),
+            "  ⡷⠶⣏⣉⣉⣉⣏⣉⣉⣉⣹⠶⠶⠶⠶⠶⠶⠶⢾"
+        );
+    }
+
+    #[test]
+    fn braille_bar_packs_a_tight_box_into_one_cell() {
+        let tight = Distribution {
+            min: 1000,
+            lower_quartile: 1010.0,
+            median: 1012.0,
+            upper_quartile: 1015.0,
+            max: 2000,
+        };
+        assert_eq!(
+            braille_bar(&tight, 2000, 20, false),
+            "          ⢸⠶⠶⠶⠶⠶⠶⠶⠶⠶⢾"
+        );
+        assert_eq!(
+            shade_bar(&tight, 2000, 20, false, &unicode()),
+            "          ▓░░░░░░░░░░"
+        );
+    }
+
+    #[test]
+    fn shade_bar_matches_the_braille_layout() {
+        assert_eq!(
+            shade_bar(&spread(), 100, 20, false, &Capabilities::ascii(80)),
+            "  --====#====--------"
+        );
+        assert_eq!(
+            shade_bar(&spread(), 100, 20, false, &unicode()),
+            "  ░░▒▒▒▒▓▒▒▒▒░░░░░░░░"
+        );
+    }
+
+    /// Files of around a gigabyte, where the bar starts far from the left
+    fn gigabytes() -> Distribution {
+        Distribution {
+            min: 900 << 20,
+            lower_quartile: (950u64 << 20) as f64,
+            median: (1000u64 << 20) as f64,
+            upper_quartile: (1010u64 << 20) as f64,
+            max: 1100 << 20,
+        }
+    }
+
+    #[test]
+    fn origin_zero_leaves_the_lead_in_blank_and_draws_an_axis() {
+        let dist = gigabytes();
+        assert_eq!(
+            shade_bar(&dist, dist.max, 20, false, &unicode()),
+            "                ░▒▓░░"
+        );
+        assert_eq!(
+            braille_bar(&dist, dist.max, 20, false),
+            "                 ⣿⣹⡷⢾"
+        );
+        assert_eq!(
+            axis(21, &size_ticks(dist.max, 20), &unicode()),
+            ["└────┴────┴────┴────┘", "0 275 MiB    1.07 GiB"]
+        );
+    }
+
+    #[test]
+    fn origin_min_dots_the_lead_in() {
+        let dist = gigabytes();
+        assert_eq!(
+            shade_bar(&dist, dist.max, 20, true, &unicode()),
+            "················░▒▓░░"
+        );
+        assert_eq!(
+            braille_bar(&dist, dist.max, 20, true),
+            "⠄ ⠄ ⠄ ⠄ ⠄ ⠄ ⠄ ⠄ ⠄⣿⣹⡷⢾"
+        );
+        assert_eq!(
+            shade_bar(&dist, dist.max, 20, true, &Capabilities::ascii(80)),
+            "................-=#--"
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! What a run of dudist itself consumed, for `--resource-stats`: the
+//! directory listings and metadata calls it issued, the entries it kept in
+//! memory, its peak memory and its time.
+//!
+//! The walk and the metadata calls count themselves through [`stat`] and
+//! [`readdir`], which only touch the counters once [`enable`] was called,
+//! so runs without the flag pay for no more than a relaxed load. The
+//! counters are shared by the whole process, parallel walks included.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::format;
+
+/// Whether the calls are counted, see [`enable`]
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static STAT_CALLS: AtomicU64 = AtomicU64::new(0);
+
+static READDIR_CALLS: AtomicU64 = AtomicU64::new(0);
+
+/// Counts the calls from now on.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Counts a stat call, or one reading the metadata of an entry.
+#[inline]
+pub fn stat() {
+    if ENABLED.load(Ordering::Relaxed) {
+        STAT_CALLS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Counts a directory opened for listing.
+#[inline]
+pub fn readdir() {
+    if ENABLED.load(Ordering::Relaxed) {
+        READDIR_CALLS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// What the run consumed, as [`Usage::measure`] found it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Usage {
+    pub readdir_calls: u64,
+    pub stat_calls: u64,
+    /// Entries the report kept in memory, one per file for most of it
+    pub retained: u64,
+    /// The largest resident set so far, where the platform reports it
+    pub peak_rss: Option<u64>,
+    pub wall: Duration,
+    /// User and system time, where the platform reports them
+    pub cpu: Option<Duration>,
+}
+
+impl Usage {
+    /// Takes the counts so far, the peak memory and the time since `start`.
+    pub fn measure(start: Instant, retained: u64) -> Usage {
+        Usage {
+            readdir_calls: READDIR_CALLS.load(Ordering::Relaxed),
+            stat_calls: STAT_CALLS.load(Ordering::Relaxed),
+            retained,
+            peak_rss: peak_rss(),
+            wall: start.elapsed(),
+            cpu: cpu_time(),
+        }
+    }
+
+    /// The lines of the footer.
+    pub fn render(&self) -> String {
+        let unknown = || "unknown".to_string();
+        let cpu = self
+            .cpu
+            .map_or_else(unknown, |cpu| format!("{:.2}s CPU", cpu.as_secs_f64()));
+        format!(
+            "Resources: {} readdir, {} stat calls, {} entries kept\n\
+             Peak memory: {}, time: {:.2}s wall, {}\n",
+            format::format_count(self.readdir_calls),
+            format::format_count(self.stat_calls),
+            format::format_count(self.retained),
+            self.peak_rss.map_or_else(unknown, format::human),
+            self.wall.as_secs_f64(),
+            cpu
+        )
+    }
+
+    /// The usage as JSON, for `--json`.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "readdir_calls": self.readdir_calls,
+            "stat_calls": self.stat_calls,
+            "retained_entries": self.retained,
+            "peak_rss_bytes": self.peak_rss,
+            "wall_seconds": self.wall.as_secs_f64(),
+            "cpu_seconds": self.cpu.map(|cpu| cpu.as_secs_f64()),
+        })
+    }
+}
+
+/// The high-water mark of the resident set, `VmHWM` in
+/// `/proc/self/status`.
+#[cfg(target_os = "linux")]
+fn peak_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kib = line
+        .trim_start_matches("VmHWM:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kib * 1024)
+}
+
+/// The largest resident set getrusage reports, in bytes on macOS and KiB
+/// on the BSDs.
+#[cfg(all(unix, not(target_os = "linux")))]
+fn peak_rss() -> Option<u64> {
+    let usage = rusage()?;
+    let max = u64::try_from(usage.ru_maxrss).ok()?;
+    match cfg!(target_os = "macos") {
+        true => Some(max),
+        false => Some(max * 1024),
+    }
+}
+
+#[cfg(not(unix))]
+fn peak_rss() -> Option<u64> {
+    None
+}
+
+#[cfg(unix)]
+fn rusage() -> Option<libc::rusage> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+    // getrusage fills in the struct when it succeeds
+    match unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) } {
+        0 => Some(unsafe { usage.assume_init() }),
+        _ => None,
+    }
+}
+
+/// The user and system time of the process so far.
+#[cfg(unix)]
+fn cpu_time() -> Option<Duration> {
+    let usage = rusage()?;
+    let time = |tv: libc::timeval| {
+        Duration::from_secs(tv.tv_sec as u64) + Duration::from_micros(tv.tv_usec as u64)
+    };
+    Some(time(usage.ru_utime) + time(usage.ru_stime))
+}
+
+#[cfg(not(unix))]
+fn cpu_time() -> Option<Duration> {
+    None
+}
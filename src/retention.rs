@@ -0,0 +1,295 @@
+//! Previews of retention policies like "delete after 180 days": which files
+//! each would delete by their modification time, and how much it frees.
+
+use std::time::{Duration, SystemTime};
+
+use serde_json::{json, Value};
+
+use crate::distribution;
+use crate::format;
+use crate::table::{Align, Table};
+
+/// Files of one side of a policy.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Set {
+    pub files: u64,
+    pub bytes: u64,
+    /// The median size, without files none
+    pub median: Option<f64>,
+    pub p95: Option<f64>,
+}
+
+impl Set {
+    /// The set of `sizes`, sorted in place.
+    fn of(sizes: &mut [u64]) -> Set {
+        sizes.sort_unstable();
+        let percentile = |p| (!sizes.is_empty()).then(|| distribution::percentile(sizes, p));
+        Set {
+            files: sizes.len() as u64,
+            bytes: sizes.iter().sum(),
+            median: percentile(50.0),
+            p95: percentile(95.0),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "files": self.files,
+            "bytes": self.bytes,
+            "median": self.median,
+            "p95": self.p95,
+        })
+    }
+}
+
+/// What a policy deleting files unmodified for `age` would do.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Policy {
+    pub age: Duration,
+    pub delete: Set,
+    pub keep: Set,
+}
+
+/// The previews of the `--retention` policies.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Retention {
+    /// Ordered by age, the one deleting the most first
+    pub policies: Vec<Policy>,
+    /// Files without a usable modification time, which no policy decides
+    pub unknown: Set,
+    /// The bytes of all the files, the unknown ones among them
+    pub total: u64,
+}
+
+impl Retention {
+    /// Splits the files of `dated` (size and modification time) by each of
+    /// `ages` before `now`, with the sizes of the files of `unknown` time.
+    /// Files from the future are kept.
+    ///
+    /// ```
+    /// use std::time::{Duration, SystemTime};
+    /// use dudist::retention::Retention;
+    ///
+    /// let day = Duration::from_secs(86400);
+    /// let now = SystemTime::now();
+    /// let dated = [(1024, now - 10 * day), (4096, now - 200 * day)];
+    /// let retention = Retention::preview(&[180 * day], &dated, &mut [], now);
+    /// assert_eq!(retention.policies[0].delete.bytes, 4096);
+    /// ```
+    pub fn preview(
+        ages: &[Duration],
+        dated: &[(u64, SystemTime)],
+        unknown: &mut [u64],
+        now: SystemTime,
+    ) -> Retention {
+        let unknown = Set::of(unknown);
+        let total = dated.iter().map(|&(size, _)| size).sum::<u64>() + unknown.bytes;
+        let mut ages = ages.to_vec();
+        ages.sort();
+        ages.dedup();
+        let policies = ages
+            .into_iter()
+            .map(|age| {
+                let cutoff = now.checked_sub(age).unwrap_or(SystemTime::UNIX_EPOCH);
+                let (mut delete, mut keep) = (Vec::new(), Vec::new());
+                for &(size, modified) in dated {
+                    match modified < cutoff {
+                        true => delete.push(size),
+                        false => keep.push(size),
+                    }
+                }
+                Policy {
+                    age,
+                    delete: Set::of(&mut delete),
+                    keep: Set::of(&mut keep),
+                }
+            })
+            .collect();
+        Retention {
+            policies,
+            unknown,
+            total,
+        }
+    }
+
+    fn share(&self, bytes: u64) -> f64 {
+        match self.total {
+            0 => 0.0,
+            total => bytes as f64 / total as f64 * 100.0,
+        }
+    }
+
+    /// The table of the sets of each policy and a line of what they free.
+    pub fn render(&self) -> String {
+        let mut out = String::from("Retention by modification time:\n");
+        let mut table = Table::new(&[
+            Align::Left,
+            Align::Left,
+            Align::Right,
+            Align::Right,
+            Align::Right,
+            Align::Right,
+            Align::Right,
+        ]);
+        table.row(["After", "Set", "Files", "Bytes", "Share", "Median", "p95"].map(str::to_string));
+        let mut row = |policy: String, name: &str, set: &Set| {
+            let stat = |value: Option<f64>| value.map_or("-".to_string(), format::human_f64);
+            table.row([
+                policy,
+                name.to_string(),
+                format::format_count(set.files),
+                format::human(set.bytes),
+                format!("{:.1}%", self.share(set.bytes)),
+                stat(set.median),
+                stat(set.p95),
+            ]);
+        };
+        for policy in &self.policies {
+            row(format::human_duration(policy.age), "delete", &policy.delete);
+            row(String::new(), "keep", &policy.keep);
+        }
+        if self.unknown.files > 0 {
+            row(String::new(), "unknown", &self.unknown);
+        }
+        out += &table.render();
+        let frees = self
+            .policies
+            .iter()
+            .map(|policy| {
+                format!(
+                    "{}: frees {} ({:.1}%)",
+                    format::human_duration(policy.age),
+                    format::human(policy.delete.bytes),
+                    self.share(policy.delete.bytes)
+                )
+            })
+            .collect::<Vec<_>>();
+        out + &frees.join(", ") + "\n"
+    }
+
+    /// The sets as JSON, for `--json`.
+    pub fn to_json(&self) -> Value {
+        let policies = self
+            .policies
+            .iter()
+            .map(|policy| {
+                json!({
+                    "age_seconds": policy.age.as_secs(),
+                    "delete": policy.delete.to_json(),
+                    "keep": policy.keep.to_json(),
+                    "frees_share": self.share(policy.delete.bytes) / 100.0,
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "total_bytes": self.total,
+            "unknown": self.unknown.to_json(),
+            "policies": policies,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(86400);
+
+    fn now() -> SystemTime {
+        SystemTime::UNIX_EPOCH + 1000 * DAY
+    }
+
+    /// The age, the files and the bytes each policy deletes.
+    fn frees(retention: &Retention) -> Vec<(u64, u64, u64)> {
+        retention
+            .policies
+            .iter()
+            .map(|policy| {
+                let days = policy.age.as_secs() / DAY.as_secs();
+                (days, policy.delete.files, policy.delete.bytes)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn policies_split_the_files_by_age() {
+        // A file a day old to one 400 days old, 1 KiB each but the oldest
+        let mut dated = (1..=400)
+            .map(|days| (1024, now() - days * DAY))
+            .collect::<Vec<_>>();
+        dated[399].0 = 1 << 20;
+        let retention = Retention::preview(&[180 * DAY, 90 * DAY], &dated, &mut [4096], now());
+        assert_eq!(
+            frees(&retention),
+            [
+                (90, 310, 309 * 1024 + (1 << 20)),
+                (180, 220, 219 * 1024 + (1 << 20))
+            ]
+        );
+        let keep = &retention.policies[0].keep;
+        assert_eq!(
+            (keep.files, keep.median, keep.p95),
+            (90, Some(1024.0), Some(1024.0))
+        );
+        assert_eq!(
+            (retention.unknown.files, retention.unknown.bytes),
+            (1, 4096)
+        );
+        assert_eq!(retention.total, 399 * 1024 + (1 << 20) + 4096);
+    }
+
+    #[test]
+    fn files_exactly_as_old_as_the_policy_are_kept() {
+        let dated = [
+            (1, now() - 30 * DAY + Duration::from_secs(1)),
+            (2, now() - 30 * DAY),
+            (4, now() - 30 * DAY - Duration::from_secs(1)),
+        ];
+        let retention = Retention::preview(&[30 * DAY], &dated, &mut [], now());
+        let policy = &retention.policies[0];
+        assert_eq!((policy.keep.files, policy.keep.bytes), (2, 3));
+        assert_eq!((policy.delete.files, policy.delete.bytes), (1, 4));
+    }
+
+    #[test]
+    fn files_from_the_future_are_kept() {
+        let dated = [(8, now() + DAY), (16, now() - 2 * DAY)];
+        let retention = Retention::preview(&[DAY], &dated, &mut [], now());
+        assert_eq!(frees(&retention), [(1, 1, 16)]);
+        assert_eq!(retention.policies[0].keep.bytes, 8);
+    }
+
+    #[test]
+    fn ages_are_sorted_once_each() {
+        let dated = [(1, now() - 5 * DAY)];
+        let retention = Retention::preview(&[7 * DAY, DAY, 7 * DAY], &dated, &mut [], now());
+        assert_eq!(frees(&retention), [(1, 1, 1), (7, 0, 0)]);
+    }
+
+    #[test]
+    fn ages_older_than_the_epoch_delete_nothing() {
+        let dated = [(1, SystemTime::UNIX_EPOCH)];
+        let retention = Retention::preview(&[2000 * DAY], &dated, &mut [], now());
+        assert_eq!(frees(&retention), [(2000, 0, 0)]);
+    }
+
+    #[test]
+    fn no_files_leave_every_set_empty() {
+        let retention = Retention::preview(&[30 * DAY], &[], &mut [], now());
+        let empty = Set::default();
+        assert_eq!(retention.policies[0].delete, empty);
+        assert_eq!(retention.policies[0].keep, empty);
+        assert_eq!(retention.unknown, empty);
+        assert_eq!(retention.total, 0);
+        assert!(retention.render().ends_with("30d: frees 0 B (0.0%)\n"));
+    }
+
+    #[test]
+    fn files_of_unknown_time_are_left_to_no_policy() {
+        let retention = Retention::preview(&[30 * DAY], &[], &mut [300, 100, 200], now());
+        assert_eq!(frees(&retention), [(30, 0, 0)]);
+        assert_eq!(retention.unknown.median, Some(200.0));
+        assert_eq!(retention.total, 600);
+        assert!(retention.render().contains("unknown"));
+    }
+}
@@ -0,0 +1,207 @@
+//! Retrying metadata calls that fail for a moment, as stat and readdir do
+//! on NFS and CIFS mounts under load.
+//!
+//! Only errors that plausibly go away are retried: EIO, ESTALE and EAGAIN.
+//! The others, like a file that is gone or can't be read, fail straight
+//! away. The delay between attempts doubles from [`FIRST_DELAY`] up to the
+//! cap of the policy.
+
+use std::io;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// The delay before the first retry
+pub const FIRST_DELAY: Duration = Duration::from_millis(10);
+
+/// How often and how patiently to retry a metadata call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Retry {
+    /// Attempts after the first, 0 not retrying at all
+    pub retries: u32,
+    /// The longest delay between attempts
+    pub max_delay: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Retry {
+            retries: 0,
+            max_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+/// What [`Retry::run`] came to.
+#[derive(Debug)]
+pub struct Attempts<T> {
+    pub result: io::Result<T>,
+    /// The attempts after the first
+    pub retries: u32,
+}
+
+impl<T> Attempts<T> {
+    /// Whether the call failed at first but succeeded when retried.
+    pub fn recovered(&self) -> bool {
+        self.retries > 0 && self.result.is_ok()
+    }
+}
+
+impl Retry {
+    /// Runs `op` until it succeeds, fails for good or runs out of retries.
+    pub fn run<T>(&self, op: impl FnMut() -> io::Result<T>) -> Attempts<T> {
+        self.run_with(op, std::thread::sleep)
+    }
+
+    /// Like [`run`](Self::run), waiting between attempts with `sleep`.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use dudist::retry::Retry;
+    ///
+    /// let retry = Retry {
+    ///     retries: 3,
+    ///     max_delay: Duration::from_millis(100),
+    /// };
+    /// let attempts = retry.run_with(|| std::fs::metadata("."), |_| {});
+    /// assert!(attempts.result.is_ok());
+    /// ```
+    pub fn run_with<T>(
+        &self,
+        mut op: impl FnMut() -> io::Result<T>,
+        mut sleep: impl FnMut(Duration),
+    ) -> Attempts<T> {
+        let mut delay = FIRST_DELAY.min(self.max_delay);
+        let mut retries = 0;
+        loop {
+            let result = op();
+            match &result {
+                Err(e) if retries < self.retries && is_transient(e) => {
+                    sleep(delay);
+                    delay = (delay * 2).min(self.max_delay);
+                    retries += 1;
+                }
+                _ => return Attempts { result, retries },
+            }
+        }
+    }
+}
+
+/// Whether `e` may well not happen again.
+#[cfg(unix)]
+pub fn is_transient(e: &io::Error) -> bool {
+    matches!(
+        e.raw_os_error(),
+        Some(libc::EIO | libc::ESTALE | libc::EAGAIN)
+    )
+}
+
+#[cfg(not(unix))]
+pub fn is_transient(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::WouldBlock
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn retry(retries: u32, max_delay_ms: u64) -> Retry {
+        Retry {
+            retries,
+            max_delay: Duration::from_millis(max_delay_ms),
+        }
+    }
+
+    /// Fails with `errors` in turn, then succeeds with 42.
+    fn flaky(errors: Vec<io::Error>) -> impl FnMut() -> io::Result<u32> {
+        let mut errors = errors.into_iter();
+        move || match errors.next() {
+            Some(e) => Err(e),
+            None => Ok(42),
+        }
+    }
+
+    fn os(code: i32) -> io::Error {
+        io::Error::from_raw_os_error(code)
+    }
+
+    /// Runs `op` with `retry`, giving what it came to and the delays.
+    fn run(retry: Retry, op: impl FnMut() -> io::Result<u32>) -> (Attempts<u32>, Vec<u128>) {
+        let mut delays = Vec::new();
+        let attempts = retry.run_with(op, |delay| delays.push(delay.as_millis()));
+        (attempts, delays)
+    }
+
+    #[test]
+    fn transient_errors_are_retried_until_the_call_succeeds() {
+        let errors = vec![os(libc::EIO), os(libc::ESTALE), os(libc::EAGAIN)];
+        let (attempts, delays) = run(retry(4, 1000), flaky(errors));
+        assert!(attempts.recovered());
+        assert_eq!(attempts.retries, 3);
+        assert_eq!(attempts.result.unwrap(), 42);
+        assert_eq!(delays, [10, 20, 40]);
+    }
+
+    #[test]
+    fn a_call_succeeding_at_once_is_not_recovered() {
+        let (attempts, delays) = run(retry(4, 1000), flaky(Vec::new()));
+        assert_eq!(attempts.retries, 0);
+        assert!(!attempts.recovered());
+        assert!(delays.is_empty());
+    }
+
+    #[test]
+    fn permanent_errors_are_not_retried() {
+        for e in [
+            os(libc::ENOENT),
+            os(libc::EACCES),
+            io::Error::from(io::ErrorKind::NotFound),
+            io::Error::from(io::ErrorKind::PermissionDenied),
+        ] {
+            let kind = e.kind();
+            let (attempts, delays) = run(retry(4, 1000), flaky(vec![e]));
+            assert_eq!(attempts.result.unwrap_err().kind(), kind);
+            assert_eq!(attempts.retries, 0);
+            assert!(delays.is_empty());
+        }
+
+        // Nor after a transient one
+        let (attempts, delays) = run(retry(4, 1000), flaky(vec![os(libc::EIO), os(libc::ENOENT)]));
+        assert!(!attempts.recovered());
+        assert_eq!(attempts.retries, 1);
+        assert_eq!(
+            attempts.result.unwrap_err().raw_os_error(),
+            Some(libc::ENOENT)
+        );
+        assert_eq!(delays, [10]);
+    }
+
+    #[test]
+    fn retrying_stops_at_the_limit() {
+        let (attempts, delays) = run(
+            retry(3, 1000),
+            flaky((0..10).map(|_| os(libc::EIO)).collect()),
+        );
+        assert!(!attempts.recovered());
+        assert_eq!(attempts.retries, 3);
+        assert_eq!(attempts.result.unwrap_err().raw_os_error(), Some(libc::EIO));
+        assert_eq!(delays.len(), 3);
+
+        // Without retries, nothing is
+        let (attempts, delays) = run(Retry::default(), flaky(vec![os(libc::EIO)]));
+        assert!(attempts.result.is_err());
+        assert_eq!(attempts.retries, 0);
+        assert!(delays.is_empty());
+    }
+
+    #[test]
+    fn the_delay_doubles_up_to_the_cap() {
+        let errors = (0..6).map(|_| os(libc::EAGAIN)).collect();
+        let (_, delays) = run(retry(6, 30), flaky(errors));
+        assert_eq!(delays, [10, 20, 30, 30, 30, 30]);
+
+        // A cap below the first delay holds from the start
+        let (_, delays) = run(retry(2, 4), flaky(vec![os(libc::EIO), os(libc::EIO)]));
+        assert_eq!(delays, [4, 4]);
+    }
+}
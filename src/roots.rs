@@ -0,0 +1,351 @@
+//! Scanning several roots at once, each on a worker thread of its own, as
+//! for a handful of slow network mounts.
+//!
+//! A root still being walked when its timeout runs out is abandoned: its
+//! walk is cancelled and the files it yielded until then are kept, so a
+//! dead mount doesn't hold up the report on the others. A worker stuck in a
+//! system call can't be stopped, it is simply no longer waited for.
+
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::format;
+use crate::interrupt;
+use crate::scan::{self, FileRecord, Progress, ScanCounts, ScanError, ScanOptions, Visited};
+use crate::terminal::{self, Capabilities};
+
+/// How often the progress line is redrawn
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Files in flight from the workers, bounding the memory a fast root can
+/// take ahead of the report
+const CHANNEL_CAPACITY: usize = 4096;
+
+/// Where the walk of a root stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RootState {
+    /// Waiting for a free worker, see `jobs`
+    Waiting,
+    Scanning,
+    Done,
+    /// Abandoned after running out of time, with the files found so far
+    TimedOut,
+}
+
+/// A root of a [`MultiScan`] and how far its walk got.
+#[derive(Debug, Clone)]
+pub struct RootStatus {
+    pub root: PathBuf,
+    pub state: RootState,
+    /// The counts of the walk, as far as it got
+    pub counts: ScanCounts,
+    /// How long the walk ran
+    pub elapsed: Duration,
+}
+
+enum Message {
+    Found(usize, Result<FileRecord, ScanError>),
+    /// The end of the walk of a root, with its topmost empty directories
+    Done(usize, Vec<PathBuf>),
+}
+
+/// What the report side keeps of a worker.
+#[derive(Default)]
+struct Worker {
+    cancel: Arc<AtomicBool>,
+    /// Kept up to date by the worker, for the progress line and for roots
+    /// that time out
+    counts: Arc<Mutex<ScanCounts>>,
+    started: Option<Instant>,
+}
+
+/// The iterator returned by [`scan_roots`], yielding the files of all roots
+/// in the order they are found.
+pub struct MultiScan {
+    options: ScanOptions,
+    jobs: usize,
+    timeout: Option<Duration>,
+    statuses: Vec<RootStatus>,
+    workers: Vec<Worker>,
+    sender: SyncSender<Message>,
+    receiver: Receiver<Message>,
+    /// What the progress line may use, when it is shown
+    progress: Option<Capabilities>,
+    drawn: Option<Instant>,
+    visited: Option<Visited>,
+    reported: Option<Progress>,
+    /// The topmost empty directories of the roots walked, when looked for
+    empty_trees: Option<Vec<PathBuf>>,
+}
+
+/// Walks `roots` at the same time, at most `jobs` of them at once, each
+/// like [`scan_iter`](scan::scan_iter). Roots taking longer than `timeout`
+/// are abandoned.
+pub fn scan_roots(
+    roots: &[PathBuf],
+    options: &ScanOptions,
+    jobs: usize,
+    timeout: Option<Duration>,
+) -> MultiScan {
+    let (sender, receiver) = mpsc::sync_channel(CHANNEL_CAPACITY);
+    MultiScan {
+        options: options.clone(),
+        jobs: jobs.max(1),
+        timeout,
+        statuses: roots
+            .iter()
+            .map(|root| RootStatus {
+                root: root.clone(),
+                state: RootState::Waiting,
+                counts: ScanCounts::default(),
+                elapsed: Duration::ZERO,
+            })
+            .collect(),
+        workers: roots.iter().map(|_| Worker::default()).collect(),
+        sender,
+        receiver,
+        progress: None,
+        drawn: None,
+        visited: None,
+        reported: None,
+        empty_trees: None,
+    }
+}
+
+impl MultiScan {
+    /// Also keeps a line on stderr up to date with the files found under
+    /// each root, if stderr is a terminal.
+    pub fn show_progress(mut self, caps: &Capabilities) -> Self {
+        self.progress = std::io::stderr().is_terminal().then_some(*caps);
+        self
+    }
+
+    /// Skips the directories `visited` has seen already, like
+    /// [`Scan::skip_visited`](scan::Scan::skip_visited), sharing it
+    /// between the roots.
+    pub fn skip_visited(mut self, visited: &Visited) -> Self {
+        self.visited = Some(visited.clone());
+        self
+    }
+
+    /// Keeps `progress` up to date with the entries of all roots, like
+    /// [`Scan::report_progress`](scan::Scan::report_progress).
+    pub fn report_progress(mut self, progress: &Progress) -> Self {
+        self.reported = Some(progress.clone());
+        self
+    }
+
+    /// Also looks for the directories without files below them, like
+    /// [`Scan::find_empty_dirs`](scan::Scan::find_empty_dirs).
+    pub fn find_empty_dirs(mut self) -> Self {
+        self.empty_trees = Some(Vec::new());
+        self
+    }
+
+    /// The topmost empty directories of the roots walked to the end, if
+    /// looked for.
+    pub fn empty_trees(&self) -> &[PathBuf] {
+        self.empty_trees.as_deref().unwrap_or_default()
+    }
+
+    /// How the walk of each root went, in the order they were given.
+    pub fn statuses(&self) -> &[RootStatus] {
+        &self.statuses
+    }
+
+    /// The counts of all roots together.
+    pub fn counts(&self) -> ScanCounts {
+        let mut total = ScanCounts::default();
+        for (status, worker) in self.statuses.iter().zip(&self.workers) {
+            match status.state {
+                RootState::Scanning => total.add(&worker.counts.lock().unwrap()),
+                _ => total.add(&status.counts),
+            }
+        }
+        total
+    }
+
+    fn running(&self) -> usize {
+        self.statuses
+            .iter()
+            .filter(|status| status.state == RootState::Scanning)
+            .count()
+    }
+
+    /// Starts workers for the waiting roots while there are free ones.
+    fn start_waiting(&mut self) {
+        while self.running() < self.jobs {
+            let Some(i) = self
+                .statuses
+                .iter()
+                .position(|status| status.state == RootState::Waiting)
+            else {
+                return;
+            };
+            self.statuses[i].state = RootState::Scanning;
+            let worker = &mut self.workers[i];
+            worker.started = Some(Instant::now());
+            let root = self.statuses[i].root.clone();
+            let options = self.options.clone();
+            let cancel = Arc::clone(&worker.cancel);
+            let counts = Arc::clone(&worker.counts);
+            let sender = self.sender.clone();
+            let visited = self.visited.clone();
+            let reported = self.reported.clone();
+            let find_empty_dirs = self.empty_trees.is_some();
+            thread::spawn(move || {
+                let mut scan = scan::scan_iter(&root, &options).cancel_with(cancel);
+                if let Some(visited) = &visited {
+                    scan = scan.skip_visited(visited);
+                }
+                if let Some(progress) = &reported {
+                    scan = scan.report_progress(progress);
+                }
+                if find_empty_dirs {
+                    scan = scan.find_empty_dirs();
+                }
+                while let Some(result) = scan.next() {
+                    *counts.lock().unwrap() = scan.counts;
+                    if sender.send(Message::Found(i, result)).is_err() {
+                        return;
+                    }
+                }
+                *counts.lock().unwrap() = scan.counts;
+                let _ = sender.send(Message::Done(i, scan.empty_trees().to_vec()));
+            });
+        }
+    }
+
+    /// Marks the walk of root `i` as over.
+    fn finish(&mut self, i: usize, state: RootState) {
+        let worker = &self.workers[i];
+        let status = &mut self.statuses[i];
+        status.state = state;
+        status.counts = *worker.counts.lock().unwrap();
+        status.elapsed = worker
+            .started
+            .map_or(Duration::ZERO, |started| started.elapsed());
+    }
+
+    /// Abandons the roots whose time ran out, telling when the next one
+    /// will.
+    fn abandon_overdue(&mut self) -> Option<Instant> {
+        let timeout = self.timeout?;
+        let mut next = None;
+        for i in 0..self.statuses.len() {
+            if self.statuses[i].state != RootState::Scanning {
+                continue;
+            }
+            let deadline = self.workers[i].started? + timeout;
+            if deadline <= Instant::now() {
+                self.workers[i].cancel.store(true, Ordering::Relaxed);
+                self.finish(i, RootState::TimedOut);
+            } else {
+                next = Some(next.map_or(deadline, |next: Instant| next.min(deadline)));
+            }
+        }
+        next
+    }
+
+    fn draw_progress(&mut self) {
+        let Some(caps) = self.progress else {
+            return;
+        };
+        if self
+            .drawn
+            .is_some_and(|drawn| drawn.elapsed() < PROGRESS_INTERVAL)
+        {
+            return;
+        }
+        self.drawn = Some(Instant::now());
+        let parts = self
+            .statuses
+            .iter()
+            .zip(&self.workers)
+            .map(|(status, worker)| {
+                let files = match status.state {
+                    RootState::Scanning => worker.counts.lock().unwrap().files,
+                    _ => status.counts.files,
+                };
+                let state = match status.state {
+                    RootState::Waiting => "waiting".to_string(),
+                    RootState::Scanning => format!("{} files", format::format_count(files)),
+                    RootState::Done => format!("done, {} files", format::format_count(files)),
+                    RootState::TimedOut => "timed out".to_string(),
+                };
+                format!("{}: {}", short_name(&status.root), state)
+            })
+            .collect::<Vec<_>>();
+        let width = terminal::get_terminal_width()
+            .filter(|&width| width > 0)
+            .unwrap_or(80) as usize;
+        let line = format::truncate(&parts.join(", "), width.saturating_sub(1), &caps);
+        let mut stderr = std::io::stderr();
+        let _ = write!(stderr, "\r\x1b[K{}", line);
+        let _ = stderr.flush();
+    }
+
+    fn clear_progress(&mut self) {
+        if self.progress.is_some() && self.drawn.is_some() {
+            let _ = write!(std::io::stderr(), "\r\x1b[K");
+            self.drawn = None;
+        }
+    }
+}
+
+/// The last component of `root`, which tells mounts apart on the
+/// progress line.
+fn short_name(root: &Path) -> String {
+    match root.file_name() {
+        Some(name) => format::path(Path::new(name)),
+        None => format::path(root),
+    }
+}
+
+impl Iterator for MultiScan {
+    type Item = Result<FileRecord, ScanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // A walk stuck in a system call wouldn't notice the interrupt
+            if interrupt::interrupted() {
+                self.clear_progress();
+                return None;
+            }
+            self.start_waiting();
+            let deadline = self.abandon_overdue();
+            if self.running() == 0 {
+                self.clear_progress();
+                return None;
+            }
+            self.draw_progress();
+            let wait = deadline
+                .map_or(PROGRESS_INTERVAL, |deadline| {
+                    deadline.saturating_duration_since(Instant::now())
+                })
+                .min(PROGRESS_INTERVAL);
+            match self.receiver.recv_timeout(wait) {
+                Ok(Message::Found(i, result)) if self.statuses[i].state == RootState::Scanning => {
+                    return Some(result);
+                }
+                // Late files of an abandoned root
+                Ok(Message::Found(..)) => {}
+                Ok(Message::Done(i, empty_trees)) => {
+                    if self.statuses[i].state == RootState::Scanning {
+                        self.finish(i, RootState::Done);
+                        if let Some(trees) = &mut self.empty_trees {
+                            trees.extend(empty_trees);
+                        }
+                    }
+                }
+                // The sender kept here never disconnects
+                Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,1557 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{File, Metadata};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Value};
+use walkdir::{DirEntry, FilterEntry, WalkDir};
+
+use crate::format;
+use crate::ignore::IgnoreStack;
+use crate::interrupt;
+use crate::mounts::FsTypeFilter;
+use crate::resources;
+use crate::retry::{self, Attempts, Retry};
+
+/// How much of the tree a scan has seen so far.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ScanCounts {
+    /// Entries of any kind the walk came across
+    pub entries: u64,
+    /// Regular files among them
+    pub files: u64,
+    /// Regular files that passed the filters
+    pub matched: u64,
+    /// Directories skipped for holding a `CACHEDIR.TAG`
+    pub cache_dirs: u64,
+    /// Entries skipped for matching a `.dudistignore` rule, directories
+    /// counting once
+    pub ignored: u64,
+    /// `.dudistignore` files read
+    pub ignore_files: u64,
+    /// Directories without files anywhere below them, when looked for
+    pub empty_dirs: u64,
+    /// The topmost of them, whose parent isn't empty
+    pub empty_trees: u64,
+    /// Metadata calls that failed for a moment and succeeded when retried
+    pub recovered: u64,
+}
+
+impl ScanCounts {
+    /// Adds the counts of another part of the tree.
+    pub fn add(&mut self, counts: &ScanCounts) {
+        self.entries += counts.entries;
+        self.files += counts.files;
+        self.matched += counts.matched;
+        self.cache_dirs += counts.cache_dirs;
+        self.ignored += counts.ignored;
+        self.ignore_files += counts.ignore_files;
+        self.empty_dirs += counts.empty_dirs;
+        self.empty_trees += counts.empty_trees;
+        self.recovered += counts.recovered;
+    }
+}
+
+/// The first bytes of a valid `CACHEDIR.TAG`, see
+/// <https://bford.info/cachedir/>
+const CACHEDIR_SIGNATURE: &[u8] = b"Signature: 8a477f597d28d172789f06886806bc55";
+
+/// Whether `dir` is marked as a cache by a tagged `CACHEDIR.TAG` file.
+fn is_cache_dir(dir: &Path) -> bool {
+    // Opening straight away costs a single failed lookup in untagged
+    // directories
+    let Ok(mut file) = File::open(dir.join("CACHEDIR.TAG")) else {
+        return false;
+    };
+    let mut start = [0; CACHEDIR_SIGNATURE.len()];
+    file.read_exact(&mut start).is_ok() && start == CACHEDIR_SIGNATURE
+}
+
+/// Levels below the root at which the walk stops descending, whatever the
+/// `max_depth`, so a pathologically deep tree can't exhaust memory
+pub const DEPTH_CEILING: usize = 65_536;
+
+/// Settings of a scan, made with [`ScanOptions::builder`].
+///
+/// They serialize with serde, as for config files, where the settings left
+/// out take their defaults, unknown ones are an error and the rest are
+/// checked as [`build`](ScanOptionsBuilder::build) does.
+///
+/// ```
+/// use dudist::scan::ScanOptions;
+///
+/// let options: ScanOptions = serde_json::from_str(r#"{"max_depth": 2}"#).unwrap();
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+// The derived impls, wrapped below to check what is deserialized
+#[serde(remote = "Self", default, deny_unknown_fields)]
+pub struct ScanOptions {
+    min_size: u64,
+    max_size: Option<u64>,
+    max_depth: Option<usize>,
+    follow_symlinks: bool,
+    one_file_system: bool,
+    /// Mount points a walk staying on one filesystem may enter anyway, with
+    /// the devices mounted there
+    allowed_mounts: Vec<(PathBuf, u64)>,
+    skip_hidden: bool,
+    fs_filter: Option<FsTypeFilter>,
+    exclude_caches: bool,
+    ignore_files: bool,
+    retry: Retry,
+    depth_ceiling: usize,
+    max_path_bytes: Option<usize>,
+}
+
+impl ScanOptions {
+    /// Starts from the defaults, which walk everything below the root and
+    /// yield the files larger than 4 KiB.
+    ///
+    /// ```
+    /// use dudist::scan::ScanOptions;
+    ///
+    /// let options = ScanOptions::builder()
+    ///     .min_size(4096)
+    ///     .max_depth(3)
+    ///     .follow_symlinks(true)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn builder() -> ScanOptionsBuilder {
+        ScanOptionsBuilder {
+            options: ScanOptions {
+                min_size: 4096,
+                max_size: None,
+                max_depth: None,
+                follow_symlinks: false,
+                one_file_system: false,
+                allowed_mounts: Vec::new(),
+                skip_hidden: false,
+                fs_filter: None,
+                exclude_caches: false,
+                ignore_files: false,
+                retry: Retry::default(),
+                depth_ceiling: DEPTH_CEILING,
+                max_path_bytes: None,
+            },
+        }
+    }
+}
+
+impl Serialize for ScanOptions {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ScanOptions::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ScanOptions {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let options = ScanOptions::deserialize(deserializer)?;
+        ScanOptionsBuilder { options }
+            .build()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl ScanOptions {
+    /// Whether a file of `size` bytes passes the size filters.
+    pub fn matches(&self, size: u64) -> bool {
+        size > self.min_size && self.max_size.is_none_or(|max| size <= max)
+    }
+
+    pub fn max_depth(&self) -> Option<usize> {
+        self.max_depth
+    }
+
+    pub fn one_file_system(&self) -> bool {
+        self.one_file_system
+    }
+
+    /// Whether the walk from a root on `root_device` enters a directory on
+    /// `device`: always unless it stays on one filesystem, and then only the
+    /// root's and those of the allowed mounts.
+    ///
+    /// ```
+    /// use dudist::scan::ScanOptions;
+    ///
+    /// // /data on device 1, /data/archive on device 2
+    /// let options = ScanOptions::builder()
+    ///     .one_file_system(true)
+    ///     .allow_mount("/data/archive", 2)
+    ///     .build()
+    ///     .unwrap();
+    /// assert!(options.enters_device(1, 1));
+    /// assert!(options.enters_device(2, 1));
+    /// assert!(!options.enters_device(3, 1));
+    /// ```
+    pub fn enters_device(&self, device: u64, root_device: u64) -> bool {
+        !self.one_file_system
+            || device == root_device
+            || self
+                .allowed_mounts
+                .iter()
+                .any(|&(_, allowed)| allowed == device)
+    }
+
+    pub fn follow_symlinks(&self) -> bool {
+        self.follow_symlinks
+    }
+
+    pub fn fs_filter(&self) -> Option<&FsTypeFilter> {
+        self.fs_filter.as_ref()
+    }
+
+    pub fn ignore_files(&self) -> bool {
+        self.ignore_files
+    }
+
+    pub fn retry(&self) -> Retry {
+        self.retry
+    }
+
+    pub fn depth_ceiling(&self) -> usize {
+        self.depth_ceiling
+    }
+
+    /// Reads the metadata of `path`, following a symbolic link if the walk
+    /// does, and retrying transient failures.
+    pub fn stat(&self, path: &Path) -> Attempts<Metadata> {
+        self.retry.run(|| {
+            resources::stat();
+            match self.follow_symlinks {
+                true => std::fs::metadata(path),
+                false => std::fs::symlink_metadata(path),
+            }
+        })
+    }
+
+    /// The settings serialized as JSON, the same for equal settings across
+    /// runs, for the cache and history to tell scans apart by.
+    pub fn fingerprint(&self) -> String {
+        // JSON only has strings for paths that are valid UTF-8, so an
+        // allowed mount that isn't falls back to the debug form
+        serde_json::to_string(self).unwrap_or_else(|_| format!("{:?}", self))
+    }
+}
+
+/// The settings of a scan as described for people by
+/// [`ScanOptions::describe`], which snapshots keep too.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanSettings {
+    /// The filters, in the order the walk applies them
+    pub filters: Vec<String>,
+    /// How the walk goes through the tree
+    pub traversal: Vec<String>,
+}
+
+impl ScanSettings {
+    /// The filters and traversal, for snapshots and `--json`.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "filters": self.filters,
+            "traversal": self.traversal,
+        })
+    }
+
+    /// Lists what differs from `other`, `-` marking its settings and `+`
+    /// these.
+    pub fn diff(&self, other: &ScanSettings) -> Vec<String> {
+        let ours = self.filters.iter().chain(&self.traversal);
+        let theirs = other.filters.iter().chain(&other.traversal);
+        let mut lines = theirs
+            .clone()
+            .filter(|setting| !ours.clone().any(|ours| ours == *setting))
+            .map(|setting| format!("- {}", setting))
+            .collect::<Vec<_>>();
+        lines.extend(
+            ours.filter(|setting| !theirs.clone().any(|theirs| theirs == *setting))
+                .map(|setting| format!("+ {}", setting)),
+        );
+        lines
+    }
+}
+
+impl ScanOptions {
+    /// Describes the settings for people, from the same fields the walk
+    /// goes by.
+    ///
+    /// ```
+    /// use dudist::scan::ScanOptions;
+    ///
+    /// let options = ScanOptions::builder()
+    ///     .min_size(0)
+    ///     .max_size(1 << 30)
+    ///     .max_depth(2)
+    ///     .skip_hidden(true)
+    ///     .exclude_caches(true)
+    ///     .build()
+    ///     .unwrap();
+    /// let settings = options.describe();
+    /// assert_eq!(
+    ///     settings.filters,
+    ///     [
+    ///         "skipping hidden entries",
+    ///         "skipping CACHEDIR.TAG directories",
+    ///         "files larger than 0 B and at most 1 GiB",
+    ///     ]
+    /// );
+    /// assert_eq!(
+    ///     settings.traversal,
+    ///     [
+    ///         "at most 2 levels deep",
+    ///         "not following symbolic links",
+    ///         "crossing filesystems",
+    ///     ]
+    /// );
+    /// ```
+    pub fn describe(&self) -> ScanSettings {
+        ScanSettings {
+            filters: self.filters(),
+            traversal: self.traversal(),
+        }
+    }
+
+    fn filters(&self) -> Vec<String> {
+        let mut filters = Vec::new();
+        if self.skip_hidden {
+            filters.push("skipping hidden entries".to_string());
+        }
+        if let Some(filter) = &self.fs_filter {
+            filters.push(format!(
+                "skipping {} filesystems",
+                filter.fs_types().join(", ")
+            ));
+        }
+        if self.ignore_files {
+            filters.push("skipping what .dudistignore files list".to_string());
+        }
+        if self.exclude_caches {
+            filters.push("skipping CACHEDIR.TAG directories".to_string());
+        }
+        filters.push(match self.max_size {
+            Some(max) => format!(
+                "files larger than {} and at most {}",
+                format::human(self.min_size),
+                format::human(max)
+            ),
+            None => format!("files larger than {}", format::human(self.min_size)),
+        });
+        filters
+    }
+
+    fn traversal(&self) -> Vec<String> {
+        let mut traversal = vec![
+            match self.max_depth {
+                Some(depth) => format!("at most {} levels deep", depth),
+                None => "any depth".to_string(),
+            },
+            if self.follow_symlinks {
+                "following symbolic links"
+            } else {
+                "not following symbolic links"
+            }
+            .to_string(),
+            match (self.one_file_system, self.allowed_mounts.as_slice()) {
+                (false, _) => "crossing filesystems".to_string(),
+                (true, []) => "staying on one filesystem".to_string(),
+                (true, allowed) => format!(
+                    "staying on one filesystem and those mounted at {}",
+                    allowed
+                        .iter()
+                        .map(|(path, _)| format::path(path))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            },
+        ];
+        if self.depth_ceiling != DEPTH_CEILING {
+            traversal.push(format!(
+                "skipping directories {} levels deep",
+                self.depth_ceiling
+            ));
+        }
+        if let Some(max) = self.max_path_bytes {
+            traversal.push(format!("skipping paths longer than {} bytes", max));
+        }
+        if self.retry.retries > 0 {
+            traversal.push(format!(
+                "retrying transient errors up to {} times",
+                self.retry.retries
+            ));
+        }
+        traversal
+    }
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions::builder().options
+    }
+}
+
+/// Builds [`ScanOptions`], checking them in [`build`](Self::build).
+#[derive(Debug, Clone)]
+pub struct ScanOptionsBuilder {
+    options: ScanOptions,
+}
+
+impl ScanOptionsBuilder {
+    /// Only yield files larger than `size`.
+    pub fn min_size(mut self, size: u64) -> Self {
+        self.options.min_size = size;
+        self
+    }
+
+    /// Only yield files of at most `size`.
+    pub fn max_size(mut self, size: u64) -> Self {
+        self.options.max_size = Some(size);
+        self
+    }
+
+    /// Don't descend more than `depth` directories below the root.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.options.max_depth = Some(depth);
+        self
+    }
+
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.options.follow_symlinks = follow;
+        self
+    }
+
+    /// Stay on the filesystem of the root.
+    pub fn one_file_system(mut self, stay: bool) -> Self {
+        self.options.one_file_system = stay;
+        self
+    }
+
+    /// With [`one_file_system`](Self::one_file_system), still enter the
+    /// filesystem on `device` mounted at `path`, see [`mount_device`].
+    pub fn allow_mount(mut self, path: impl Into<PathBuf>, device: u64) -> Self {
+        self.options.allowed_mounts.push((path.into(), device));
+        self
+    }
+
+    /// Skip files and directories whose name starts with a dot.
+    pub fn skip_hidden(mut self, skip: bool) -> Self {
+        self.options.skip_hidden = skip;
+        self
+    }
+
+    /// Prune directories on the filesystem types `filter` excludes.
+    pub fn fs_filter(mut self, filter: FsTypeFilter) -> Self {
+        self.options.fs_filter = Some(filter);
+        self
+    }
+
+    /// Skip directories tagged with a `CACHEDIR.TAG`.
+    pub fn exclude_caches(mut self, exclude: bool) -> Self {
+        self.options.exclude_caches = exclude;
+        self
+    }
+
+    /// Skip what the `.dudistignore` files of the walked directories list,
+    /// see [`ignore`](crate::ignore).
+    pub fn read_ignore_files(mut self, read: bool) -> Self {
+        self.options.ignore_files = read;
+        self
+    }
+
+    /// Retry the metadata calls of the walk that fail for a moment, see
+    /// [`retry`](crate::retry).
+    pub fn retry(mut self, retry: Retry) -> Self {
+        self.options.retry = retry;
+        self
+    }
+
+    /// Skip the directories `depth` levels below the root with everything
+    /// below them, yielding a [`ScanError`] for each, rather than the
+    /// [`DEPTH_CEILING`]. Unlike [`max_depth`](Self::max_depth), hitting it
+    /// isn't expected.
+    pub fn depth_ceiling(mut self, depth: usize) -> Self {
+        self.options.depth_ceiling = depth;
+        self
+    }
+
+    /// Skip the entries whose path is longer than `bytes`, directories with
+    /// everything below them, yielding a [`ScanError`] for each.
+    pub fn max_path_bytes(mut self, bytes: usize) -> Self {
+        self.options.max_path_bytes = Some(bytes);
+        self
+    }
+
+    pub fn build(self) -> Result<ScanOptions, ScanOptionsError> {
+        let options = self.options;
+        if let Some(max) = options.max_size {
+            if options.min_size >= max {
+                return Err(ScanOptionsError::EmptySizeRange {
+                    min: options.min_size,
+                    max,
+                });
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Settings that can't go together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanOptionsError {
+    /// No size is both above `min` and at most `max`
+    EmptySizeRange { min: u64, max: u64 },
+}
+
+impl fmt::Display for ScanOptionsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScanOptionsError::EmptySizeRange { min, max } => write!(
+                f,
+                "no file can be larger than {} and at most {}",
+                format::human(*min),
+                format::human(*max)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ScanOptionsError {}
+
+/// A file that passed the filters.
+#[derive(Debug, Clone)]
+pub struct FileRecord {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Last modification, where the platform reports it
+    pub modified: Option<SystemTime>,
+    /// Last access, where the platform reports it
+    pub accessed: Option<SystemTime>,
+    /// Id of the device holding the file, 0 where there is no such thing
+    pub device: u64,
+    /// User id of the owner, where the platform has them
+    pub owner: Option<u32>,
+    /// Bytes of the blocks holding the file, where the platform reports
+    /// them
+    pub allocated: Option<u64>,
+    /// Inode of the file on its `device`, where the platform has them
+    pub inode: Option<u64>,
+    /// Names the file has on its filesystem, 1 where the platform can't
+    /// tell
+    pub links: u64,
+}
+
+impl FileRecord {
+    fn new(entry: DirEntry, metadata: &Metadata) -> FileRecord {
+        FileRecord::from_metadata(entry.into_path(), metadata)
+    }
+
+    pub fn from_metadata(path: PathBuf, metadata: &Metadata) -> FileRecord {
+        FileRecord {
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+            accessed: metadata.accessed().ok(),
+            device: device(metadata),
+            owner: owner(metadata),
+            allocated: allocated(metadata),
+            inode: directory_id(metadata).map(|(_, inode)| inode),
+            links: links(metadata),
+            path,
+        }
+    }
+}
+
+/// A directory the walk went through.
+#[derive(Debug, Clone)]
+pub struct DirRecord {
+    pub path: PathBuf,
+    pub modified: Option<SystemTime>,
+    /// What the walk found directly inside the directory
+    pub children: ScanCounts,
+}
+
+#[cfg(unix)]
+fn owner(metadata: &Metadata) -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.uid())
+}
+
+#[cfg(not(unix))]
+fn owner(_metadata: &Metadata) -> Option<u32> {
+    None
+}
+
+/// `st_blocks` counts 512-byte units whatever the block size
+#[cfg(unix)]
+fn allocated(metadata: &Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.blocks() * 512)
+}
+
+#[cfg(not(unix))]
+fn allocated(_metadata: &Metadata) -> Option<u64> {
+    None
+}
+
+#[cfg(unix)]
+fn links(metadata: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.nlink().max(1)
+}
+
+#[cfg(not(unix))]
+fn links(_metadata: &Metadata) -> u64 {
+    1
+}
+
+/// The device of the filesystem mounted at `path`, or why there is none:
+/// `path` must be a directory on another device than its parent.
+pub fn mount_device(path: &Path) -> Result<u64, String> {
+    let shown = format::path(path);
+    let metadata = std::fs::metadata(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => format!("{} doesn't exist", shown),
+        _ => format!("can't read {}: {}", shown, e),
+    })?;
+    if !metadata.is_dir() {
+        return Err(format!("{} isn't a directory", shown));
+    }
+    let parent = std::fs::canonicalize(path)
+        .ok()
+        .and_then(|path| Some(std::fs::metadata(path.parent()?)));
+    match parent {
+        Some(Ok(parent)) if device(&parent) == device(&metadata) => {
+            Err(format!("{} isn't a mount point", shown))
+        }
+        _ => Ok(device(&metadata)),
+    }
+}
+
+/// The device of the filesystem holding the entry, always 0 where there
+/// are no devices.
+#[cfg(unix)]
+pub fn device(metadata: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.dev()
+}
+
+#[cfg(not(unix))]
+pub fn device(_metadata: &Metadata) -> u64 {
+    0
+}
+
+/// What tells a directory or file apart from others, wherever it is mounted.
+#[cfg(unix)]
+fn directory_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    // Some network and FUSE filesystems give every entry inode 0
+    (metadata.ino() != 0).then(|| (metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn directory_id(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[derive(Debug, Default)]
+struct VisitedDirs {
+    /// The path each directory was first walked under
+    paths: HashMap<(u64, u64), PathBuf>,
+    revisits: Vec<(PathBuf, PathBuf)>,
+}
+
+/// The directories walked so far by any number of scans, so that a
+/// directory reached again, through a bind mount, a followed symbolic
+/// link or an overlapping root, isn't counted twice. Clones share the
+/// directories, and scans on several threads can share them.
+///
+/// Directories are told apart by device and inode, which only Unix
+/// provides, so elsewhere nothing is skipped.
+#[derive(Debug, Clone, Default)]
+pub struct Visited {
+    dirs: Arc<Mutex<VisitedDirs>>,
+}
+
+impl Visited {
+    /// Records the directory at `path`, telling whether it was walked
+    /// before.
+    fn revisits(&self, path: &Path, metadata: &Metadata) -> bool {
+        let Some(id) = directory_id(metadata) else {
+            return false;
+        };
+        let mut dirs = self.dirs.lock().unwrap();
+        match dirs.paths.get(&id) {
+            Some(first) => {
+                let revisit = (path.to_path_buf(), first.clone());
+                dirs.revisits.push(revisit);
+                true
+            }
+            None => {
+                dirs.paths.insert(id, path.to_path_buf());
+                false
+            }
+        }
+    }
+
+    /// The directories skipped for having been walked already, each with
+    /// the path it was walked under.
+    pub fn skipped(&self) -> Vec<(PathBuf, PathBuf)> {
+        self.dirs.lock().unwrap().revisits.clone()
+    }
+}
+
+/// Where any number of walks stand, for reporting on them while they run:
+/// the entries they came across and the directory entered last. Clones
+/// share it, and scans on several threads can report to it.
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    entries: Arc<AtomicU64>,
+    current_dir: Arc<Mutex<Option<PathBuf>>>,
+}
+
+impl Progress {
+    /// Entries of any kind the walks came across so far
+    pub fn entries(&self) -> u64 {
+        self.entries.load(Ordering::Relaxed)
+    }
+
+    /// The directory a walk entered last, if any did.
+    pub fn current_dir(&self) -> Option<PathBuf> {
+        self.current_dir.lock().unwrap().clone()
+    }
+
+    fn enter(&self, dir: &Path) {
+        let mut current = self.current_dir.lock().unwrap();
+        match current.as_mut() {
+            Some(current) => dir.clone_into(current),
+            None => *current = Some(dir.to_path_buf()),
+        }
+    }
+}
+
+/// An entry the walk couldn't read. The scan carries on after it.
+#[derive(Debug)]
+pub struct ScanError {
+    /// The entry concerned, when known
+    pub path: Option<PathBuf>,
+    pub message: String,
+    /// The directory a symbolic link loops back to, when that was the
+    /// problem
+    pub loop_ancestor: Option<PathBuf>,
+    /// Whether the entry was readable, but past the depth ceiling or the
+    /// path limit of the walk
+    pub too_long: bool,
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{}: {}", path.display(), self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl std::error::Error for ScanError {}
+
+impl From<walkdir::Error> for ScanError {
+    fn from(e: walkdir::Error) -> Self {
+        ScanError {
+            path: e.path().map(Path::to_path_buf),
+            message: match e.io_error() {
+                Some(io_error) => io_error.to_string(),
+                None => e.to_string(),
+            },
+            loop_ancestor: e.loop_ancestor().map(Path::to_path_buf),
+            too_long: false,
+        }
+    }
+}
+
+/// Walks the tree at `path`, yielding the files that pass the filters of
+/// `options` as they are found.
+///
+/// ```no_run
+/// use dudist::scan::{scan_iter, ScanOptions};
+///
+/// let mut total = 0;
+/// for record in scan_iter("/var/log", &ScanOptions::default()) {
+///     match record {
+///         Ok(record) => total += record.size,
+///         Err(e) => eprintln!("skipped {}", e),
+///     }
+/// }
+/// println!("{} bytes", total);
+/// ```
+pub fn scan_iter(path: impl AsRef<Path>, options: &ScanOptions) -> Scan<'_> {
+    walk(path.as_ref(), options, options.max_depth)
+}
+
+/// Like [`scan_iter`], but only looks at the direct children of `dir`.
+pub fn scan_level<'a>(dir: &Path, options: &'a ScanOptions) -> Scan<'a> {
+    walk(dir, options, Some(1))
+}
+
+fn walk<'a>(path: &Path, options: &'a ScanOptions, max_depth: Option<usize>) -> Scan<'a> {
+    // walkdir only knows the root's filesystem, other allowed ones are
+    // checked here
+    let root_device = (options.one_file_system && !options.allowed_mounts.is_empty())
+        .then(|| std::fs::metadata(path).map_or(0, |metadata| device(&metadata)));
+    Scan {
+        walker: walker(path, options, max_depth, root_device),
+        rewalks: Vec::new(),
+        options,
+        root: path.to_path_buf(),
+        root_device,
+        max_depth,
+        ignore: options.ignore_files.then(IgnoreStack::default),
+        counts: ScanCounts::default(),
+        directories: None,
+        directory_index: HashMap::new(),
+        cancel: None,
+        visited: None,
+        progress: None,
+        empty_dirs: None,
+    }
+}
+
+/// The walkdir walk of `path` with the filters of `options`, down to
+/// `max_depth` below it, whose root is on `root_device` when walkdir
+/// can't tell the filesystems apart itself.
+fn walker<'a>(
+    path: &Path,
+    options: &'a ScanOptions,
+    max_depth: Option<usize>,
+    root_device: Option<u64>,
+) -> Walker<'a> {
+    let fs_filter = options.fs_filter.as_ref();
+    let skip_hidden = options.skip_hidden;
+    let filter: EntryFilter<'_> = Box::new(move |entry| {
+        // The root is scanned however it is named
+        let hidden = skip_hidden
+            && entry.depth() > 0
+            && entry.file_name().to_string_lossy().starts_with('.');
+        let foreign = root_device.is_some_and(|root_device| {
+            entry.depth() > 0 && entry.file_type().is_dir() && {
+                resources::stat();
+                entry
+                    .metadata()
+                    .is_ok_and(|metadata| !options.enters_device(device(&metadata), root_device))
+            }
+        });
+        !hidden && !foreign && !fs_filter.is_some_and(|filter| filter.excludes(entry))
+    });
+    // walkdir stats the root before anything else
+    resources::stat();
+    let mut walker = WalkDir::new(path)
+        .follow_links(options.follow_symlinks)
+        .same_file_system(options.one_file_system && root_device.is_none());
+    if let Some(depth) = max_depth {
+        walker = walker.max_depth(depth);
+    }
+    walker.into_iter().filter_entry(filter)
+}
+
+type Walker<'a> = FilterEntry<walkdir::IntoIter, EntryFilter<'a>>;
+
+/// A directory walked again on its own after it failed to open for a
+/// moment, see [`Scan::rewalk`].
+struct Rewalk<'a> {
+    walker: Walker<'a>,
+    /// The depth of the directory in the whole walk, which its own entries
+    /// count from
+    depth: usize,
+    /// The first entry below it, taken when opening it was retried
+    first: Option<walkdir::Result<DirEntry>>,
+}
+
+type EntryFilter<'a> = Box<dyn FnMut(&DirEntry) -> bool + 'a>;
+
+/// The iterator returned by [`scan_iter`].
+///
+/// The walk ends early when interrupted with Ctrl-C, or when cancelled
+/// through [`cancel_with`](Self::cancel_with).
+pub struct Scan<'a> {
+    walker: Walker<'a>,
+    /// The directories walked again, the innermost last, whose entries
+    /// come before those of the walk they failed in
+    rewalks: Vec<Rewalk<'a>>,
+    options: &'a ScanOptions,
+    root: PathBuf,
+    root_device: Option<u64>,
+    max_depth: Option<usize>,
+    ignore: Option<IgnoreStack>,
+    /// How much of the tree was seen so far
+    pub counts: ScanCounts,
+    directories: Option<Vec<DirRecord>>,
+    /// Position of each recorded directory in `directories`
+    directory_index: HashMap<PathBuf, usize>,
+    cancel: Option<Arc<AtomicBool>>,
+    visited: Option<Visited>,
+    progress: Option<Progress>,
+    empty_dirs: Option<EmptyDirs>,
+}
+
+/// A directory the walk is inside of, for [`EmptyDirs`].
+#[derive(Debug)]
+struct OpenDir {
+    path: PathBuf,
+    depth: usize,
+    /// Whether anything but empty directories was found below it
+    occupied: bool,
+    /// Its empty subdirectories, whose own empty subdirectories are left
+    /// out
+    empty_children: Vec<PathBuf>,
+}
+
+/// Finds the directories without files anywhere below them, deciding on
+/// each as the walk leaves it. Only the directories the walk is inside of
+/// are kept, so a directory holding nothing but empty directories is
+/// empty too without the tree being held in memory.
+#[derive(Debug, Default)]
+struct EmptyDirs {
+    /// From the root down to the directory being walked
+    open: Vec<OpenDir>,
+    /// The topmost empty directories found so far
+    trees: Vec<PathBuf>,
+}
+
+impl EmptyDirs {
+    /// Leaves the directories that an entry at `depth` isn't inside of.
+    fn leave_to(&mut self, depth: usize, counts: &mut ScanCounts) {
+        while self.open.last().is_some_and(|dir| dir.depth >= depth) {
+            self.leave(counts);
+        }
+    }
+
+    fn leave(&mut self, counts: &mut ScanCounts) {
+        let Some(dir) = self.open.pop() else {
+            return;
+        };
+        if dir.occupied {
+            counts.empty_trees += dir.empty_children.len() as u64;
+            self.trees.extend(dir.empty_children);
+            self.occupy();
+            return;
+        }
+        counts.empty_dirs += 1;
+        match self.open.last_mut() {
+            Some(parent) => parent.empty_children.push(dir.path),
+            None => {
+                counts.empty_trees += 1;
+                self.trees.push(dir.path);
+            }
+        }
+    }
+
+    fn enter(&mut self, path: &Path, depth: usize) {
+        self.open.push(OpenDir {
+            path: path.to_path_buf(),
+            depth,
+            occupied: false,
+            empty_children: Vec::new(),
+        });
+    }
+
+    /// Marks the directory being walked as holding something.
+    fn occupy(&mut self) {
+        if let Some(dir) = self.open.last_mut() {
+            dir.occupied = true;
+        }
+    }
+
+    /// Takes an entry the walk couldn't read for something, so the
+    /// directories holding it aren't taken for empty.
+    fn unreadable(&mut self, error: &walkdir::Error, depth: usize, counts: &mut ScanCounts) {
+        // A directory that couldn't be listed is the one being walked
+        let listed = self
+            .open
+            .iter()
+            .position(|dir| Some(dir.path.as_path()) == error.path());
+        match listed {
+            Some(i) => {
+                while self.open.len() > i + 1 {
+                    self.leave(counts);
+                }
+            }
+            None => self.leave_to(depth, counts),
+        }
+        self.occupy();
+    }
+}
+
+impl Scan<'_> {
+    /// Also keeps track of the directories walked through, see
+    /// [`directories`](Self::directories).
+    pub fn record_directories(mut self) -> Self {
+        self.directories = Some(Vec::new());
+        self
+    }
+
+    /// Ends the walk at the next entry once `flag` is set, e.g. from
+    /// another thread.
+    pub fn cancel_with(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(flag);
+        self
+    }
+
+    /// Keeps `progress` up to date with every entry the walk comes across,
+    /// whether or not it is a file that matches.
+    pub fn report_progress(mut self, progress: &Progress) -> Self {
+        self.progress = Some(progress.clone());
+        self
+    }
+
+    /// Skips the directories `visited` has seen already, and records the
+    /// others there.
+    pub fn skip_visited(mut self, visited: &Visited) -> Self {
+        self.visited = Some(visited.clone());
+        self
+    }
+
+    /// Applies the `.dudistignore` files of the directories from `root`
+    /// down to the walked one, for walks of part of a tree.
+    pub(crate) fn below_root(mut self, root: &Path) -> Self {
+        if let Some(ignore) = &mut self.ignore {
+            *ignore = IgnoreStack::below(root, &self.root);
+        }
+        self
+    }
+
+    /// Also looks for the directories without files anywhere below them,
+    /// counted in `counts.empty_dirs` once the walk is over, see
+    /// [`empty_trees`](Self::empty_trees). Entries the walk filters leave
+    /// out, like hidden files with `skip_hidden`, don't count as
+    /// something in a directory.
+    pub fn find_empty_dirs(mut self) -> Self {
+        self.empty_dirs = Some(EmptyDirs::default());
+        self
+    }
+
+    /// The topmost directories without files below them, those whose
+    /// parent isn't empty, if looked for. Complete once the walk is over.
+    pub fn empty_trees(&self) -> &[PathBuf] {
+        self.empty_dirs
+            .as_ref()
+            .map_or(&[], |empty_dirs| empty_dirs.trees.as_slice())
+    }
+
+    /// The directories walked through so far, if recorded.
+    pub fn directories(&self) -> &[DirRecord] {
+        self.directories.as_deref().unwrap_or_default()
+    }
+
+    /// Counts `entry` towards its directory, if directories are recorded.
+    fn tally(&mut self, entry: &DirEntry, count: impl FnOnce(&mut ScanCounts)) {
+        let Some(directories) = &mut self.directories else {
+            return;
+        };
+        if let Some(&i) = entry
+            .path()
+            .parent()
+            .and_then(|parent| self.directory_index.get(parent))
+        {
+            count(&mut directories[i].children);
+        }
+    }
+}
+
+impl<'a> Scan<'a> {
+    /// Like [`next`](Iterator::next), but yields the paths of all regular
+    /// files without reading their metadata, so the size filters don't
+    /// apply and `counts.matched` stays 0.
+    pub fn next_path(&mut self) -> Option<Result<PathBuf, ScanError>> {
+        self.next_file().map(|entry| entry.map(DirEntry::into_path))
+    }
+
+    /// The next regular file the walk filters let through.
+    fn next_file(&mut self) -> Option<Result<DirEntry, ScanError>> {
+        while let Some((entry, offset)) = self.next_entry() {
+            let cancelled = self
+                .cancel
+                .as_ref()
+                .is_some_and(|flag| flag.load(Ordering::Relaxed));
+            if interrupt::interrupted() || cancelled {
+                return None;
+            }
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    if let Some(rewalk) = self.rewalk(&e, offset) {
+                        self.rewalks.push(rewalk);
+                        continue;
+                    }
+                    if let Some(empty_dirs) = &mut self.empty_dirs {
+                        empty_dirs.unreadable(&e, e.depth() + offset, &mut self.counts);
+                    }
+                    return Some(Err(e.into()));
+                }
+            };
+            let depth = entry.depth() + offset;
+            if let Some(empty_dirs) = &mut self.empty_dirs {
+                empty_dirs.leave_to(depth, &mut self.counts);
+            }
+            self.counts.entries += 1;
+            self.tally(&entry, |counts| counts.entries += 1);
+            if let Some(progress) = &self.progress {
+                progress.entries.fetch_add(1, Ordering::Relaxed);
+            }
+            // Following links, walkdir stats each one to tell what it
+            // points to
+            if self.options.follow_symlinks && entry.path_is_symlink() {
+                resources::stat();
+            }
+            let is_dir = entry.file_type().is_dir();
+            if let Some(ignore) = &mut self.ignore {
+                // The root is scanned whatever its parent ignores
+                if depth > 0 && ignore.ignores(entry.path(), is_dir) {
+                    self.counts.ignored += 1;
+                    self.tally(&entry, |counts| counts.ignored += 1);
+                    self.occupy();
+                    if is_dir {
+                        self.skip_current_dir();
+                    }
+                    continue;
+                }
+            }
+            if self.options.exclude_caches
+                && entry.file_type().is_dir()
+                && is_cache_dir(entry.path())
+            {
+                self.counts.cache_dirs += 1;
+                self.tally(&entry, |counts| counts.cache_dirs += 1);
+                self.occupy();
+                self.skip_current_dir();
+                continue;
+            }
+            if let Some(visited) = &self.visited {
+                let revisit = is_dir && {
+                    resources::stat();
+                    entry
+                        .metadata()
+                        .is_ok_and(|metadata| visited.revisits(entry.path(), &metadata))
+                };
+                if revisit {
+                    self.occupy();
+                    self.skip_current_dir();
+                    continue;
+                }
+            }
+            let descends = self.max_depth.is_none_or(|max| depth < max);
+            if let Some(error) = self.too_long(&entry, depth, is_dir && descends) {
+                self.occupy();
+                if is_dir {
+                    self.skip_current_dir();
+                }
+                return Some(Err(error));
+            }
+            if let Some(directories) = &mut self.directories {
+                if entry.file_type().is_dir() {
+                    resources::stat();
+                    self.directory_index
+                        .insert(entry.path().to_path_buf(), directories.len());
+                    directories.push(DirRecord {
+                        modified: entry.metadata().ok().and_then(|m| m.modified().ok()),
+                        path: entry.path().to_path_buf(),
+                        children: ScanCounts::default(),
+                    });
+                }
+            }
+            if let Some(ignore) = &mut self.ignore {
+                if is_dir && descends && ignore.enter(entry.path()) {
+                    self.counts.ignore_files += 1;
+                    // Counted towards the directory holding the file
+                    if let Some(dir) = self.directories.as_mut().and_then(|dirs| dirs.last_mut()) {
+                        dir.children.ignore_files += 1;
+                    }
+                }
+            }
+            if is_dir && descends {
+                resources::readdir();
+                if let Some(progress) = &self.progress {
+                    progress.enter(entry.path());
+                }
+            }
+            if let Some(empty_dirs) = &mut self.empty_dirs {
+                // What is below the depth limit isn't known, so a directory
+                // there counts as something
+                match is_dir && descends {
+                    true => empty_dirs.enter(entry.path(), depth),
+                    false => empty_dirs.occupy(),
+                }
+            }
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            self.counts.files += 1;
+            self.tally(&entry, |counts| counts.files += 1);
+            return Some(Ok(entry));
+        }
+        if let Some(empty_dirs) = &mut self.empty_dirs {
+            empty_dirs.leave_to(0, &mut self.counts);
+        }
+        None
+    }
+
+    /// The next entry of the innermost walk, with the depth its entries
+    /// count from.
+    fn next_entry(&mut self) -> Option<(walkdir::Result<DirEntry>, usize)> {
+        while let Some(rewalk) = self.rewalks.last_mut() {
+            if let Some(entry) = rewalk.first.take().or_else(|| rewalk.walker.next()) {
+                return Some((entry, rewalk.depth));
+            }
+            self.rewalks.pop();
+        }
+        self.walker.next().map(|entry| (entry, 0))
+    }
+
+    /// Skips the directory just entered by the walk it came from.
+    fn skip_current_dir(&mut self) {
+        match self.rewalks.last_mut() {
+            Some(rewalk) => rewalk.walker.skip_current_dir(),
+            None => self.walker.skip_current_dir(),
+        }
+    }
+
+    /// The walk of the directory `error` failed to open, once opening it
+    /// again succeeds within the retries. walkdir reports the failure and
+    /// goes on with the next entry, so the directory is walked again on its
+    /// own, from `offset`, the depth the failed walk counts from.
+    fn rewalk(&mut self, error: &walkdir::Error, offset: usize) -> Option<Rewalk<'a>> {
+        let (path, io_error) = (error.path()?, error.io_error()?);
+        if self.options.retry.retries == 0 || !retry::is_transient(io_error) {
+            return None;
+        }
+        let failed = match io_error.raw_os_error() {
+            Some(code) => io::Error::from_raw_os_error(code),
+            None => io_error.kind().into(),
+        };
+        self.reopen(path, error.depth() + offset, failed)
+    }
+
+    /// Opens the directory at `path`, `depth` deep in the whole walk, until
+    /// it doesn't fail as it did with `failed`, counting each attempt.
+    fn reopen(&mut self, path: &Path, depth: usize, failed: io::Error) -> Option<Rewalk<'a>> {
+        let (options, root_device) = (self.options, self.root_device);
+        let max_depth = self.max_depth.map(|max| max - depth);
+        let into_io = |e: walkdir::Error| e.into_io_error().unwrap_or(io::ErrorKind::Other.into());
+        // Failing to stat the root of the scan, the walk never got to it
+        let stat_failed = depth == 0 && self.counts.entries == 0;
+        // The failed attempt of the walk is the first
+        let mut failed = Some(failed);
+        let attempts = self.options.retry.run(|| {
+            if let Some(failed) = failed.take() {
+                return Err(failed);
+            }
+            let mut walker = walker(path, options, max_depth, root_device);
+            match walker.next() {
+                Some(Ok(dir)) if stat_failed => return Ok(Some((walker, Some(Ok(dir))))),
+                Some(Ok(dir)) if dir.file_type().is_dir() => {}
+                Some(Err(e)) => return Err(into_io(e)),
+                // Not a directory any more, nothing to walk
+                _ => return Ok(None),
+            }
+            resources::readdir();
+            match walker.next() {
+                Some(Err(e)) if e.depth() == 0 => Err(into_io(e)),
+                first => Ok(Some((walker, first))),
+            }
+        });
+        let (walker, first) = attempts.result.ok()??;
+        self.counts.recovered += 1;
+        Some(Rewalk {
+            walker,
+            depth,
+            first,
+        })
+    }
+
+    /// The error for `entry` when it is past the depth ceiling, for a
+    /// directory the walk would otherwise enter, or the path limit.
+    fn too_long(&self, entry: &DirEntry, depth: usize, enters: bool) -> Option<ScanError> {
+        // The walk builds each path from its parent's, so this is only the
+        // length of one
+        let bytes = entry.path().as_os_str().len();
+        let below = match entry.file_type().is_dir() {
+            true => " with everything below",
+            false => "",
+        };
+        let message = if enters && depth >= self.options.depth_ceiling {
+            format!("{} levels deep, skipped{}", depth, below)
+        } else if self.options.max_path_bytes.is_some_and(|max| bytes > max) {
+            format!("path of {} bytes, skipped{}", bytes, below)
+        } else {
+            return None;
+        };
+        Some(ScanError {
+            path: Some(entry.path().to_path_buf()),
+            message,
+            loop_ancestor: None,
+            too_long: true,
+        })
+    }
+
+    /// Marks the directory being walked as holding something, when looking
+    /// for empty ones.
+    fn occupy(&mut self) {
+        if let Some(empty_dirs) = &mut self.empty_dirs {
+            empty_dirs.occupy();
+        }
+    }
+}
+
+impl Iterator for Scan<'_> {
+    type Item = Result<FileRecord, ScanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let entry = match self.next_file()? {
+                Ok(entry) => entry,
+                Err(e) => return Some(Err(e)),
+            };
+            let attempts = self.options.retry.run(|| {
+                resources::stat();
+                entry.metadata().map_err(io::Error::from)
+            });
+            if attempts.recovered() {
+                self.counts.recovered += 1;
+            }
+            let metadata = match attempts.result {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    return Some(Err(ScanError {
+                        path: Some(entry.into_path()),
+                        message: e.to_string(),
+                        loop_ancestor: None,
+                        too_long: false,
+                    }))
+                }
+            };
+            if self.options.matches(metadata.len()) {
+                self.counts.matched += 1;
+                self.tally(&entry, |counts| counts.matched += 1);
+                return Some(Ok(FileRecord::new(entry, &metadata)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn options(max_depth: usize) -> ScanOptions {
+        ScanOptions::builder()
+            .min_size(0)
+            .max_depth(max_depth)
+            .retry(Retry {
+                retries: 2,
+                max_delay: Duration::from_millis(1),
+            })
+            .build()
+            .unwrap()
+    }
+
+    /// A file in the root and two levels of directories below it
+    fn tree() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("sub/deeper")).unwrap();
+        for file in ["a", "sub/b", "sub/deeper/c"] {
+            std::fs::write(dir.path().join(file), "x").unwrap();
+        }
+        dir
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reopened_directories_are_walked_at_their_depth() {
+        let dir = tree();
+        let options = options(2);
+        let mut scan = scan_iter(dir.path(), &options);
+        let sub = dir.path().join("sub");
+        let rewalk = scan
+            .reopen(&sub, 1, io::Error::from_raw_os_error(libc::EIO))
+            .unwrap();
+        scan.rewalks.push(rewalk);
+
+        let files = scan.by_ref().map(|record| record.unwrap().path);
+        let files = files.collect::<Vec<_>>();
+        // The directory walked again comes first and keeps to the depth
+        // limit of the whole walk, the rest follows
+        assert_eq!(files[0], sub.join("b"));
+        assert!(!files.contains(&sub.join("deeper/c")));
+        assert!(files.contains(&dir.path().join("a")));
+        assert_eq!(scan.counts.recovered, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn directories_that_keep_failing_are_given_up() {
+        let dir = tree();
+        let options = options(2);
+        let mut scan = scan_iter(dir.path(), &options);
+        let gone = dir.path().join("gone");
+        let failed = io::Error::from_raw_os_error(libc::EIO);
+
+        assert!(scan.reopen(&gone, 1, failed).is_none());
+        assert_eq!(scan.counts.recovered, 0);
+    }
+
+    /// Walks `root` with `options`, counting the files and keeping what
+    /// was skipped.
+    fn walk(root: &Path, options: &ScanOptions) -> (usize, Vec<ScanError>) {
+        let (mut files, mut skipped) = (0, Vec::new());
+        for result in scan_iter(root, options) {
+            match result {
+                Ok(_) => files += 1,
+                Err(e) => skipped.push(e),
+            }
+        }
+        (files, skipped)
+    }
+
+    #[test]
+    fn the_depth_ceiling_skips_what_is_below_it() {
+        // A chain of 300 directories with a file in each
+        let root = tempfile::tempdir().unwrap();
+        let mut dir = root.path().to_path_buf();
+        for _ in 0..300 {
+            dir.push("d");
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("f"), "x").unwrap();
+        }
+
+        // The directories 1 to 99 levels deep are walked, the 100th skipped
+        let options = ScanOptions::builder()
+            .min_size(0)
+            .depth_ceiling(100)
+            .build()
+            .unwrap();
+        let (files, skipped) = walk(root.path(), &options);
+        assert_eq!(files, 99);
+        assert_eq!(skipped.len(), 1);
+        assert!(skipped[0].too_long);
+        assert_eq!(
+            skipped[0].message,
+            "100 levels deep, skipped with everything below"
+        );
+
+        // Directory 50 fits, but neither its file nor directory 51 do
+        let bytes = root.path().as_os_str().len() + 2 * 50;
+        let options = ScanOptions::builder()
+            .min_size(0)
+            .max_path_bytes(bytes)
+            .build()
+            .unwrap();
+        let (files, skipped) = walk(root.path(), &options);
+        assert_eq!(files, 49);
+        assert_eq!(skipped.len(), 2);
+        assert!(skipped.iter().all(|e| e.too_long));
+    }
+
+    #[test]
+    fn directories_holding_only_empty_ones_are_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        for empty in ["a/b/c", "a/d", "e/f", "g"] {
+            std::fs::create_dir_all(dir.path().join(empty)).unwrap();
+        }
+        std::fs::write(dir.path().join("e/file"), "").unwrap();
+
+        let options = ScanOptions::builder().build().unwrap();
+        let mut scan = scan_iter(dir.path(), &options).find_empty_dirs();
+        scan.by_ref().for_each(drop);
+        // a holds nothing but empty directories, so it is empty too
+        assert_eq!(scan.counts.empty_dirs, 6);
+        assert_eq!(scan.counts.empty_trees, 3);
+        let mut trees = scan.empty_trees().to_vec();
+        trees.sort();
+        let path = |name: &str| dir.path().join(name);
+        assert_eq!(trees, [path("a"), path("e/f"), path("g")]);
+    }
+
+    #[test]
+    fn hidden_files_left_out_leave_their_directory_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("a")).unwrap();
+        std::fs::write(dir.path().join("a/.hidden"), "x").unwrap();
+        std::fs::write(dir.path().join("b"), "x").unwrap();
+
+        let options = ScanOptions::builder().skip_hidden(true).build().unwrap();
+        let mut scan = scan_iter(dir.path(), &options).find_empty_dirs();
+        scan.by_ref().for_each(drop);
+        assert_eq!(scan.empty_trees(), [dir.path().join("a")]);
+    }
+
+    #[test]
+    fn options_survive_serializing() {
+        let options = ScanOptions::builder()
+            .min_size(0)
+            .max_size(1 << 30)
+            .max_depth(3)
+            .one_file_system(true)
+            .allow_mount("/data/archive", 7)
+            .read_ignore_files(true)
+            .retry(Retry {
+                retries: 2,
+                max_delay: Duration::from_millis(250),
+            })
+            .build()
+            .unwrap();
+        let json = serde_json::to_string(&options).unwrap();
+        let back = serde_json::from_str::<ScanOptions>(&json).unwrap();
+        assert_eq!(back.fingerprint(), options.fingerprint());
+        assert_eq!(back.describe(), options.describe());
+        assert_eq!(back.retry(), options.retry());
+    }
+
+    #[test]
+    fn settings_left_out_take_their_defaults() {
+        let options = serde_json::from_str::<ScanOptions>(r#"{"max_depth": 2}"#).unwrap();
+        let defaults = ScanOptions::default();
+        assert_eq!(options.max_depth(), Some(2));
+        assert!(options.matches(4097) && !options.matches(4096));
+        assert_eq!(options.depth_ceiling(), defaults.depth_ceiling());
+        assert_eq!(options.retry(), defaults.retry());
+        assert!(serde_json::from_str::<ScanOptions>("{}").is_ok());
+    }
+
+    #[test]
+    fn deserializing_checks_like_build() {
+        let e =
+            serde_json::from_str::<ScanOptions>(r#"{"min_size": 9, "max_size": 9}"#).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "no file can be larger than 9 B and at most 9 B"
+        );
+        assert!(serde_json::from_str::<ScanOptions>(r#"{"min_size": -1}"#).is_err());
+        let e = serde_json::from_str::<ScanOptions>(r#"{"min_sise": 0}"#).unwrap_err();
+        assert!(
+            e.to_string().starts_with("unknown field `min_sise`"),
+            "{}",
+            e
+        );
+    }
+
+    #[test]
+    fn fingerprints_tell_different_settings_apart() {
+        let fingerprint = |builder: ScanOptionsBuilder| builder.build().unwrap().fingerprint();
+        let default = fingerprint(ScanOptions::builder());
+        assert_eq!(fingerprint(ScanOptions::builder()), default);
+        for changed in [
+            ScanOptions::builder().min_size(0),
+            ScanOptions::builder().max_depth(1),
+            ScanOptions::builder().skip_hidden(true),
+            ScanOptions::builder().depth_ceiling(10),
+            ScanOptions::builder().max_path_bytes(100),
+            ScanOptions::builder()
+                .one_file_system(true)
+                .allow_mount("/data", 2),
+        ] {
+            assert_ne!(fingerprint(changed), default);
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use crate::format;
+use crate::scan::FileRecord;
+
+/// Adds up the space lost to rounding files up to whole blocks.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SlackTally {
+    /// Allocated bytes beyond the apparent size
+    pub slack: u64,
+    /// Allocated bytes of the files counted in `slack`
+    pub allocated: u64,
+    pub files: u64,
+    /// Files wasting more than they hold, smaller than one block
+    pub mostly_slack: u64,
+    /// Files allocating less than their size, left out of `slack`
+    pub sparse_files: u64,
+    pub sparse_bytes: u64,
+    /// Files the platform gave no allocated size for, like archive members
+    pub unknown: u64,
+}
+
+impl SlackTally {
+    pub fn add(&mut self, record: &FileRecord) {
+        let Some(allocated) = record.allocated else {
+            self.unknown += 1;
+            return;
+        };
+        if allocated < record.size {
+            self.sparse_files += 1;
+            self.sparse_bytes += record.size - allocated;
+            return;
+        }
+        let slack = allocated - record.size;
+        self.files += 1;
+        self.allocated += allocated;
+        self.slack += slack;
+        if slack > record.size {
+            self.mostly_slack += 1;
+        }
+    }
+
+    pub fn print(&self) {
+        let share = if self.allocated > 0 {
+            self.slack as f64 / self.allocated as f64 * 100.0
+        } else {
+            0.0
+        };
+        println!(
+            "Block slack:     {} ({:.1}% of the allocated space), {} files smaller than their slack",
+            format::human(self.slack),
+            share,
+            format::format_count(self.mostly_slack)
+        );
+        if self.sparse_files > 0 {
+            println!(
+                "Sparse files:    {}, {} smaller on disk than their size, left out of the slack",
+                format::format_count(self.sparse_files),
+                format::human(self.sparse_bytes)
+            );
+        }
+        if self.unknown > 0 {
+            println!(
+                "                 {} files without an allocated size left out",
+                format::format_count(self.unknown)
+            );
+        }
+    }
+}
@@ -0,0 +1,425 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::distribution::Distribution;
+use crate::scan::ScanSettings;
+
+/// Bumped whenever the layout of snapshot files changes
+const VERSION: u64 = 1;
+
+/// Magic bytes of compressed files. Only zstd is read, and only by builds
+/// with the `zstd` feature.
+const COMPRESSED: [(&[u8], &str); 3] = [
+    (b"\x28\xb5\x2f\xfd", "zstd"),
+    (b"\x1f\x8b", "gzip"),
+    (b"\xfd7zXZ\0", "xz"),
+];
+
+/// The sizes a scan found, kept so that scans of several roots, possibly
+/// on several machines, can be combined exactly.
+///
+/// ```no_run
+/// use dudist::scan::{scan_iter, ScanOptions};
+/// use dudist::snapshot::Snapshot;
+///
+/// let options = ScanOptions::default();
+/// let [home, srv] = ["/home", "/srv"].map(|root| {
+///     let sizes = scan_iter(root, &options).flatten().map(|record| record.size);
+///     Snapshot::new(root, sizes)
+/// });
+/// let both = Snapshot::merge(&[home, srv]);
+/// if let Some(dist) = both.distribution() {
+///     println!("median {} bytes", dist.median);
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    /// The scanned roots, for showing where the sizes came from
+    pub roots: Vec<String>,
+    /// Sorted ascending
+    pub sizes: Vec<u64>,
+    /// The settings of the scan, unknown for merged snapshots of scans
+    /// with different settings and for those written before they were kept
+    pub settings: Option<ScanSettings>,
+}
+
+impl Snapshot {
+    pub fn new(root: &str, sizes: impl IntoIterator<Item = u64>) -> Snapshot {
+        let mut sizes = sizes.into_iter().collect::<Vec<_>>();
+        sizes.sort_unstable();
+        Snapshot {
+            roots: vec![root.to_string()],
+            sizes,
+            settings: None,
+        }
+    }
+
+    /// Combines `snapshots` into one holding all of their sizes, so any
+    /// statistic of the result is the same as over the concatenated scans.
+    pub fn merge(snapshots: &[Snapshot]) -> Snapshot {
+        let mut merged = Snapshot::default();
+        for snapshot in snapshots {
+            merged.roots.extend(snapshot.roots.iter().cloned());
+            merged.sizes = merge_sorted(&merged.sizes, &snapshot.sizes);
+        }
+        merged.settings = snapshots
+            .first()
+            .and_then(|first| first.settings.clone())
+            .filter(|settings| {
+                snapshots
+                    .iter()
+                    .all(|snapshot| snapshot.settings.as_ref() == Some(settings))
+            });
+        merged
+    }
+
+    /// `None` when there are no sizes.
+    pub fn distribution(&self) -> Option<Distribution> {
+        (!self.sizes.is_empty()).then(|| Distribution::from_sorted(&self.sizes))
+    }
+
+    /// Writes the snapshot as JSON, runs of equal sizes being stored as
+    /// `[size, count]` pairs, and zstd-compressed if `compress`. The
+    /// compression streams, so it holds no more than the JSON.
+    pub fn save(&self, path: &Path, compress: bool) -> Result<(), String> {
+        let runs = self
+            .sizes
+            .chunk_by(|a, b| a == b)
+            .map(|run| json!([run[0], run.len()]))
+            .collect::<Vec<_>>();
+        let mut value = json!({
+            "version": VERSION,
+            "roots": self.roots,
+            "sizes": runs,
+        });
+        if let Some(settings) = &self.settings {
+            value["settings"] = settings.to_json();
+        }
+        let write_error = |e: io::Error| format!("failed to write {}: {}", path.display(), e);
+        let mut file = BufWriter::new(File::create(path).map_err(write_error)?);
+        if compress {
+            #[cfg(feature = "zstd")]
+            {
+                let mut encoder = zstd::Encoder::new(file, 0).map_err(write_error)?;
+                write_json(&mut encoder, &value).map_err(write_error)?;
+                return encoder
+                    .finish()
+                    .and_then(|mut file| file.flush())
+                    .map_err(write_error);
+            }
+            #[cfg(not(feature = "zstd"))]
+            return Err(format!(
+                "can't write {}, this build of dudist can't compress snapshots",
+                path.display()
+            ));
+        }
+        write_json(&mut file, &value).map_err(write_error)?;
+        file.flush().map_err(write_error)
+    }
+
+    /// Reads a snapshot saved by [`Snapshot::save`], compressed or not.
+    pub fn load(path: &Path) -> Result<Snapshot, String> {
+        let read_error = |e: io::Error| format!("failed to read {}: {}", path.display(), e);
+        let mut file = BufReader::new(File::open(path).map_err(read_error)?);
+        let head = file.fill_buf().map_err(read_error)?;
+        let invalid = || format!("{} is not a dudist snapshot", path.display());
+        // Told by their contents, as the name needn't say
+        let value = match COMPRESSED.iter().find(|(magic, _)| head.starts_with(magic)) {
+            #[cfg(feature = "zstd")]
+            Some((_, "zstd")) => {
+                let decoder = zstd::Decoder::with_buffer(file).map_err(read_error)?;
+                serde_json::from_reader::<_, Value>(decoder).map_err(|e| {
+                    match e.io_error_kind() {
+                        Some(_) => format!(
+                            "{} is a damaged zstd file: {}",
+                            path.display(),
+                            io::Error::from(e)
+                        ),
+                        None => invalid(),
+                    }
+                })?
+            }
+            Some((_, format)) => {
+                return Err(format!(
+                "{} is {}-compressed, which this build of dudist can't read; decompress it first",
+                path.display(),
+                format
+            ))
+            }
+            None => {
+                serde_json::from_reader::<_, Value>(file).map_err(|e| match e.io_error_kind() {
+                    Some(_) => read_error(e.into()),
+                    None => invalid(),
+                })?
+            }
+        };
+        if value["version"].as_u64() != Some(VERSION) {
+            return Err(format!(
+                "{} is a snapshot of another dudist version",
+                path.display()
+            ));
+        }
+        Snapshot::parse(&value).ok_or_else(invalid)
+    }
+
+    fn parse(value: &Value) -> Option<Snapshot> {
+        let roots = value["roots"]
+            .as_array()?
+            .iter()
+            .map(|root| root.as_str().map(str::to_string))
+            .collect::<Option<Vec<_>>>()?;
+        let mut sizes = Vec::new();
+        for run in value["sizes"].as_array()? {
+            let size = run.get(0)?.as_u64()?;
+            let count = run.get(1)?.as_u64()?;
+            sizes.extend(std::iter::repeat_n(size, count as usize));
+        }
+        // Written sorted, but a file edited by hand needn't be
+        if !sizes.is_sorted() {
+            sizes.sort_unstable();
+        }
+        let strings = |value: &Value| {
+            value
+                .as_array()?
+                .iter()
+                .map(|setting| setting.as_str().map(str::to_string))
+                .collect::<Option<Vec<_>>>()
+        };
+        let settings = match &value["settings"] {
+            Value::Null => None,
+            settings => Some(ScanSettings {
+                filters: strings(&settings["filters"])?,
+                traversal: strings(&settings["traversal"])?,
+            }),
+        };
+        Some(Snapshot {
+            roots,
+            sizes,
+            settings,
+        })
+    }
+}
+
+/// Writes `value` as one line of JSON.
+fn write_json(out: &mut impl Write, value: &Value) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, value)?;
+    out.write_all(b"\n")
+}
+
+/// Merges two sorted slices into one sorted vector.
+fn merge_sorted(a: &[u64], b: &[u64]) -> Vec<u64> {
+    let mut merged = Vec::with_capacity(a.len() + b.len());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] <= b[j] {
+            merged.push(a[i]);
+            i += 1;
+        } else {
+            merged.push(b[j]);
+            j += 1;
+        }
+    }
+    merged.extend_from_slice(&a[i..]);
+    merged.extend_from_slice(&b[j..]);
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        let mut snapshot = Snapshot::new("/srv", [4096, 0, 12, 4096, 1 << 40, 12, 12]);
+        snapshot.settings = Some(ScanSettings {
+            filters: vec!["files of any size".to_string()],
+            traversal: vec!["any depth".to_string()],
+        });
+        snapshot
+    }
+
+    /// The magic bytes `path` starts with.
+    #[cfg(feature = "zstd")]
+    fn fs_head(path: &Path) -> Vec<u8> {
+        std::fs::read(path).unwrap()[..4].to_vec()
+    }
+
+    #[test]
+    fn plain_snapshots_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("web1.json");
+        snapshot().save(&path, false).unwrap();
+        let loaded = Snapshot::load(&path).unwrap();
+        assert_eq!(loaded.roots, ["/srv"]);
+        assert_eq!(loaded.sizes, snapshot().sizes);
+        assert_eq!(loaded.settings, snapshot().settings);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn compressed_snapshots_give_the_same_statistics() {
+        let dir = tempfile::tempdir().unwrap();
+        let (plain, compressed) = (dir.path().join("a.json"), dir.path().join("a.json.zst"));
+        // Enough runs for the compression to show
+        let many = Snapshot::new("/srv", (0..100_000).map(|i| i % 5000 * 7));
+        many.save(&plain, false).unwrap();
+        many.save(&compressed, true).unwrap();
+        assert_ne!(fs_head(&compressed), fs_head(&plain));
+        assert!(
+            std::fs::metadata(&compressed).unwrap().len()
+                < std::fs::metadata(&plain).unwrap().len()
+        );
+
+        let (plain, compressed) = (
+            Snapshot::load(&plain).unwrap(),
+            Snapshot::load(&compressed).unwrap(),
+        );
+        assert_eq!(compressed.sizes, plain.sizes);
+        let (a, b) = (
+            plain.distribution().unwrap(),
+            compressed.distribution().unwrap(),
+        );
+        assert_eq!((a.min, a.max), (b.min, b.max));
+        assert_eq!(
+            (a.lower_quartile, a.median, a.upper_quartile),
+            (b.lower_quartile, b.median, b.upper_quartile)
+        );
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn compression_is_told_by_the_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        // Compressed, whatever the name says
+        let path = dir.path().join("web1.json");
+        snapshot().save(&path, true).unwrap();
+        assert_eq!(fs_head(&path), b"\x28\xb5\x2f\xfd");
+        assert_eq!(Snapshot::load(&path).unwrap().sizes, snapshot().sizes);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn damaged_compressed_snapshots_name_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("web1.json.zst");
+        snapshot().save(&path, true).unwrap();
+        let mut contents = std::fs::read(&path).unwrap();
+        let middle = contents.len() / 2;
+        contents.truncate(middle);
+        std::fs::write(&path, contents).unwrap();
+
+        let error = Snapshot::load(&path).unwrap_err();
+        assert!(
+            error.starts_with(&format!("{} is a damaged zstd file", path.display())),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn other_compressions_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("web1.json.gz");
+        std::fs::write(&path, b"\x1f\x8b\x08\x00").unwrap();
+        let error = Snapshot::load(&path).unwrap_err();
+        assert!(error.contains("gzip-compressed"), "{}", error);
+    }
+
+    /// xorshift64, random enough and the same every run
+    fn random(seed: u64) -> impl FnMut() -> u64 {
+        let mut state = seed;
+        move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        }
+    }
+
+    /// Up to `most` sizes, small ones often repeated and some huge.
+    fn sizes(next: &mut impl FnMut() -> u64, most: u64) -> Vec<u64> {
+        (0..next() % (most + 1))
+            .map(|_| match next() % 4 {
+                0 => next() % 8,
+                1 => next() % 100_000,
+                2 => 1 << (next() % 50),
+                _ => next() >> (next() % 64),
+            })
+            .collect()
+    }
+
+    fn statistics(snapshot: &Snapshot) -> Option<(u64, f64, f64, f64, u64)> {
+        snapshot.distribution().map(|dist| {
+            (
+                dist.min,
+                dist.lower_quartile,
+                dist.median,
+                dist.upper_quartile,
+                dist.max,
+            )
+        })
+    }
+
+    #[test]
+    fn merging_equals_scanning_the_concatenation() {
+        let mut next = random(0x2545_f491_4f6c_dd1d);
+        for round in 0..500 {
+            let parts = (0..1 + next() % 5)
+                .map(|_| sizes(&mut next, 60))
+                .collect::<Vec<_>>();
+            let snapshots = parts
+                .iter()
+                .enumerate()
+                .map(|(i, sizes)| Snapshot::new(&format!("/srv/{}", i), sizes.iter().copied()))
+                .collect::<Vec<_>>();
+            let all = Snapshot::new("/srv", parts.concat());
+
+            let merged = Snapshot::merge(&snapshots);
+            assert_eq!(merged.sizes, all.sizes, "round {}", round);
+            assert_eq!(statistics(&merged), statistics(&all), "round {}", round);
+            assert_eq!(merged.roots.len(), snapshots.len());
+
+            // Merging in steps, split anywhere, gives the same
+            let (first, rest) = snapshots.split_at((next() % (parts.len() as u64 + 1)) as usize);
+            let stepwise = Snapshot::merge(&[Snapshot::merge(first), Snapshot::merge(rest)]);
+            assert_eq!(stepwise.sizes, merged.sizes, "round {}", round);
+            assert_eq!(stepwise.roots, merged.roots);
+        }
+    }
+
+    #[test]
+    fn merged_snapshots_round_trip_through_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut next = random(7);
+        let snapshots = (0..3)
+            .map(|i| {
+                let path = dir.path().join(format!("{}.json", i));
+                Snapshot::new("/srv", sizes(&mut next, 1000))
+                    .save(&path, false)
+                    .unwrap();
+                Snapshot::load(&path).unwrap()
+            })
+            .collect::<Vec<_>>();
+        let concatenated = snapshots.iter().flat_map(|snapshot| snapshot.sizes.clone());
+        let all = Snapshot::new("/srv", concatenated);
+        assert_eq!(Snapshot::merge(&snapshots).sizes, all.sizes);
+    }
+
+    #[test]
+    fn only_shared_settings_survive_a_merge() {
+        let other = ScanSettings {
+            filters: vec!["files larger than 4 KiB".to_string()],
+            traversal: vec!["any depth".to_string()],
+        };
+        let mut different = snapshot();
+        different.settings = Some(other);
+        assert_eq!(
+            Snapshot::merge(&[snapshot(), snapshot()]).settings,
+            snapshot().settings
+        );
+        assert_eq!(Snapshot::merge(&[snapshot(), different]).settings, None);
+        assert!(Snapshot::merge(&[]).distribution().is_none());
+    }
+}
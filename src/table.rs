@@ -0,0 +1,70 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+/// A plain text table whose columns are as wide as their widest cell.
+#[derive(Debug)]
+pub struct Table {
+    aligns: Vec<Align>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(aligns: &[Align]) -> Table {
+        Table {
+            aligns: aligns.to_vec(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// Adds a row. Missing trailing cells are left empty.
+    pub fn row<I, S>(&mut self, cells: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.rows.push(cells.into_iter().map(Into::into).collect());
+    }
+
+    /// Renders the table with two spaces between columns and no trailing
+    /// whitespace, one line per row.
+    pub fn render(&self) -> String {
+        let widths = (0..self.aligns.len())
+            .map(|column| {
+                self.rows
+                    .iter()
+                    .filter_map(|row| row.get(column))
+                    .map(|cell| cell.chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect::<Vec<_>>();
+
+        let mut rendered = String::new();
+        for row in &self.rows {
+            let mut line = String::new();
+            for (column, align) in self.aligns.iter().enumerate() {
+                let cell = row.get(column).map_or("", String::as_str);
+                if column > 0 {
+                    line.push_str("  ");
+                }
+                let padding = " ".repeat(widths[column] - cell.chars().count());
+                match align {
+                    Align::Left => {
+                        line.push_str(cell);
+                        line.push_str(&padding);
+                    }
+                    Align::Right => {
+                        line.push_str(&padding);
+                        line.push_str(cell);
+                    }
+                }
+            }
+            rendered.push_str(line.trim_end());
+            rendered.push('\n');
+        }
+        rendered
+    }
+}
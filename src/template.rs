@@ -0,0 +1,249 @@
+//! A minimal template language for reports.
+//!
+//! `{{key}}` is replaced by the value of `key`, and `{{#list}}...{{/list}}`
+//! repeats its contents for every item of `list`, with the keys of the item
+//! in scope. A newline right after a section tag is dropped, so tags can
+//! stand on lines of their own.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone)]
+enum Value {
+    Text(String),
+    List {
+        items: Vec<Scope>,
+        /// Keys every item has, so templates can be checked against empty
+        /// lists too
+        item_keys: Vec<String>,
+    },
+}
+
+/// The values a template is rendered from.
+#[derive(Debug, Clone, Default)]
+pub struct Scope {
+    values: BTreeMap<String, Value>,
+}
+
+impl Scope {
+    pub fn new() -> Scope {
+        Scope::default()
+    }
+
+    pub fn text(&mut self, key: &str, value: impl Into<String>) {
+        self.values
+            .insert(key.to_string(), Value::Text(value.into()));
+    }
+
+    /// Sets a list of `items`, each of which must have the `item_keys`.
+    pub fn list(&mut self, key: &str, items: Vec<Scope>, item_keys: &[&str]) {
+        self.values.insert(
+            key.to_string(),
+            Value::List {
+                items,
+                item_keys: item_keys.iter().map(|key| key.to_string()).collect(),
+            },
+        );
+    }
+
+    /// The keys, lists marked with `#`.
+    fn keys(&self) -> impl Iterator<Item = String> + '_ {
+        self.values.iter().map(|(key, value)| match value {
+            Value::Text(_) => key.clone(),
+            Value::List { .. } => format!("#{}", key),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Text(String),
+    Key(String),
+    Section(String, Vec<Node>),
+}
+
+/// A parsed template.
+#[derive(Debug, Clone)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Template, String> {
+        // Open sections with the nodes before them
+        let mut stack: Vec<(String, Vec<Node>)> = Vec::new();
+        let mut nodes = Vec::new();
+        let mut rest = source;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                nodes.push(Node::Text(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find("}}")
+                .ok_or("template has a '{{' without a closing '}}'")?;
+            let tag = rest[start + 2..start + end].trim();
+            rest = &rest[start + end + 2..];
+            if let Some(name) = tag.strip_prefix('#') {
+                stack.push((name.trim().to_string(), std::mem::take(&mut nodes)));
+                rest = rest.strip_prefix('\n').unwrap_or(rest);
+            } else if let Some(name) = tag.strip_prefix('/') {
+                let name = name.trim();
+                match stack.pop() {
+                    Some((open, outer)) if open == name => {
+                        let inner = std::mem::replace(&mut nodes, outer);
+                        nodes.push(Node::Section(open, inner));
+                    }
+                    Some((open, _)) => {
+                        return Err(format!(
+                            "template closes '{}' where '{}' is still open",
+                            name, open
+                        ))
+                    }
+                    None => return Err(format!("template closes '{}', which isn't open", name)),
+                }
+                rest = rest.strip_prefix('\n').unwrap_or(rest);
+            } else if tag.is_empty() {
+                return Err("template has an empty '{{}}'".to_string());
+            } else {
+                nodes.push(Node::Key(tag.to_string()));
+            }
+        }
+        if let Some((open, _)) = stack.last() {
+            return Err(format!("template never closes '{}'", open));
+        }
+        if !rest.is_empty() {
+            nodes.push(Node::Text(rest.to_string()));
+        }
+        Ok(Template { nodes })
+    }
+
+    /// Renders the template, failing on the first key `scope` doesn't have.
+    pub fn render(&self, scope: &Scope) -> Result<String, String> {
+        self.check(scope)?;
+        let mut output = String::new();
+        render_nodes(&self.nodes, &[scope], &mut output);
+        Ok(output)
+    }
+
+    /// Checks that every key of the template is known to `scope`, even
+    /// inside sections over empty lists.
+    pub fn check(&self, scope: &Scope) -> Result<(), String> {
+        check_nodes(&self.nodes, &[scope])
+    }
+}
+
+/// Finds `key` in the innermost scope having it.
+fn lookup<'a>(scopes: &[&'a Scope], key: &str) -> Option<&'a Value> {
+    scopes.iter().rev().find_map(|scope| scope.values.get(key))
+}
+
+fn unknown(scopes: &[&Scope], key: &str) -> String {
+    let keys = scopes
+        .iter()
+        .flat_map(|scope| scope.keys())
+        .collect::<BTreeSet<_>>();
+    format!(
+        "unknown template key '{}' (valid keys: {})",
+        key,
+        keys.into_iter().collect::<Vec<_>>().join(", ")
+    )
+}
+
+/// Renders nodes already [checked](Template::check) against `scopes`.
+fn render_nodes(nodes: &[Node], scopes: &[&Scope], output: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => output.push_str(text),
+            Node::Key(key) => {
+                if let Some(Value::Text(value)) = lookup(scopes, key) {
+                    output.push_str(value);
+                }
+            }
+            Node::Section(key, inner) => {
+                if let Some(Value::List { items, .. }) = lookup(scopes, key) {
+                    for item in items {
+                        let mut nested = scopes.to_vec();
+                        nested.push(item);
+                        render_nodes(inner, &nested, output);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn check_nodes(nodes: &[Node], scopes: &[&Scope]) -> Result<(), String> {
+    for node in nodes {
+        match node {
+            Node::Text(_) => {}
+            Node::Key(key) => match lookup(scopes, key) {
+                Some(Value::Text(_)) => {}
+                Some(Value::List { .. }) => {
+                    return Err(format!(
+                        "template key '{}' is a list, use {{{{#{}}}}}...{{{{/{}}}}}",
+                        key, key, key
+                    ))
+                }
+                None => return Err(unknown(scopes, key)),
+            },
+            Node::Section(key, inner) => match lookup(scopes, key) {
+                Some(Value::List { item_keys, .. }) => {
+                    let mut prototype = Scope::new();
+                    for item_key in item_keys {
+                        prototype.text(item_key, "");
+                    }
+                    let mut nested = scopes.to_vec();
+                    nested.push(&prototype);
+                    check_nodes(inner, &nested)?;
+                }
+                Some(Value::Text(_)) => return Err(format!("template key '{}' isn't a list", key)),
+                None => return Err(unknown(scopes, key)),
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Templates shipped with dudist, by name.
+pub const PRESETS: [(&str, &str); 2] = [
+    (
+        "slack",
+        "*{{root}}*: {{count}} files, {{total.human}} in total, median {{median.human}}, \
+         p95 {{p95.human}}, largest {{max.human}}\n",
+    ),
+    (
+        "email",
+        "\
+Disk usage report for {{root}}
+
+Files:           {{count}}
+Total size:      {{total.human}}
+
+Smallest:        {{min.human}}
+Lower quartile:  {{q1.human}}
+Median:          {{median.human}}
+Upper quartile:  {{q3.human}}
+95th percentile: {{p95.human}}
+99th percentile: {{p99.human}}
+Largest:         {{max.human}}
+{{#groups}}
+  {{name}}: {{count}} files, {{total.human}}, median {{median.human}}
+{{/groups}}
+",
+    ),
+];
+
+/// The source of the preset called `name`.
+pub fn preset(name: &str) -> Result<&'static str, String> {
+    PRESETS
+        .iter()
+        .find(|(preset, _)| *preset == name)
+        .map(|(_, source)| *source)
+        .ok_or_else(|| {
+            let names = PRESETS.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+            format!(
+                "unknown template preset '{}' (expected {})",
+                name,
+                names.join(" or ")
+            )
+        })
+}
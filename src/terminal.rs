@@ -0,0 +1,155 @@
+#[cfg(unix)]
+pub fn get_terminal_width() -> Option<u16> {
+    use libc::{ioctl, isatty, STDOUT_FILENO, TIOCGWINSZ};
+
+    // Check if stdout is a terminal first to avoid unnecessary unsafe operations
+    if unsafe { isatty(STDOUT_FILENO) } != 1 {
+        return None;
+    }
+
+    // Isolate the unsafe code in a small, well-defined block
+    let ws = unsafe {
+        let mut ws: libc::winsize = std::mem::zeroed();
+        if ioctl(STDOUT_FILENO, TIOCGWINSZ, &mut ws) != 0 {
+            return None;
+        }
+        ws
+    };
+
+    Some(ws.ws_col)
+}
+
+#[cfg(windows)]
+pub fn get_terminal_width() -> Option<u16> {
+    use std::ffi::c_void;
+
+    // Only filled in by the system, most fields are never read
+    #[allow(dead_code)]
+    #[repr(C)]
+    struct Coord {
+        x: i16,
+        y: i16,
+    }
+
+    #[allow(dead_code)]
+    #[repr(C)]
+    struct SmallRect {
+        left: i16,
+        top: i16,
+        right: i16,
+        bottom: i16,
+    }
+
+    #[allow(dead_code)]
+    #[repr(C)]
+    struct ConsoleScreenBufferInfo {
+        size: Coord,
+        cursor_position: Coord,
+        attributes: u16,
+        window: SmallRect,
+        maximum_window_size: Coord,
+    }
+
+    const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+    const INVALID_HANDLE_VALUE: *mut c_void = -1isize as *mut c_void;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetStdHandle(std_handle: u32) -> *mut c_void;
+        fn GetConsoleScreenBufferInfo(
+            console_output: *mut c_void,
+            console_screen_buffer_info: *mut ConsoleScreenBufferInfo,
+        ) -> i32;
+    }
+
+    // The call fails when stdout is not a console, e.g. when redirected
+    let info = unsafe {
+        let handle = GetStdHandle(STD_OUTPUT_HANDLE);
+        if handle.is_null() || handle == INVALID_HANDLE_VALUE {
+            return None;
+        }
+        let mut info: ConsoleScreenBufferInfo = std::mem::zeroed();
+        if GetConsoleScreenBufferInfo(handle, &mut info) == 0 {
+            return None;
+        }
+        info
+    };
+
+    u16::try_from(info.window.right - info.window.left + 1).ok()
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn get_terminal_width() -> Option<u16> {
+    None
+}
+
+/// Whether the locale asks for UTF-8 output, following the usual
+/// `LC_ALL` > `LC_CTYPE` > `LANG` precedence.
+#[cfg(not(windows))]
+pub fn locale_is_utf8() -> bool {
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .map(|value| {
+            let value = value.to_ascii_lowercase();
+            value.contains("utf-8") || value.contains("utf8")
+        })
+        .unwrap_or(false)
+}
+
+/// The Windows console takes text as UTF-16, so any character can be shown.
+#[cfg(windows)]
+pub fn locale_is_utf8() -> bool {
+    true
+}
+
+/// What the output may use, settled once at startup and handed to
+/// everything that renders, so `--no-unicode` reaches every glyph.
+#[derive(Debug, Clone, Copy)]
+pub struct Capabilities {
+    /// Characters beyond ASCII, for plots, bars and symbols
+    pub unicode: bool,
+    /// Columns to fill, 80 when stdout isn't a terminal
+    pub width: u16,
+}
+
+impl Capabilities {
+    /// Detects what stdout can show, using ASCII only when `no_unicode` or
+    /// the locale says so.
+    pub fn detect(no_unicode: bool) -> Capabilities {
+        Capabilities {
+            unicode: !no_unicode && locale_is_utf8(),
+            width: get_terminal_width()
+                .filter(|&width| width > 0)
+                .unwrap_or(80),
+        }
+    }
+
+    /// Pure ASCII, `width` columns wide.
+    pub fn ascii(width: u16) -> Capabilities {
+        Capabilities {
+            unicode: false,
+            width,
+        }
+    }
+
+    /// `unicode` if it can be shown, `ascii` otherwise.
+    pub fn glyph(&self, unicode: char, ascii: char) -> char {
+        if self.unicode {
+            unicode
+        } else {
+            ascii
+        }
+    }
+
+    /// Like [`glyph`](Self::glyph), for glyphs that take several ASCII
+    /// characters.
+    pub fn text<'a>(&self, unicode: &'a str, ascii: &'a str) -> &'a str {
+        if self.unicode {
+            unicode
+        } else {
+            ascii
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::format;
+use crate::plot;
+use crate::terminal::Capabilities;
+
+/// How long each row of the timeline covers, in UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Day,
+    /// Monday to Sunday
+    Week,
+    Month,
+    Year,
+}
+
+impl FromStr for Period {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "day" => Ok(Period::Day),
+            "week" => Ok(Period::Week),
+            "month" => Ok(Period::Month),
+            "year" => Ok(Period::Year),
+            _ => Err(format!(
+                "invalid period '{}' (expected day, week, month or year)",
+                s
+            )),
+        }
+    }
+}
+
+/// Days since the epoch of `time`, rounded down, also before the epoch.
+fn epoch_day(time: SystemTime) -> i64 {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs_f64().ceil() as i64),
+    };
+    secs.div_euclid(86400)
+}
+
+/// The (year, month, day) of a day since the epoch, in the proleptic
+/// Gregorian calendar, following Howard Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Formats `time` in UTC as RFC 3339, to the second, e.g.
+/// `2024-05-01T08:30:00Z`.
+pub fn utc_timestamp(time: SystemTime) -> String {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs_f64().ceil() as i64),
+    };
+    let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+    let seconds = secs.rem_euclid(86400);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+impl Period {
+    /// Numbers the periods so that consecutive ones differ by one.
+    fn index(self, time: SystemTime) -> i64 {
+        let days = epoch_day(time);
+        match self {
+            Period::Day => days,
+            // The epoch was a Thursday
+            Period::Week => (days + 3).div_euclid(7),
+            Period::Month => {
+                let (year, month, _) = civil_from_days(days);
+                year * 12 + month as i64 - 1
+            }
+            Period::Year => civil_from_days(days).0,
+        }
+    }
+
+    /// Names the period with the given index, e.g. `2024-05` for a month.
+    /// Weeks are named by their Monday.
+    fn label(self, index: i64) -> String {
+        let date = |days: i64| {
+            let (year, month, day) = civil_from_days(days);
+            format!("{:04}-{:02}-{:02}", year, month, day)
+        };
+        match self {
+            Period::Day => date(index),
+            Period::Week => date(index * 7 - 3),
+            Period::Month => format!(
+                "{:04}-{:02}",
+                index.div_euclid(12),
+                index.rem_euclid(12) + 1
+            ),
+            Period::Year => format!("{:04}", index),
+        }
+    }
+}
+
+/// Prints the bytes of the files in `dated` as (size, modification time)
+/// pairs per period, for the last `limit` periods up to now, the newest at
+/// the bottom. Periods without files are shown too.
+pub fn print_timeline(
+    dated: &[(u64, SystemTime)],
+    period: Period,
+    limit: usize,
+    caps: &Capabilities,
+) {
+    let now = period.index(SystemTime::now());
+    let last = dated
+        .iter()
+        .map(|&(_, time)| period.index(time))
+        .max()
+        .map_or(now, |newest| newest.max(now));
+    let first = last - limit.max(1) as i64 + 1;
+
+    let mut rows = vec![(0u64, 0u64); (last - first + 1) as usize];
+    let mut earlier = 0;
+    for &(size, time) in dated {
+        let index = period.index(time);
+        if index < first {
+            earlier += 1;
+            continue;
+        }
+        let (count, total) = &mut rows[(index - first) as usize];
+        *count += 1;
+        *total += size;
+    }
+
+    let labels = (first..=last)
+        .map(|index| period.label(index))
+        .collect::<Vec<_>>();
+    let counts = rows
+        .iter()
+        .map(|&(count, _)| format::format_count(count))
+        .collect::<Vec<_>>();
+    let totals = rows
+        .iter()
+        .map(|&(_, total)| format::human(total))
+        .collect::<Vec<_>>();
+    let column = |cells: &[String]| {
+        cells
+            .iter()
+            .map(|cell| cell.chars().count())
+            .max()
+            .unwrap_or(0)
+    };
+    let (label_width, count_width, total_width) =
+        (column(&labels), column(&counts), column(&totals));
+    let bar_width =
+        (caps.width as usize).saturating_sub(label_width + count_width + total_width + 6);
+    let largest = rows.iter().map(|&(_, total)| total).max().unwrap_or(0);
+
+    println!("Bytes by modification {}:", period_name(period));
+    for (i, &(_, total)) in rows.iter().enumerate() {
+        let fraction = if largest > 0 {
+            total as f64 / largest as f64
+        } else {
+            0.0
+        };
+        let line = format!(
+            "{:<label_width$}  {:<bar_width$}  {:>count_width$}  {:>total_width$}",
+            labels[i],
+            plot::horizontal_bar(fraction, bar_width, caps),
+            counts[i],
+            totals[i],
+        );
+        println!("{}", line.trim_end());
+    }
+    if earlier > 0 {
+        println!("({} older files not shown)", format::format_count(earlier));
+    }
+    println!("(modification times only approximate when the bytes were added)");
+}
+
+fn period_name(period: Period) -> &'static str {
+    match period {
+        Period::Day => "day",
+        Period::Week => "week",
+        Period::Month => "month",
+        Period::Year => "year",
+    }
+}
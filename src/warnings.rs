@@ -0,0 +1,400 @@
+//! Warnings gathered over a run and printed together once the report is
+//! done, rather than mixed in with it.
+//!
+//! They are kept by [`Category`], with a count and the first few entries
+//! they concern. [`Warnings`] can be shared between threads.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde_json::{json, Value};
+
+use crate::format;
+use crate::terminal::Capabilities;
+
+/// Components kept at each end of the paths of examples, so that a
+/// pathologically deep one doesn't flood the terminal
+const PATH_ENDS: usize = 8;
+
+/// What went wrong, by the order the warnings are printed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Category {
+    /// Entries the walk couldn't read, and so left out
+    Unreadable,
+    /// Entries past the depth ceiling or `--max-path-bytes`, skipped with
+    /// everything below them
+    TooLong,
+    /// Metadata calls that failed for a moment and succeeded on `--retries`
+    Recovered,
+    /// Symbolic links pointing back at a directory above them
+    SymlinkLoop,
+    /// Directories already walked under another path, through a bind
+    /// mount or overlapping PATHs, and skipped
+    Revisited,
+    /// Roots abandoned after `--root-timeout`, with the files found so far
+    RootTimeout,
+    /// `--allow-mount` paths that aren't mount points
+    AllowMount,
+    /// `--locate` files that couldn't be statted or aren't files
+    Locate,
+    /// Archives `--archives` couldn't list, counted as single files
+    Archive,
+    /// Files last modified after the run started
+    FutureModified,
+    /// Paths that had to be shown with replacement characters
+    NonUtf8,
+    /// Statistics that came out as no size at all, like NaN
+    InvalidValue,
+    /// Lines of the `--history` file that couldn't be read as a run
+    History,
+    /// The scan cache couldn't be read or written
+    Cache,
+    /// The `--progress-json` events couldn't be written
+    ProgressJson,
+}
+
+impl Category {
+    /// A name for the category that stays the same across releases, for
+    /// machine-readable output.
+    pub fn key(self) -> &'static str {
+        match self {
+            Category::Unreadable => "unreadable",
+            Category::TooLong => "too_long",
+            Category::Recovered => "recovered",
+            Category::SymlinkLoop => "symlink_loop",
+            Category::Revisited => "revisited",
+            Category::RootTimeout => "root_timeout",
+            Category::AllowMount => "allow_mount",
+            Category::Locate => "locate",
+            Category::Archive => "archive",
+            Category::FutureModified => "future_modified",
+            Category::NonUtf8 => "non_utf8",
+            Category::InvalidValue => "invalid_value",
+            Category::History => "history",
+            Category::Cache => "cache",
+            Category::ProgressJson => "progress_json",
+        }
+    }
+
+    fn describe(self, count: u64, caps: &Capabilities) -> String {
+        let one = count == 1;
+        let (noun, rest) = match self {
+            Category::Unreadable => (
+                if one { "entry" } else { "entries" },
+                "couldn't be read and left out",
+            ),
+            Category::TooLong => (
+                if one { "entry" } else { "entries" },
+                "too deep or with too long a path and skipped",
+            ),
+            Category::Recovered => (
+                if one {
+                    "metadata call"
+                } else {
+                    "metadata calls"
+                },
+                "failed for a moment and recovered after retry",
+            ),
+            Category::SymlinkLoop => (
+                if one {
+                    "symbolic link"
+                } else {
+                    "symbolic links"
+                },
+                if one {
+                    "loops back to a directory above and wasn't followed"
+                } else {
+                    "loop back to a directory above and weren't followed"
+                },
+            ),
+            Category::Revisited => (
+                if one { "directory" } else { "directories" },
+                "already counted under another path and skipped",
+            ),
+            Category::RootTimeout => (
+                if one { "PATH" } else { "PATHs" },
+                "timed out, only the files found until then are counted",
+            ),
+            Category::AllowMount => (
+                "--allow-mount",
+                if one {
+                    "PATH isn't a mount point and was left out"
+                } else {
+                    "PATHs aren't mount points and were left out"
+                },
+            ),
+            Category::Locate => (
+                "--locate",
+                if one {
+                    "FILE couldn't be located and isn't marked"
+                } else {
+                    "FILEs couldn't be located and aren't marked"
+                },
+            ),
+            Category::Archive => (
+                if one { "archive" } else { "archives" },
+                "couldn't be listed and counted as single files",
+            ),
+            Category::FutureModified => {
+                (if one { "file" } else { "files" }, "modified in the future")
+            }
+            Category::NonUtf8 => (
+                if one { "path" } else { "paths" },
+                caps.text(
+                    "not valid UTF-8, shown with \u{FFFD}",
+                    "not valid UTF-8, shown with ?",
+                ),
+            ),
+            Category::InvalidValue => (
+                if one { "statistic" } else { "statistics" },
+                if one {
+                    "couldn't be computed and is shown as n/a"
+                } else {
+                    "couldn't be computed and are shown as n/a"
+                },
+            ),
+            Category::History => (
+                if one { "history file" } else { "history files" },
+                "had unreadable lines, which were skipped",
+            ),
+            Category::Cache => (
+                if one {
+                    "cache problem"
+                } else {
+                    "cache problems"
+                },
+                "the scan cache couldn't be read or saved",
+            ),
+            Category::ProgressJson => ("--progress-json", "the events couldn't all be written"),
+        };
+        format!("{} {}: {}", format::format_count(count), noun, rest)
+    }
+}
+
+/// One warning of a category, shown as an example.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Example {
+    pub path: Option<PathBuf>,
+    pub message: String,
+}
+
+#[derive(Debug, Default)]
+struct Tally {
+    count: u64,
+    examples: Vec<Example>,
+}
+
+/// The warnings of one category, as [`Warnings::tallies`] lists them.
+#[derive(Debug, Clone)]
+pub struct CategoryTally {
+    pub category: Category,
+    pub count: u64,
+    /// The first few warnings, or all of them without an example limit
+    pub examples: Vec<Example>,
+}
+
+impl CategoryTally {
+    /// The category's key, count and examples, for machine-readable output.
+    pub fn to_json(&self) -> Value {
+        let examples = self
+            .examples
+            .iter()
+            .map(|example| {
+                json!({
+                    "path": example.path.as_deref().map(format::path_to_json),
+                    "message": example.message,
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "category": self.category.key(),
+            "count": self.count,
+            "examples": examples,
+        })
+    }
+}
+
+/// The warnings of a run.
+#[derive(Debug)]
+pub struct Warnings {
+    categories: Mutex<BTreeMap<Category, Tally>>,
+    /// Examples kept per category, all of them when `None`
+    example_limit: Option<usize>,
+}
+
+impl Warnings {
+    pub fn new(example_limit: Option<usize>) -> Warnings {
+        Warnings {
+            categories: Mutex::new(BTreeMap::new()),
+            example_limit,
+        }
+    }
+
+    /// Adds a warning about `path`, when it concerns an entry.
+    pub fn add(&self, category: Category, path: Option<&Path>, message: impl Into<String>) {
+        let mut categories = self.categories.lock().unwrap();
+        let tally = categories.entry(category).or_default();
+        tally.count += 1;
+        let example = Example {
+            path: path.map(Path::to_path_buf),
+            message: message.into(),
+        };
+        // Threads add in any order, so the examples kept are the first by
+        // path rather than the first to come
+        match self.example_limit {
+            Some(limit) if tally.examples.len() >= limit => {
+                if let Some(last) = tally.examples.last_mut().filter(|last| example < **last) {
+                    *last = example;
+                    tally.examples.sort();
+                }
+            }
+            _ => {
+                tally.examples.push(example);
+                tally.examples.sort();
+            }
+        }
+    }
+
+    /// Adds `count` warnings without examples.
+    pub fn add_count(&self, category: Category, count: u64) {
+        if count > 0 {
+            let mut categories = self.categories.lock().unwrap();
+            categories.entry(category).or_default().count += count;
+        }
+    }
+
+    /// The warnings of each category, in the order they are printed.
+    pub fn tallies(&self) -> Vec<CategoryTally> {
+        self.categories
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(&category, tally)| CategoryTally {
+                category,
+                count: tally.count,
+                examples: tally.examples.clone(),
+            })
+            .collect()
+    }
+
+    /// Renders the warnings section, empty when there were none.
+    ///
+    /// ```
+    /// use std::path::Path;
+    /// use dudist::terminal::Capabilities;
+    /// use dudist::warnings::{Category, Warnings};
+    ///
+    /// let warnings = Warnings::new(Some(1));
+    /// warnings.add(Category::Unreadable, Some(Path::new("b")), "Permission denied");
+    /// warnings.add(Category::Unreadable, Some(Path::new("a")), "Permission denied");
+    /// assert_eq!(
+    ///     warnings.render(&Capabilities::ascii(80)),
+    ///     "Warnings:\n  2 entries: couldn't be read and left out\n    \
+    ///      a: Permission denied\n    and 1 more (-v lists all)\n"
+    /// );
+    /// ```
+    pub fn render(&self, caps: &Capabilities) -> String {
+        let categories = self.categories.lock().unwrap();
+        if categories.is_empty() {
+            return String::new();
+        }
+        let mut out = String::from("Warnings:\n");
+        for (category, tally) in categories.iter() {
+            out += &format!("  {}\n", category.describe(tally.count, caps));
+            for example in &tally.examples {
+                match &example.path {
+                    Some(path) => {
+                        out += &format!(
+                            "    {}: {}\n",
+                            format::path(&format::elide(path, PATH_ENDS)),
+                            example.message
+                        )
+                    }
+                    None => out += &format!("    {}\n", example.message),
+                }
+            }
+            let rest = tally.count - tally.examples.len() as u64;
+            // Counted warnings don't keep examples to list
+            if rest > 0 && !tally.examples.is_empty() {
+                out += &format!(
+                    "    and {} more (-v lists all)\n",
+                    format::format_count(rest)
+                );
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn categories_aggregate_in_their_order() {
+        let warnings = Warnings::new(None);
+        warnings.add(Category::Cache, None, "no space left");
+        warnings.add(Category::Unreadable, Some(Path::new("b")), "denied");
+        warnings.add(Category::Unreadable, Some(Path::new("a")), "denied");
+        warnings.add_count(Category::NonUtf8, 4);
+        warnings.add_count(Category::InvalidValue, 0);
+        let tallies = warnings.tallies();
+        let counts = tallies
+            .iter()
+            .map(|tally| (tally.category, tally.count))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            counts,
+            [
+                (Category::Unreadable, 2),
+                (Category::NonUtf8, 4),
+                (Category::Cache, 1)
+            ]
+        );
+        assert_eq!(tallies[0].examples[0].path.as_deref(), Some(Path::new("a")));
+    }
+
+    #[test]
+    fn the_examples_kept_are_the_first_by_path() {
+        let warnings = Warnings::new(Some(2));
+        for path in ["d", "b", "c", "a"] {
+            warnings.add(Category::Unreadable, Some(Path::new(path)), "denied");
+        }
+        let tally = &warnings.tallies()[0];
+        assert_eq!(tally.count, 4);
+        let paths = tally
+            .examples
+            .iter()
+            .map(|example| example.path.as_deref().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, [Path::new("a"), Path::new("b")]);
+    }
+
+    #[test]
+    fn tallies_become_json() {
+        let warnings = Warnings::new(None);
+        warnings.add(Category::RootTimeout, Some(Path::new("/srv")), "after 10s");
+        warnings.add(Category::ProgressJson, None, "broken pipe");
+        let json = warnings
+            .tallies()
+            .iter()
+            .map(CategoryTally::to_json)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            json,
+            [
+                json!({
+                    "category": "root_timeout",
+                    "count": 1,
+                    "examples": [{"path": "/srv", "message": "after 10s"}],
+                }),
+                json!({
+                    "category": "progress_json",
+                    "count": 1,
+                    "examples": [{"path": null, "message": "broken pipe"}],
+                }),
+            ]
+        );
+    }
+}
@@ -0,0 +1,145 @@
+//! `--budgets` checking the totals of `--group-by` groups.
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn dudist(args: &[&str], root: &Path, budgets: &str) -> Output {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(file.path(), budgets).unwrap();
+    Command::new(env!("CARGO_BIN_EXE_dudist"))
+        .args([
+            "--no-header",
+            "--no-timing",
+            "--no-hints",
+            "--min-size",
+            "0",
+        ])
+        .args(args)
+        .arg("--budgets")
+        .arg(file.path())
+        .arg(root)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+/// 3 KiB of images under `assets`, 9000 bytes under `docs` and 20000
+/// under `src`.
+fn tree() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for (file, size) in [
+        ("assets/a.png", 2048),
+        ("assets/b.png", 1024),
+        ("docs/d.md", 9000),
+        ("src/main.rs", 20_000),
+    ] {
+        let path = dir.path().join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, vec![0; size]).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn groups_over_budget_fail_the_run_and_are_named_on_stderr() {
+    let dir = tree();
+    let output = dudist(
+        &["--group-by", "dir"],
+        dir.path(),
+        "assets = \"2KiB\"\ndocs = 9000\nvendor = \"1GiB\"\n",
+    );
+    assert_eq!(output.status.code(), Some(4));
+    // src has no budget, and vendor none of the files
+    assert!(
+        stdout(&output).ends_with(
+            "\nBudgets:\n\
+             Group         Used    Budget   Share  Status\n\
+             src/     19.53 KiB         -\n\
+             docs/     8.79 KiB  8.79 KiB  100.0%  ok\n\
+             assets/      3 KiB     2 KiB  150.0%  OVER\n\
+             vendor/        0 B     1 GiB    0.0%  ok\n"
+        ),
+        "{}",
+        stdout(&output)
+    );
+    assert_eq!(
+        stderr(&output),
+        "error: over budget: assets/ uses 3 KiB of its 2 KiB budget (150.0%)\n"
+    );
+
+    // Also with the report as JSON
+    let output = dudist(&["--group-by", "dir", "--json"], dir.path(), "assets = 1\n");
+    assert_eq!(output.status.code(), Some(4));
+    assert!(stderr(&output).starts_with("error: over budget: assets/ uses 3 KiB"));
+}
+
+#[test]
+fn groups_without_a_budget_never_fail() {
+    let dir = tree();
+    let output = dudist(&["--group-by", "dir"], dir.path(), "assets = \"1MiB\"\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout(&output).contains("\nsrc/     19.53 KiB       -\n"));
+    assert!(output.stderr.is_empty(), "{}", stderr(&output));
+
+    let output = dudist(&["--group-by", "dir"], dir.path(), "");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn extensions_are_checked_with_or_without_the_dot() {
+    let dir = tree();
+    for budgets in ["png = \"2KiB\"\n", "\".PNG\" = 2048\n"] {
+        let output = dudist(&["--group-by", "ext"], dir.path(), budgets);
+        assert_eq!(output.status.code(), Some(4), "{}", budgets);
+        assert!(stdout(&output).ends_with("\n.png       3 KiB   2 KiB  150.0%  OVER\n"));
+        assert_eq!(
+            stderr(&output),
+            "error: over budget: .png uses 3 KiB of its 2 KiB budget (150.0%)\n"
+        );
+    }
+}
+
+#[test]
+fn invalid_budgets_name_the_key_before_scanning() {
+    let dir = tree();
+    for (budgets, error) in [
+        (
+            "assets = \"2 parsecs\"\n",
+            "budget for 'assets' is an invalid size",
+        ),
+        (
+            "[assets]\nmax = 1\n",
+            "budget for 'assets' must be a size like",
+        ),
+        ("docs = -5\n", "budget for 'docs' can't be negative"),
+    ] {
+        let output = dudist(&["--group-by", "dir"], dir.path(), budgets);
+        assert_eq!(output.status.code(), Some(2));
+        assert!(output.stdout.is_empty());
+        assert!(stderr(&output).starts_with("error: invalid budgets in "));
+        assert!(stderr(&output).contains(error), "{}", stderr(&output));
+    }
+}
+
+#[test]
+fn budgets_need_the_grouped_report() {
+    let dir = tree();
+    for (args, error) in [
+        (&[][..], "error: '--budgets' needs '--group-by'\n"),
+        (
+            &["--group-by", "dir", "--count"],
+            "error: '--budgets' needs the full report, not --summary, --count, --check or a template\n",
+        ),
+    ] {
+        let output = dudist(args, dir.path(), "assets = 1\n");
+        assert_eq!(output.status.code(), Some(2));
+        assert_eq!(stderr(&output), error);
+    }
+}
@@ -0,0 +1,80 @@
+//! `--check` as a monitoring system runs it: one line and an exit code.
+
+use std::path::Path;
+use std::process::Command;
+
+fn dudist(args: &[&str], root: &Path) -> (String, i32) {
+    let output = Command::new(env!("CARGO_BIN_EXE_dudist"))
+        .arg("--check")
+        .args(args)
+        .arg(root)
+        .output()
+        .unwrap();
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        output.status.code().unwrap(),
+    )
+}
+
+/// Files of 8 and 16 KiB.
+fn tree() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("a"), vec![0; 8 << 10]).unwrap();
+    std::fs::write(dir.path().join("b"), vec![0; 16 << 10]).unwrap();
+    dir
+}
+
+#[test]
+fn each_status_has_its_exit_code() {
+    let dir = tree();
+    for (args, status, code) in [
+        (&["--warn", "total>1MiB"][..], "OK", 0),
+        (&["--warn", "total>16KiB"], "WARNING", 1),
+        (
+            &["--warn", "total>16KiB", "--crit", "max>=16KiB"],
+            "CRITICAL",
+            2,
+        ),
+        (&["--warn", "median>1", "--min-size", "1GiB"], "UNKNOWN", 3),
+    ] {
+        let (line, exit) = dudist(args, dir.path());
+        assert!(
+            line.starts_with(&format!("DUDIST {} - ", status)),
+            "{}",
+            line
+        );
+        assert_eq!(line.lines().count(), 1);
+        assert_eq!(exit, code, "{}", line);
+    }
+}
+
+#[test]
+fn bad_expressions_are_unknown_and_named() {
+    let dir = tree();
+    let (line, code) = dudist(&["--crit", "p42>1GiB"], dir.path());
+    assert!(
+        line.starts_with(
+            "DUDIST UNKNOWN - invalid value for '--crit': invalid threshold 'p42>1GiB'"
+        ),
+        "{}",
+        line
+    );
+    assert_eq!(code, 3);
+}
+
+#[cfg(unix)]
+#[test]
+fn a_scan_that_skipped_entries_is_unknown() {
+    let dir = tree();
+    std::fs::create_dir_all(dir.path().join("sub/deeper")).unwrap();
+    std::os::unix::fs::symlink("..", dir.path().join("sub/deeper/loop")).unwrap();
+    let (line, code) = dudist(
+        &["-L", "--strict=errors-only", "--warn", "total>1MiB"],
+        dir.path(),
+    );
+    assert_eq!(
+        line,
+        "DUDIST UNKNOWN - skipped 1 entries that couldn't be read\n"
+    );
+    assert_eq!(code, 3);
+}
@@ -0,0 +1,92 @@
+//! `--count` against the full report, for the same filters.
+
+use std::path::Path;
+use std::process::Command;
+
+use serde_json::Value;
+
+/// Runs dudist with `args`, returning what it printed and its exit code.
+fn dudist(args: &[&str], root: &Path) -> (String, i32) {
+    let output = Command::new(env!("CARGO_BIN_EXE_dudist"))
+        .args(args)
+        .arg(root)
+        .output()
+        .unwrap();
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        output.status.code().unwrap(),
+    )
+}
+
+/// A tree of files of every size up to 12 KiB in 512 byte steps, nested
+/// and hidden, with an ignore file.
+fn tree() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for sub in ["", "nested/deeper", ".hidden", "skipped"] {
+        std::fs::create_dir_all(dir.path().join(sub)).unwrap();
+        for (i, size) in (0..=12 << 10).step_by(512).enumerate() {
+            let name = if i % 5 == 0 {
+                format!(".{}.dat", i)
+            } else {
+                format!("{}.dat", i)
+            };
+            std::fs::write(dir.path().join(sub).join(name), vec![b'x'; size]).unwrap();
+        }
+    }
+    std::fs::write(dir.path().join(".dudistignore"), "skipped/\n").unwrap();
+    dir
+}
+
+#[test]
+fn the_count_and_total_agree_with_a_full_run() {
+    let dir = tree();
+    for filters in [
+        &[][..],
+        &["--min-size", "0"],
+        &["--min-size", "2KiB", "--max-size", "9KiB"],
+        &["--exclude-hidden"],
+        &["--max-depth", "1", "--min-size", "0"],
+        &["--no-dudistignore", "--min-size", "1"],
+    ] {
+        let (json, code) = dudist(&[filters, &["--json"]].concat(), dir.path());
+        assert_eq!(code, 0, "{:?}", filters);
+        let report: Value = serde_json::from_str(&json).unwrap();
+
+        let (count, code) = dudist(&[filters, &["--count"]].concat(), dir.path());
+        assert_eq!(code, 0, "{:?}", filters);
+        assert_eq!(count, format!("{}\n", report["files"]), "{:?}", filters);
+
+        let (both, _) = dudist(&[filters, &["--count", "--total"]].concat(), dir.path());
+        assert_eq!(
+            both,
+            format!("{} {}\n", report["files"], report["total"]),
+            "{:?}",
+            filters
+        );
+    }
+}
+
+#[test]
+fn no_matches_count_zero_and_fail() {
+    let dir = tree();
+    let (count, code) = dudist(&["--count", "--min-size", "1GiB"], dir.path());
+    assert_eq!(count, "0\n");
+    assert_eq!(code, 1);
+}
+
+#[test]
+fn outputs_needing_the_sizes_are_refused() {
+    let dir = tree();
+    let snapshot = dir.path().join("sizes.json");
+    let output = Command::new(env!("CARGO_BIN_EXE_dudist"))
+        .args(["--count", "--snapshot"])
+        .arg(&snapshot)
+        .arg(dir.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("'--count' can't be combined with '--snapshot'"));
+    assert!(!snapshot.exists());
+}
@@ -0,0 +1,94 @@
+//! Reports over the same files must be byte-identical, so they diff
+//! cleanly when committed nightly.
+
+use std::path::Path;
+use std::process::Command;
+
+fn dudist(args: &[&str], roots: &[&Path]) -> Vec<u8> {
+    let output = Command::new(env!("CARGO_BIN_EXE_dudist"))
+        .args(["--no-timing", "--no-hints", "--jobs", "4"])
+        .args(args)
+        .args(roots)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{:?}", args);
+    [output.stdout, output.stderr].concat()
+}
+
+/// Directories and extensions holding the same number of files of the
+/// same sizes, so every table and listing has ties to break, and a few
+/// files repeating sizes found elsewhere.
+fn tree() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for sub in ["alpha", "beta", "gamma", "delta"] {
+        std::fs::create_dir(dir.path().join(sub)).unwrap();
+        for ext in ["a", "b", "c"] {
+            for (i, size) in [5000, 9000, 9000, 70_000].into_iter().enumerate() {
+                let name = format!("{}/{}.{}", sub, i, ext);
+                std::fs::write(dir.path().join(name), vec![0; size]).unwrap();
+            }
+        }
+    }
+    dir
+}
+
+#[test]
+fn runs_over_the_same_files_print_the_same_bytes() {
+    let (one, two) = (tree(), tree());
+    let roots = [one.path(), two.path()];
+    for args in [
+        &[][..],
+        &["--examples", "--modes", "3", "--rank", "8KiB"],
+        &["--group-by", "ext"],
+        &["--group-by", "ext", "--small-multiples"],
+        &["--anomalies", "--anomaly-min-group", "4"],
+        &["--list-empty-dirs", "--hardlink-report"],
+        &["--json", "--group-by", "ext", "--examples"],
+        &["--summary"],
+    ] {
+        let first = dudist(args, &roots);
+        for _ in 0..3 {
+            assert!(
+                dudist(args, &roots) == first,
+                "{:?} printed something else:\n{}",
+                args,
+                String::from_utf8_lossy(&first)
+            );
+        }
+    }
+}
+
+#[test]
+fn single_root_reports_break_ties_the_same_way_every_run() {
+    let dir = tree();
+    for args in [
+        &["--group-by", "dir", "--sort", "count"][..],
+        &["--group-by", "dir", "--sort", "median", "--reverse"],
+        &["--json", "--group-by", "dir"],
+        &["--stat", "dir-entries"],
+        &["--template-preset", "email"],
+    ] {
+        let first = dudist(args, &[dir.path()]);
+        for _ in 0..3 {
+            assert!(dudist(args, &[dir.path()]) == first, "{:?}", args);
+        }
+    }
+}
+
+#[test]
+fn the_order_of_the_roots_only_changes_the_header() {
+    let (one, two) = (tree(), tree());
+    let args = ["--no-header", "--group-by", "ext", "--examples"];
+    let forward = dudist(&args, &[one.path(), two.path()]);
+    let backward = dudist(&args, &[two.path(), one.path()]);
+    let without_paths = |report: Vec<u8>| {
+        String::from_utf8(report)
+            .unwrap()
+            .lines()
+            .filter(|line| !line.contains(one.path().to_str().unwrap()))
+            .filter(|line| !line.contains(two.path().to_str().unwrap()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    assert_eq!(without_paths(forward), without_paths(backward));
+}
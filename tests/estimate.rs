@@ -0,0 +1,84 @@
+//! `--fast-estimate` on a synthetic tree, against a full scan of it.
+
+use std::path::Path;
+use std::process::Command;
+
+use serde_json::Value;
+
+fn dudist(args: &[&str], root: &Path) -> Value {
+    let output = Command::new(env!("CARGO_BIN_EXE_dudist"))
+        .args(["--json", "--no-timing"])
+        .args(args)
+        .arg(root)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{:?}", args);
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+/// 2000 files in 20 directories, of sizes drawn evenly from 0 to 100 KB,
+/// so about 4% are below the default --min-size. The files are sparse, as
+/// only their sizes are read.
+fn tree() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+    for sub in 0..20 {
+        let sub = dir.path().join(format!("d{}", sub));
+        std::fs::create_dir(&sub).unwrap();
+        for i in 0..100 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let file = std::fs::File::create(sub.join(format!("{}.dat", i))).unwrap();
+            file.set_len(state % 100_000).unwrap();
+        }
+    }
+    dir
+}
+
+fn error(estimate: &Value, exact: &Value, key: &str) -> f64 {
+    let (estimate, exact) = (
+        estimate[key].as_f64().unwrap(),
+        exact[key].as_f64().unwrap(),
+    );
+    (estimate - exact).abs() / exact
+}
+
+#[test]
+fn the_estimate_converges_as_the_stat_budget_grows() {
+    let dir = tree();
+    let exact = dudist(&[], dir.path());
+    // The bounds on the relative errors of the file count, the total and
+    // the median are about four standard errors of a random sample of
+    // that many, so a failure means a bias rather than bad luck
+    for (budget, stride, statted, bounds) in [
+        (20, 100, 20, [0.2, 0.5, 0.9]),
+        (100, 20, 100, [0.08, 0.22, 0.4]),
+        (300, 7, 286, [0.05, 0.13, 0.22]),
+        (1000, 2, 1000, [0.02, 0.05, 0.09]),
+        (2000, 1, 2000, [0.0, 0.0, 0.0]),
+    ] {
+        let estimate = dudist(
+            &["--fast-estimate", "--stat-budget", &budget.to_string()],
+            dir.path(),
+        );
+        // The listing is complete whatever the budget
+        assert_eq!(estimate["estimate"]["listed"], 2000);
+        assert_eq!(estimate["estimate"]["stride"], stride);
+        assert_eq!(estimate["estimate"]["statted"], statted);
+        let errors = [
+            error(&estimate, &exact, "files"),
+            error(&estimate, &exact, "total"),
+            error(&estimate["distribution"], &exact["distribution"], "median"),
+        ];
+        for (error, bound) in errors.iter().zip(bounds) {
+            assert!(*error <= bound, "{}: {:?}", budget, errors);
+        }
+    }
+
+    // A budget past the number of files is the full scan
+    assert_eq!(
+        dudist(&["--fast-estimate", "--stat-budget", "5000"], dir.path())["distribution"],
+        exact["distribution"]
+    );
+}
@@ -0,0 +1,75 @@
+//! `.dudistignore` files at the root and further down.
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn dudist(args: &[&str], root: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_dudist"))
+        .args(args)
+        .arg(root)
+        .output()
+        .unwrap()
+}
+
+fn count(args: &[&str], root: &Path) -> String {
+    let output = dudist(&[args, &["--count"]].concat(), root);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Build outputs and disk images, ignored at the root, with `src` taking
+/// one image back in and a hidden one the root also takes back.
+fn tree() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for sub in ["build", "src", ".cache"] {
+        std::fs::create_dir(dir.path().join(sub)).unwrap();
+    }
+    for file in [
+        "build/a.o",
+        "build/b.o",
+        "src/main.c",
+        "src/kept.iso",
+        ".cache/cached.iso",
+        "top.iso",
+    ] {
+        std::fs::write(dir.path().join(file), vec![0; 9000]).unwrap();
+    }
+    std::fs::write(
+        dir.path().join(".dudistignore"),
+        "# build outputs\nbuild/\n*.iso\n!.cache/cached.iso\n",
+    )
+    .unwrap();
+    std::fs::write(dir.path().join("src/.dudistignore"), "!kept.iso\n").unwrap();
+    dir
+}
+
+#[test]
+fn the_ignore_files_prune_the_walk_and_the_report_says_so() {
+    let dir = tree();
+    // src/main.c, src/kept.iso and .cache/cached.iso
+    assert_eq!(count(&[], dir.path()), "3\n");
+    let output = dudist(&[], dir.path());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Number of files: 3"), "{}", stdout);
+    assert!(stdout.contains("skipping what .dudistignore files list"));
+    // build and top.iso
+    assert!(
+        stdout.contains("Applied 2 .dudistignore files, skipping 2 entries"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn no_dudistignore_counts_everything() {
+    let dir = tree();
+    assert_eq!(count(&["--no-dudistignore"], dir.path()), "6\n");
+    let output = dudist(&["--no-dudistignore"], dir.path());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!stdout.contains(".dudistignore"), "{}", stdout);
+}
+
+#[test]
+fn ignore_rules_never_take_back_what_other_filters_skip() {
+    let dir = tree();
+    assert_eq!(count(&["--exclude-hidden"], dir.path()), "2\n");
+}
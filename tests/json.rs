@@ -0,0 +1,512 @@
+//! The `--json` report, read back the way scripts read it.
+
+use std::path::Path;
+use std::process::Command;
+
+use serde_json::Value;
+
+/// Runs dudist with `args` and parses what it printed, with its exit code.
+fn dudist(args: &[&str], root: &Path) -> (Value, i32) {
+    let output = Command::new(env!("CARGO_BIN_EXE_dudist"))
+        .args(args)
+        .arg(root)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let report =
+        serde_json::from_str(&stdout).unwrap_or_else(|e| panic!("not JSON ({}): {}", e, stdout));
+    (report, output.status.code().unwrap())
+}
+
+/// A tree of files of 1 to 5 KiB and an empty one.
+fn tree() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for (name, kib) in [
+        ("a.txt", 1),
+        ("b.txt", 2),
+        ("c.log", 3),
+        ("d.log", 4),
+        ("e.bin", 5),
+    ] {
+        std::fs::write(dir.path().join(name), vec![b'x'; kib << 10]).unwrap();
+    }
+    std::fs::write(dir.path().join("empty"), b"").unwrap();
+    dir
+}
+
+#[test]
+fn the_report_has_the_counts_and_the_quartiles() {
+    let dir = tree();
+    let (report, code) = dudist(&["--json", "--min-size", "0"], dir.path());
+    assert_eq!(code, 0);
+    assert_eq!(report["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(report["roots"][0], dir.path().to_str().unwrap());
+    assert_eq!(report["files"], 5);
+    assert_eq!(report["total"], 15 << 10);
+    assert_eq!(report["interrupted"], false);
+    let distribution = &report["distribution"];
+    assert_eq!(distribution["min"], 1 << 10);
+    assert_eq!(distribution["median"], 3072.0);
+    assert_eq!(distribution["max"], 5 << 10);
+    assert_eq!(distribution["interquartile_range"], 2048.0);
+    // Deviations from 3 KiB of 2, 1, 0, 1 and 2 KiB
+    assert_eq!(distribution["median_absolute_deviation"], 1024.0);
+    assert!(report["timing"]["scan_seconds"].as_f64().unwrap() >= 0.0);
+    assert!(report.get("generated").is_none());
+}
+
+#[test]
+fn no_timing_leaves_the_timing_out() {
+    let dir = tree();
+    let (report, _) = dudist(&["--json", "--no-timing", "--timestamp"], dir.path());
+    assert!(report.get("timing").is_none());
+    assert!(report["generated"].as_str().unwrap().ends_with('Z'));
+}
+
+#[test]
+fn no_matches_give_a_null_distribution() {
+    let dir = tree();
+    let (report, code) = dudist(&["--json", "--min-size", "1G"], dir.path());
+    assert_eq!(code, 1);
+    assert_eq!(report["files"], 0);
+    assert!(report["distribution"].is_null());
+}
+
+#[test]
+fn estimates_say_how_many_files_were_statted() {
+    let dir = tree();
+    let (report, code) = dudist(
+        &["--json", "--fast-estimate", "--min-size", "0"],
+        dir.path(),
+    );
+    assert_eq!(code, 0);
+    assert_eq!(report["estimate"]["listed"], 6);
+    assert_eq!(report["estimate"]["statted"], 6);
+    assert_eq!(report["files"], 5);
+}
+
+/// The names of the groups in the report, in order.
+fn group_names(report: &Value) -> Vec<&str> {
+    report["groups"]["rows"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|row| row["name"].as_str().unwrap())
+        .collect()
+}
+
+#[test]
+fn groups_come_in_the_order_of_the_table() {
+    let dir = tree();
+    let (report, _) = dudist(
+        &["--json", "--min-size", "0", "--group-by", "ext"],
+        dir.path(),
+    );
+    let groups = &report["groups"];
+    assert_eq!(groups["by"], "extension");
+    assert_eq!(groups["sort"]["key"], "total");
+    assert_eq!(groups["sort"]["reverse"], false);
+    // 7, 5 and 3 KiB
+    assert_eq!(group_names(&report), [".log", ".bin", ".txt"]);
+    assert_eq!(groups["rows"][0]["files"], 2);
+    assert_eq!(groups["rows"][0]["total"], 7 << 10);
+    assert_eq!(groups["rows"][0]["median"], 3584.0);
+
+    let (report, _) = dudist(
+        &[
+            "--json",
+            "--min-size",
+            "0",
+            "--group-by",
+            "ext",
+            "--sort",
+            "name",
+            "--reverse",
+        ],
+        dir.path(),
+    );
+    assert_eq!(report["groups"]["sort"]["key"], "name");
+    assert_eq!(group_names(&report), [".txt", ".log", ".bin"]);
+}
+
+#[test]
+fn ranks_give_the_share_below_each_size() {
+    let dir = tree();
+    let (report, _) = dudist(
+        &[
+            "--json",
+            "--min-size",
+            "0",
+            "--rank",
+            "3KiB",
+            "--rank",
+            "1",
+            "--rank",
+            "1TiB",
+        ],
+        dir.path(),
+    );
+    let ranks = report["ranks"].as_array().unwrap();
+    assert_eq!(ranks.len(), 3);
+    // 1 and 2 KiB of the 15 KiB
+    assert_eq!(ranks[0]["size"], 3 << 10);
+    assert_eq!(ranks[0]["files_below_percent"], 40.0);
+    assert_eq!(ranks[0]["bytes_below_percent"], 20.0);
+    // Outside the sizes seen, nothing and everything
+    assert_eq!(ranks[1]["files_below_percent"], 0.0);
+    assert_eq!(ranks[2]["files_below_percent"], 100.0);
+    assert_eq!(ranks[2]["bytes_below_percent"], 100.0);
+}
+
+#[test]
+fn the_other_row_names_the_groups_merged_into_it() {
+    let dir = tree();
+    let (report, _) = dudist(
+        &[
+            "--json",
+            "--min-size",
+            "0",
+            "--group-by",
+            "ext",
+            "--max-groups",
+            "1",
+        ],
+        dir.path(),
+    );
+    assert_eq!(group_names(&report), [".log", "(other)"]);
+    let other = &report["groups"]["rows"][1];
+    assert_eq!(other["other_members"], serde_json::json!([".bin", ".txt"]));
+    assert_eq!(other["files"], 3);
+    assert!(report["groups"]["rows"][0].get("other_members").is_none());
+}
+
+#[cfg(unix)]
+#[test]
+fn fs_usage_relates_the_total_to_the_filesystem() {
+    let dir = tree();
+    let (report, _) = dudist(&["--json", "--min-size", "0", "--fs-usage"], dir.path());
+    let usage = &report["fs_usage"];
+    assert!(usage["total"].as_u64().unwrap() >= usage["used"].as_u64().unwrap());
+    assert_eq!(usage["scanned"], 15 << 10);
+    assert!(usage["used_percent"].as_f64().unwrap() <= 100.0);
+    let (report, _) = dudist(&["--json"], dir.path());
+    assert!(report.get("fs_usage").is_none());
+}
+
+#[test]
+fn modes_list_the_sizes_that_repeat() {
+    let dir = tree();
+    std::fs::write(dir.path().join("f.txt"), vec![b'x'; 2 << 10]).unwrap();
+    let (report, _) = dudist(&["--json", "--min-size", "0"], dir.path());
+    assert_eq!(
+        report["modes"],
+        serde_json::json!([{"size": 2 << 10, "count": 2}])
+    );
+    let (report, _) = dudist(&["--json", "--min-size", "0", "--compact"], dir.path());
+    assert!(report.get("modes").is_none());
+}
+
+#[test]
+fn examples_name_a_file_for_each_statistic() {
+    let dir = tree();
+    let (report, _) = dudist(&["--json", "--min-size", "0", "--examples"], dir.path());
+    let examples = &report["examples"];
+    let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+    assert_eq!(examples["min"]["path"], path("a.txt"));
+    assert_eq!(examples["min"]["size"], 1 << 10);
+    assert_eq!(examples["median"]["path"], path("c.log"));
+    assert_eq!(examples["max"]["path"], path("e.bin"));
+    assert!(examples.get("sampled").is_none());
+
+    let (report, _) = dudist(
+        &["--json", "--min-size", "0", "--examples", "--fast-estimate"],
+        dir.path(),
+    );
+    assert_eq!(report["examples"]["median"]["path"], path("c.log"));
+    assert_eq!(report["examples"]["sampled"], false);
+}
+
+#[test]
+fn warnings_come_as_an_array_in_the_report() {
+    let dir = tree();
+    let future = dir.path().join("a.txt");
+    std::fs::File::options()
+        .write(true)
+        .open(&future)
+        .unwrap()
+        .set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(86400))
+        .unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_dudist"))
+        .args(["--json", "--min-size", "0"])
+        .arg(dir.path())
+        .output()
+        .unwrap();
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let warnings = report["warnings"].as_array().unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0]["category"], "future_modified");
+    assert_eq!(warnings[0]["count"], 1);
+    assert_eq!(warnings[0]["examples"][0]["path"], future.to_str().unwrap());
+    // Not repeated on stderr
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("Warnings:"));
+
+    let (report, _) = dudist(&["--json", "--no-warnings"], dir.path());
+    assert!(report.get("warnings").is_none());
+    std::fs::remove_file(&future).unwrap();
+    let (report, _) = dudist(&["--json"], dir.path());
+    assert_eq!(report["warnings"], serde_json::json!([]));
+}
+
+#[test]
+fn a_file_that_cant_be_located_is_a_warning() {
+    let dir = tree();
+    let out = tempfile::tempdir().unwrap();
+    let missing = out.path().join("missing");
+    let missing = missing.to_str().unwrap();
+    let events = out.path().join("events.jsonl");
+    let events = events.to_str().unwrap();
+    let (report, code) = dudist(
+        &["--json", "--locate", missing, "--progress-json", events],
+        dir.path(),
+    );
+    assert_eq!(code, 0);
+    let warnings = report["warnings"].as_array().unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0]["category"], "locate");
+    assert_eq!(warnings[0]["examples"][0]["path"], missing);
+    // Sent with the scan's warnings, before it finished
+    let events = std::fs::read_to_string(events).unwrap();
+    let warning = events
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap())
+        .find(|event| event["event"] == "warning")
+        .unwrap();
+    assert_eq!(warning["category"], "locate");
+    assert_eq!(warning["examples"], warnings[0]["examples"]);
+
+    let (report, _) = dudist(
+        &["--json", "--no-warnings", "--locate", missing],
+        dir.path(),
+    );
+    assert!(report.get("warnings").is_none());
+}
+
+#[test]
+fn settings_list_the_filters_and_traversal() {
+    let dir = tree();
+    let (report, _) = dudist(
+        &[
+            "--json",
+            "--min-size",
+            "1K",
+            "--max-depth",
+            "2",
+            "--exclude-hidden",
+        ],
+        dir.path(),
+    );
+    assert_eq!(
+        report["settings"],
+        serde_json::json!({
+            "filters": [
+                "skipping hidden entries",
+                "skipping what .dudistignore files list",
+                "files larger than 1000 B",
+            ],
+            "traversal": [
+                "at most 2 levels deep",
+                "not following symbolic links",
+                "crossing filesystems",
+            ],
+        })
+    );
+}
+
+#[test]
+fn estimates_from_a_sample_have_confidence_intervals() {
+    let dir = tempfile::tempdir().unwrap();
+    for kib in 1..=40 {
+        std::fs::write(dir.path().join(format!("{}.bin", kib)), vec![0; kib << 10]).unwrap();
+    }
+    let (report, _) = dudist(
+        &[
+            "--json",
+            "--min-size",
+            "0",
+            "--fast-estimate",
+            "--stat-budget",
+            "10",
+            "--plot-marks",
+            "90",
+        ],
+        dir.path(),
+    );
+    assert!(report["estimate"]["stride"].as_u64().unwrap() > 1);
+    let intervals = &report["confidence_intervals"];
+    let median = report["distribution"]["median"].as_f64().unwrap();
+    assert!(intervals["median"]["low"].as_f64().unwrap() <= median);
+    assert!(intervals["median"]["high"].as_f64().unwrap() >= median);
+    assert!(intervals["p90"]["high"].as_f64().is_some());
+    assert!(intervals.get("min").is_none());
+
+    // Statting every file leaves nothing to estimate
+    let (report, _) = dudist(&["--json", "--fast-estimate"], dir.path());
+    assert_eq!(report["estimate"]["stride"], 1);
+    assert!(report.get("confidence_intervals").is_none());
+}
+
+#[test]
+fn groups_say_which_flags_they_earn() {
+    let dir = tree();
+    for i in 0..20 {
+        std::fs::write(dir.path().join(format!("{}.log", i)), vec![0; 1 << 10]).unwrap();
+    }
+    std::fs::write(dir.path().join("huge.log"), vec![0; 1 << 20]).unwrap();
+    let (report, _) = dudist(
+        &["--json", "--min-size", "0", "--group-by", "ext"],
+        dir.path(),
+    );
+    assert_eq!(group_names(&report)[0], ".log");
+    assert_eq!(
+        report["groups"]["rows"][0]["flags"],
+        serde_json::json!({"skewed": true, "outlier": false, "concentrated": true})
+    );
+    // Too few files to tell
+    assert_eq!(
+        report["groups"]["rows"][1]["flags"],
+        serde_json::json!({"skewed": false, "outlier": false, "concentrated": false})
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn hardlinks_count_the_names_and_the_bytes_saved() {
+    let dir = tree();
+    std::fs::hard_link(dir.path().join("e.bin"), dir.path().join("f.bin")).unwrap();
+    std::fs::hard_link(dir.path().join("e.bin"), dir.path().join("g.bin")).unwrap();
+    let (report, _) = dudist(
+        &["--json", "--min-size", "0", "--hardlink-report"],
+        dir.path(),
+    );
+    let links = &report["hardlinks"];
+    assert_eq!(links["names"], 3);
+    assert_eq!(links["files"], 1);
+    assert_eq!(links["logical"], 15 << 10);
+    assert_eq!(links["physical"], 5 << 10);
+    assert_eq!(links["saved"], 10 << 10);
+    let largest = &links["largest"][0];
+    assert_eq!(largest["path"], dir.path().join("e.bin").to_str().unwrap());
+    assert_eq!(largest["names"], 3);
+    assert_eq!(largest["links"], 3);
+}
+
+#[test]
+fn empty_dirs_count_nested_ones_and_list_the_topmost() {
+    let dir = tree();
+    for empty in ["a/b/c", "a/d", "e"] {
+        std::fs::create_dir_all(dir.path().join(empty)).unwrap();
+    }
+    let (report, _) = dudist(&["--json", "--empty-dirs"], dir.path());
+    assert_eq!(
+        report["empty_dirs"],
+        serde_json::json!({"count": 5, "topmost": 2})
+    );
+    let (report, _) = dudist(&["--json", "--empty-dirs", "--list-empty-dirs"], dir.path());
+    let mut paths = report["empty_dirs"]["paths"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|path| path.as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    paths.sort();
+    let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+    assert_eq!(paths, [path("a"), path("e")]);
+    let (report, _) = dudist(&["--json"], dir.path());
+    assert!(report.get("empty_dirs").is_none());
+}
+
+#[test]
+fn anomalies_list_the_atypical_files() {
+    let dir = tree();
+    for i in 0..25 {
+        std::fs::write(dir.path().join(format!("{}.dat", i)), vec![0; 1 << 10]).unwrap();
+    }
+    std::fs::write(dir.path().join("huge.dat"), vec![0; 1 << 20]).unwrap();
+    let (report, _) = dudist(&["--json", "--min-size", "0", "--anomalies"], dir.path());
+    let anomalies = &report["anomalies"];
+    assert_eq!(anomalies["thresholds"]["min_group"], 20);
+    let files = anomalies["anomalies"].as_array().unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(
+        files[0]["path"],
+        dir.path().join("huge.dat").to_str().unwrap()
+    );
+    assert_eq!(files[0]["extension"], ".dat");
+    // The median comes from buckets, within 0.39%
+    let times = files[0]["times_median"].as_f64().unwrap();
+    assert!((times - 1024.0).abs() < 1024.0 * 0.004, "{}", times);
+}
+
+#[test]
+fn retention_previews_each_policy() {
+    let dir = tree();
+    let old = std::time::SystemTime::now() - std::time::Duration::from_secs(400 * 86400);
+    for name in ["d.log", "e.bin"] {
+        std::fs::File::options()
+            .write(true)
+            .open(dir.path().join(name))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+    }
+    let (report, _) = dudist(
+        &[
+            "--json",
+            "--min-size",
+            "0",
+            "--retention",
+            "180d",
+            "--retention",
+            "2y",
+        ],
+        dir.path(),
+    );
+    let retention = &report["retention"];
+    assert_eq!(retention["total_bytes"], 15 << 10);
+    let policies = retention["policies"].as_array().unwrap();
+    assert_eq!(policies[0]["age_seconds"], 180 * 86400);
+    assert_eq!(policies[0]["delete"]["files"], 2);
+    assert_eq!(policies[0]["delete"]["bytes"], 9 << 10);
+    assert_eq!(policies[0]["keep"]["files"], 3);
+    assert_eq!(policies[0]["frees_share"], 0.6);
+    assert_eq!(policies[1]["delete"]["files"], 0);
+}
+
+#[test]
+fn resources_say_what_the_run_consumed() {
+    let dir = tree();
+    let (report, _) = dudist(
+        &["--json", "--min-size", "0", "--resource-stats"],
+        dir.path(),
+    );
+    let resources = &report["resources"];
+    assert_eq!(resources["readdir_calls"], 1);
+    assert!(resources["stat_calls"].as_u64().unwrap() >= 6);
+    assert_eq!(resources["retained_entries"], 5);
+    assert!(resources["wall_seconds"].as_f64().unwrap() > 0.0);
+
+    let (report, _) = dudist(
+        &[
+            "--json",
+            "--min-size",
+            "0",
+            "--resource-stats",
+            "--fast-estimate",
+        ],
+        dir.path(),
+    );
+    // The six files listed and the records of the five that matched
+    assert_eq!(report["resources"]["retained_entries"], 11);
+    let (report, _) = dudist(&["--json"], dir.path());
+    assert!(report.get("resources").is_none());
+}
@@ -0,0 +1,120 @@
+//! The streaming library API, used the way an embedding application would.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use dudist::compact::Sizes;
+use dudist::distribution::Distribution;
+use dudist::scan::{scan_iter, ScanOptions};
+
+/// Files of 100, 2000 and 5000 bytes at three depths, and a hidden one of
+/// 300 bytes.
+fn tree() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("sub/deeper")).unwrap();
+    std::fs::create_dir_all(dir.path().join(".hidden")).unwrap();
+    for (name, size) in [
+        ("a", 100),
+        ("sub/b", 2000),
+        ("sub/deeper/c", 5000),
+        (".hidden/d", 300),
+    ] {
+        std::fs::write(dir.path().join(name), vec![b'x'; size]).unwrap();
+    }
+    dir
+}
+
+/// The size of each file found, by its path below `root`.
+fn found(root: &Path, options: &ScanOptions) -> BTreeMap<PathBuf, u64> {
+    scan_iter(root, options)
+        .map(|record| {
+            let record = record.unwrap();
+            let path = record.path.strip_prefix(root).unwrap().to_path_buf();
+            (path, record.size)
+        })
+        .collect()
+}
+
+#[test]
+fn every_file_is_yielded_with_its_size_and_time() {
+    let dir = tree();
+    let options = ScanOptions::builder().min_size(0).build().unwrap();
+    let files = found(dir.path(), &options);
+    assert_eq!(
+        files,
+        BTreeMap::from([
+            (PathBuf::from(".hidden/d"), 300),
+            (PathBuf::from("a"), 100),
+            (PathBuf::from("sub/b"), 2000),
+            (PathBuf::from("sub/deeper/c"), 5000),
+        ])
+    );
+    for record in scan_iter(dir.path(), &options) {
+        assert!(record.unwrap().modified.is_some());
+    }
+}
+
+#[test]
+fn the_filters_apply_while_streaming() {
+    let dir = tree();
+    let options = ScanOptions::builder()
+        .min_size(200)
+        .max_size(4000)
+        .build()
+        .unwrap();
+    let files = found(dir.path(), &options);
+    assert_eq!(files.keys().collect::<Vec<_>>(), [".hidden/d", "sub/b"]);
+
+    let options = ScanOptions::builder()
+        .min_size(0)
+        .skip_hidden(true)
+        .max_depth(2)
+        .build()
+        .unwrap();
+    let files = found(dir.path(), &options);
+    assert_eq!(files.keys().collect::<Vec<_>>(), ["a", "sub/b"]);
+}
+
+#[test]
+fn a_missing_root_is_one_error() {
+    let dir = tree();
+    let missing = dir.path().join("missing");
+    let options = ScanOptions::default();
+    let results = scan_iter(&missing, &options).collect::<Vec<_>>();
+    assert_eq!(results.len(), 1);
+    let error = results.into_iter().next().unwrap().unwrap_err();
+    assert_eq!(error.path.as_deref(), Some(missing.as_path()));
+}
+
+#[test]
+fn the_distribution_of_a_scan() {
+    let dir = tree();
+    let options = ScanOptions::builder().min_size(0).build().unwrap();
+    let sizes = scan_iter(dir.path(), &options).map(|record| record.unwrap().size);
+    let dist = Distribution::from_sizes(sizes).unwrap();
+    assert_eq!((dist.min, dist.max), (100, 5000));
+    assert_eq!(dist.median, 1150.0);
+    // The quartiles are the medians of either half
+    assert_eq!(dist.lower_quartile, 200.0);
+    assert_eq!(dist.upper_quartile, 3500.0);
+
+    let options = ScanOptions::builder().min_size(1 << 20).build().unwrap();
+    let sizes = scan_iter(dir.path(), &options).map(|record| record.unwrap().size);
+    assert!(Distribution::from_sizes(sizes).is_none());
+}
+
+#[test]
+fn compact_sizes_stream_into_buckets() {
+    let dir = tree();
+    let options = ScanOptions::builder().min_size(0).build().unwrap();
+    let mut sizes = Sizes::new(true);
+    for record in scan_iter(dir.path(), &options) {
+        sizes.add(record.unwrap().size);
+    }
+    assert!(sizes.exact().is_none());
+    let dist = Distribution::from_ranked(&sizes);
+    let close = |value: f64, exact: f64| (value - exact).abs() <= exact * 0.004;
+    assert!(close(dist.min as f64, 100.0));
+    assert!(close(dist.median, 1150.0));
+    assert!(close(dist.max as f64, 5000.0));
+}
@@ -0,0 +1,50 @@
+//! `dudist merge` over snapshots saved by separate scans.
+
+use std::path::Path;
+use std::process::Command;
+
+fn dudist(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_dudist"))
+        .args(args)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{:?}", args);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// A tree of files of the given sizes.
+fn tree(sizes: &[usize]) -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for (i, &size) in sizes.iter().enumerate() {
+        std::fs::write(dir.path().join(format!("{}.dat", i)), vec![0; size]).unwrap();
+    }
+    dir
+}
+
+fn path(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+#[test]
+fn merged_snapshots_report_as_one_scan_of_both_roots() {
+    let (web1, web2) = (
+        tree(&[5000, 5000, 70_000, 1 << 20]),
+        tree(&[4097, 9000, 9000, 300_000, 2 << 20, 8]),
+    );
+    let out = tempfile::tempdir().unwrap();
+    let snapshots = [out.path().join("web1.json"), out.path().join("web2.json")];
+    for (root, snapshot) in [(&web1, &snapshots[0]), (&web2, &snapshots[1])] {
+        dudist(&["--summary", "--snapshot", path(snapshot), path(root.path())]);
+    }
+
+    let merged = dudist(&[
+        "merge",
+        "--summary",
+        "--bytes",
+        path(&snapshots[0]),
+        path(&snapshots[1]),
+    ]);
+    let both = dudist(&["--summary", "--bytes", path(web1.path()), path(web2.path())]);
+    assert_eq!(merged, both);
+    assert!(merged.starts_with("n=9 "), "{}", merged);
+}
@@ -0,0 +1,122 @@
+//! PATH arguments that aren't plain directories.
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn dudist(args: &[&str], root: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_dudist"))
+        .arg("--no-header")
+        .args(args)
+        .arg(root)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+/// A file of 9000 bytes, one of 100, a directory holding one of 5000 and
+/// an empty directory.
+fn tree() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("file.bin"), vec![0; 9000]).unwrap();
+    std::fs::write(dir.path().join("small.bin"), vec![0; 100]).unwrap();
+    std::fs::create_dir(dir.path().join("dir")).unwrap();
+    std::fs::write(dir.path().join("dir/inside.bin"), vec![0; 5000]).unwrap();
+    std::fs::create_dir(dir.path().join("empty")).unwrap();
+    dir
+}
+
+#[test]
+fn a_file_is_reported_as_the_only_file() {
+    let dir = tree();
+    let output = dudist(&[], &dir.path().join("file.bin"));
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout(&output)
+        .starts_with("Number of files: 1\nSize:            8.79 KiB (9,000 B), the only file\n"));
+
+    // Even below --min-size, as it was asked for by name
+    let output = dudist(&[], &dir.path().join("small.bin"));
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout(&output).contains("100 B (100 B), the only file"));
+
+    let output = dudist(&["--json"], &dir.path().join("file.bin"));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["files"], 1);
+    assert_eq!(report["distribution"]["median"], 9000.0);
+}
+
+#[cfg(unix)]
+#[test]
+fn a_symbolic_link_to_a_file_is_resolved_with_a_note() {
+    let dir = tree();
+    let link = dir.path().join("link");
+    std::os::unix::fs::symlink("file.bin", &link).unwrap();
+    let output = dudist(&[], &link);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout(&output).contains("8.79 KiB (9,000 B), the only file"));
+    assert_eq!(
+        stderr(&output),
+        format!(
+            "note: {} is a symbolic link, scanning {}\n",
+            link.display(),
+            dir.path().join("file.bin").display()
+        )
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn a_symbolic_link_to_a_directory_scans_the_directory() {
+    let dir = tree();
+    let link = dir.path().join("dirlink");
+    std::os::unix::fs::symlink("dir", &link).unwrap();
+    let output = dudist(&["--min-size", "0"], &link);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout(&output).contains("4.88 KiB (5,000 B), the only file"));
+    assert!(stderr(&output).contains(&format!(
+        "{} is a symbolic link, scanning {}",
+        link.display(),
+        dir.path().join("dir").display()
+    )));
+}
+
+#[cfg(unix)]
+#[test]
+fn a_dangling_symbolic_link_is_an_error() {
+    let dir = tree();
+    let link = dir.path().join("dangling");
+    std::os::unix::fs::symlink("nowhere", &link).unwrap();
+    let output = dudist(&[], &link);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        stderr(&output),
+        format!(
+            "error: {} is a symbolic link to nowhere, which doesn't exist\n",
+            link.display()
+        )
+    );
+}
+
+#[test]
+fn a_missing_path_is_an_error_unlike_an_empty_directory() {
+    let dir = tree();
+    let missing = dir.path().join("missing");
+    let output = dudist(&[], &missing);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        stderr(&output),
+        format!("error: {} doesn't exist\n", missing.display())
+    );
+
+    let output = dudist(&[], &dir.path().join("empty"));
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout(&output).starts_with("No files found in the directory\n"));
+}
@@ -0,0 +1,30 @@
+//! The counters behind `--resource-stats`, in a process of their own so
+//! that no other test walks a tree while they count.
+
+use std::time::Instant;
+
+use dudist::resources::{self, Usage};
+use dudist::scan::{scan_iter, ScanOptions};
+
+#[test]
+fn the_walk_counts_its_listings_and_stat_calls() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("sub")).unwrap();
+    for name in ["a", "b", "sub/c"] {
+        std::fs::write(dir.path().join(name), "x").unwrap();
+    }
+    let start = Instant::now();
+    let before = Usage::measure(start, 0);
+    resources::enable();
+    let options = ScanOptions::builder().min_size(0).build().unwrap();
+    let files = scan_iter(dir.path(), &options).flatten().count();
+
+    let usage = Usage::measure(start, files as u64);
+    // The root and sub listed, the files statted
+    assert_eq!(usage.readdir_calls - before.readdir_calls, 2);
+    assert!(usage.stat_calls - before.stat_calls >= 3);
+    assert_eq!(usage.retained, 3);
+    assert!(usage.wall >= before.wall);
+    #[cfg(target_os = "linux")]
+    assert!(usage.peak_rss.unwrap() > 0);
+}
@@ -0,0 +1,124 @@
+//! `--strict` and `--strict=errors-only` over entries the walk can't read.
+
+#![cfg(unix)]
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn dudist(args: &[&str], root: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_dudist"))
+        .args(args)
+        .arg(root)
+        .output()
+        .unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+/// A subdirectory made unreadable, readable again when dropped so the
+/// tree can be removed.
+struct Unreadable(PathBuf);
+
+impl Unreadable {
+    /// Makes `dir` unreadable, or `None` where that doesn't stop this
+    /// process reading it, as for root.
+    fn new(dir: PathBuf) -> Option<Unreadable> {
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o000)).unwrap();
+        let unreadable = Unreadable(dir);
+        match fs::read_dir(&unreadable.0) {
+            Ok(_) => {
+                eprintln!("skipped, {} is still readable", unreadable.0.display());
+                None
+            }
+            Err(_) => Some(unreadable),
+        }
+    }
+}
+
+impl Drop for Unreadable {
+    fn drop(&mut self) {
+        fs::set_permissions(&self.0, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+}
+
+/// A file of 9000 bytes beside the directory `locked` holding another.
+fn tree() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("locked")).unwrap();
+    fs::write(dir.path().join("locked/hidden.dat"), vec![0; 9000]).unwrap();
+    fs::write(dir.path().join("seen.dat"), vec![0; 9000]).unwrap();
+    dir
+}
+
+#[test]
+fn strict_stops_at_an_unreadable_directory() {
+    let dir = tree();
+    let locked = dir.path().join("locked");
+    let Some(_locked) = Unreadable::new(locked.clone()) else {
+        return;
+    };
+    let output = dudist(&["--strict"], dir.path());
+    assert_eq!(output.status.code(), Some(3));
+    assert!(output.stdout.is_empty());
+    assert!(stderr(&output).starts_with(&format!("error: {}", locked.display())));
+}
+
+#[test]
+fn errors_only_finishes_the_report_then_lists_what_it_skipped() {
+    let dir = tree();
+    let locked = dir.path().join("locked");
+    let Some(_locked) = Unreadable::new(locked.clone()) else {
+        return;
+    };
+    let output = dudist(&["--strict=errors-only"], dir.path());
+    assert_eq!(output.status.code(), Some(3));
+    let stdout = String::from_utf8(output.stdout.clone()).unwrap();
+    assert!(stdout.contains("Number of files: 1"), "{}", stdout);
+    let stderr = stderr(&output);
+    assert!(stderr.contains("error: skipped 1 entries that couldn't be read:"));
+    assert!(stderr.contains(&format!("  {}", locked.display())));
+}
+
+#[test]
+fn by_default_an_unreadable_directory_is_a_warning() {
+    let dir = tree();
+    let locked = dir.path().join("locked");
+    let Some(_locked) = Unreadable::new(locked.clone()) else {
+        return;
+    };
+    let output = dudist(&[], dir.path());
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stderr(&output).contains(&locked.display().to_string()));
+}
+
+/// A file of 9000 bytes and a symbolic link looping back up the tree,
+/// which the walk can't follow whoever runs it.
+fn looping_tree() -> (tempfile::TempDir, PathBuf) {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("a/b")).unwrap();
+    let link = dir.path().join("a/b/loop");
+    std::os::unix::fs::symlink("..", &link).unwrap();
+    fs::write(dir.path().join("seen.dat"), vec![0; 9000]).unwrap();
+    (dir, link)
+}
+
+#[test]
+fn both_variants_fail_on_a_symbolic_link_loop() {
+    let (dir, link) = looping_tree();
+    let output = dudist(&["-L", "--strict"], dir.path());
+    assert_eq!(output.status.code(), Some(3));
+    assert!(output.stdout.is_empty());
+    assert!(stderr(&output).starts_with(&format!("error: {}", link.display())));
+
+    let output = dudist(&["-L", "--strict=errors-only"], dir.path());
+    assert_eq!(output.status.code(), Some(3));
+    assert!(!output.stdout.is_empty());
+    assert!(stderr(&output).contains(&format!("  {}: File system loop", link.display())));
+
+    let output = dudist(&["-L"], dir.path());
+    assert_eq!(output.status.code(), Some(0));
+}
@@ -0,0 +1,87 @@
+//! The `--summary` line, which status bars and monitoring checks parse.
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn dudist(args: &[&str], root: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_dudist"))
+        .args(args)
+        .arg(root)
+        .output()
+        .unwrap()
+}
+
+/// Files of 1 to 5 KiB and one of 1.5 KiB.
+fn tree() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for kib in 1..=5 {
+        std::fs::write(dir.path().join(format!("{}.dat", kib)), vec![0; kib << 10]).unwrap();
+    }
+    std::fs::write(dir.path().join("half.dat"), vec![0; 1536]).unwrap();
+    dir
+}
+
+#[test]
+fn the_summary_is_one_line_of_stable_keys() {
+    let dir = tree();
+    let output = dudist(&["--summary", "--min-size", "0"], dir.path());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "n=6 total=16.5KiB min=1KiB q1=1.5KiB med=2.5KiB q3=4KiB max=5KiB\n"
+    );
+    assert!(output.stderr.is_empty());
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn bytes_make_the_values_raw() {
+    let dir = tree();
+    let output = dudist(&["--summary", "--bytes", "--min-size", "0"], dir.path());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "n=6 total=16896 min=1024 q1=1536 med=2560 q3=4096 max=5120\n"
+    );
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn no_matches_keep_the_keys_without_values() {
+    let dir = tree();
+    let output = dudist(&["--summary", "--min-size", "1GiB"], dir.path());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "n=0 total=0B min=- q1=- med=- q3=- max=-\n"
+    );
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn unreadable_history_lines_are_a_warning_after_the_line() {
+    let dir = tree();
+    let out = tempfile::tempdir().unwrap();
+    let history = out.path().join("history.jsonl");
+    std::fs::write(&history, "not a run\n").unwrap();
+    let history = history.to_str().unwrap();
+    let args = [
+        "--summary",
+        "--min-size",
+        "0",
+        "--history",
+        history,
+        "--show-trend",
+    ];
+    let output = dudist(&args, dir.path());
+    assert_eq!(output.status.code(), Some(0));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.starts_with(&format!(
+            "Warnings:\n  1 history file: had unreadable lines, which were skipped\n    {}: skipped 1 unreadable line\n",
+            history
+        )),
+        "{}",
+        stderr
+    );
+
+    let output = dudist(&[&args[..], &["--no-warnings"]].concat(), dir.path());
+    assert!(output.stderr.is_empty());
+}
@@ -0,0 +1,168 @@
+//! Names that aren't valid UTF-8, which Unix allows, through every output.
+
+#![cfg(unix)]
+
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use serde_json::Value;
+
+/// Runs dudist in a UTF-8 locale, with `cache` as its cache directory.
+fn dudist(args: &[&str], root: &Path, cache: &Path) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_dudist"))
+        .args(args)
+        .arg(root)
+        .env("LC_ALL", "C.UTF-8")
+        .env("XDG_CACHE_HOME", cache)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("panicked"), "{:?}: {}", args, stderr);
+    output
+}
+
+/// A Latin-1 directory holding a file whose name is two invalid bytes, a
+/// valid file beside it and an empty Latin-1 directory. Returns the path of
+/// the invalid file too.
+fn tree() -> (tempfile::TempDir, PathBuf) {
+    let dir = tempfile::tempdir().unwrap();
+    let latin1 = dir.path().join(OsStr::from_bytes(b"caf\xe9"));
+    std::fs::create_dir(&latin1).unwrap();
+    std::fs::create_dir(dir.path().join(OsStr::from_bytes(b"\xe9t\xe9"))).unwrap();
+    let invalid = latin1.join(OsStr::from_bytes(b"\xff\xfe.dat"));
+    std::fs::write(&invalid, vec![0; 5000]).unwrap();
+    std::fs::write(dir.path().join("ok.dat"), vec![0; 9000]).unwrap();
+    (dir, invalid)
+}
+
+#[test]
+fn the_report_marks_the_names_and_counts_them() {
+    let (dir, _) = tree();
+    let output = dudist(
+        &[
+            "--examples",
+            "--list-empty-dirs",
+            "--stat",
+            "dir-entries",
+            "--group-by",
+            "dir",
+            "--anomalies",
+        ],
+        dir.path(),
+        dir.path(),
+    );
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("caf\u{FFFD}/\u{FFFD}\u{FFFD}.dat"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("\u{FFFD}t\u{FFFD}"), "{}", stdout);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("not valid UTF-8, shown with \u{FFFD}"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn json_keeps_the_bytes_of_the_names() {
+    let (dir, invalid) = tree();
+    let output = dudist(
+        &["--json", "--examples", "--list-empty-dirs"],
+        dir.path(),
+        dir.path(),
+    );
+    assert_eq!(output.status.code(), Some(0));
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let bytes = |value: &Value| {
+        let bytes = value["bytes"].as_array().unwrap();
+        bytes
+            .iter()
+            .map(|byte| byte.as_u64().unwrap() as u8)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        bytes(&report["examples"]["min"]["path"]),
+        invalid.as_os_str().as_bytes()
+    );
+    assert_eq!(
+        bytes(&report["empty_dirs"]["paths"][0]),
+        dir.path()
+            .join(OsStr::from_bytes(b"\xe9t\xe9"))
+            .as_os_str()
+            .as_bytes()
+    );
+    assert_eq!(
+        report["examples"]["max"]["path"],
+        dir.path().join("ok.dat").to_str().unwrap()
+    );
+}
+
+#[test]
+fn every_other_output_survives_them() {
+    let (dir, _) = tree();
+    let out = tempfile::tempdir().unwrap();
+    let snapshot = out.path().join("sizes.snapshot");
+    let snapshot = snapshot.to_str().unwrap();
+    let events = out.path().join("events.jsonl");
+    let events = events.to_str().unwrap();
+    let chart = out.path().join("chart.svg");
+    let chart = chart.to_str().unwrap();
+    let gnuplot = out.path().join("gnuplot");
+    let gnuplot = gnuplot.to_str().unwrap();
+    for args in [
+        &["--summary"][..],
+        &["--count", "--total"],
+        &["--template-str", "{{count}} {{median.human}}"],
+        &["--snapshot", snapshot],
+        &["--progress-json", events],
+        &["--chart-out", chart],
+        &["--export", "gnuplot", gnuplot],
+        &[
+            "--hardlink-report",
+            "--list-cold",
+            "3",
+            "--cold-after",
+            "0s",
+        ],
+        &["--cache"],
+        &["--cache"],
+        &["--incremental"],
+    ] {
+        let output = dudist(args, dir.path(), out.path());
+        assert_eq!(output.status.code(), Some(0), "{:?}", args);
+        assert!(!output.stdout.is_empty(), "{:?}", args);
+    }
+
+    let merged = Command::new(env!("CARGO_BIN_EXE_dudist"))
+        .args(["merge", "--summary", "--bytes", snapshot])
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(merged.stdout).unwrap(),
+        "n=2 total=14000 min=5000 q1=5000 med=7000 q3=9000 max=9000\n"
+    );
+}
+
+#[test]
+fn strict_utf8_fails_on_the_first_such_name() {
+    let (dir, _) = tree();
+    let output = dudist(&["--strict-utf8"], dir.path(), dir.path());
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("\u{FFFD}\u{FFFD}.dat is not valid UTF-8"),
+        "{}",
+        stderr
+    );
+
+    let valid = tempfile::tempdir().unwrap();
+    std::fs::write(valid.path().join("caf\u{e9}.dat"), vec![0; 9000]).unwrap();
+    let output = dudist(&["--strict-utf8", "--count"], valid.path(), valid.path());
+    assert_eq!(output.status.code(), Some(0));
+}